@@ -0,0 +1,92 @@
+//! Crawls pages into JSON Lines without a database or any model: links are
+//! fetched with the crate's HTTP client and extracted with the scrapers of
+//! `scrapers.ron`, the generic extractor for other sites with the title
+//! rules of `config.toml`. With `--pages` the
+//! pages are read from a file of fetched pages, the output of
+//! [`encrawl_rust::pipeline::fetch`], instead of the network.
+//!
+//! ```sh
+//! cargo run --example crawl_to_jsonl -- https://example.com/news/1 > articles.jsonl
+//! cargo run --example crawl_to_jsonl -- --pages examples/fixtures/pages.jsonl
+//! ```
+//!
+//! The output can be stored later with `encrawl-rust import`.
+
+use clap::Parser;
+use encrawl_rust::candidate::CandidateUrl;
+use encrawl_rust::http::{HttpClient, USER_AGENT};
+use encrawl_rust::pipeline::FetchedPage;
+use encrawl_rust::scrape::{self, Page};
+use encrawl_rust::settings::{self, Settings};
+use encrawl_rust::ScraperConfig;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+struct Args {
+    /// Pages to crawl
+    urls: Vec<String>,
+
+    /// JSON Lines of fetched pages to extract instead of fetching `urls`
+    #[arg(long)]
+    pages: Option<PathBuf>,
+
+    #[arg(long, default_value = "scrapers.ron")]
+    scrapers: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    colog::init();
+    let args = Args::parse();
+    let scrapers = if args.scrapers.exists() {
+        ScraperConfig::from_file(args.scrapers)?
+    } else {
+        vec![]
+    };
+    let title_rules = Settings::load(Path::new(settings::DEFAULT_PATH))?.title_rules;
+
+    let mut pages = vec![];
+    if let Some(path) = &args.pages {
+        for line in std::io::BufReader::new(std::fs::File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                pages.push(serde_json::from_str::<FetchedPage>(&line)?);
+            }
+        }
+    }
+    let http = HttpClient::new(1.0, 3, USER_AGENT)?;
+    for url in &args.urls {
+        let candidate = CandidateUrl::new(url, "example")?;
+        let html = match http.send(http.get(url)).await {
+            Ok(resp) => resp.error_for_status()?.text().await?,
+            Err(e) => {
+                log::error!("Failed to fetch {}: {}", url, e);
+                continue;
+            }
+        };
+        let page = Page {
+            html,
+            etag: None,
+            last_modified: None,
+        };
+        pages.push(FetchedPage { candidate, page });
+    }
+
+    let mut out = std::io::stdout().lock();
+    for FetchedPage { candidate, page } in pages {
+        let url = candidate.as_str().to_string();
+        let mut article = match scrape::extract_page(&scrapers, &title_rules, url, &page.html) {
+            Ok(article) => article,
+            Err(e) => {
+                log::error!("Failed to extract {}: {}", candidate, e);
+                continue;
+            }
+        };
+        article.source = Some(candidate.source);
+        article.lang = article.language();
+        serde_json::to_writer(&mut out, &article)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! Runs only the Telegram bot: answering searches and sending the digests
+//! chats subscribe to, over the articles crawled into Postgres by a separate
+//! `encrawl-rust daemon`. The database URL and bot token come from
+//! `config.toml` or the environment, like for `encrawl-rust bot`.
+//!
+//! ```sh
+//! ENCRAWL_TELEGRAM_BOT_TOKEN=... cargo run --example digest_bot -- --chats 12345
+//! ```
+//!
+//! With `--commands` the bot answers the commands in a file, one per line,
+//! as the first of `--chats` instead of polling Telegram, which needs no
+//! token. Without a database URL the articles are then kept in a SQLite
+//! file, where `--articles` imports an export into, so this runs offline.
+//! Subscriptions need Postgres and fail there:
+//!
+//! ```sh
+//! cargo run --example digest_bot -- --commands examples/fixtures/bot_commands.txt \
+//!     --articles examples/fixtures/articles.jsonl
+//! ```
+
+use clap::Parser;
+use encrawl_rust::settings::{self, Settings};
+use encrawl_rust::telegram::{self, BotCommand, TelegramBot};
+use encrawl_rust::{corpus, Config, Encrawl};
+use std::path::PathBuf;
+
+/// Where `--commands` keeps the articles when no database URL is configured.
+const OFFLINE_DATABASE_URL: &str = "sqlite://digest-bot-example.db";
+
+#[derive(Parser)]
+struct Args {
+    /// Chats allowed to use the bot
+    #[arg(long, value_delimiter = ',')]
+    chats: Vec<i64>,
+
+    /// Commands to answer instead of the ones sent on Telegram
+    #[arg(long)]
+    commands: Option<PathBuf>,
+
+    /// Export to import before answering, as written by `encrawl-rust export`
+    #[arg(long)]
+    articles: Option<PathBuf>,
+
+    /// Database the articles are read from, `database_url` of the config by default
+    #[arg(long)]
+    database_url: Option<String>,
+
+    #[arg(long, default_value = settings::DEFAULT_PATH)]
+    config: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    colog::init();
+    let args = Args::parse();
+    let settings = Settings::load(&args.config)?;
+    let database_url = match args.database_url.or(settings.database_url.clone()) {
+        Some(url) => url,
+        None if args.commands.is_some() => OFFLINE_DATABASE_URL.to_string(),
+        None => anyhow::bail!("set database_url in config.toml or ENCRAWL_DATABASE_URL"),
+    };
+    let config = Config::load(
+        "scrapers.ron".into(),
+        "finance_subs.list".into(),
+        "feeds.list".into(),
+        "sources.ron".into(),
+        "sinks.ron".into(),
+        "policy.ron".into(),
+    )?;
+    let app = Encrawl::new(&database_url, None, config).await?;
+    if let Some(path) = &args.articles {
+        let input = std::io::BufReader::new(std::fs::File::open(path)?);
+        let counts = corpus::import(&app, input, 32).await?;
+        log::info!(
+            "Imported {} new and {} updated articles, {} were already there",
+            counts.new,
+            counts.updated,
+            counts.duplicates
+        );
+    }
+
+    if let Some(path) = &args.commands {
+        let chat_id = args.chats.first().copied().unwrap_or_default();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let Some(command) = BotCommand::parse(line) else {
+                continue;
+            };
+            println!("> {}", line.trim());
+            match telegram::handle(&app, chat_id, &command).await {
+                Ok(reply) => println!("{reply}\n"),
+                Err(e) => println!("Failed: {e}\n"),
+            }
+        }
+        return Ok(());
+    }
+    let token = settings.telegram.bot_token.ok_or_else(|| {
+        anyhow::anyhow!("set telegram.bot_token in config.toml or ENCRAWL_TELEGRAM_BOT_TOKEN")
+    })?;
+    let bot = TelegramBot::new(app.http().clone(), token, args.chats);
+    bot.run(&app).await
+}
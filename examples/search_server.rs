@@ -0,0 +1,66 @@
+//! Serves searches over an export of articles, without Postgres or a crawl:
+//! the articles are imported into a SQLite file, embedded with the
+//! configured model, and searched through the `/search` endpoint of the HTTP
+//! API. Endpoints other than `/search` need Postgres and fail.
+//!
+//! ```sh
+//! cargo run --example search_server -- examples/fixtures/articles.jsonl
+//! curl 'localhost:3000/search?q=interest+rates&limit=2'
+//! ```
+
+use clap::Parser;
+use encrawl_rust::{corpus, search, server, Config, Encrawl};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+struct Args {
+    /// Export to serve, as written by `encrawl-rust export`
+    articles: PathBuf,
+
+    /// SQLite database the articles are imported into
+    #[arg(long, default_value = "sqlite://search-example.db")]
+    database_url: String,
+
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    listen: String,
+
+    /// Search once for this and exit instead of serving
+    #[arg(long)]
+    query: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    colog::init();
+    let args = Args::parse();
+    // Only the scrapers and sources are read from files, none are needed here.
+    let config = Config::load(
+        "scrapers.ron".into(),
+        "finance_subs.list".into(),
+        "feeds.list".into(),
+        "sources.ron".into(),
+        "sinks.ron".into(),
+        "policy.ron".into(),
+    )?;
+    let app = Encrawl::new(&args.database_url, None, config).await?;
+
+    let input = std::io::BufReader::new(std::fs::File::open(&args.articles)?);
+    let counts = corpus::import(&app, input, 32).await?;
+    log::info!(
+        "Imported {} new and {} updated articles, {} were already there",
+        counts.new,
+        counts.updated,
+        counts.duplicates
+    );
+
+    if let Some(query) = args.query {
+        for article in search(&app, query, 5).await? {
+            println!("{}\n  {}", article.title, article.url);
+        }
+        return Ok(());
+    }
+    let listener = tokio::net::TcpListener::bind(&args.listen).await?;
+    log::info!("Searching on http://{}/search?q=", args.listen);
+    axum::serve(listener, server::router(app)).await?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+-- Schema as it was set up at startup before migrations existed. Everything is
+-- idempotent so deployments that already have these tables upgrade in place.
+CREATE EXTENSION IF NOT EXISTS vector;
+
+CREATE TABLE IF NOT EXISTS articles (
+    title TEXT NOT NULL,
+    url TEXT NOT NULL,
+    content TEXT NOT NULL,
+    author TEXT NOT NULL,
+    embedding vector(384)
+);
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS id BIGSERIAL;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS annotation TEXT;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS extractor TEXT;
+DELETE FROM articles a USING articles b WHERE a.url = b.url AND a.ctid > b.ctid;
+CREATE UNIQUE INDEX IF NOT EXISTS articles_url_key ON articles (url);
+CREATE INDEX IF NOT EXISTS articles_content_hash_idx ON articles (content_hash);
+
+CREATE TABLE IF NOT EXISTS usage (
+    id BIGSERIAL PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    topic TEXT NOT NULL,
+    backend TEXT NOT NULL,
+    prompt_tokens BIGINT NOT NULL,
+    completion_tokens BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS watchlists (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    query TEXT NOT NULL,
+    drift_threshold REAL NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS watchlist_centroids (
+    id BIGSERIAL PRIMARY KEY,
+    watchlist_id BIGINT NOT NULL REFERENCES watchlists (id) ON DELETE CASCADE,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    article_count INT NOT NULL,
+    centroid vector NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS digests (
+    id BIGSERIAL PRIMARY KEY,
+    topic TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS deliveries (
+    digest_id BIGINT NOT NULL REFERENCES digests (id) ON DELETE CASCADE,
+    sink TEXT NOT NULL,
+    attempts INT NOT NULL,
+    delivered BOOLEAN NOT NULL,
+    error TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (digest_id, sink)
+);
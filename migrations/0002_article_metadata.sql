@@ -0,0 +1,18 @@
+-- Where and when articles were found and published.
+ALTER TABLE articles ADD COLUMN published_at TIMESTAMPTZ;
+ALTER TABLE articles ADD COLUMN source TEXT;
+ALTER TABLE articles ADD COLUMN domain TEXT;
+ALTER TABLE articles ADD COLUMN fetched_at TIMESTAMPTZ;
+
+-- Existing rows were fetched at some point before now and their source wasn't
+-- recorded, the domain can be recovered from the URL.
+UPDATE articles SET
+    fetched_at = now(),
+    source = 'unknown',
+    domain = lower(substring(url FROM '^[a-zA-Z]+://(?:www\.)?([^/:?#]+)'));
+
+ALTER TABLE articles ALTER COLUMN fetched_at SET DEFAULT now();
+ALTER TABLE articles ALTER COLUMN fetched_at SET NOT NULL;
+
+CREATE INDEX articles_domain_idx ON articles (domain);
+CREATE INDEX articles_published_at_idx ON articles (published_at);
@@ -0,0 +1,11 @@
+-- Chunks of article content embedded separately so the body is searchable.
+CREATE UNIQUE INDEX IF NOT EXISTS articles_id_key ON articles (id);
+
+CREATE TABLE article_chunks (
+    article_id BIGINT NOT NULL REFERENCES articles (id) ON DELETE CASCADE,
+    seq INT NOT NULL,
+    start_byte INT NOT NULL,
+    end_byte INT NOT NULL,
+    embedding vector(384) NOT NULL,
+    PRIMARY KEY (article_id, seq)
+);
@@ -0,0 +1,7 @@
+-- Full-text index over titles and content for keyword matches in hybrid search.
+ALTER TABLE articles ADD COLUMN search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A')
+    || setweight(to_tsvector('english', coalesce(content, '')), 'B')
+) STORED;
+
+CREATE INDEX articles_search_idx ON articles USING GIN (search);
@@ -0,0 +1,7 @@
+-- Approximate nearest neighbour indexes, so searches don't scan every embedding.
+-- They are rebuilt with other parameters by `encrawl-rust reindex`.
+CREATE INDEX IF NOT EXISTS articles_embedding_idx
+    ON articles USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64);
+
+CREATE INDEX IF NOT EXISTS article_chunks_embedding_idx
+    ON article_chunks USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64);
@@ -0,0 +1,10 @@
+-- Topics Telegram chats receive a summary of on their own schedule.
+CREATE TABLE telegram_subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL,
+    topic TEXT NOT NULL,
+    interval_secs BIGINT NOT NULL,
+    last_sent_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (chat_id, topic)
+);
@@ -0,0 +1,15 @@
+-- Which model produced every embedding, so switching models can be detected and
+-- migrated. Everything before this was embedded with all-MiniLM-L12-v2.
+ALTER TABLE articles
+    ADD COLUMN embedding_model TEXT NOT NULL DEFAULT 'all-MiniLM-L12-v2',
+    ADD COLUMN embedding_dim INT NOT NULL DEFAULT 384;
+ALTER TABLE articles
+    ALTER COLUMN embedding_model DROP DEFAULT,
+    ALTER COLUMN embedding_dim DROP DEFAULT;
+
+ALTER TABLE article_chunks
+    ADD COLUMN embedding_model TEXT NOT NULL DEFAULT 'all-MiniLM-L12-v2',
+    ADD COLUMN embedding_dim INT NOT NULL DEFAULT 384;
+ALTER TABLE article_chunks
+    ALTER COLUMN embedding_model DROP DEFAULT,
+    ALTER COLUMN embedding_dim DROP DEFAULT;
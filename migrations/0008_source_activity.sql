@@ -0,0 +1,10 @@
+-- How often every crawl source yields new articles and when it is polled next.
+CREATE TABLE source_activity (
+    source TEXT PRIMARY KEY,
+    polls BIGINT NOT NULL DEFAULT 0,
+    new_articles BIGINT NOT NULL DEFAULT 0,
+    last_polled_at TIMESTAMPTZ,
+    last_new_at TIMESTAMPTZ,
+    interval_secs BIGINT NOT NULL,
+    next_poll_at TIMESTAMPTZ NOT NULL
+);
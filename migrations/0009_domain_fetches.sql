@@ -0,0 +1,7 @@
+-- Pages fetched per domain and day, for the daily limits of the crawl policy.
+CREATE TABLE domain_fetches (
+    domain TEXT NOT NULL,
+    day DATE NOT NULL,
+    pages INT NOT NULL,
+    PRIMARY KEY (domain, day)
+);
@@ -0,0 +1,7 @@
+-- The report of every crawl run, see `CrawlReport`.
+CREATE TABLE crawl_runs (
+    id BIGSERIAL PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL,
+    report JSONB NOT NULL
+);
@@ -0,0 +1,7 @@
+-- The newest post seen from every source by the crawl daemon.
+CREATE TABLE source_cursors (
+    source TEXT PRIMARY KEY,
+    last_post_id TEXT,
+    last_posted_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,23 @@
+-- Articles fetched per day, source and domain, so stats don't have to count
+-- the whole articles table. Articles without a domain are counted under ''.
+CREATE TABLE article_rollups (
+    day DATE NOT NULL,
+    source TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    articles BIGINT NOT NULL,
+    PRIMARY KEY (day, source, domain)
+);
+
+-- When each rollup was last refreshed, days from then on are recounted.
+CREATE TABLE rollup_refreshes (
+    name TEXT PRIMARY KEY,
+    refreshed_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX articles_fetched_at_idx ON articles (fetched_at);
+
+INSERT INTO article_rollups (day, source, domain, articles)
+SELECT fetched_at::date, COALESCE(source, 'unknown'), COALESCE(domain, ''), COUNT(*)
+FROM articles GROUP BY 1, 2, 3;
+
+INSERT INTO rollup_refreshes (name, refreshed_at) VALUES ('articles', now());
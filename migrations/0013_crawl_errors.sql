@@ -0,0 +1,12 @@
+-- Every URL a crawl run failed to scrape or store, with what kind of error.
+CREATE TABLE crawl_errors (
+    id BIGSERIAL PRIMARY KEY,
+    run_id BIGINT NOT NULL REFERENCES crawl_runs (id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    error TEXT NOT NULL
+);
+
+CREATE INDEX crawl_errors_run_id_idx ON crawl_errors (run_id);
+CREATE INDEX crawl_errors_domain_idx ON crawl_errors (domain);
@@ -0,0 +1,25 @@
+-- Totals of every run over all sources, so they can be charted without
+-- unpacking the reports.
+ALTER TABLE crawl_runs ADD COLUMN posts INT NOT NULL DEFAULT 0;
+ALTER TABLE crawl_runs ADD COLUMN new_articles INT NOT NULL DEFAULT 0;
+ALTER TABLE crawl_runs ADD COLUMN updated INT NOT NULL DEFAULT 0;
+ALTER TABLE crawl_runs ADD COLUMN duplicates INT NOT NULL DEFAULT 0;
+ALTER TABLE crawl_runs ADD COLUMN failed INT NOT NULL DEFAULT 0;
+
+UPDATE crawl_runs r SET
+    posts = t.posts,
+    new_articles = t.new,
+    updated = t.updated,
+    duplicates = t.duplicates,
+    failed = t.failed
+FROM (
+    SELECT id,
+        COALESCE(SUM((s.value->>'posts')::int), 0) AS posts,
+        COALESCE(SUM((s.value->>'new')::int), 0) AS new,
+        COALESCE(SUM((s.value->>'updated')::int), 0) AS updated,
+        COALESCE(SUM((s.value->>'duplicates')::int), 0) AS duplicates,
+        COALESCE(SUM((s.value->>'failed')::int), 0) AS failed
+    FROM crawl_runs LEFT JOIN LATERAL jsonb_each(report->'sources') s ON true
+    GROUP BY id
+) t
+WHERE r.id = t.id;
@@ -0,0 +1,5 @@
+-- Language articles are written in, NULL until detected. Articles stored
+-- before this are filled in by `encrawl detect-languages`.
+ALTER TABLE articles ADD COLUMN lang TEXT;
+
+CREATE INDEX articles_lang_idx ON articles (lang);
@@ -0,0 +1,9 @@
+-- Validators of every page stored from, so crawls can ask whether it changed
+-- instead of downloading it again. Keyed by the URL as fetched, before
+-- canonicalisation.
+CREATE TABLE page_cache (
+    url TEXT PRIMARY KEY,
+    etag TEXT,
+    last_modified TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Model, seed and prompt hashes a digest was generated with, NULL for
+-- digests that weren't generated or were stored before this.
+ALTER TABLE digests ADD COLUMN provenance JSONB;
@@ -0,0 +1,18 @@
+-- Top-level comments of Reddit posts, which often hold the links and facts
+-- the post itself lacks. `post_url` is the URL the post links to, which is
+-- the article's URL once it is crawled.
+CREATE TABLE reddit_comments (
+    name TEXT PRIMARY KEY,
+    post_name TEXT NOT NULL,
+    post_url TEXT NOT NULL,
+    subreddit TEXT NOT NULL,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    score BIGINT NOT NULL,
+    created_at TIMESTAMPTZ,
+    urls TEXT[] NOT NULL DEFAULT '{}',
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX reddit_comments_post_name_idx ON reddit_comments (post_name);
+CREATE INDEX reddit_comments_post_url_idx ON reddit_comments (post_url);
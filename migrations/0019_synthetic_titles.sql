@@ -0,0 +1,3 @@
+-- Whether the title was generated from the content because the page had
+-- none that the selectors matched.
+ALTER TABLE articles ADD COLUMN synthetic_title BOOLEAN NOT NULL DEFAULT false;
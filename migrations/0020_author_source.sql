@@ -0,0 +1,3 @@
+-- How the author of an article was found: `selector`, `meta`, `json-ld` or
+-- `byline`. NULL when none was, and for articles stored before this.
+ALTER TABLE articles ADD COLUMN author_source TEXT;
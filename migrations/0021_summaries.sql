@@ -0,0 +1,17 @@
+-- Generated summaries, kept to be read again and reused while the articles
+-- they were generated from stay the same.
+CREATE TABLE summaries (
+    id BIGSERIAL PRIMARY KEY,
+    topic TEXT NOT NULL,
+    body TEXT NOT NULL,
+    backend TEXT NOT NULL,
+    model TEXT NOT NULL,
+    -- Articles that fed the prompt, in prompt order.
+    article_ids BIGINT[] NOT NULL,
+    -- SHA-256 of the ids and contents of those articles.
+    articles_sha256 TEXT NOT NULL,
+    provenance JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX summaries_reuse_idx ON summaries (topic, articles_sha256, created_at DESC);
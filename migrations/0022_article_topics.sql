@@ -0,0 +1,10 @@
+-- The configured digest topic every article is closest to, assigned when it
+-- is stored. Articles close to none of them have no row.
+CREATE TABLE article_topics (
+    article_id BIGINT PRIMARY KEY REFERENCES articles (id) ON DELETE CASCADE,
+    topic TEXT NOT NULL,
+    -- Cosine similarity of the title to the topic's query.
+    similarity REAL NOT NULL,
+    routed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX article_topics_topic_idx ON article_topics (topic, similarity DESC);
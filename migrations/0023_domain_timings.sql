@@ -0,0 +1,17 @@
+-- Scrape latency of every domain in every crawl run, so domains that keep
+-- slowing crawls down can be found without unpacking the reports.
+CREATE TABLE crawl_domain_timings (
+    run_id BIGINT NOT NULL REFERENCES crawl_runs (id) ON DELETE CASCADE,
+    domain TEXT NOT NULL,
+    pages INT NOT NULL,
+    fetch_p50 DOUBLE PRECISION NOT NULL,
+    fetch_p90 DOUBLE PRECISION NOT NULL,
+    fetch_p99 DOUBLE PRECISION NOT NULL,
+    extract_p50 DOUBLE PRECISION NOT NULL,
+    extract_p90 DOUBLE PRECISION NOT NULL,
+    extract_p99 DOUBLE PRECISION NOT NULL,
+    total_secs DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (run_id, domain)
+);
+
+CREATE INDEX crawl_domain_timings_domain_idx ON crawl_domain_timings (domain);
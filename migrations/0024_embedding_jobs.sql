@@ -0,0 +1,11 @@
+-- Articles stored without embeddings, for the embedding worker to embed.
+ALTER TABLE articles ADD COLUMN pending_embedding BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE embedding_jobs (
+    article_id BIGINT PRIMARY KEY REFERENCES articles (id) ON DELETE CASCADE,
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts INT NOT NULL DEFAULT 0,
+    error TEXT
+);
+
+CREATE INDEX embedding_jobs_queued_at_idx ON embedding_jobs (queued_at);
@@ -0,0 +1,4 @@
+-- Embedding of the start of every article's content, weighed against the
+-- title embedding by searches. Filled by `backfill-chunks` for articles stored
+-- before it existed.
+ALTER TABLE articles ADD COLUMN content_embedding vector;
@@ -0,0 +1,16 @@
+-- Content-addressed keys of the work done on every page and article, so a
+-- crawl run again after failing part way only redoes what is missing.
+--
+-- Hash of the HTML a page's article was stored from, to skip extracting and
+-- storing pages that come back unchanged without validators.
+ALTER TABLE page_cache ADD COLUMN page_key TEXT;
+
+-- Hash of the embedding model, content hash and title, set once an article
+-- and its chunks are embedded with that model. Kept in sync with
+-- `store::embedding_key`.
+ALTER TABLE articles ADD COLUMN embedding_key TEXT;
+
+UPDATE articles a
+SET embedding_key = encode(sha256(convert_to(embedding_model || ':' || content_hash || ':' || title, 'UTF8')), 'hex')
+WHERE embedding IS NOT NULL AND NOT pending_embedding
+    AND EXISTS (SELECT 1 FROM article_chunks c WHERE c.article_id = a.id);
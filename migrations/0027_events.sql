@@ -0,0 +1,14 @@
+-- Append-only log of what crawls decided about every URL: skipped by a
+-- filter, extracted, found to be a duplicate, stored or failed. Rows are
+-- never updated, and outlive the articles they mention.
+CREATE TABLE events (
+    id BIGSERIAL PRIMARY KEY,
+    at TIMESTAMPTZ NOT NULL,
+    -- As fetched, before canonicalisation.
+    url TEXT NOT NULL,
+    action TEXT NOT NULL,
+    detail TEXT,
+    article_id BIGINT
+);
+
+CREATE INDEX events_url_idx ON events (url);
@@ -0,0 +1,4 @@
+-- Results of the enrichers run on an article before it was stored, by
+-- enricher name, e.g. `{"tickers": ["AAPL"], "sentiment": 0.5}`. Empty for
+-- articles stored before this and ones no such enricher ran on.
+ALTER TABLE articles ADD COLUMN enrichments JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,9 @@
+-- The article a Reddit comment's post links to, set when the comment is
+-- stored if the article already is and otherwise once it is. Comments of
+-- posts whose link was never stored keep it unset.
+ALTER TABLE reddit_comments
+    ADD COLUMN article_id BIGINT REFERENCES articles (id) ON DELETE SET NULL;
+
+UPDATE reddit_comments c SET article_id = a.id FROM articles a WHERE a.url = c.post_url;
+
+CREATE INDEX reddit_comments_article_id_idx ON reddit_comments (article_id);
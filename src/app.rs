@@ -0,0 +1,655 @@
+//! Shared application state.
+
+use clap::ValueEnum;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OnceCell, OwnedMutexGuard};
+
+use crate::chaos::{Chaos, ChaosConfig};
+use crate::crawl::Subreddit;
+use crate::device::{ComputeDType, ComputeDevice};
+use crate::embed_text::EmbedText;
+use crate::embedding::{EmbedderPool, EmbeddingBackend, EmbeddingModel};
+use crate::enrich::EnricherKind;
+use crate::guardrails::Guardrails;
+use crate::http::{HttpClient, USER_AGENT};
+use crate::mamba::InitConfig;
+use crate::metrics::Metrics;
+use crate::openai::OpenAiConfig;
+use crate::page_cache::PageCache;
+use crate::policy::{Policy, PolicyConfig};
+use crate::reddit::Listing;
+use crate::render::Renderer;
+use crate::rerank::{self, CrossEncoder};
+use crate::robots::RobotsCache;
+use crate::scrape::ScraperConfig;
+use crate::settings::{Paths, Settings};
+use crate::sink::SinkConfig;
+use crate::site::SiteLimits;
+use crate::source::{FeedSource, SourceConfig};
+use crate::sqlite::{is_sqlite, SqliteStore};
+use crate::store::{ArticleStore, PgStore};
+use crate::summarise::{self, Summarizer, SummarizerBackend, SummaryBudget};
+use crate::title::TitleRules;
+
+/// Settings shared by every part of the application.
+pub struct Config {
+    pub scraper_path: PathBuf,
+    pub subs_path: PathBuf,
+    pub feeds_path: PathBuf,
+    pub sources_path: PathBuf,
+    pub sinks_path: PathBuf,
+    pub policy_path: PathBuf,
+    pub scrapers: Vec<ScraperConfig>,
+    pub subs: Vec<Subreddit>,
+    /// RSS and Atom feed URLs.
+    pub feeds: Vec<String>,
+    /// Additional sources of any kind, read from `sources.ron` when it exists.
+    pub sources: Vec<SourceConfig>,
+    /// Where digests are delivered, read from `sinks.ron` when it exists.
+    pub sinks: Vec<SinkConfig>,
+    /// What may be crawled, read from `policy.ron` when it exists.
+    pub policy: PolicyConfig,
+    /// Model articles and queries are embedded with, loaded once at startup.
+    pub embedding_model: EmbeddingModel,
+    pub embedding_backend: EmbeddingBackend,
+    /// Device and weight type of the embedding model on the candle backend.
+    pub embedding_device: ComputeDevice,
+    pub embedding_dtype: ComputeDType,
+    /// Copies of the embedding model loaded, which embed in parallel.
+    pub embedding_instances: usize,
+    /// Cross-encoder search results are reranked with, loaded on first use.
+    /// Runs on the embedding model's device.
+    pub rerank_model: String,
+    pub concurrency: usize,
+    /// Number of articles embedded and inserted together while crawling.
+    pub embed_batch_size: usize,
+    /// Text of an article its embedding is made from, see
+    /// [`crate::embed_text`].
+    pub embed_text: EmbedText,
+    /// Compositions of the articles of some sources, by source name.
+    pub embed_text_sources: BTreeMap<String, EmbedText>,
+    /// Enrichers run on crawled articles, in order, see [`crate::enrich`].
+    pub enrichers: Vec<EnricherKind>,
+    /// Enrichers of the articles of some sources, by source name.
+    pub enricher_sources: BTreeMap<String, Vec<EnricherKind>>,
+    /// How the titles of sites read with the generic extractor are cleaned,
+    /// by domain, see [`crate::title`].
+    pub title_rules: BTreeMap<String, TitleRules>,
+    /// Store crawled articles without embeddings and embed them from a
+    /// queue, see [`crate::embed_queue`].
+    pub defer_embedding: bool,
+    /// Links to crawl and links seen kept in memory during a crawl, more
+    /// spill over to a temporary file, see [`crate::frontier`].
+    pub frontier_memory_limit: usize,
+    /// Requests per second allowed to every domain, `0` disables the limit.
+    pub rate_limit: f64,
+    /// How often failed HTTP requests are retried.
+    pub max_retries: u32,
+    /// Sent with every HTTP request and matched against robots.txt.
+    pub user_agent: String,
+    /// Scrape pages even when the site's robots.txt disallows it.
+    pub ignore_robots: bool,
+    /// How long a stored page is taken to be unchanged before it is fetched
+    /// again, with a conditional request.
+    pub page_max_age: Duration,
+    /// WebDriver server the pages of scrapers with `render: true` are loaded
+    /// through, e.g. `http://localhost:4444`.
+    pub webdriver_url: Option<String>,
+    /// Which posts are fetched from every subreddit.
+    pub listing: Listing,
+    /// ISO 639-1 codes of the languages crawled, any when empty.
+    pub languages: Vec<String>,
+    /// How far the sites of the scrapers are crawled for links, not at all when unset.
+    pub sites: Option<SiteLimits>,
+    /// Failures injected into crawls to test their error handling.
+    pub chaos: ChaosConfig,
+    /// Bearer token required by the admin endpoints, which are disabled when unset.
+    pub admin_token: Option<String>,
+    /// Key share links of digests are signed with, `/share/digests` is
+    /// disabled when unset. See [`crate::share`].
+    pub share_secret: Option<String>,
+    pub guardrails: Guardrails,
+    /// Where summaries are generated.
+    pub summarizer: SummarizerBackend,
+    /// How long prompts and summaries may get.
+    pub summary_budget: SummaryBudget,
+    /// How long a stored summary is reused while its articles are unchanged.
+    pub summary_max_age: Duration,
+    /// Queries of the digest topics stored articles are routed to.
+    pub topics: Vec<String>,
+    /// Least cosine similarity of an article's title to a topic for it to be
+    /// routed there.
+    pub route_threshold: f32,
+    /// Weight of the title embedding against the content embedding when
+    /// searches rank articles, from 0 to 1.
+    pub title_weight: f32,
+    /// Device, model and sampling of the text generator.
+    pub generation: InitConfig,
+    /// Server used by [`SummarizerBackend::OpenAi`].
+    pub openai: OpenAiConfig,
+    /// Settings file re-read by [`Self::reload`], if any.
+    pub settings_path: Option<PathBuf>,
+    /// Ids of the flags given on the command line, whose settings a reload
+    /// leaves alone, e.g. `embed_text`.
+    pub flags: BTreeSet<String>,
+}
+
+impl Config {
+    /// Reads the scraper rules and the source lists, using defaults for
+    /// everything else. Missing files count as empty, so encrawl also runs
+    /// configured from the environment alone.
+    pub fn load(
+        scraper_path: PathBuf,
+        subs_path: PathBuf,
+        feeds_path: PathBuf,
+        sources_path: PathBuf,
+        sinks_path: PathBuf,
+        policy_path: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let sources = if sources_path.exists() {
+            SourceConfig::from_file(sources_path.clone())?
+        } else {
+            vec![]
+        };
+        let sinks = if sinks_path.exists() {
+            SinkConfig::from_file(sinks_path.clone())?
+        } else {
+            vec![]
+        };
+        let policy = if policy_path.exists() {
+            PolicyConfig::from_file(policy_path.clone())?
+        } else {
+            PolicyConfig::default()
+        };
+        let scrapers = if scraper_path.exists() {
+            ScraperConfig::from_file(scraper_path.clone())?
+        } else {
+            log::warn!(
+                "{} doesn't exist, every site is read with the generic extractor",
+                scraper_path.display()
+            );
+            vec![]
+        };
+        let subs = if subs_path.exists() {
+            Subreddit::from_file(subs_path.clone())?
+        } else {
+            vec![]
+        };
+        let feeds = if feeds_path.exists() {
+            FeedSource::read_list(feeds_path.clone())?
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            scrapers,
+            subs,
+            feeds,
+            sources,
+            sinks,
+            policy,
+            scraper_path,
+            subs_path,
+            feeds_path,
+            sources_path,
+            sinks_path,
+            policy_path,
+            embedding_model: EmbeddingModel::default(),
+            embedding_backend: EmbeddingBackend::default(),
+            embedding_device: ComputeDevice::Cpu,
+            embedding_dtype: ComputeDType::Auto,
+            embedding_instances: 1,
+            rerank_model: rerank::DEFAULT_MODEL.to_string(),
+            concurrency: 8,
+            embed_batch_size: 32,
+            embed_text: EmbedText::default(),
+            embed_text_sources: BTreeMap::new(),
+            enrichers: EnricherKind::DEFAULT.to_vec(),
+            enricher_sources: BTreeMap::new(),
+            title_rules: BTreeMap::new(),
+            defer_embedding: false,
+            frontier_memory_limit: 100_000,
+            rate_limit: 1.0,
+            max_retries: 3,
+            user_agent: USER_AGENT.to_string(),
+            ignore_robots: false,
+            page_max_age: Duration::from_secs(60 * 60),
+            webdriver_url: None,
+            listing: Listing::default(),
+            languages: vec![],
+            sites: None,
+            chaos: ChaosConfig::default(),
+            admin_token: None,
+            share_secret: None,
+            guardrails: Guardrails::default(),
+            summarizer: SummarizerBackend::default(),
+            summary_budget: SummaryBudget::default(),
+            summary_max_age: Duration::from_secs(6 * 60 * 60),
+            topics: vec![],
+            route_threshold: 0.3,
+            title_weight: 0.5,
+            generation: InitConfig::default(),
+            openai: OpenAiConfig::default(),
+            settings_path: None,
+            flags: BTreeSet::new(),
+        })
+    }
+
+    /// Re-reads the settings file, then the scraper rules and sources from
+    /// disk. Only the settings that can change while running are taken, see
+    /// [`Self::apply_settings`], the others keep their values.
+    pub fn reload(&self) -> anyhow::Result<Self> {
+        let settings = self
+            .settings_path
+            .as_deref()
+            .map(Settings::load)
+            .transpose()?;
+        let no_paths = Paths::default();
+        let paths = settings
+            .as_ref()
+            .map_or(&no_paths, |settings| &settings.paths);
+        // Paths given as flags win over the settings, like at startup.
+        let path = |id: &str, configured: &Option<PathBuf>, current: &PathBuf| match configured {
+            Some(configured) if !self.flags.contains(id) => configured.clone(),
+            _ => current.clone(),
+        };
+        let loaded = Self::load(
+            path("scraper", &paths.scrapers, &self.scraper_path),
+            path("subs", &paths.subs, &self.subs_path),
+            path("feeds", &paths.feeds, &self.feeds_path),
+            path("sources", &paths.sources, &self.sources_path),
+            path("sinks", &paths.sinks, &self.sinks_path),
+            path("policy", &paths.policy, &self.policy_path),
+        )?;
+        let mut config = Self {
+            embedding_model: self.embedding_model.clone(),
+            embedding_backend: self.embedding_backend,
+            embedding_device: self.embedding_device,
+            embedding_dtype: self.embedding_dtype,
+            embedding_instances: self.embedding_instances,
+            rerank_model: self.rerank_model.clone(),
+            concurrency: self.concurrency,
+            embed_batch_size: self.embed_batch_size,
+            embed_text: self.embed_text,
+            embed_text_sources: self.embed_text_sources.clone(),
+            enrichers: self.enrichers.clone(),
+            enricher_sources: self.enricher_sources.clone(),
+            title_rules: self.title_rules.clone(),
+            defer_embedding: self.defer_embedding,
+            frontier_memory_limit: self.frontier_memory_limit,
+            rate_limit: self.rate_limit,
+            max_retries: self.max_retries,
+            user_agent: self.user_agent.clone(),
+            ignore_robots: self.ignore_robots,
+            page_max_age: self.page_max_age,
+            webdriver_url: self.webdriver_url.clone(),
+            listing: self.listing.clone(),
+            languages: self.languages.clone(),
+            sites: self.sites.clone(),
+            chaos: self.chaos.clone(),
+            admin_token: self.admin_token.clone(),
+            share_secret: self.share_secret.clone(),
+            guardrails: self.guardrails.clone(),
+            summarizer: self.summarizer,
+            summary_budget: self.summary_budget.clone(),
+            summary_max_age: self.summary_max_age,
+            topics: self.topics.clone(),
+            route_threshold: self.route_threshold,
+            title_weight: self.title_weight,
+            generation: self.generation.clone(),
+            openai: self.openai.clone(),
+            settings_path: self.settings_path.clone(),
+            flags: self.flags.clone(),
+            ..loaded
+        };
+        if let Some(settings) = &settings {
+            config.apply_settings(settings)?;
+        }
+        Ok(config)
+    }
+
+    /// Takes the settings that apply while running from `settings`, except
+    /// those of [`Self::flags`]: the embedded text, enrichers, title rules,
+    /// digest topics, banned phrases and the admin token and share secret. Database URLs,
+    /// the user agent, models, devices and the OpenAI server are only read
+    /// at startup, changing them needs a restart.
+    pub fn apply_settings(&mut self, settings: &Settings) -> anyhow::Result<()> {
+        if let Some(text) = settings
+            .models
+            .embed_text
+            .as_ref()
+            .filter(|_| !self.flags.contains("embed_text"))
+        {
+            self.embed_text =
+                EmbedText::from_str(text, true).map_err(|e| anyhow::anyhow!("embed_text: {e}"))?;
+        }
+        self.embed_text_sources = settings.models.embed_text_sources.clone();
+        if !settings.enrich.enrichers.is_empty() && !self.flags.contains("enrichers") {
+            self.enrichers = settings.enrich.enrichers.clone();
+        }
+        self.enricher_sources = settings.enrich.sources.clone();
+        self.title_rules = settings.title_rules.clone();
+        self.topics = settings.digest.topics.clone();
+        if let Some(path) = settings
+            .paths
+            .banned_phrases
+            .as_ref()
+            .filter(|_| !self.flags.contains("banned_phrases"))
+        {
+            self.guardrails.banned_phrases = Guardrails::read_banned_phrases(path)?;
+        }
+        let secrets = [
+            (&mut self.admin_token, &settings.admin_token, "admin_token"),
+            (
+                &mut self.share_secret,
+                &settings.share_secret,
+                "share_secret",
+            ),
+        ];
+        for (value, configured, id) in secrets {
+            if configured.is_some() && !self.flags.contains(id) {
+                value.clone_from(configured);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How long searches wait for a connection to the read replica before
+/// falling back to the primary.
+const REPLICA_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the primary is used once the read replica failed, before it is
+/// tried again.
+const REPLICA_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Read-only database searches and stats are sent to.
+struct Replica {
+    db: Pool<Postgres>,
+    /// Until when the replica is skipped after failing to connect.
+    down_until: std::sync::Mutex<Option<Instant>>,
+}
+
+/// The summariser, locked by whichever blocking task is generating.
+type SharedSummarizer = Arc<Mutex<Box<dyn Summarizer>>>;
+
+/// Cheaply clonable handle to the database, the models and the config.
+///
+/// The summariser is only loaded the first time it is needed, so paths
+/// that never summarise don't pay for downloading the Mamba weights.
+#[derive(Clone)]
+pub struct Encrawl {
+    /// Unset when the database URL is a SQLite one.
+    db: Option<Pool<Postgres>>,
+    /// Where articles are stored and searched, which is `db` unless the
+    /// database URL is a SQLite one.
+    articles: Arc<dyn ArticleStore>,
+    replica: Option<Arc<Replica>>,
+    embedder: Arc<EmbedderPool>,
+    /// Name of the embedding model, stored along with every embedding.
+    embedding_model: Arc<str>,
+    embedding_dim: usize,
+    generator: Arc<OnceCell<SharedSummarizer>>,
+    reranker: Arc<OnceCell<Arc<CrossEncoder>>>,
+    http: HttpClient,
+    policy: Arc<Policy>,
+    page_cache: Arc<PageCache>,
+    renderer: Option<Arc<Renderer>>,
+    metrics: Arc<Metrics>,
+    /// Set when [`Config::chaos`] injects anything.
+    chaos: Option<Arc<Chaos>>,
+    config: Arc<RwLock<Arc<Config>>>,
+}
+
+impl Encrawl {
+    /// Connects to the primary database at `db_url` and migrates it. Reads
+    /// for searches and stats go to `replica_url` when given, which is only
+    /// connected to once it is first used.
+    ///
+    /// A `sqlite:` URL stores and searches articles in that file instead,
+    /// see [`crate::sqlite`]. There is no Postgres then: the page cache and
+    /// the rollups are skipped, daily page limits are counted in memory and
+    /// everything else that needs Postgres fails.
+    pub async fn new(
+        db_url: &str,
+        replica_url: Option<&str>,
+        config: Config,
+    ) -> anyhow::Result<Self> {
+        let (db, articles): (_, Arc<dyn ArticleStore>) = if is_sqlite(db_url) {
+            (None, Arc::new(SqliteStore::open(db_url).await?))
+        } else {
+            let db = PgPoolOptions::new()
+                .max_connections(5)
+                .connect(db_url)
+                .await?;
+            sqlx::migrate!().run(&db).await?;
+            (Some(db), Arc::new(PgStore))
+        };
+        let replica = replica_url
+            .map(|url| {
+                let db = PgPoolOptions::new()
+                    .max_connections(5)
+                    .acquire_timeout(REPLICA_ACQUIRE_TIMEOUT)
+                    .connect_lazy(url)?;
+                anyhow::Ok(Arc::new(Replica {
+                    db,
+                    down_until: std::sync::Mutex::new(None),
+                }))
+            })
+            .transpose()?;
+        let model = config.embedding_model.clone();
+        let (backend, device, dtype) = (
+            config.embedding_backend,
+            config.embedding_device,
+            config.embedding_dtype,
+        );
+        let instances = config.embedding_instances;
+        let embedder = tokio::task::spawn_blocking(move || {
+            EmbedderPool::load(&model, backend, device, dtype, instances)
+        })
+        .await??;
+        let embedding_dim = embedder.dim();
+        let policy = Policy::new(
+            db.clone(),
+            RobotsCache::new(&config.user_agent, config.ignore_robots),
+            config.policy.clone(),
+        );
+        let page_cache = PageCache::new(db.clone(), config.page_max_age);
+        let renderer = config
+            .webdriver_url
+            .as_deref()
+            .map(|url| anyhow::Ok(Arc::new(Renderer::new(url, &config.user_agent)?)))
+            .transpose()?;
+        let chaos = Some(&config.chaos)
+            .filter(|chaos| chaos.is_active())
+            .map(|chaos| Arc::new(Chaos::new(chaos)));
+        let http = HttpClient::new(config.rate_limit, config.max_retries, &config.user_agent)?
+            .with_chaos(chaos.clone());
+        Ok(Self {
+            db,
+            articles,
+            replica,
+            embedder: Arc::new(embedder),
+            embedding_model: config.embedding_model.to_string().into(),
+            embedding_dim,
+            generator: Arc::new(OnceCell::new()),
+            reranker: Arc::new(OnceCell::new()),
+            http,
+            policy: Arc::new(policy),
+            page_cache: Arc::new(page_cache),
+            renderer,
+            metrics: Arc::new(Metrics::default()),
+            chaos,
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    /// Primary database, which every write has to go to, unless the
+    /// articles are kept in SQLite.
+    pub fn db(&self) -> Option<&Pool<Postgres>> {
+        self.db.as_ref()
+    }
+
+    /// [`Self::db`], or an error for work that needs Postgres when the
+    /// articles are kept in SQLite.
+    pub fn postgres(&self) -> Result<&Pool<Postgres>, sqlx::Error> {
+        self.db.as_ref().ok_or_else(|| {
+            sqlx::Error::Configuration(
+                "this needs a Postgres database_url, the articles are kept in SQLite".into(),
+            )
+        })
+    }
+
+    /// Where articles are stored and searched, see [`crate::store`].
+    pub fn articles(&self) -> &dyn ArticleStore {
+        &*self.articles
+    }
+
+    /// Whether [`Self::db`] can be used, which it can't when the articles
+    /// are kept in SQLite.
+    pub fn has_postgres(&self) -> bool {
+        self.db.is_some()
+    }
+
+    /// Database for searches and stats: the read replica when one is
+    /// configured and reachable, the primary otherwise. A replica that can't
+    /// be connected to is skipped for [`REPLICA_RETRY_AFTER`]. Fails like
+    /// [`Self::postgres`] without Postgres.
+    pub async fn read_db(&self) -> Result<&Pool<Postgres>, sqlx::Error> {
+        let primary = self.postgres()?;
+        let Some(replica) = &self.replica else {
+            return Ok(primary);
+        };
+        let down_until = *replica.down_until.lock().unwrap();
+        if down_until.is_some_and(|until| Instant::now() < until) {
+            return Ok(primary);
+        }
+        match replica.db.acquire().await {
+            Ok(_) => Ok(&replica.db),
+            Err(e) => {
+                log::warn!("Read replica unavailable, reading from the primary: {}", e);
+                *replica.down_until.lock().unwrap() = Some(Instant::now() + REPLICA_RETRY_AFTER);
+                Ok(primary)
+            }
+        }
+    }
+
+    /// Rate limited client all outbound HTTP requests should go through.
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    /// Failures injected into fetches and stores, when [`Config::chaos`] is
+    /// set. Its draws carry on from one crawl to the next.
+    pub fn chaos(&self) -> Option<&Chaos> {
+        self.chaos.as_deref()
+    }
+
+    /// Crawl policy and robots.txt rules the scraper checks before fetching a page.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// When the pages articles were stored from were fetched.
+    pub fn page_cache(&self) -> &PageCache {
+        &self.page_cache
+    }
+
+    /// Headless browser pages of scrapers with `render: true` are loaded in,
+    /// when a WebDriver server is configured.
+    pub fn renderer(&self) -> Option<&Renderer> {
+        self.renderer.as_deref()
+    }
+
+    /// Counters of what crawls did, for `/metrics`.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns a snapshot of the current config, unaffected by later reloads.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Reloads the config from disk and swaps it in atomically.
+    ///
+    /// Work already running keeps the snapshot it started with, and nothing is
+    /// swapped if any of the files fails to parse.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let config = self.config();
+        let config = tokio::task::spawn_blocking(move || config.reload()).await??;
+        self.policy.set_config(config.policy.clone());
+        *self.config.write().unwrap() = Arc::new(config);
+        log::info!("Reloaded config");
+        Ok(())
+    }
+
+    /// Name of the model [`Self::embed`] uses.
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    /// Length of the vectors [`Self::embed`] returns.
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    /// Embeds `texts` with the shared pool of sentence embedding models.
+    pub async fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let start = Instant::now();
+        let embeddings = self.embedder.encode(texts).await?;
+        self.metrics.embedded(texts.len(), start.elapsed());
+        Ok(embeddings)
+    }
+
+    /// Locks the summariser, loading it on first use. It blocks while
+    /// generating, so only use it from a blocking task, see
+    /// [`Self::with_generator`].
+    pub async fn generator(&self) -> anyhow::Result<OwnedMutexGuard<Box<dyn Summarizer>>> {
+        let generator = self
+            .generator
+            .get_or_try_init(|| async {
+                let config = self.config();
+                let generator =
+                    tokio::task::spawn_blocking(move || summarise::load(&config)).await??;
+                anyhow::Ok(Arc::new(Mutex::new(generator)))
+            })
+            .await?;
+        Ok(generator.clone().lock_owned().await)
+    }
+
+    /// Runs `f` with the locked summariser on a blocking thread, so
+    /// generating never stalls the runtime.
+    pub async fn with_generator<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Summarizer) -> anyhow::Result<T> + Send + 'static,
+    {
+        let mut generator = self.generator().await?;
+        tokio::task::spawn_blocking(move || f(generator.as_mut())).await?
+    }
+
+    /// The cross-encoder of [`Config::rerank_model`], loading it on first use.
+    /// It blocks while scoring, so use it from a blocking task.
+    pub async fn reranker(&self) -> anyhow::Result<Arc<CrossEncoder>> {
+        self.reranker
+            .get_or_try_init(|| async {
+                let config = self.config();
+                tokio::task::spawn_blocking(move || {
+                    CrossEncoder::load(
+                        &config.rerank_model,
+                        config.embedding_device,
+                        config.embedding_dtype,
+                    )
+                    .map(Arc::new)
+                })
+                .await?
+            })
+            .await
+            .cloned()
+    }
+}
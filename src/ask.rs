@@ -0,0 +1,144 @@
+//! Question answering over retrieved article chunks with citations.
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::Encrawl;
+use crate::mamba::TokenUsage;
+use crate::store::{cosine_similarity, search};
+
+/// Paragraphs are merged into chunks of up to this many bytes.
+const CHUNK_SIZE: usize = 1200;
+
+/// A chunk of an article that was put into the context of an answer.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Citation {
+    /// Number the chunk is referred to by in the prompt and answer, e.g. `[1]`.
+    pub number: usize,
+    pub article_id: Option<i64>,
+    pub title: String,
+    pub url: String,
+    /// Position of the chunk within its article.
+    pub chunk_seq: usize,
+    /// Byte range of the chunk within the article content.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// Whether the answer refers to this chunk.
+    pub cited: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Answer {
+    pub answer: String,
+    pub citations: Vec<Citation>,
+    #[serde(skip)]
+    pub usage: TokenUsage,
+}
+
+/// Splits `content` on line breaks into byte ranges of at most roughly `max` bytes.
+pub fn chunk_spans(content: &str, max: usize) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = None;
+    let mut end = 0;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        if line.trim().is_empty() {
+            continue;
+        }
+        match start {
+            Some(s) if offset - s > max => {
+                spans.push((s, end));
+                start = Some(line_start);
+            }
+            None => start = Some(line_start),
+            Some(_) => {}
+        }
+        end = line_start + line.trim_end().len();
+    }
+    if let Some(start) = start {
+        spans.push((start, end));
+    }
+    spans
+}
+
+/// Answers `question` from the `chunks` chunks most similar to it, taken from
+/// the `limit` closest articles.
+pub async fn ask(
+    app: &Encrawl,
+    question: &str,
+    limit: i32,
+    chunks: usize,
+) -> anyhow::Result<Answer> {
+    let articles = search(app, question.to_string(), limit).await?;
+    let mut candidates = vec![];
+    for article in &articles {
+        for (seq, (start, end)) in chunk_spans(&article.content, CHUNK_SIZE)
+            .into_iter()
+            .enumerate()
+        {
+            candidates.push((article, seq, start, end));
+        }
+    }
+    let mut texts = vec![question.to_string()];
+    texts.extend(
+        candidates
+            .iter()
+            .map(|(article, _, start, end)| article.content[*start..*end].to_string()),
+    );
+    let embeddings = app.embed(&texts).await?;
+    let mut ranked = candidates
+        .into_iter()
+        .zip(
+            embeddings[1..]
+                .iter()
+                .map(|e| cosine_similarity(&embeddings[0], e)),
+        )
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(chunks);
+
+    let mut citations = ranked
+        .into_iter()
+        .enumerate()
+        .map(|(i, ((article, seq, start, end), _))| Citation {
+            number: i + 1,
+            article_id: article.id,
+            title: article.title.clone(),
+            url: article.url.clone(),
+            chunk_seq: seq,
+            start,
+            end,
+            text: article.content[start..end].to_string(),
+            cited: false,
+        })
+        .collect::<Vec<_>>();
+    let context = citations
+        .iter()
+        .map(|c| format!("[{}] {} ({})\n{}\n", c.number, c.title, c.url, c.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt = format!(
+        "You are an AI model answering questions about the news using only the numbered sources given to you.\n{context}\nUser: {question} Refer to the sources you used by their number, e.g. [1].\nResponse: "
+    );
+
+    let (answer, usage) = app
+        .with_generator(move |generator| {
+            let mut answer = String::new();
+            generator.run_stream(&prompt, 200, &mut |text| {
+                answer.push_str(text);
+                Ok(())
+            })?;
+            Ok((answer, generator.last_usage()))
+        })
+        .await?;
+    for citation in &mut citations {
+        citation.cited = answer.contains(&format!("[{}]", citation.number));
+    }
+    Ok(Answer {
+        answer: answer.trim().to_string(),
+        citations,
+        usage,
+    })
+}
@@ -0,0 +1,216 @@
+//! Author extraction, trying the configured selectors, `<meta>` tags, JSON-LD
+//! and finally a byline at the start of the article, and keeping the most
+//! plausible name found.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Selectors of the elements generic pages usually put the author in.
+pub const GENERIC_SELECTORS: &[&str] = &[
+    "[rel='author']",
+    "[itemprop='author']",
+    ".author",
+    ".byline",
+];
+
+/// `<meta>` tags naming the author.
+const META_SELECTORS: &[&str] = &["meta[name='author']", "meta[property='article:author']"];
+
+/// Placeholders sites put where an author would be.
+const PLACEHOLDERS: &[&str] = &[
+    "admin",
+    "administrator",
+    "editor",
+    "editorial",
+    "editors",
+    "guest",
+    "news desk",
+    "newsdesk",
+    "staff",
+    "staff writer",
+    "unknown",
+    "web desk",
+];
+
+/// "By Jane Doe" or "By Jane Doe and John Smith" opening the first paragraph.
+static BYLINE: LazyLock<Regex> = LazyLock::new(|| {
+    let name = r"\p{Lu}[\p{L}.'-]*(?:\s+\p{Lu}[\p{L}.'-]*){0,3}";
+    Regex::new(&format!(
+        r"^\s*(?i:by)\s+({name}(?:\s*(?:,|&|\band\b)\s*{name})*)"
+    ))
+    .unwrap()
+});
+
+/// How an article's author was found, stored as [`crate::Article::author_source`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthorSource {
+    /// The scraper's author selector, or the usual author markup on pages
+    /// without a scraper.
+    Selector,
+    /// An `author` `<meta>` tag.
+    Meta,
+    /// The `author` of the page's JSON-LD.
+    JsonLd,
+    /// A "By ..." line opening the article.
+    Byline,
+}
+
+impl AuthorSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Selector => "selector",
+            Self::Meta => "meta",
+            Self::JsonLd => "json-ld",
+            Self::Byline => "byline",
+        }
+    }
+
+    /// How much a name found this way is trusted, between 0 and 1.
+    pub fn confidence(self) -> f32 {
+        match self {
+            Self::Selector => 0.9,
+            Self::JsonLd => 0.85,
+            Self::Meta => 0.7,
+            Self::Byline => 0.5,
+        }
+    }
+}
+
+impl std::fmt::Display for AuthorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The author of `document`, trying `selectors`, the `<meta>` tags, JSON-LD
+/// and a byline opening `content` in that order. Every name found is weighed
+/// by the [`AuthorSource::confidence`] of where it came from and how much it
+/// looks like a name, and the best one is returned. `None` when all of them
+/// look like junk.
+pub fn find<S: AsRef<str>>(
+    document: &scraper::Html,
+    selectors: &[S],
+    content: &str,
+) -> Option<(String, AuthorSource)> {
+    let candidates = [
+        (selected(document, selectors), AuthorSource::Selector),
+        (meta(document), AuthorSource::Meta),
+        (json_ld(document), AuthorSource::JsonLd),
+        (byline(content), AuthorSource::Byline),
+    ];
+    candidates
+        .into_iter()
+        .filter_map(|(name, source)| {
+            let name = clean(&name?);
+            let score = plausibility(&name) * source.confidence();
+            (score > 0.0).then_some((name, source, score))
+        })
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(name, source, _)| (name, source))
+}
+
+fn selected<S: AsRef<str>>(document: &scraper::Html, selectors: &[S]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector.as_ref()).ok()?;
+        document
+            .select(&selector)
+            .map(|e| e.text().collect::<Vec<_>>().join(" "))
+            .find(|text| !text.trim().is_empty())
+    })
+}
+
+fn meta(document: &scraper::Html) -> Option<String> {
+    META_SELECTORS.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("content"))
+            .find(|content| !content.trim().is_empty())
+            .map(str::to_string)
+    })
+}
+
+fn json_ld(document: &scraper::Html) -> Option<String> {
+    let selector = scraper::Selector::parse("script[type='application/ld+json']").unwrap();
+    document.select(&selector).find_map(|script| {
+        let value = serde_json::from_str(&script.text().collect::<String>()).ok()?;
+        json_ld_author(&value)
+    })
+}
+
+/// The first `author` in a JSON-LD value, looking through arrays and
+/// `@graph`. Several authors are joined with commas.
+fn json_ld_author(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().find_map(json_ld_author),
+        serde_json::Value::Object(object) => object
+            .get("author")
+            .and_then(author_names)
+            .or_else(|| object.get("@graph").and_then(json_ld_author)),
+        _ => None,
+    }
+}
+
+fn author_names(author: &serde_json::Value) -> Option<String> {
+    match author {
+        serde_json::Value::String(name) => Some(name.clone()),
+        serde_json::Value::Object(object) => object.get("name").and_then(author_names),
+        serde_json::Value::Array(authors) => {
+            let names = authors.iter().filter_map(author_names).collect::<Vec<_>>();
+            (!names.is_empty()).then(|| names.join(", "))
+        }
+        _ => None,
+    }
+}
+
+fn byline(content: &str) -> Option<String> {
+    let first = content.lines().find(|line| !line.trim().is_empty())?;
+    Some(BYLINE.captures(first)?[1].to_string())
+}
+
+/// Collapses whitespace and strips a leading "By" and a trailing
+/// "| Site name" or "- Site name".
+fn clean(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name = name
+        .strip_prefix("By ")
+        .or_else(|| name.strip_prefix("by "))
+        .or_else(|| name.strip_prefix("BY "))
+        .unwrap_or(&name);
+    let name = name.split(" | ").next().unwrap_or_default();
+    let name = name.split(" - ").next().unwrap_or_default();
+    name.trim_matches(|c: char| c == ',' || c == ':' || c.is_whitespace())
+        .to_string()
+}
+
+/// How much `name` looks like the name of a person or several, between 0
+/// for junk such as URLs, handles and placeholders and 1.
+fn plausibility(name: &str) -> f32 {
+    let lower = name.to_lowercase();
+    if name.chars().count() < 3
+        || name.chars().count() > 100
+        || name.contains("://")
+        || name.contains('@')
+        || name.contains('/')
+        || !name.chars().any(char::is_alphabetic)
+        || PLACEHOLDERS.contains(&lower.as_str())
+    {
+        return 0.0;
+    }
+    let words = name.split_whitespace().count();
+    let mut score: f32 = 1.0;
+    if words == 1 {
+        // Surnames alone and site names are common, full names more so.
+        score *= 0.6;
+    }
+    if words > 8 {
+        score *= 0.3;
+    }
+    if name.chars().any(|c| c.is_ascii_digit()) {
+        score *= 0.3;
+    }
+    if !name.chars().next().is_some_and(char::is_uppercase) {
+        score *= 0.5;
+    }
+    score
+}
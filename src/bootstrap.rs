@@ -0,0 +1,90 @@
+//! `bootstrap`, which gets a fresh Postgres ready for encrawl so containers
+//! can start in any order: it waits for the server, creates the database
+//! when it is missing and runs the migrations.
+
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Pool, Postgres};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// SQLSTATE Postgres answers with when the database doesn't exist.
+const INVALID_CATALOG_NAME: &str = "3D000";
+
+/// SQLSTATE of creating a database that already exists.
+const DUPLICATE_DATABASE: &str = "42P04";
+
+/// Wait before the first retry, doubled after every failed attempt.
+const FIRST_RETRY: Duration = Duration::from_millis(500);
+
+/// Longest wait between two attempts.
+const MAX_RETRY: Duration = Duration::from_secs(10);
+
+/// Connects to `db_url`, retrying until `timeout` runs out while the server
+/// isn't up yet. The database is created when the server says it doesn't
+/// exist, through the `postgres` maintenance database, then pgvector is
+/// checked for and the migrations are run.
+pub async fn run(db_url: &str, timeout: Duration) -> anyhow::Result<()> {
+    let options = PgConnectOptions::from_str(db_url)?;
+    let deadline = Instant::now() + timeout;
+    let mut retry = FIRST_RETRY;
+    let db = loop {
+        match connect(options.clone()).await {
+            Ok(db) => break db,
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(INVALID_CATALOG_NAME) => {
+                create_database(&options).await?;
+                continue;
+            }
+            Err(e) if Instant::now() + retry < deadline => {
+                log::info!("Postgres isn't ready ({}), retrying in {:?}", e, retry);
+                tokio::time::sleep(retry).await;
+                retry = (retry * 2).min(MAX_RETRY);
+            }
+            Err(e) => anyhow::bail!("Postgres wasn't ready within {:?}: {}", timeout, e),
+        }
+    };
+    let (available,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector')",
+    )
+    .fetch_one(&db)
+    .await?;
+    if !available {
+        anyhow::bail!(
+            "the pgvector extension isn't installed on the server, use an image that has it \
+            such as pgvector/pgvector"
+        );
+    }
+    sqlx::migrate!().run(&db).await?;
+    log::info!(
+        "Database {} is ready",
+        options.get_database().unwrap_or("postgres")
+    );
+    Ok(())
+}
+
+async fn connect(options: PgConnectOptions) -> Result<Pool<Postgres>, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(MAX_RETRY)
+        .connect_with(options.disable_statement_logging())
+        .await
+}
+
+/// Creates the database of `options` from the `postgres` database on the
+/// same server.
+async fn create_database(options: &PgConnectOptions) -> anyhow::Result<()> {
+    let name = options
+        .get_database()
+        .ok_or_else(|| anyhow::anyhow!("the database URL names no database"))?;
+    log::info!("Creating database {}", name);
+    let maintenance = connect(options.clone().database("postgres")).await?;
+    let quoted = format!("\"{}\"", name.replace('"', "\"\""));
+    let created = sqlx::query(&format!("CREATE DATABASE {quoted}"))
+        .execute(&maintenance)
+        .await;
+    maintenance.close().await;
+    match created {
+        // Another instance bootstrapping at the same time won the race.
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(DUPLICATE_DATABASE) => Ok(()),
+        created => Ok(created.map(|_| ())?),
+    }
+}
@@ -0,0 +1,182 @@
+//! High-frequency polling of a few sources for breaking news.
+//!
+//! Unlike [`crate::crawl::crawl`], every new article is stored as soon as it
+//! is scraped and checked against a watchlist right away. It goes through
+//! the same [`crate::pipeline`] stages as in a crawl on the way.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::app::Encrawl;
+use crate::candidate::CandidateUrl;
+use crate::crawl::{stored_event, Subreddit};
+use crate::ingest::{Action, Event, EventLog};
+use crate::notify::{Batcher, Notification};
+use crate::pipeline;
+use crate::reddit::RedditClient;
+use crate::store::{Article, Stored};
+
+/// How long a link is remembered as seen. Listings only show recent posts,
+/// so links older than this don't come back.
+const SEEN_FOR: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct BreakingConfig {
+    /// Subreddits to poll, configured flairs are used when the name is in the subs list.
+    pub sources: Vec<String>,
+    pub interval: Duration,
+    /// Case-insensitive terms an article's title or content has to contain to be reported.
+    pub watch: Vec<String>,
+}
+
+impl BreakingConfig {
+    /// The first watch term contained in the article, if any.
+    fn matched_term(&self, article: &Article) -> Option<&str> {
+        let title = article.title.to_lowercase();
+        let content = article.content.to_lowercase();
+        self.watch
+            .iter()
+            .find(|term| {
+                let term = term.to_lowercase();
+                title.contains(&term) || content.contains(&term)
+            })
+            .map(String::as_str)
+    }
+}
+
+/// Polls the configured sources forever, queueing every watchlist hit on `batcher`.
+pub async fn run(
+    app: &Encrawl,
+    reddit_client: &RedditClient,
+    breaking: BreakingConfig,
+    batcher: &Batcher,
+) -> anyhow::Result<()> {
+    // When every link was first seen, pruned to the last `SEEN_FOR`.
+    let mut seen = HashMap::new();
+    let events = EventLog::new(app.has_postgres());
+    let mut interval = tokio::time::interval(breaking.interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        seen.retain(|_, at: &mut Instant| at.elapsed() < SEEN_FOR);
+        let config = app.config();
+        let subs = breaking.sources.iter().map(|name| {
+            config
+                .subs
+                .iter()
+                .find(|sub| &sub.name == name)
+                .cloned()
+                .unwrap_or_else(|| Subreddit {
+                    name: name.clone(),
+                    flairs: vec![],
+                })
+        });
+        for sub in subs {
+            let posts = match reddit_client.get_posts(sub.name.clone(), sub.flairs).await {
+                Ok(posts) => posts,
+                Err(e) => {
+                    log::error!("Failed to fetch posts from r/{}: {}", sub.name, e);
+                    continue;
+                }
+            };
+            let source = format!("r/{}", sub.name);
+            let candidates = posts.into_iter().flat_map(|post| {
+                let referrer = Some(post.permalink)
+                    .filter(|permalink| !permalink.is_empty())
+                    .map(|permalink| format!("https://www.reddit.com{permalink}"));
+                let (score, source) = (post.score as f32, source.clone());
+                std::iter::once(post.url)
+                    .chain(post.referenced_urls)
+                    .filter_map(move |url| {
+                        let mut candidate = CandidateUrl::new(&url, source.clone()).ok()?;
+                        candidate.referrer = referrer.clone();
+                        candidate.score = score;
+                        Some(candidate)
+                    })
+            });
+            for candidate in candidates {
+                let key = candidate.dedup_key();
+                if !candidate.is_external() || seen.contains_key(&key) {
+                    continue;
+                }
+                seen.insert(key, Instant::now());
+                let Some(article) = ingest(app, candidate, &events).await else {
+                    continue;
+                };
+                if let Some(term) = breaking.matched_term(&article) {
+                    log::info!("Watchlist hit from r/{}: {}", sub.name, article.url);
+                    batcher.push(Notification {
+                        title: article.title,
+                        url: article.url,
+                        reason: format!("matched \"{term}\" in r/{}", sub.name),
+                        watchlist: Some(term.to_string()),
+                    });
+                }
+            }
+        }
+        if let Some(db) = app.db() {
+            if let Err(e) = events.flush(db).await {
+                log::error!("Failed to write the event log: {}", e);
+            }
+        }
+    }
+}
+
+/// Runs `candidate` through the [`pipeline`], skipping articles in languages
+/// other than the configured ones, and returns its article if it is new.
+async fn ingest(app: &Encrawl, candidate: CandidateUrl, events: &EventLog) -> Option<Article> {
+    let config = app.config();
+    let url = candidate.as_str().to_string();
+    let fetched = match pipeline::fetch(app, candidate).await {
+        Ok(Some(fetched)) => fetched,
+        Ok(None) => {
+            let detail = "unchanged since it was last stored".to_string();
+            events.record(Event::new(url, Action::Skipped, Some(detail)));
+            return None;
+        }
+        Err(e) => {
+            log::error!("Failed to scrape {}: {}", url, e);
+            events.record(Event::new(url, Action::Failed, Some(e.to_string())));
+            return None;
+        }
+    };
+    let mut article = match pipeline::extract(app, fetched) {
+        Ok(article) => article,
+        Err(e) => {
+            log::error!("Failed to scrape {}: {}", url, e);
+            events.record(Event::new(url, Action::Failed, Some(e.to_string())));
+            return None;
+        }
+    };
+    events.record(Event::new(
+        url.as_str(),
+        Action::Extracted,
+        article.extractor.clone(),
+    ));
+    article.lang = article.language();
+    let languages = &config.languages;
+    if let Some(lang) = article
+        .lang
+        .as_ref()
+        .filter(|lang| !languages.is_empty() && !languages.contains(lang))
+    {
+        log::debug!("Skipping {}, written in {}", url, lang);
+        let detail = format!("written in {lang}, not one of the configured languages");
+        events.record(Event::new(url, Action::Skipped, Some(detail)));
+        return None;
+    }
+    pipeline::enrich(app, std::slice::from_mut(&mut article)).await;
+    match pipeline::store(app, std::slice::from_ref(&article)).await {
+        Ok(stored) => {
+            let stored = stored.into_iter().next()?;
+            app.metrics().stored(Some(stored));
+            events.record(stored_event(&article, stored));
+            matches!(stored, Stored::New(_)).then_some(article)
+        }
+        Err(e) => {
+            log::error!("Failed to store {}: {}", url, e);
+            app.metrics().stored(None);
+            events.record(Event::new(url, Action::Failed, Some(e.to_string())));
+            None
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! URLs that may hold an article, together with where they were found, as
+//! they go from the sources through filtering and dedup to the scrapers.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use url::Url;
+
+use crate::source::PostCandidate;
+use crate::store::canonicalize_url;
+
+/// A link to what may be an article and where it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidateUrl {
+    pub url: Url,
+    /// Name of the source that found the link, e.g. `r/finance`.
+    pub source: String,
+    /// When the link was found, or when the post linking to it was published
+    /// when the source says.
+    pub discovered_at: DateTime<Utc>,
+    /// Page the link was found on, e.g. the Reddit thread or the sitemap.
+    pub referrer: Option<String>,
+    /// How popular the link was where it was found, e.g. the upvotes of the
+    /// post, `0` when the source doesn't say.
+    pub score: f32,
+}
+
+impl CandidateUrl {
+    /// A link found by `source` just now, without a referrer or score.
+    pub fn new(url: &str, source: impl Into<String>) -> Result<Self, url::ParseError> {
+        Ok(Self {
+            url: Url::parse(url)?,
+            source: source.into(),
+            discovered_at: Utc::now(),
+            referrer: None,
+            score: 0.0,
+        })
+    }
+
+    /// Whether the link points outside of Reddit.
+    pub fn is_external(&self) -> bool {
+        !self.url.host_str().is_some_and(|host| {
+            let host = host.to_lowercase();
+            ["reddit.com", "redd.it"]
+                .iter()
+                .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+        })
+    }
+
+    /// The link without tracking parameters, equal for every link to the
+    /// same page.
+    pub fn dedup_key(&self) -> String {
+        canonicalize_url(self.url.as_str())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.url.as_str()
+    }
+}
+
+impl TryFrom<PostCandidate> for CandidateUrl {
+    type Error = url::ParseError;
+
+    fn try_from(post: PostCandidate) -> Result<Self, Self::Error> {
+        Ok(Self {
+            url: Url::parse(&post.url)?,
+            source: post.source,
+            discovered_at: post.posted_at.unwrap_or_else(Utc::now),
+            referrer: post.referrer,
+            score: post.score.unwrap_or_default(),
+        })
+    }
+}
+
+impl fmt::Display for CandidateUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (from {}", self.url, self.source)?;
+        if let Some(referrer) = &self.referrer {
+            write!(f, " via {referrer}")?;
+        }
+        f.write_str(")")
+    }
+}
@@ -0,0 +1,153 @@
+//! Failure injection for crawls, which makes fetches time out, pages come
+//! back malformed and batches fail to store at configured rates, so the error
+//! handling of the pipeline is exercised on purpose and not only when a site
+//! or the database misbehaves.
+//!
+//! The failures are injected where the real ones happen: timeouts and
+//! malformed pages in [`crate::http::HttpClient::send`], which retries the
+//! timeouts like any other, and database errors in
+//! [`crate::store::store_batch_with`].
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::error::EncrawlError;
+
+/// Page handed to the extractor in place of the fetched one: no text and
+/// tags that never close.
+pub const MALFORMED_HTML: &str = "<html><body><div class=\"<\"><span></div></p><script>";
+
+/// Share of the operations failed on purpose, each from 0 to 1.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ChaosConfig {
+    /// Fetches failing with a timeout before a request is sent.
+    pub fetch_timeout: f64,
+    /// Fetched pages replaced by [`MALFORMED_HTML`].
+    pub malformed_html: f64,
+    /// Batches of articles failing to store with a database error.
+    pub db_error: f64,
+    /// Seed of the draws, so a failing run can be repeated.
+    pub seed: u64,
+}
+
+impl ChaosConfig {
+    /// Whether anything is injected at all.
+    pub fn is_active(&self) -> bool {
+        self.fetch_timeout > 0.0 || self.malformed_html > 0.0 || self.db_error > 0.0
+    }
+}
+
+/// Failures injected into one crawl run, as counted in its report.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct InjectedFailures {
+    pub fetch_timeouts: usize,
+    pub malformed_html: usize,
+    /// Batches failed, every article of which counts as failed.
+    pub db_errors: usize,
+    /// Articles in the failed batches.
+    pub db_error_articles: usize,
+}
+
+impl InjectedFailures {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The failures injected since `earlier` was taken.
+    pub fn since(&self, earlier: &InjectedFailures) -> Self {
+        Self {
+            fetch_timeouts: self.fetch_timeouts - earlier.fetch_timeouts,
+            malformed_html: self.malformed_html - earlier.malformed_html,
+            db_errors: self.db_errors - earlier.db_errors,
+            db_error_articles: self.db_error_articles - earlier.db_error_articles,
+        }
+    }
+}
+
+impl std::ops::AddAssign<&InjectedFailures> for InjectedFailures {
+    fn add_assign(&mut self, other: &InjectedFailures) {
+        self.fetch_timeouts += other.fetch_timeouts;
+        self.malformed_html += other.malformed_html;
+        self.db_errors += other.db_errors;
+        self.db_error_articles += other.db_error_articles;
+    }
+}
+
+/// The error of a fetch failed with [`Chaos::fetch_timeout`], retried like a
+/// timeout by [`crate::http::HttpClient::send`].
+#[derive(Debug, thiserror::Error)]
+#[error("injected failure: timed out fetching {0}")]
+pub struct InjectedTimeout(pub String);
+
+/// Draws which operations fail, from a xorshift generator seeded with
+/// [`ChaosConfig::seed`], and counts the failures injected.
+pub struct Chaos {
+    config: ChaosConfig,
+    state: Mutex<u64>,
+    injected: Mutex<InjectedFailures>,
+}
+
+impl Chaos {
+    pub fn new(config: &ChaosConfig) -> Self {
+        log::warn!(
+            "Injecting failures: {:.0}% of fetches time out, {:.0}% of pages are malformed, \
+            {:.0}% of batches fail to store",
+            config.fetch_timeout * 100.0,
+            config.malformed_html * 100.0,
+            config.db_error * 100.0
+        );
+        Self {
+            config: config.clone(),
+            // Xorshift never leaves zero.
+            state: Mutex::new(config.seed.max(1)),
+            injected: Mutex::new(InjectedFailures::default()),
+        }
+    }
+
+    /// The failures injected so far.
+    pub fn injected(&self) -> InjectedFailures {
+        self.injected.lock().unwrap().clone()
+    }
+
+    /// The timeout a fetch of `url` fails with, if it is drawn to.
+    pub fn fetch_timeout(&self, url: &str) -> Option<InjectedTimeout> {
+        let timeout = self.draw(self.config.fetch_timeout);
+        if timeout {
+            self.injected.lock().unwrap().fetch_timeouts += 1;
+        }
+        timeout.then(|| InjectedTimeout(url.to_string()))
+    }
+
+    /// Whether the page fetched next is replaced by [`MALFORMED_HTML`].
+    pub fn malformed_html(&self) -> bool {
+        let malformed = self.draw(self.config.malformed_html);
+        if malformed {
+            self.injected.lock().unwrap().malformed_html += 1;
+        }
+        malformed
+    }
+
+    /// The database error storing the next batch of `articles` fails with,
+    /// if it is drawn to.
+    pub fn db_error(&self, articles: usize) -> Option<EncrawlError> {
+        let failed = self.draw(self.config.db_error);
+        if failed {
+            let mut injected = self.injected.lock().unwrap();
+            injected.db_errors += 1;
+            injected.db_error_articles += articles;
+        }
+        failed.then_some(EncrawlError::Db(sqlx::Error::PoolTimedOut))
+    }
+
+    /// True with probability `rate`.
+    fn draw(&self, rate: f64) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        ((*state >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+}
@@ -0,0 +1,133 @@
+//! Checks of the configuration against the services it refers to, run by
+//! `encrawl-rust check`, so mistakes show up before a crawl quietly finds
+//! nothing.
+
+use std::fmt;
+
+use crate::app::Config;
+use crate::reddit::RedditClient;
+use crate::source::SourceConfig;
+
+/// A configured flair that its subreddit doesn't have.
+#[derive(Debug)]
+pub struct UnknownFlair {
+    pub flair: String,
+    /// Flair of the subreddit the configured one probably means.
+    pub suggestion: Option<String>,
+}
+
+/// Something wrong with the flairs configured for a subreddit.
+#[derive(Debug)]
+pub enum FlairProblem {
+    /// The flairs of the subreddit could not be listed.
+    ListFailed { subreddit: String, error: String },
+    Unknown {
+        subreddit: String,
+        flair: UnknownFlair,
+    },
+}
+
+impl fmt::Display for FlairProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ListFailed { subreddit, error } => {
+                write!(f, "r/{subreddit}: failed to list flairs: {error}")
+            }
+            Self::Unknown { subreddit, flair } => match &flair.suggestion {
+                Some(suggestion) => write!(
+                    f,
+                    "r/{subreddit}: unknown flair {:?}, did you mean {:?}?",
+                    flair.flair, suggestion
+                ),
+                None => write!(
+                    f,
+                    "r/{subreddit}: unknown flair {:?}, see `encrawl-rust flairs {subreddit}`",
+                    flair.flair
+                ),
+            },
+        }
+    }
+}
+
+/// Every subreddit of the subs list and `sources.ron` restricted to flairs,
+/// with its flairs.
+pub fn configured_flairs(config: &Config) -> Vec<(String, Vec<String>)> {
+    let subs = config
+        .subs
+        .iter()
+        .map(|sub| (sub.name.clone(), sub.flairs.clone()));
+    let sources = config.sources.iter().filter_map(|source| match source {
+        SourceConfig::Reddit { name, flairs } => Some((name.clone(), flairs.clone())),
+        _ => None,
+    });
+    subs.chain(sources)
+        .filter(|(_, flairs)| !flairs.is_empty())
+        .collect()
+}
+
+/// The flairs of `configured` that are not in `listed`, the flairs of their
+/// subreddit. Reddit matches flairs regardless of case, so neither does this.
+pub fn unknown_flairs(configured: &[String], listed: &[String]) -> Vec<UnknownFlair> {
+    let normalize = |flair: &str| {
+        flair
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    configured
+        .iter()
+        .filter(|flair| {
+            !listed
+                .iter()
+                .any(|listed| listed.eq_ignore_ascii_case(flair))
+        })
+        .map(|flair| {
+            let wanted = normalize(flair);
+            let suggestion = listed
+                .iter()
+                .find(|listed| normalize(listed) == wanted)
+                .or_else(|| {
+                    listed.iter().find(|listed| {
+                        let listed = normalize(listed);
+                        !wanted.is_empty()
+                            && !listed.is_empty()
+                            && (listed.contains(&wanted) || wanted.contains(&listed))
+                    })
+                })
+                .cloned();
+            UnknownFlair {
+                flair: flair.clone(),
+                suggestion,
+            }
+        })
+        .collect()
+}
+
+/// Checks the flairs of every subreddit of `subs`, see [`configured_flairs`],
+/// against the ones Reddit lists for it.
+pub async fn check_flairs(
+    reddit: &RedditClient,
+    subs: &[(String, Vec<String>)],
+) -> Vec<FlairProblem> {
+    let mut problems = vec![];
+    for (subreddit, flairs) in subs {
+        let listed = match reddit.link_flairs(subreddit).await {
+            Ok(listed) => listed,
+            Err(e) => {
+                problems.push(FlairProblem::ListFailed {
+                    subreddit: subreddit.clone(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        problems.extend(unknown_flairs(flairs, &listed).into_iter().map(|flair| {
+            FlairProblem::Unknown {
+                subreddit: subreddit.clone(),
+                flair,
+            }
+        }));
+    }
+    problems
+}
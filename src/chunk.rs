@@ -0,0 +1,32 @@
+//! Splitting of article content into overlapping windows for embedding.
+
+/// Words per chunk, roughly 512 tokens of English text.
+pub const CHUNK_WORDS: usize = 384;
+
+/// Words shared by consecutive chunks, so sentences on a boundary are kept whole in one of them.
+pub const OVERLAP_WORDS: usize = 64;
+
+/// Byte ranges of windows of `words` whitespace separated words, each
+/// starting `words - overlap` words after the previous one.
+pub fn windows(content: &str, words: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let spans = content
+        .split_whitespace()
+        .map(|word| {
+            let start = word.as_ptr() as usize - content.as_ptr() as usize;
+            (start, start + word.len())
+        })
+        .collect::<Vec<_>>();
+    let words = words.max(1);
+    let step = words.saturating_sub(overlap).max(1);
+    let mut windows = vec![];
+    let mut first = 0;
+    while first < spans.len() {
+        let last = (first + words).min(spans.len()) - 1;
+        windows.push((spans[first].0, spans[last].1));
+        if last == spans.len() - 1 {
+            break;
+        }
+        first += step;
+    }
+    windows
+}
@@ -0,0 +1,208 @@
+//! Moving the stored articles between databases as JSON Lines, one article
+//! per line, optionally with their embeddings.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::app::Encrawl;
+use crate::stats::refresh_rollups;
+use crate::store::{store_batch_with, Article, ArticleEmbeddings, ChunkEmbedding, Stored};
+
+/// Articles read from the database at a time while exporting.
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+}
+
+/// One line of an export.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportedArticle {
+    #[serde(flatten)]
+    pub article: Article,
+    /// Left out unless the export was asked to include them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<ArticleEmbeddings>,
+}
+
+/// What an import did with the articles it read.
+#[derive(Debug, Default)]
+pub struct ImportCounts {
+    pub new: usize,
+    pub updated: usize,
+    pub duplicates: usize,
+}
+
+/// Embeddings of the articles `ids`, by article id.
+async fn embeddings(app: &Encrawl, ids: &[i64]) -> anyhow::Result<HashMap<i64, ArticleEmbeddings>> {
+    let titles: Vec<(
+        i64,
+        String,
+        Option<pgvector::Vector>,
+        Option<pgvector::Vector>,
+    )> = sqlx::query_as(
+        "SELECT id, embedding_model, embedding, content_embedding FROM articles
+            WHERE id = ANY($1)",
+    )
+    .bind(ids)
+    .fetch_all(app.postgres()?)
+    .await?;
+    let chunks: Vec<(i64, i32, i32, i32, pgvector::Vector)> = sqlx::query_as(
+        "SELECT article_id, seq, start_byte, end_byte, embedding FROM article_chunks
+        WHERE article_id = ANY($1) ORDER BY article_id, seq",
+    )
+    .bind(ids)
+    .fetch_all(app.postgres()?)
+    .await?;
+    let mut embeddings = titles
+        .into_iter()
+        .filter_map(|(id, model, title, content)| {
+            Some((
+                id,
+                ArticleEmbeddings {
+                    model,
+                    title: title?.to_vec(),
+                    content: content.map(|content| content.to_vec()),
+                    chunks: vec![],
+                },
+            ))
+        })
+        .collect::<HashMap<_, _>>();
+    for (id, seq, start, end, embedding) in chunks {
+        if let Some(embeddings) = embeddings.get_mut(&id) {
+            embeddings.chunks.push(ChunkEmbedding {
+                seq,
+                start: start as usize,
+                end: end as usize,
+                embedding: embedding.to_vec(),
+            });
+        }
+    }
+    Ok(embeddings)
+}
+
+/// Writes every stored article to `out` in `format`, with their title and
+/// chunk embeddings when `with_embeddings` is set. Returns the number of
+/// articles written.
+pub async fn export(
+    app: &Encrawl,
+    out: &mut dyn Write,
+    format: ExportFormat,
+    with_embeddings: bool,
+) -> anyhow::Result<usize> {
+    let ExportFormat::Jsonl = format;
+    let mut last_id = 0;
+    let mut total = 0;
+    loop {
+        let articles: Vec<Article> = sqlx::query_as(
+            "SELECT id, title, content, url, author, annotation, extractor, source, domain, lang,
+                published_at, fetched_at
+            FROM articles WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(EXPORT_BATCH_SIZE)
+        .fetch_all(app.postgres()?)
+        .await?;
+        let Some(last) = articles.last() else {
+            break;
+        };
+        last_id = last.id.unwrap_or(last_id);
+        let ids = articles
+            .iter()
+            .filter_map(|article| article.id)
+            .collect::<Vec<_>>();
+        let mut embeddings = if with_embeddings {
+            embeddings(app, &ids).await?
+        } else {
+            HashMap::new()
+        };
+        for mut article in articles {
+            let embeddings = article.id.and_then(|id| embeddings.remove(&id));
+            // Ids are specific to the database exported from.
+            article.id = None;
+            serde_json::to_writer(
+                &mut *out,
+                &ExportedArticle {
+                    article,
+                    embeddings,
+                },
+            )?;
+            out.write_all(b"\n")?;
+        }
+        total += ids.len();
+        log::info!("Exported {} articles", total);
+    }
+    out.flush()?;
+    Ok(total)
+}
+
+/// Stores the articles of an export read from `input`, `batch_size` at a
+/// time, skipping the ones already stored like a crawl does.
+///
+/// Embeddings in the export are reused when they come from the configured
+/// model, the articles are embedded again otherwise.
+pub async fn import(
+    app: &Encrawl,
+    input: impl BufRead,
+    batch_size: usize,
+) -> anyhow::Result<ImportCounts> {
+    let mut counts = ImportCounts::default();
+    let mut lines = input.lines().enumerate();
+    let mut warned = false;
+    loop {
+        let mut articles = vec![];
+        let mut embeddings = vec![];
+        for (i, line) in lines.by_ref() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exported: ExportedArticle = serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("line {} is not an exported article: {}", i + 1, e))?;
+            if let Some(model) = exported
+                .embeddings
+                .as_ref()
+                .map(|embeddings| &embeddings.model)
+                .filter(|model| *model != app.embedding_model() && !warned)
+            {
+                log::warn!(
+                    "The export was embedded with {}, embedding again with {}",
+                    model,
+                    app.embedding_model()
+                );
+                warned = true;
+            }
+            articles.push(exported.article);
+            embeddings.push(exported.embeddings);
+            if articles.len() >= batch_size.max(1) {
+                break;
+            }
+        }
+        if articles.is_empty() {
+            break;
+        }
+        for stored in store_batch_with(app, &articles, &embeddings).await? {
+            match stored {
+                Stored::New(_) => counts.new += 1,
+                Stored::Updated(_) => counts.updated += 1,
+                Stored::Duplicate => counts.duplicates += 1,
+            }
+        }
+        log::info!(
+            "Imported {} new and {} updated articles so far",
+            counts.new,
+            counts.updated
+        );
+    }
+    // Imported articles keep the day they were fetched on, which an
+    // incremental refresh doesn't look at.
+    if let Some(db) = app.db() {
+        refresh_rollups(db, true).await?;
+    }
+    Ok(counts)
+}
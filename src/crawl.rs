@@ -0,0 +1,386 @@
+//! The crawl pipeline: source listings → scraped articles → stored embeddings.
+
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::app::Encrawl;
+use crate::chaos::Chaos;
+use crate::embed_queue;
+use crate::error::ErrorKind;
+use crate::frontier::Frontier;
+use crate::ingest::{Action, Event, EventLog};
+use crate::pipeline;
+use crate::reddit::RedditClient;
+use crate::report::{CrawlReport, DomainTimings, UrlError};
+use crate::scrape::ScraperConfig;
+use crate::source::{self, Source};
+use crate::stats::refresh_rollups;
+use crate::store::{Article, Stored};
+
+/// How often the queue of articles to embed is checked during crawls with
+/// `defer_embedding`.
+const EMBED_QUEUE_POLL: Duration = Duration::from_secs(2);
+
+/// Slowest domains in the summary logged at the end of a crawl.
+pub const SUMMARY_DOMAINS: usize = 5;
+
+/// A subreddit to crawl along with the flairs used to filter its posts.
+#[derive(Clone)]
+pub struct Subreddit {
+    pub name: String,
+    pub flairs: Vec<String>,
+}
+
+impl Subreddit {
+    /// Reads a subs list where every line is `<subreddit> [flair1,flair2,...]`.
+    pub fn from_file(path: PathBuf) -> anyhow::Result<Vec<Self>> {
+        let sub_file = BufReader::new(std::fs::File::open(path)?);
+        let mut subs = Vec::new();
+        for line in sub_file.lines() {
+            let line = line?;
+            let mut line = line.split_ascii_whitespace();
+            let name = match line.next() {
+                Some(sub) => sub.to_string(),
+                None => continue,
+            };
+            let flairs = match line.next() {
+                Some(flairs) => flairs.split(',').map(|v| v.to_string()).collect(),
+                None => vec![],
+            };
+            subs.push(Self { name, flairs });
+        }
+        Ok(subs)
+    }
+}
+
+/// Whether `url` points outside of Reddit.
+pub fn is_external(url: &str) -> bool {
+    !url.contains("reddit.com") && !url.contains("redd.it")
+}
+
+/// Finds the scraper responsible for `url`, if any.
+pub fn find_scraper<'a>(scrapers: &'a [ScraperConfig], url: &str) -> Option<&'a ScraperConfig> {
+    let scraper = scrapers
+        .iter()
+        .find(|scraper| url.contains(&scraper.domain));
+    if scraper.is_none() {
+        log::debug!("Scraper for {} not found, using the generic extractor", url);
+    }
+    scraper
+}
+
+/// Crawls every source configured in `app` once, see [`crawl`]. Subreddits
+/// are only crawled with a `reddit` client.
+pub async fn run(app: &Encrawl, reddit: Option<Arc<RedditClient>>) -> CrawlReport {
+    let mut sources = source::from_config(app, reddit);
+    sources.extend(source::sites(app));
+    crawl(app, &sources).await
+}
+
+/// Fetches posts from every source, then scrapes, embeds and stores the
+/// linked articles with at most `concurrency` requests in flight. Returns a
+/// report of what every source yielded, what failed and how long it took.
+///
+/// Scraped articles are stored `embed_batch_size` at a time, so the model
+/// embeds a whole batch at once. Batches are embedded in parallel on as many
+/// copies of the model as `embedding_instances` loads, see
+/// [`crate::embedding::EmbedderPool`]. With `defer_embedding`, batches are
+/// stored without embeddings and a worker embeds them from the queue next to
+/// the crawl, see [`crate::embed_queue`], draining it before returning.
+///
+/// Links are scraped as their sources are fetched, from a [`Frontier`] that
+/// spills to disk past `frontier_memory_limit` links.
+///
+/// When `languages` are configured, articles detected to be written in
+/// another language are skipped. Ones whose language can't be told are kept.
+/// What was decided about every link is written to the event log, see
+/// [`crate::ingest`].
+pub async fn crawl(app: &Encrawl, sources: &[Box<dyn Source>]) -> CrawlReport {
+    let config = app.config();
+    let concurrency = config.concurrency.max(1);
+    let batch_size = config.embed_batch_size.max(1);
+    let languages = &config.languages;
+    let injected_before = app.chaos().map(Chaos::injected);
+    let start = Instant::now();
+    let work_before = app.metrics().work_totals();
+    let report = Mutex::new(CrawlReport::new(Utc::now()));
+    let report = &report;
+    let frontier = Frontier::new(config.frontier_memory_limit);
+    let frontier = &frontier;
+    let events = EventLog::new(app.has_postgres());
+    let events = &events;
+    // Fetch and extraction seconds of every page scraped, by domain.
+    let latencies = Mutex::new(BTreeMap::<String, (Vec<f64>, Vec<f64>)>::new());
+    let latencies = &latencies;
+    let discovered = stream::iter(sources)
+        .map(|source| async move {
+            let fetch_start = Instant::now();
+            let candidates = pipeline::discover(source.as_ref()).await;
+            let elapsed = fetch_start.elapsed().as_secs_f64();
+            let candidates = match candidates {
+                Ok(candidates) => {
+                    app.metrics()
+                        .posts_fetched(&source.name(), candidates.len());
+                    let posts = candidates.len();
+                    frontier.push(candidates).await.map(|seen_earlier| {
+                        for candidate in &seen_earlier {
+                            let detail = "found earlier in this crawl".to_string();
+                            events.record(Event::new(
+                                candidate.as_str(),
+                                Action::Skipped,
+                                Some(detail),
+                            ));
+                        }
+                        (posts, seen_earlier.len())
+                    })
+                }
+                Err(e) => Err(e),
+            };
+            let mut report = report.lock().unwrap();
+            report.timings.fetch_posts_secs += elapsed;
+            let source_report = report.sources.entry(source.name()).or_default();
+            source_report.fetch_secs = elapsed;
+            match candidates {
+                Ok((posts, seen_earlier)) => {
+                    source_report.posts = posts;
+                    source_report.seen_earlier = seen_earlier;
+                }
+                Err(e) => {
+                    log::error!("Failed to fetch posts from {}: {}", source.name(), e);
+                    source_report.error = Some(e.to_string());
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<()>>();
+    let discovered = async {
+        discovered.await;
+        frontier.close().await;
+    };
+    let candidates = stream::unfold(frontier, |frontier| async move {
+        frontier.pop().await.map(|candidate| (candidate, frontier))
+    });
+
+    let batches = candidates
+        .map(|candidate| async move {
+            let scrape_start = Instant::now();
+            let url = candidate.as_str().to_string();
+            let domain = Article::domain_of(&url).unwrap_or_default();
+            let fetch_start = Instant::now();
+            let fetched = pipeline::fetch(app, candidate.clone()).await;
+            let fetch_secs = fetch_start.elapsed().as_secs_f64();
+            let mut extract_secs = None;
+            let downloaded = matches!(fetched, Ok(Some(_)));
+            let article = match fetched {
+                Ok(Some(fetched)) => {
+                    let extract_start = Instant::now();
+                    let article = pipeline::extract(app, fetched).map(Some);
+                    extract_secs = Some(extract_start.elapsed().as_secs_f64());
+                    article
+                }
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            {
+                let mut latencies = latencies.lock().unwrap();
+                let (fetch, extract) = latencies.entry(domain.clone()).or_default();
+                fetch.push(fetch_secs);
+                extract.extend(extract_secs);
+            }
+            let mut report = report.lock().unwrap();
+            report.timings.scrape_secs += scrape_start.elapsed().as_secs_f64();
+            report.stages.fetched += usize::from(downloaded);
+            match article {
+                Ok(None) => {
+                    let detail = "unchanged since it was last stored".to_string();
+                    events.record(Event::new(url, Action::Skipped, Some(detail)));
+                    report
+                        .sources
+                        .entry(candidate.source)
+                        .or_default()
+                        .unchanged += 1;
+                    None
+                }
+                Ok(Some(mut article)) => {
+                    app.metrics().scraped(&domain, true);
+                    report.stages.extracted += 1;
+                    events.record(Event::new(
+                        url.as_str(),
+                        Action::Extracted,
+                        article.extractor.clone(),
+                    ));
+                    article.lang = article.language();
+                    if let Some(lang) = article
+                        .lang
+                        .as_ref()
+                        .filter(|lang| !languages.is_empty() && !languages.contains(lang))
+                    {
+                        log::debug!("Skipping {}, written in {}", candidate, lang);
+                        let detail =
+                            format!("written in {lang}, not one of the configured languages");
+                        events.record(Event::new(url, Action::Skipped, Some(detail)));
+                        report
+                            .sources
+                            .entry(candidate.source)
+                            .or_default()
+                            .other_language += 1;
+                        return None;
+                    }
+                    Some(article)
+                }
+                Err(e) => {
+                    app.metrics().scraped(&domain, false);
+                    log::error!("Failed to scrape {}: {}", candidate, e);
+                    let action = match e.kind() {
+                        ErrorKind::Blocked => Action::Skipped,
+                        _ => Action::Failed,
+                    };
+                    events.record(Event::new(url.as_str(), action, Some(e.to_string())));
+                    report.sources.entry(candidate.source).or_default().failed += 1;
+                    report
+                        .domain_errors
+                        .entry(domain)
+                        .or_default()
+                        .push(UrlError::new(url, &e));
+                    None
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(|article| async move { article })
+        .chunks(batch_size);
+
+    let stored = batches.for_each_concurrent(concurrency, |mut batch| async move {
+        pipeline::enrich(app, &mut batch).await;
+        let store_start = Instant::now();
+        let stored = pipeline::store(app, &batch).await;
+        {
+            let mut report = report.lock().unwrap();
+            report.timings.store_secs += store_start.elapsed().as_secs_f64();
+            match stored {
+                Ok(stored) => {
+                    for (article, stored) in batch.iter().zip(stored) {
+                        app.metrics().stored(Some(stored));
+                        events.record(stored_event(article, stored));
+                        if let Stored::New(id) = stored {
+                            report.new_article_ids.push(id);
+                        }
+                        let Some(source) = &article.source else {
+                            continue;
+                        };
+                        let source = report.sources.entry(source.clone()).or_default();
+                        match stored {
+                            Stored::New(_) => source.new += 1,
+                            Stored::Updated(_) => source.updated += 1,
+                            Stored::Duplicate => source.duplicates += 1,
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to store {} articles: {}", batch.len(), e);
+                    report
+                        .store_errors
+                        .push(format!("{} articles: {}", batch.len(), e));
+                    for article in &batch {
+                        app.metrics().stored(None);
+                        events.record(Event::new(
+                            article.url.as_str(),
+                            Action::Failed,
+                            Some(e.to_string()),
+                        ));
+                        let domain = Article::domain_of(&article.url).unwrap_or_default();
+                        report
+                            .domain_errors
+                            .entry(domain)
+                            .or_default()
+                            .push(UrlError::new(article.url.clone(), &e));
+                        if let Some(source) = &article.source {
+                            report.sources.entry(source.clone()).or_default().failed += 1;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(db) = app.db() {
+            if let Err(e) = events.flush(db).await {
+                log::error!("Failed to write the event log: {}", e);
+            }
+        }
+    });
+    // SQLite embeds articles while storing them, it has no queue.
+    if config.defer_embedding && app.has_postgres() {
+        let done = AtomicBool::new(false);
+        let stored = async {
+            stored.await;
+            done.store(true, Ordering::Release);
+        };
+        let worker = embed_queue::work_until(app, batch_size as i64, EMBED_QUEUE_POLL, &done);
+        futures::join!(discovered, stored, worker);
+    } else {
+        futures::join!(discovered, stored);
+    }
+    // Events of the links skipped after the last batch was stored.
+    if let Some(db) = app.db() {
+        if let Err(e) = events.flush(db).await {
+            log::error!("Failed to write the event log: {}", e);
+        }
+    }
+    let mut report = report.lock().unwrap().clone();
+    if let (Some(chaos), Some(before)) = (app.chaos(), &injected_before) {
+        report.injected = chaos.injected().since(before);
+    }
+    report.domain_timings = std::mem::take(&mut *latencies.lock().unwrap())
+        .into_iter()
+        .map(|(domain, (mut fetch, mut extract))| {
+            report.timings.fetch_secs += fetch.iter().sum::<f64>();
+            report.timings.extract_secs += extract.iter().sum::<f64>();
+            (domain, DomainTimings::new(&mut fetch, &mut extract))
+        })
+        .collect();
+    let work = app.metrics().work_totals();
+    report.timings.embed_secs = work.embed_secs - work_before.embed_secs;
+    report.timings.insert_secs = work.insert_secs - work_before.insert_secs;
+    let totals = report.totals();
+    report.stages.discovered = totals.posts;
+    report.stages.queued = totals.posts - totals.seen_earlier;
+    report.stages.embedded_texts = work.embedded_texts - work_before.embedded_texts;
+    report.stages.stored = totals.new + totals.updated;
+    report.stages.failed = totals.failed;
+    if let Some(db) = app.db() {
+        if let Err(e) = refresh_rollups(db, false).await {
+            log::error!("Failed to refresh the article rollups: {}", e);
+        }
+    }
+    report.finished_at = Utc::now();
+    app.metrics().crawl_finished();
+    report.timings.total_secs = start.elapsed().as_secs_f64();
+    match serde_json::to_string(&report.summary(SUMMARY_DOMAINS)) {
+        Ok(summary) => log::info!("Crawl summary: {}", summary),
+        Err(e) => log::error!("Failed to serialize the crawl summary: {}", e),
+    }
+    report
+}
+
+/// What [`store_batch`](crate::store::store_batch) did with `article`, as an event.
+pub(crate) fn stored_event(article: &Article, stored: Stored) -> Event {
+    let (action, article_id, detail) = match stored {
+        Stored::New(id) => (Action::Stored, Some(id), None),
+        Stored::Updated(id) => (Action::Updated, Some(id), None),
+        Stored::Duplicate => (
+            Action::Duplicate,
+            None,
+            Some("same content or URL as an article already stored".to_string()),
+        ),
+    };
+    Event {
+        article_id,
+        ..Event::new(article.url.as_str(), action, detail)
+    }
+}
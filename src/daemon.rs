@@ -0,0 +1,192 @@
+//! Crawling every few minutes as a long-lived service.
+//!
+//! Every source remembers the newest post it has seen in the
+//! `source_cursors` table, so each cycle only scrapes posts published since
+//! and a restarted daemon picks up where it left off. Posts whose source
+//! doesn't say when they were published are always passed on and left to
+//! the deduplication of the store.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::app::Encrawl;
+use crate::crawl::crawl;
+use crate::report::save;
+use crate::source::{PostCandidate, Source};
+
+/// The newest post seen from a source.
+#[derive(Debug, Clone)]
+pub struct LastSeen {
+    pub id: Option<String>,
+    pub posted_at: DateTime<Utc>,
+}
+
+/// The newest post seen from every source, by name.
+pub async fn last_seen(db: &Pool<Postgres>) -> anyhow::Result<HashMap<String, LastSeen>> {
+    let rows: Vec<(String, Option<String>, DateTime<Utc>)> =
+        sqlx::query_as("SELECT source, last_post_id, last_posted_at FROM source_cursors")
+            .fetch_all(db)
+            .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(source, id, posted_at)| (source, LastSeen { id, posted_at }))
+        .collect())
+}
+
+pub async fn save_last_seen(
+    db: &Pool<Postgres>,
+    source: &str,
+    seen: &LastSeen,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO source_cursors (source, last_post_id, last_posted_at) VALUES ($1, $2, $3)
+        ON CONFLICT (source) DO UPDATE SET last_post_id = EXCLUDED.last_post_id,
+            last_posted_at = EXCLUDED.last_posted_at, updated_at = now()",
+    )
+    .bind(source)
+    .bind(&seen.id)
+    .bind(seen.posted_at)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Passes on only the posts of `inner` newer than the last one seen, and
+/// moves `last_seen` to the newest one.
+struct NewPosts {
+    inner: Box<dyn Source>,
+    last_seen: Arc<Mutex<Option<LastSeen>>>,
+}
+
+#[async_trait]
+impl Source for NewPosts {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>> {
+        let posts = self.inner.fetch_posts().await?;
+        let mut last_seen = self.last_seen.lock().unwrap();
+        let since = last_seen.as_ref().map(|seen| seen.posted_at);
+        let newest = posts
+            .iter()
+            .filter_map(|post| Some((post.posted_at?, post)))
+            .max_by_key(|(posted_at, _)| *posted_at);
+        if let Some((posted_at, post)) = newest {
+            if since.is_none_or(|since| posted_at > since) {
+                *last_seen = Some(LastSeen {
+                    id: post.id.clone(),
+                    posted_at,
+                });
+            }
+        }
+        let total = posts.len();
+        let new = posts
+            .into_iter()
+            .filter(|post| match (since, post.posted_at) {
+                (Some(since), Some(posted_at)) => posted_at > since,
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        log::debug!(
+            "{} of {} posts from {} are new",
+            new.len(),
+            total,
+            self.name()
+        );
+        Ok(new)
+    }
+}
+
+/// Resolves once the process receives SIGTERM or Ctrl-C.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => {}
+            result = tokio::signal::ctrl_c() => result?,
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Crawls only the new posts of every source once, saving the report and
+/// the last posts seen to `db`.
+async fn cycle(
+    app: &Encrawl,
+    db: &Pool<Postgres>,
+    sources: &[Box<dyn Source>],
+    last_seen: &[(String, Arc<Mutex<Option<LastSeen>>>)],
+) {
+    let report = crawl(app, sources).await;
+    match save(db, &report).await {
+        Ok(id) => log::info!(
+            "Crawl run {} stored {} new articles",
+            id,
+            report.new_article_ids.len()
+        ),
+        Err(e) => log::error!("Failed to save the crawl report: {}", e),
+    }
+    for (source, seen) in last_seen {
+        let seen = seen.lock().unwrap().clone();
+        let Some(seen) = seen else {
+            continue;
+        };
+        if let Err(e) = save_last_seen(db, source, &seen).await {
+            log::error!("Failed to save the last post seen from {}: {}", source, e);
+        }
+    }
+}
+
+/// Crawls the new posts of `sources` every `interval` until SIGTERM or
+/// Ctrl-C. A cycle that is running when the signal arrives is finished
+/// first, so no post is marked as seen without being stored.
+pub async fn run(
+    app: &Encrawl,
+    sources: Vec<Box<dyn Source>>,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let db = app.postgres()?;
+    let mut saved = last_seen(db).await?;
+    let mut tracked = vec![];
+    let sources = sources
+        .into_iter()
+        .map(|inner| {
+            let name = inner.name();
+            let last_seen = Arc::new(Mutex::new(saved.remove(&name)));
+            tracked.push((name, last_seen.clone()));
+            Box::new(NewPosts { inner, last_seen }) as Box<dyn Source>
+        })
+        .collect::<Vec<_>>();
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        let current = cycle(app, db, &sources, &tracked);
+        tokio::pin!(current);
+        tokio::select! {
+            _ = &mut current => {}
+            result = &mut shutdown => {
+                log::info!("Finishing the current crawl before shutting down");
+                current.await;
+                return result;
+            }
+        }
+        log::info!("Crawling again in {}", humantime::format_duration(interval));
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            result = &mut shutdown => {
+                log::info!("Shutting down");
+                return result;
+            }
+        }
+    }
+}
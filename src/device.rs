@@ -0,0 +1,96 @@
+//! Choice of the device and weight type candle models run with.
+
+use candle_core::{DType, Device, Tensor};
+use clap::ValueEnum;
+
+/// Device a model runs on.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ComputeDevice {
+    Cpu,
+    /// The first CUDA GPU, needs candle built with the `cuda` feature.
+    Cuda,
+    /// The first Metal GPU, needs candle built with the `metal` feature.
+    Metal,
+}
+
+/// Type the weights of a model are loaded as.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ComputeDType {
+    /// bf16 on CUDA, f16 on Metal and f32 on the CPU.
+    Auto,
+    F16,
+    Bf16,
+    F32,
+}
+
+fn open(device: ComputeDevice) -> candle_core::Result<Device> {
+    match device {
+        ComputeDevice::Cpu => Ok(Device::Cpu),
+        ComputeDevice::Cuda => Device::new_cuda(0),
+        ComputeDevice::Metal => Device::new_metal(0),
+    }
+}
+
+fn device_name(device: &Device) -> &'static str {
+    match device {
+        Device::Cpu => "the CPU",
+        Device::Cuda(_) => "CUDA",
+        Device::Metal(_) => "Metal",
+    }
+}
+
+/// Whether `device` can multiply matrices of `dtype`, which not every GPU
+/// can for half precision types.
+fn supports(device: &Device, dtype: DType) -> bool {
+    Tensor::ones((2, 2), dtype, device)
+        .and_then(|t| t.matmul(&t))
+        .is_ok()
+}
+
+/// Opens `device` and picks the type to load the weights of `model` as,
+/// falling back to the CPU when the device can't be opened and to f32 when
+/// it doesn't support `dtype`. Half precision is only used on GPUs, on the
+/// CPU it is slower than f32. Logs what was chosen.
+pub fn select(model: &str, device: ComputeDevice, dtype: ComputeDType) -> (Device, DType) {
+    let device = open(device).unwrap_or_else(|e| {
+        log::warn!(
+            "Can't use {:?} for the {}, falling back to the CPU: {}",
+            device,
+            model,
+            e
+        );
+        Device::Cpu
+    });
+    let wanted = match (dtype, &device) {
+        (ComputeDType::Auto, Device::Cpu) | (ComputeDType::F32, _) => DType::F32,
+        (ComputeDType::Auto, Device::Cuda(_)) | (ComputeDType::Bf16, _) => DType::BF16,
+        (ComputeDType::Auto, Device::Metal(_)) | (ComputeDType::F16, _) => DType::F16,
+    };
+    let dtype = if wanted == DType::F32 {
+        wanted
+    } else if device.is_cpu() {
+        log::warn!(
+            "{:?} needs a GPU, loading the {} as f32 instead",
+            wanted,
+            model
+        );
+        DType::F32
+    } else if !supports(&device, wanted) {
+        log::warn!(
+            "{} doesn't support {:?}, loading the {} as f32 instead",
+            device_name(&device),
+            wanted,
+            model
+        );
+        DType::F32
+    } else {
+        wanted
+    };
+    log::info!(
+        "Running the {} on {} as {:?}",
+        model,
+        device_name(&device),
+        dtype
+    );
+    (device, dtype)
+}
@@ -0,0 +1,145 @@
+//! Digests of several topics at once, in which an article retrieved for more
+//! than one topic is only summarised under the topic it matches best.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::app::Encrawl;
+use crate::routing;
+use crate::store::{cosine_similarity, search, Article};
+use crate::summaries;
+use crate::summarise::Provenance;
+
+/// The articles retrieved for one topic of a digest.
+#[derive(Debug)]
+pub struct Section {
+    pub topic: String,
+    /// Articles summarised under this topic, the closest first.
+    pub articles: Vec<Article>,
+    /// Articles retrieved for this topic too but matching another one
+    /// better, with the topic they are summarised under.
+    pub also_covered: Vec<(Article, String)>,
+}
+
+impl Section {
+    /// Summarises [`Self::articles`] with [`summaries::summarize`] and lists
+    /// the articles covered under other topics after the summary. Returns
+    /// what the summary was generated with too, unless every article was
+    /// covered elsewhere and nothing was generated.
+    pub async fn summarize(&self, app: &Encrawl) -> anyhow::Result<(String, Option<Provenance>)> {
+        let (mut body, provenance) = if self.articles.is_empty() {
+            (
+                "Every article found is covered under another topic.".to_string(),
+                None,
+            )
+        } else {
+            let max_age = app.config().summary_max_age;
+            let summary = summaries::summarize(app, &self.topic, &self.articles, max_age).await?;
+            (summary.body.trim().to_string(), summary.provenance)
+        };
+        let mut by_topic = BTreeMap::<&str, Vec<&Article>>::new();
+        for (article, topic) in &self.also_covered {
+            by_topic.entry(topic).or_default().push(article);
+        }
+        for (topic, articles) in by_topic {
+            body.push_str(&format!("\n\nAlso covered in {topic}:"));
+            for article in articles {
+                body.push_str(&format!("\n- [{}]({})", article.title, article.url));
+            }
+        }
+        Ok((body, provenance))
+    }
+}
+
+/// Retrieves the `limit` articles closest to every topic and leaves each
+/// article retrieved for several topics to the one whose embedding is
+/// closest to its title. The other topics list it as also covered.
+///
+/// When every topic is a configured one, the articles routed to it when
+/// they were stored are taken instead, which are already left to a single
+/// topic each. Routes are only kept in Postgres, with the articles in
+/// SQLite every digest is searched for.
+pub async fn sections(
+    app: &Encrawl,
+    topics: &[String],
+    limit: i32,
+) -> anyhow::Result<Vec<Section>> {
+    let configured = app.config().topics.clone();
+    if app.has_postgres() && topics.iter().all(|topic| configured.contains(topic)) {
+        let mut sections = vec![];
+        for topic in topics {
+            sections.push(Section {
+                topic: topic.clone(),
+                articles: routing::articles(app, topic, limit).await?,
+                also_covered: vec![],
+            });
+        }
+        return Ok(sections);
+    }
+
+    let mut results = vec![];
+    for topic in topics {
+        results.push(search(app, topic.clone(), limit).await?);
+    }
+
+    let mut retrieved_by = HashMap::<i64, Vec<usize>>::new();
+    for (i, articles) in results.iter().enumerate() {
+        for id in articles.iter().filter_map(|article| article.id) {
+            retrieved_by.entry(id).or_default().push(i);
+        }
+    }
+    retrieved_by.retain(|_, topics| topics.len() > 1);
+    let mut owners = HashMap::new();
+    if !retrieved_by.is_empty() {
+        let mut shared = results
+            .iter()
+            .flatten()
+            .filter(|article| article.id.is_some_and(|id| retrieved_by.contains_key(&id)))
+            .map(|article| (article.id.unwrap(), article.title.clone()))
+            .collect::<Vec<_>>();
+        shared.sort_by_key(|(id, _)| *id);
+        shared.dedup_by_key(|(id, _)| *id);
+        let titles = shared
+            .iter()
+            .map(|(_, title)| title.clone())
+            .collect::<Vec<_>>();
+        let title_embeddings = app.embed(&titles).await?;
+        let topic_embeddings = app.embed(topics).await?;
+        for ((id, _), embedding) in shared.iter().zip(&title_embeddings) {
+            let best = retrieved_by[id]
+                .iter()
+                .copied()
+                .max_by(|a, b| {
+                    cosine_similarity(embedding, &topic_embeddings[*a])
+                        .total_cmp(&cosine_similarity(embedding, &topic_embeddings[*b]))
+                })
+                .unwrap();
+            owners.insert(*id, best);
+        }
+    }
+
+    Ok(topics
+        .iter()
+        .zip(results)
+        .enumerate()
+        .map(|(i, (topic, articles))| {
+            let (articles, also_covered): (Vec<_>, Vec<_>) =
+                articles.into_iter().partition(|article| {
+                    article
+                        .id
+                        .and_then(|id| owners.get(&id))
+                        .is_none_or(|owner| *owner == i)
+                });
+            Section {
+                topic: topic.clone(),
+                articles,
+                also_covered: also_covered
+                    .into_iter()
+                    .map(|article| {
+                        let owner = owners[&article.id.unwrap()];
+                        (article, topics[owner].clone())
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,200 @@
+//! Queue of articles stored without embeddings, so crawls with
+//! `--defer-embedding` never wait on the embedding model. Articles are stored
+//! with `pending_embedding` set and a row in `embedding_jobs`, which a worker
+//! drains: alongside the crawl that queued them, or in another process with
+//! `encrawl-rust embed-worker`. Until then they are only found by keyword.
+
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::app::Encrawl;
+use crate::embed_text::EmbedText;
+use crate::error::EncrawlError;
+use crate::routing;
+use crate::store::{
+    check_embedding_dim, chunk_embeddings, configured_key, embed_articles, mark_embedded,
+    replace_chunks_in,
+};
+
+/// Jobs failing this often are left in the queue without being retried,
+/// until they are queued again.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Articles waiting in the queue.
+#[derive(Debug, Serialize, FromRow)]
+pub struct QueueStatus {
+    pub pending: i64,
+    /// Jobs that failed [`MAX_ATTEMPTS`] times.
+    pub failed: i64,
+}
+
+/// Queues the articles `ids` for embedding, again when they already are.
+pub async fn enqueue(db: &Pool<Postgres>, ids: &[i64]) -> Result<(), EncrawlError> {
+    sqlx::query(
+        "INSERT INTO embedding_jobs (article_id) SELECT unnest($1::bigint[])
+        ON CONFLICT (article_id) DO UPDATE SET queued_at = now(), attempts = 0, error = NULL",
+    )
+    .bind(ids)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn status(db: &Pool<Postgres>) -> anyhow::Result<QueueStatus> {
+    Ok(sqlx::query_as(
+        "SELECT COUNT(*) FILTER (WHERE attempts < $1) AS pending,
+            COUNT(*) FILTER (WHERE attempts >= $1) AS failed
+        FROM embedding_jobs",
+    )
+    .bind(MAX_ATTEMPTS)
+    .fetch_one(db)
+    .await?)
+}
+
+/// Makes the jobs that failed too often be retried.
+pub async fn retry_failed(db: &Pool<Postgres>) -> anyhow::Result<u64> {
+    Ok(
+        sqlx::query("UPDATE embedding_jobs SET attempts = 0 WHERE attempts >= $1")
+            .bind(MAX_ATTEMPTS)
+            .execute(db)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Embeds the titles and chunks of up to `batch_size` queued articles, the
+/// longest queued first, and routes them to topics. Jobs taken by another
+/// worker are skipped. When embedding fails, the jobs are kept with the
+/// error. Returns the number of articles embedded.
+pub async fn embed_batch(app: &Encrawl, batch_size: i64) -> Result<usize, EncrawlError> {
+    check_embedding_dim(app)
+        .await
+        .map_err(EncrawlError::Model)?;
+    let mut tx = app.postgres()?.begin().await?;
+    let jobs: Vec<(i64, String, String, Option<String>)> = sqlx::query_as(
+        "SELECT a.id, a.title, a.content, a.source
+        FROM embedding_jobs j JOIN articles a ON a.id = j.article_id
+        WHERE j.attempts < $2
+        ORDER BY j.queued_at, j.article_id LIMIT $1
+        FOR UPDATE OF j SKIP LOCKED",
+    )
+    .bind(batch_size.max(1))
+    .bind(MAX_ATTEMPTS)
+    .fetch_all(&mut *tx)
+    .await?;
+    if jobs.is_empty() {
+        return Ok(0);
+    }
+    let ids = jobs.iter().map(|(id, _, _, _)| *id).collect::<Vec<_>>();
+    let config = app.config();
+    let texts = jobs
+        .iter()
+        .map(|(_, title, content, source)| {
+            let composition = EmbedText::for_source(&config, source.as_deref());
+            (composition, title.as_str(), content.as_str())
+        })
+        .collect::<Vec<_>>();
+    let keys = jobs
+        .iter()
+        .map(|(id, title, content, source)| {
+            (*id, configured_key(app, source.as_deref(), title, content))
+        })
+        .collect::<Vec<_>>();
+    let contents = jobs
+        .iter()
+        .map(|(id, _, content, _)| (*id, content.as_str()))
+        .collect::<Vec<_>>();
+    let embedded = match embed_articles(app, &texts).await {
+        Ok(embeddings) => chunk_embeddings(app, &contents)
+            .await
+            .map(|chunks| (embeddings, chunks)),
+        Err(e) => Err(EncrawlError::Model(e)),
+    };
+    let (embeddings, chunks) = match embedded {
+        Ok(embedded) => embedded,
+        Err(e) => {
+            sqlx::query(
+                "UPDATE embedding_jobs SET attempts = attempts + 1, error = $2
+                WHERE article_id = ANY($1)",
+            )
+            .bind(&ids)
+            .bind(e.to_string())
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            return Err(e);
+        }
+    };
+
+    let mut query = sqlx::QueryBuilder::<Postgres>::new(
+        "UPDATE articles SET embedding = v.embedding, content_embedding = v.content_embedding,
+            pending_embedding = false, embedding_model = ",
+    );
+    query
+        .push_bind(app.embedding_model())
+        .push(", embedding_dim = ")
+        .push_bind(app.embedding_dim() as i32)
+        .push(" FROM (");
+    query.push_values(
+        ids.iter().zip(embeddings),
+        |mut row, (id, (embedding, content_embedding))| {
+            row.push_bind(*id)
+                .push_bind(pgvector::Vector::from(embedding))
+                .push_bind(pgvector::Vector::from(content_embedding));
+        },
+    );
+    query.push(") AS v (id, embedding, content_embedding) WHERE articles.id = v.id");
+    query.build().execute(&mut *tx).await?;
+    replace_chunks_in(app, &mut tx, &ids, &chunks).await?;
+    mark_embedded(&mut tx, &keys).await?;
+    sqlx::query("DELETE FROM embedding_jobs WHERE article_id = ANY($1)")
+        .bind(&ids)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    if let Err(e) = routing::route(app, &ids).await {
+        log::error!("Failed to route {} articles to topics: {}", ids.len(), e);
+    }
+    Ok(ids.len())
+}
+
+/// Embeds queued articles until the queue is empty or a batch fails.
+/// Returns the number of articles embedded.
+pub async fn drain(app: &Encrawl, batch_size: i64) -> Result<usize, EncrawlError> {
+    let mut total = 0;
+    loop {
+        let embedded = embed_batch(app, batch_size).await?;
+        if embedded == 0 {
+            return Ok(total);
+        }
+        total += embedded;
+        log::info!("Embedded {} queued articles", total);
+    }
+}
+
+/// Drains the queue every `poll` until `done` is set, then drains it once
+/// more and returns. Runs next to a crawl queuing articles.
+pub async fn work_until(app: &Encrawl, batch_size: i64, poll: Duration, done: &AtomicBool) {
+    loop {
+        let finished = done.load(Ordering::Acquire);
+        if let Err(e) = drain(app, batch_size).await {
+            log::error!("Failed to embed queued articles: {}", e);
+        }
+        if finished {
+            return;
+        }
+        tokio::time::sleep(poll).await;
+    }
+}
+
+/// Drains the queue every `poll`, forever.
+pub async fn run(app: &Encrawl, batch_size: i64, poll: Duration) -> ! {
+    loop {
+        if let Err(e) = drain(app, batch_size).await {
+            log::error!("Failed to embed queued articles: {}", e);
+        }
+        tokio::time::sleep(poll).await;
+    }
+}
@@ -0,0 +1,111 @@
+//! What text of an article its `embedding` is made from. Titles suit short
+//! news blurbs, whose title says it all, while long-form reports are better
+//! found by what their body says. The composition is set with `--embed-text`
+//! for all articles and in `[models.embed_text_sources]` of the settings per
+//! source, and is part of the [`crate::store::embedding_key`], so articles
+//! crawled again after it changed are embedded again.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::app::{Config, Encrawl};
+use crate::chunk::{self, CHUNK_WORDS, OVERLAP_WORDS};
+use crate::extractive::key_sentences;
+use crate::store::lead;
+
+/// Sentences of an article embedded with [`EmbedText::Summary`].
+const SUMMARY_SENTENCES: usize = 3;
+
+/// Text an article's embedding is made from.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmbedText {
+    /// The title only.
+    #[default]
+    Title,
+    /// The title followed by the first words of the content.
+    TitleLead,
+    /// The title followed by the sentences closest to the gist of the content.
+    Summary,
+    /// The average of the embeddings of all chunks of the content, so every
+    /// part of a long article counts.
+    Chunks,
+}
+
+impl EmbedText {
+    /// The composition configured for articles of `source`.
+    pub fn for_source(config: &Config, source: Option<&str>) -> Self {
+        source
+            .and_then(|source| config.embed_text_sources.get(source))
+            .copied()
+            .unwrap_or(config.embed_text)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmbedText::Title => "title",
+            EmbedText::TitleLead => "title-lead",
+            EmbedText::Summary => "summary",
+            EmbedText::Chunks => "chunks",
+        }
+    }
+}
+
+impl fmt::Display for EmbedText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Embeds every `(composition, title, content)` with a single call to the
+/// model, besides the ones picking summary sentences, and returns them in
+/// order.
+pub async fn embed(
+    app: &Encrawl,
+    articles: &[(EmbedText, &str, &str)],
+) -> anyhow::Result<Vec<Vec<f32>>> {
+    let mut texts = vec![];
+    // Texts of every article, averaged into its embedding.
+    let mut spans = vec![];
+    for (composition, title, content) in articles {
+        let start = texts.len();
+        match composition {
+            EmbedText::Title => texts.push(title.to_string()),
+            EmbedText::TitleLead => texts.push(format!("{title}\n\n{}", lead(content))),
+            EmbedText::Summary => {
+                let sentences = key_sentences(app, content, SUMMARY_SENTENCES).await?;
+                texts.push(format!("{title}\n\n{}", sentences.join(" ")));
+            }
+            EmbedText::Chunks => {
+                texts.extend(
+                    chunk::windows(content, CHUNK_WORDS, OVERLAP_WORDS)
+                        .into_iter()
+                        .map(|(start, end)| content[start..end].to_string()),
+                );
+                // Empty articles are known by their title alone.
+                if texts.len() == start {
+                    texts.push(title.to_string());
+                }
+            }
+        }
+        spans.push(start..texts.len());
+    }
+    if texts.is_empty() {
+        return Ok(vec![]);
+    }
+    let embeddings = app.embed(&texts).await?;
+    Ok(spans
+        .into_iter()
+        .map(|span| {
+            let parts = &embeddings[span];
+            let mut mean = vec![0f32; parts[0].len()];
+            for part in parts {
+                for (m, x) in mean.iter_mut().zip(part) {
+                    *m += x / parts.len() as f32;
+                }
+            }
+            mean
+        })
+        .collect())
+}
@@ -0,0 +1,495 @@
+//! Choice of the sentence embedding model and the library running it, and
+//! migration of stored embeddings from one model to another.
+
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{self, BertModel};
+use clap::ValueEnum;
+use hf_hub::api::sync::Api;
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokenizers::{Tokenizer, TruncationParams};
+use tokio::sync::Semaphore;
+
+use crate::app::Encrawl;
+use crate::device::{self, ComputeDType, ComputeDevice};
+use crate::embed_text::EmbedText;
+use crate::index::{self, IndexParams};
+use crate::store::{self, store_chunks};
+
+/// Pretrained sentence-transformers models, by their Hugging Face names.
+/// The candle backend only runs the BERT based ones, rust-bert all but
+/// `paraphrase-multilingual-MiniLM-L12-v2`.
+pub const REMOTE_MODELS: [&str; 8] = [
+    "all-MiniLM-L12-v2",
+    "all-MiniLM-L6-v2",
+    "all-distilroberta-v1",
+    "bert-base-nli-mean-tokens",
+    "distiluse-base-multilingual-cased",
+    "paraphrase-albert-small-v2",
+    "paraphrase-multilingual-MiniLM-L12-v2",
+    "sentence-t5-base",
+];
+
+/// Sentence embedding model articles are embedded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmbeddingModel {
+    /// One of the pretrained models downloaded on first use, e.g. `all-MiniLM-L12-v2`.
+    Remote(&'static str),
+    /// Directory holding a model converted for rust-bert, or with `config.json`,
+    /// `tokenizer.json` and `model.safetensors` for candle.
+    Local(PathBuf),
+}
+
+impl Default for EmbeddingModel {
+    fn default() -> Self {
+        Self::Remote(REMOTE_MODELS[0])
+    }
+}
+
+impl FromStr for EmbeddingModel {
+    type Err = anyhow::Error;
+
+    /// Accepts the name of a pretrained model or the path of a local one.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if let Some(name) = REMOTE_MODELS
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self::Remote(name));
+        }
+        let path = PathBuf::from(s);
+        if path.is_dir() {
+            return Ok(Self::Local(path));
+        }
+        anyhow::bail!(
+            "{} is neither a model directory nor one of {}",
+            s,
+            REMOTE_MODELS.join(", ")
+        )
+    }
+}
+
+/// The name stored along with every embedding. Both backends produce the same
+/// embeddings, so it doesn't include the backend.
+impl fmt::Display for EmbeddingModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Remote(name) => f.write_str(name),
+            Self::Local(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl EmbeddingModel {
+    /// Whether the model was trained on other languages than English, so
+    /// articles and queries in them land close to English ones. Unknown, and
+    /// taken to be false, for local models.
+    pub fn is_multilingual(&self) -> bool {
+        match self {
+            Self::Remote(name) => name.contains("multilingual"),
+            Self::Local(_) => false,
+        }
+    }
+
+    /// Loads the model with `backend`, downloading pretrained ones on first
+    /// use. This blocks. `device` and `dtype` only apply to the candle backend.
+    pub fn load(
+        &self,
+        backend: EmbeddingBackend,
+        device: ComputeDevice,
+        dtype: ComputeDType,
+    ) -> anyhow::Result<Box<dyn Embedder>> {
+        match backend {
+            #[cfg(feature = "rust-bert")]
+            EmbeddingBackend::RustBert => {
+                log::info!("Running the embedding model with rust-bert on the CPU as F32");
+                Ok(Box::new(RustBertEmbedder::load(self)?))
+            }
+            #[cfg(not(feature = "rust-bert"))]
+            EmbeddingBackend::RustBert => {
+                anyhow::bail!(
+                    "the rust-bert backend needs encrawl built with the rust-bert feature"
+                )
+            }
+            EmbeddingBackend::Candle => Ok(Box::new(CandleEmbedder::load(self, device, dtype)?)),
+        }
+    }
+}
+
+/// Library the embedding model runs on.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbeddingBackend {
+    /// rust-bert on libtorch, only available with the `rust-bert` feature.
+    RustBert,
+    /// Candle, which the text generator runs on too, so no libtorch is needed.
+    Candle,
+}
+
+impl Default for EmbeddingBackend {
+    fn default() -> Self {
+        if cfg!(feature = "rust-bert") {
+            Self::RustBert
+        } else {
+            Self::Candle
+        }
+    }
+}
+
+/// A loaded sentence embedding model.
+pub trait Embedder: Send {
+    fn encode(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>>;
+    /// Length of the vectors [`Embedder::encode`] returns.
+    fn dim(&self) -> usize;
+}
+
+#[cfg(feature = "rust-bert")]
+struct RustBertEmbedder {
+    model: rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel,
+    dim: usize,
+}
+
+#[cfg(feature = "rust-bert")]
+impl RustBertEmbedder {
+    fn load(model: &EmbeddingModel) -> anyhow::Result<Self> {
+        use rust_bert::pipelines::sentence_embeddings::{
+            SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
+        };
+        let model = match model {
+            EmbeddingModel::Remote(name) => {
+                let model_type = match *name {
+                    "all-MiniLM-L6-v2" => SentenceEmbeddingsModelType::AllMiniLmL6V2,
+                    "all-distilroberta-v1" => SentenceEmbeddingsModelType::AllDistilrobertaV1,
+                    "bert-base-nli-mean-tokens" => {
+                        SentenceEmbeddingsModelType::BertBaseNliMeanTokens
+                    }
+                    "distiluse-base-multilingual-cased" => {
+                        SentenceEmbeddingsModelType::DistiluseBaseMultilingualCased
+                    }
+                    "paraphrase-albert-small-v2" => {
+                        SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2
+                    }
+                    "sentence-t5-base" => SentenceEmbeddingsModelType::SentenceT5Base,
+                    "all-MiniLM-L12-v2" => SentenceEmbeddingsModelType::AllMiniLmL12V2,
+                    other => anyhow::bail!("{} only runs with the candle backend", other),
+                };
+                SentenceEmbeddingsBuilder::remote(model_type).create_model()?
+            }
+            EmbeddingModel::Local(path) => SentenceEmbeddingsBuilder::local(path).create_model()?,
+        };
+        let dim = model.get_embedding_dim()? as usize;
+        Ok(Self { model, dim })
+    }
+}
+
+#[cfg(feature = "rust-bert")]
+impl Embedder for RustBertEmbedder {
+    fn encode(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        Ok(self.model.encode(texts)?)
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+}
+
+/// Runs BERT based sentence-transformers models with candle, mean pooling
+/// the token embeddings like sentence-transformers does.
+struct CandleEmbedder {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+    dim: usize,
+}
+
+/// The fields of a model's `config.json` that [`bert::Config`] keeps private.
+#[derive(Deserialize)]
+struct ModelSize {
+    hidden_size: usize,
+    max_position_embeddings: usize,
+}
+
+impl CandleEmbedder {
+    fn load(
+        model: &EmbeddingModel,
+        device: ComputeDevice,
+        dtype: ComputeDType,
+    ) -> anyhow::Result<Self> {
+        let (config, tokenizer, weights) = match model {
+            EmbeddingModel::Remote(name) => {
+                let repo = Api::new()?.model(format!("sentence-transformers/{name}"));
+                (
+                    repo.get("config.json")?,
+                    repo.get("tokenizer.json")?,
+                    repo.get("model.safetensors")?,
+                )
+            }
+            EmbeddingModel::Local(dir) => (
+                dir.join("config.json"),
+                dir.join("tokenizer.json"),
+                dir.join("model.safetensors"),
+            ),
+        };
+        let config = std::fs::read(config)?;
+        let size: ModelSize = serde_json::from_slice(&config)?;
+        let config: bert::Config = serde_json::from_slice(&config)
+            .map_err(|e| anyhow::anyhow!("the candle backend only runs BERT models: {}", e))?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer).map_err(anyhow::Error::msg)?;
+        if tokenizer.get_truncation().is_none() {
+            tokenizer
+                .with_truncation(Some(TruncationParams {
+                    max_length: size.max_position_embeddings,
+                    ..Default::default()
+                }))
+                .map_err(anyhow::Error::msg)?;
+        }
+        let (device, dtype) = device::select("embedding model", device, dtype);
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], dtype, &device)? };
+        Ok(Self {
+            model: BertModel::load(vb, &config)?,
+            tokenizer,
+            device,
+            dim: size.hidden_size,
+        })
+    }
+}
+
+impl Embedder for CandleEmbedder {
+    /// Embeds one text at a time, since this version of the model can't mask
+    /// the padding of a batch.
+    fn encode(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        texts
+            .iter()
+            .map(|text| {
+                let encoding = self
+                    .tokenizer
+                    .encode(text.as_str(), true)
+                    .map_err(anyhow::Error::msg)?;
+                let input_ids = Tensor::new(encoding.get_ids(), &self.device)?.unsqueeze(0)?;
+                let token_type_ids = input_ids.zeros_like()?;
+                let tokens = self.model.forward(&input_ids, &token_type_ids)?;
+                let embedding = tokens.to_dtype(DType::F32)?.mean(1)?.squeeze(0)?;
+                let norm = embedding.sqr()?.sum_all()?.sqrt()?;
+                Ok(embedding.broadcast_div(&norm)?.to_vec1::<f32>()?)
+            })
+            .collect()
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+}
+
+/// Fewest texts a batch is split into per instance of an [`EmbedderPool`],
+/// smaller parts take longer to hand over than to embed.
+const MIN_TEXTS_PER_INSTANCE: usize = 8;
+
+/// Copies of the same embedding model, each embedding on its own blocking
+/// thread. Batches are split into parts taken by whichever instance is idle
+/// first, so both concurrent and large batches keep every instance busy.
+pub struct EmbedderPool {
+    idle: std::sync::Mutex<Vec<Box<dyn Embedder>>>,
+    available: Semaphore,
+    instances: usize,
+    dim: usize,
+}
+
+impl EmbedderPool {
+    /// Loads `instances` copies of `model`, at least one, see
+    /// [`EmbeddingModel::load`]. This blocks.
+    pub fn load(
+        model: &EmbeddingModel,
+        backend: EmbeddingBackend,
+        device: ComputeDevice,
+        dtype: ComputeDType,
+        instances: usize,
+    ) -> anyhow::Result<Self> {
+        let instances = instances.max(1);
+        let embedders = (0..instances)
+            .map(|_| model.load(backend, device, dtype))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if instances > 1 {
+            log::info!("Loaded {} instances of {}", instances, model);
+        }
+        Ok(Self {
+            dim: embedders[0].dim(),
+            idle: std::sync::Mutex::new(embedders),
+            available: Semaphore::new(instances),
+            instances,
+        })
+    }
+
+    /// Length of the vectors [`Self::encode`] returns.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Embeds `texts` on as many instances as it is worth splitting them
+    /// over, returning the embeddings in the order of `texts`.
+    pub async fn encode(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let parts = texts
+            .len()
+            .div_ceil(MIN_TEXTS_PER_INSTANCE)
+            .clamp(1, self.instances);
+        let part_len = texts.len().div_ceil(parts).max(1);
+        let embedded = futures::future::try_join_all(
+            texts
+                .chunks(part_len)
+                .map(|part| self.encode_on_idle(part.to_vec())),
+        )
+        .await?;
+        Ok(embedded.into_iter().flatten().collect())
+    }
+
+    /// Waits for an idle instance and embeds `texts` with it.
+    async fn encode_on_idle(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+        let permit = self.available.acquire().await?;
+        let embedder = self
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("every permit stands for an idle instance");
+        let encoded = tokio::task::spawn_blocking(move || {
+            let embeddings = embedder.encode(&texts);
+            (embedder, embeddings)
+        })
+        .await;
+        match encoded {
+            Ok((embedder, embeddings)) => {
+                self.idle.lock().unwrap().push(embedder);
+                embeddings
+            }
+            // The instance panicked and is gone, so is its permit.
+            Err(e) => {
+                permit.forget();
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Embeds every article and chunk not embedded by the configured model again
+/// with it, `batch_size` articles at a time. Returns the number of articles
+/// re-embedded.
+///
+/// When the dimension changes, the embedding columns accept any dimension
+/// and searches are refused until every row is migrated, after which the
+/// indexes are rebuilt with the default parameters. An interrupted run is
+/// resumed by running it again.
+pub async fn reembed(app: &Encrawl, batch_size: i64) -> anyhow::Result<usize> {
+    let model = app.embedding_model();
+    let dim = app.embedding_dim();
+    let resize = store::embedding_column_dim(app.postgres()?).await? != Some(dim);
+    if resize {
+        log::info!("Resizing the embedding columns to {} dimensions", dim);
+        index::drop_indexes(app).await?;
+        for table in index::EMBEDDING_TABLES {
+            sqlx::query(&format!(
+                "ALTER TABLE {table} ALTER COLUMN embedding TYPE vector"
+            ))
+            .execute(app.postgres()?)
+            .await?;
+        }
+    }
+
+    let mut total = 0;
+    loop {
+        let articles: Vec<(i64, String, String, Option<String>)> = sqlx::query_as(
+            "SELECT id, title, content, source FROM articles
+            WHERE embedding_model <> $1 OR embedding_dim <> $2 ORDER BY id LIMIT $3",
+        )
+        .bind(model)
+        .bind(dim as i32)
+        .bind(batch_size.max(1))
+        .fetch_all(app.postgres()?)
+        .await?;
+        if articles.is_empty() {
+            break;
+        }
+        let config = app.config();
+        let texts = articles
+            .iter()
+            .map(|(_, title, content, source)| {
+                let composition = EmbedText::for_source(&config, source.as_deref());
+                (composition, title.as_str(), content.as_str())
+            })
+            .collect::<Vec<_>>();
+        let embeddings = store::embed_articles(app, &texts).await?;
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "UPDATE articles SET embedding = v.embedding, content_embedding = v.content_embedding,
+                embedding_model = ",
+        );
+        query
+            .push_bind(model)
+            .push(", embedding_dim = ")
+            .push_bind(dim as i32)
+            .push(" FROM (");
+        query.push_values(
+            articles.iter().zip(embeddings),
+            |mut row, ((id, _, _, _), (embedding, content_embedding))| {
+                row.push_bind(*id)
+                    .push_bind(pgvector::Vector::from(embedding))
+                    .push_bind(pgvector::Vector::from(content_embedding));
+            },
+        );
+        query.push(") AS v (id, embedding, content_embedding) WHERE articles.id = v.id");
+        query.build().execute(app.postgres()?).await?;
+        let contents = articles
+            .iter()
+            .map(|(id, _, content, _)| (*id, content.as_str()))
+            .collect::<Vec<_>>();
+        store_chunks(app, &contents).await?;
+        let keys = articles
+            .iter()
+            .map(|(id, title, content, source)| {
+                (
+                    *id,
+                    store::configured_key(app, source.as_deref(), title, content),
+                )
+            })
+            .collect::<Vec<_>>();
+        store::mark_embedded(&mut *app.postgres()?.acquire().await?, &keys).await?;
+        total += articles.len();
+        log::info!("Re-embedded {} articles", total);
+    }
+    // Chunks of articles embedded by this model earlier, e.g. by `backfill-chunks`.
+    let stale: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, content FROM articles a WHERE EXISTS (
+            SELECT 1 FROM article_chunks c
+            WHERE c.article_id = a.id AND (c.embedding_model <> $1 OR c.embedding_dim <> $2)
+        )",
+    )
+    .bind(model)
+    .bind(dim as i32)
+    .fetch_all(app.postgres()?)
+    .await?;
+    for batch in stale.chunks(batch_size.max(1) as usize) {
+        let contents = batch
+            .iter()
+            .map(|(id, content)| (*id, content.as_str()))
+            .collect::<Vec<_>>();
+        store_chunks(app, &contents).await?;
+    }
+
+    if resize {
+        for table in index::EMBEDDING_TABLES {
+            sqlx::query(&format!(
+                "ALTER TABLE {table} ALTER COLUMN embedding TYPE vector({dim})"
+            ))
+            .execute(app.postgres()?)
+            .await?;
+        }
+        index::reindex(app, &IndexParams::default()).await?;
+    }
+    if total > 0 {
+        // Centroids of the old model can't be compared with new ones.
+        sqlx::query("DELETE FROM watchlist_centroids")
+            .execute(app.postgres()?)
+            .await?;
+    }
+    Ok(total)
+}
@@ -0,0 +1,395 @@
+//! Analyses run on crawled articles before they are stored, each an
+//! [`Enricher`]. Which ones run, and in what order, is set with `--enrichers`
+//! for all articles and in `[enrich.sources]` of the settings per source, so
+//! crawls only pay for the analysis they need:
+//!
+//! ```toml
+//! [enrich]
+//! enrichers = ["language", "headlines"]
+//!
+//! [enrich.sources]
+//! "r/stocks" = ["language", "tickers", "sentiment"]
+//! ```
+//!
+//! Enrichers other than the language and headline ones leave their results
+//! in [`Article::enrichments`] under their name, stored with the article.
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::LazyLock;
+
+use crate::app::{Config, Encrawl};
+use crate::store::Article;
+use crate::summarise::generate_headlines;
+
+/// Most entities and keywords kept per article.
+const MAX_TERMS: usize = 10;
+
+/// Words that are never keywords or entities on their own.
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "before", "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has",
+    "have", "he", "her", "his", "how", "i", "if", "in", "into", "is", "it", "its", "last", "may",
+    "more", "most", "new", "no", "not", "of", "on", "one", "or", "other", "our", "out", "over",
+    "said", "says", "she", "so", "some", "than", "that", "the", "their", "them", "then", "there",
+    "these", "they", "this", "those", "to", "two", "up", "was", "we", "were", "what", "when",
+    "which", "while", "who", "will", "with", "would", "year", "you",
+];
+
+/// Words that lean a financial text positive.
+const POSITIVE: &[&str] = &[
+    "beat",
+    "beats",
+    "boost",
+    "boosted",
+    "bullish",
+    "gain",
+    "gained",
+    "gains",
+    "growth",
+    "high",
+    "improve",
+    "improved",
+    "jump",
+    "jumped",
+    "outperform",
+    "profit",
+    "profits",
+    "rally",
+    "rallied",
+    "record",
+    "recover",
+    "recovery",
+    "rise",
+    "rises",
+    "rose",
+    "soar",
+    "soared",
+    "strong",
+    "surge",
+    "surged",
+    "upgrade",
+    "upgraded",
+];
+
+/// Words that lean a financial text negative.
+const NEGATIVE: &[&str] = &[
+    "bearish",
+    "collapse",
+    "crash",
+    "crashed",
+    "cut",
+    "cuts",
+    "decline",
+    "declined",
+    "default",
+    "deficit",
+    "downgrade",
+    "downgraded",
+    "drop",
+    "dropped",
+    "fall",
+    "fell",
+    "fraud",
+    "lawsuit",
+    "layoffs",
+    "loss",
+    "losses",
+    "miss",
+    "missed",
+    "plunge",
+    "plunged",
+    "recession",
+    "slump",
+    "tumble",
+    "tumbled",
+    "weak",
+    "weaker",
+];
+
+/// `$AAPL` cashtags and `(NASDAQ: AAPL)` listings.
+static TICKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\$([A-Z]{1,5})\b|\((?i:NYSE|NASDAQ|AMEX|LSE|TSX|OTC)\s*:\s*([A-Z][A-Z.]{0,5})\)")
+        .unwrap()
+});
+
+/// Runs of capitalised words, like `Federal Reserve` or `Jerome Powell`.
+static PROPER_NOUNS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b[A-Z][\w&'.-]*[a-zA-Z](?:\s+(?:of\s+|de\s+)?[A-Z][\w&'.-]*[a-zA-Z]){0,3}\b")
+        .unwrap()
+});
+
+/// An analysis of articles, run on a batch of them at once.
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    /// Name it is configured and its results are stored under.
+    fn name(&self) -> &'static str;
+
+    /// Analyses `articles`, setting their fields or adding their
+    /// [`Article::enrichments`].
+    async fn enrich(&self, app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()>;
+}
+
+/// The enrichers that come with encrawl, as configured.
+#[derive(
+    ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnricherKind {
+    /// The language the article is written in, see [`Language`].
+    Language,
+    /// Headlines of articles without a title, see [`Headlines`].
+    Headlines,
+    /// People, organisations and places named, see [`Entities`].
+    Entities,
+    /// Whether the article reads positive or negative, see [`Sentiment`].
+    Sentiment,
+    /// Stock tickers mentioned, see [`Tickers`].
+    Tickers,
+    /// The words the article is about, see [`Keywords`].
+    Keywords,
+}
+
+impl EnricherKind {
+    /// Enrichers run when none are configured, the ones crawls always ran.
+    pub const DEFAULT: [EnricherKind; 2] = [EnricherKind::Language, EnricherKind::Headlines];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EnricherKind::Language => "language",
+            EnricherKind::Headlines => "headlines",
+            EnricherKind::Entities => "entities",
+            EnricherKind::Sentiment => "sentiment",
+            EnricherKind::Tickers => "tickers",
+            EnricherKind::Keywords => "keywords",
+        }
+    }
+
+    pub fn enricher(self) -> Box<dyn Enricher> {
+        match self {
+            EnricherKind::Language => Box::new(Language),
+            EnricherKind::Headlines => Box::new(Headlines),
+            EnricherKind::Entities => Box::new(Entities),
+            EnricherKind::Sentiment => Box::new(Sentiment),
+            EnricherKind::Tickers => Box::new(Tickers),
+            EnricherKind::Keywords => Box::new(Keywords),
+        }
+    }
+
+    /// The enrichers configured for articles of `source`, in order.
+    pub fn for_source<'a>(config: &'a Config, source: Option<&str>) -> &'a [EnricherKind] {
+        source
+            .and_then(|source| config.enricher_sources.get(source))
+            .unwrap_or(&config.enrichers)
+    }
+}
+
+impl fmt::Display for EnricherKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Runs the enrichers configured for the source of every article in
+/// `articles`, in order. A failing enricher is logged and skipped, leaving
+/// the articles to the next one.
+pub async fn run(app: &Encrawl, articles: &mut [Article]) {
+    let config = app.config();
+    let mut groups = BTreeMap::<&[EnricherKind], Vec<usize>>::new();
+    for (i, article) in articles.iter().enumerate() {
+        let kinds = EnricherKind::for_source(&config, article.source.as_deref());
+        groups.entry(kinds).or_default().push(i);
+    }
+    for (kinds, indices) in groups {
+        if kinds.is_empty() {
+            continue;
+        }
+        let mut group = indices
+            .iter()
+            .map(|&i| std::mem::take(&mut articles[i]))
+            .collect::<Vec<_>>();
+        for kind in kinds {
+            let enricher = kind.enricher();
+            if let Err(e) = enricher.enrich(app, &mut group).await {
+                log::error!("Failed to run the {} enricher: {}", enricher.name(), e);
+            }
+        }
+        for (i, article) in indices.into_iter().zip(group) {
+            articles[i] = article;
+        }
+    }
+}
+
+/// Detects the language of the articles that don't have one, see
+/// [`Article::language`].
+pub struct Language;
+
+#[async_trait]
+impl Enricher for Language {
+    fn name(&self) -> &'static str {
+        "language"
+    }
+
+    async fn enrich(&self, _app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+        for article in articles {
+            article.lang = article.language();
+        }
+        Ok(())
+    }
+}
+
+/// Generates the missing titles, see [`generate_headlines`]. Articles the
+/// generator fails on keep their empty title.
+pub struct Headlines;
+
+#[async_trait]
+impl Enricher for Headlines {
+    fn name(&self) -> &'static str {
+        "headlines"
+    }
+
+    async fn enrich(&self, app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+        generate_headlines(app, articles).await
+    }
+}
+
+/// Names of people, organisations and places, the runs of capitalised words
+/// that aren't just the start of a sentence, most mentioned first.
+pub struct Entities;
+
+#[async_trait]
+impl Enricher for Entities {
+    fn name(&self) -> &'static str {
+        "entities"
+    }
+
+    async fn enrich(&self, _app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+        for article in articles {
+            let text = format!("{}.\n{}", article.title, article.content);
+            let mut counts = HashMap::<&str, usize>::new();
+            for name in PROPER_NOUNS.find_iter(&text) {
+                let single = !name.as_str().contains(char::is_whitespace);
+                let before = text[..name.start()].trim_end();
+                let starts_sentence = before.is_empty() || before.ends_with(['.', '!', '?', '"']);
+                if single && (starts_sentence || is_stopword(name.as_str())) {
+                    continue;
+                }
+                *counts.entry(name.as_str()).or_default() += 1;
+            }
+            article
+                .enrichments
+                .insert(self.name().to_string(), serde_json::json!(top(counts)));
+        }
+        Ok(())
+    }
+}
+
+/// A score from -1, all negative, to 1, all positive, counting the words of
+/// a small financial lexicon.
+pub struct Sentiment;
+
+#[async_trait]
+impl Enricher for Sentiment {
+    fn name(&self) -> &'static str {
+        "sentiment"
+    }
+
+    async fn enrich(&self, _app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+        for article in articles {
+            let (mut positive, mut negative) = (0usize, 0usize);
+            for word in words(&format!("{} {}", article.title, article.content)) {
+                positive += POSITIVE.contains(&word.as_str()) as usize;
+                negative += NEGATIVE.contains(&word.as_str()) as usize;
+            }
+            let score = match positive + negative {
+                0 => 0.0,
+                total => (positive as f64 - negative as f64) / total as f64,
+            };
+            article
+                .enrichments
+                .insert(self.name().to_string(), serde_json::json!(score));
+        }
+        Ok(())
+    }
+}
+
+/// Stock tickers written as cashtags or with their exchange, in order.
+pub struct Tickers;
+
+#[async_trait]
+impl Enricher for Tickers {
+    fn name(&self) -> &'static str {
+        "tickers"
+    }
+
+    async fn enrich(&self, _app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+        for article in articles {
+            let text = format!("{}\n{}", article.title, article.content);
+            let tickers = TICKER
+                .captures_iter(&text)
+                .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+                .map(|ticker| ticker.as_str().to_string())
+                .collect::<BTreeSet<_>>();
+            article
+                .enrichments
+                .insert(self.name().to_string(), serde_json::json!(tickers));
+        }
+        Ok(())
+    }
+}
+
+/// The most frequent words that aren't stopwords, title words counting
+/// thrice.
+pub struct Keywords;
+
+#[async_trait]
+impl Enricher for Keywords {
+    fn name(&self) -> &'static str {
+        "keywords"
+    }
+
+    async fn enrich(&self, _app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+        for article in articles {
+            let mut counts = HashMap::<String, usize>::new();
+            let title = words(&article.title).into_iter().map(|word| (word, 3));
+            let content = words(&article.content).into_iter().map(|word| (word, 1));
+            for (word, weight) in title.chain(content) {
+                if word.chars().count() > 2 && !is_stopword(&word) {
+                    *counts.entry(word).or_default() += weight;
+                }
+            }
+            let keywords = top(counts.iter().map(|(word, count)| (word.as_str(), *count)));
+            article
+                .enrichments
+                .insert(self.name().to_string(), serde_json::json!(keywords));
+        }
+        Ok(())
+    }
+}
+
+/// Lowercased alphabetic words of `text`.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphabetic() && c != '\'')
+        .map(|word| word.trim_matches('\'').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn is_stopword(word: &str) -> bool {
+    STOPWORDS.contains(&word.to_lowercase().as_str())
+}
+
+/// The [`MAX_TERMS`] most counted terms, ties in alphabetical order.
+fn top<'a>(counts: impl IntoIterator<Item = (&'a str, usize)>) -> Vec<String> {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    counts
+        .into_iter()
+        .take(MAX_TERMS)
+        .map(|(term, _)| term.to_string())
+        .collect()
+}
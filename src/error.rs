@@ -0,0 +1,64 @@
+//! Errors of the crawl pipeline, told apart by what failed so a crawl can
+//! record them per URL and carry on.
+
+use serde::{Deserialize, Serialize};
+
+use crate::scrape::ExtractError;
+
+/// Why fetching, extracting or storing an article failed.
+#[derive(Debug, thiserror::Error)]
+pub enum EncrawlError {
+    /// The page could not be downloaded or answered with an error status.
+    #[error("network error: {0:#}")]
+    Network(anyhow::Error),
+    /// The crawl policy or the site's robots.txt disallows the page.
+    #[error("blocked: {0:#}")]
+    Blocked(anyhow::Error),
+    /// None of the configured selectors matched.
+    #[error(transparent)]
+    Extract(#[from] ExtractError),
+    /// The page was downloaded but holds nothing usable.
+    #[error("parse error: {0}")]
+    Parse(String),
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+    /// Embedding failed, or the stored embeddings don't fit the model.
+    #[error("model error: {0:#}")]
+    Model(anyhow::Error),
+}
+
+/// What kind of [`EncrawlError`] an article failed with, as stored in the
+/// `crawl_errors` table.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Network,
+    Blocked,
+    Parse,
+    Db,
+    Model,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Network => "network",
+            ErrorKind::Blocked => "blocked",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Db => "db",
+            ErrorKind::Model => "model",
+        }
+    }
+}
+
+impl EncrawlError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            EncrawlError::Network(_) => ErrorKind::Network,
+            EncrawlError::Blocked(_) => ErrorKind::Blocked,
+            EncrawlError::Extract(_) | EncrawlError::Parse(_) => ErrorKind::Parse,
+            EncrawlError::Db(_) => ErrorKind::Db,
+            EncrawlError::Model(_) => ErrorKind::Model,
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Dates of upcoming events mentioned in articles, e.g. earnings calls or
+//! central bank meetings, exported as an ICS calendar.
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use crate::app::Encrawl;
+use crate::store::{content_hash, Article};
+
+/// Terms a sentence has to contain for a date in it to be taken as an event.
+const EVENT_TERMS: [&str; 17] = [
+    "earnings",
+    "results",
+    "meeting",
+    "conference",
+    "hearing",
+    "vote",
+    "election",
+    "ipo",
+    "listing",
+    "policy decision",
+    "rate decision",
+    "summit",
+    "deadline",
+    "launch",
+    "budget",
+    "auction",
+    "expiry",
+];
+
+/// Longest event summary, the rest of the sentence is cut off.
+const MAX_SUMMARY_CHARS: usize = 200;
+
+const MONTHS: &str = "(jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|june?|july?|aug(?:ust)?|sep(?:t(?:ember)?)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?)";
+
+/// `2024-06-12`
+static ISO_DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").unwrap());
+
+/// `June 12`, `Jun. 12th, 2024`
+static MONTH_DAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b{MONTHS}\.?\s+(\d{{1,2}})(?:st|nd|rd|th)?\b(?:,?\s+(\d{{4}})\b)?"
+    ))
+    .unwrap()
+});
+
+/// `12 June`, `12th of June 2024`
+static DAY_MONTH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b(\d{{1,2}})(?:st|nd|rd|th)?\s+(?:of\s+)?{MONTHS}\b\.?(?:,?\s+(\d{{4}})\b)?"
+    ))
+    .unwrap()
+});
+
+/// A dated event mentioned in an article.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub date: NaiveDate,
+    /// The sentence mentioning the event.
+    pub summary: String,
+    pub article_title: String,
+    pub article_url: String,
+}
+
+/// Events in `article` dated after `today`.
+///
+/// Dates without a year are taken to be the next occurrence after the
+/// article was published.
+pub fn extract(article: &Article, today: NaiveDate) -> Vec<Event> {
+    let written = article
+        .published_at
+        .or(article.fetched_at)
+        .map_or(today, |date| date.date_naive());
+    let mut events = vec![];
+    for sentence in sentences(&article.content) {
+        let lower = sentence.to_lowercase();
+        if !EVENT_TERMS.iter().any(|term| lower.contains(term)) {
+            continue;
+        }
+        for date in dates(sentence, written) {
+            if date > today {
+                events.push(Event {
+                    date,
+                    summary: summary(sentence),
+                    article_title: article.title.clone(),
+                    article_url: article.url.clone(),
+                });
+            }
+        }
+    }
+    events
+}
+
+/// Splits `text` at line breaks and at sentence ends followed by a capital letter.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    for line in text.lines() {
+        let mut start = 0;
+        for (i, c) in line.char_indices() {
+            if !matches!(c, '.' | '!' | '?') {
+                continue;
+            }
+            let rest = &line[i + 1..];
+            let next = rest.trim_start();
+            if next.len() < rest.len() && next.starts_with(char::is_uppercase) {
+                sentences.push(line[start..=i].trim());
+                start = i + 1;
+            }
+        }
+        sentences.push(line[start..].trim());
+    }
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+fn month(name: &str) -> Option<u32> {
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let name = name.to_lowercase();
+    months
+        .iter()
+        .position(|month| name.starts_with(month))
+        .map(|i| i as u32 + 1)
+}
+
+/// Every date in `sentence`, resolving missing years relative to `written`.
+fn dates(sentence: &str, written: NaiveDate) -> Vec<NaiveDate> {
+    let resolve = |year: Option<&str>, month: Option<u32>, day: &str| {
+        let month = month?;
+        let day = day.parse().ok()?;
+        match year {
+            Some(year) => NaiveDate::from_ymd_opt(year.parse().ok()?, month, day),
+            None => {
+                let date = NaiveDate::from_ymd_opt(written.year(), month, day)?;
+                if date < written {
+                    NaiveDate::from_ymd_opt(written.year() + 1, month, day)
+                } else {
+                    Some(date)
+                }
+            }
+        }
+    };
+    let mut dates = vec![];
+    for captures in ISO_DATE.captures_iter(sentence) {
+        dates.extend(resolve(
+            Some(&captures[1]),
+            captures[2].parse().ok(),
+            &captures[3],
+        ));
+    }
+    for captures in MONTH_DAY.captures_iter(sentence) {
+        dates.extend(resolve(
+            captures.get(3).map(|year| year.as_str()),
+            month(&captures[1]),
+            &captures[2],
+        ));
+    }
+    for captures in DAY_MONTH.captures_iter(sentence) {
+        dates.extend(resolve(
+            captures.get(3).map(|year| year.as_str()),
+            month(&captures[2]),
+            &captures[1],
+        ));
+    }
+    dates.sort();
+    dates.dedup();
+    dates
+}
+
+fn summary(sentence: &str) -> String {
+    match sentence.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}…", &sentence[..end]),
+        None => sentence.to_string(),
+    }
+}
+
+/// Upcoming events in the articles published within `lookback`, by date.
+pub async fn upcoming(app: &Encrawl, lookback: chrono::Duration) -> anyhow::Result<Vec<Event>> {
+    let articles: Vec<Article> = sqlx::query_as(
+        "SELECT id, title, content, url, author, annotation, extractor, source, domain, published_at, fetched_at
+        FROM articles WHERE COALESCE(published_at, fetched_at) >= $1",
+    )
+    .bind(Utc::now() - lookback)
+    .fetch_all(app.postgres()?)
+    .await?;
+    let today = Utc::now().date_naive();
+    let mut seen = HashSet::new();
+    let mut events = articles
+        .iter()
+        .flat_map(|article| extract(article, today))
+        .filter(|event| seen.insert((event.date, event.summary.clone())))
+        .collect::<Vec<_>>();
+    events.sort_by_key(|event| event.date);
+    Ok(events)
+}
+
+/// Renders `events` as an iCalendar (RFC 5545) feed of all-day events.
+pub fn to_ics(events: &[Event], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//encrawl//encrawl-rust//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:encrawl events".to_string(),
+    ];
+    let stamp = now.format("%Y%m%dT%H%M%SZ");
+    for event in events {
+        let uid = content_hash(&format!(
+            "{}\n{}\n{}",
+            event.article_url, event.date, event.summary
+        ));
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{uid}@encrawl"),
+            format!("DTSTAMP:{stamp}"),
+            format!("DTSTART;VALUE=DATE:{}", event.date.format("%Y%m%d")),
+            format!(
+                "DTEND;VALUE=DATE:{}",
+                event.date.succ_opt().unwrap_or(event.date).format("%Y%m%d")
+            ),
+            format!("SUMMARY:{}", escape(&event.summary)),
+            format!(
+                "DESCRIPTION:{}",
+                escape(&format!("{}\n{}", event.article_title, event.article_url))
+            ),
+            format!("URL:{}", event.article_url),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// Escapes a TEXT value.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Ends `line` with CRLF, folding it into continuation lines of at most 75 bytes.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
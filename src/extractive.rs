@@ -0,0 +1,115 @@
+//! Summaries made of the articles' own sentences, the ones closest to the
+//! centroid of all of them, for when no text generator can be loaded.
+
+use crate::app::Encrawl;
+use crate::rerank::sentences;
+use crate::store::{cosine_similarity, Article};
+
+/// Backend extractive summaries are stored under.
+pub const EXTRACTIVE_BACKEND: &str = "extractive";
+
+/// Sentences picked for a summary.
+const SUMMARY_SENTENCES: usize = 5;
+
+/// Sentences of every article considered, from its start where news put
+/// what matters most.
+const SENTENCES_PER_ARTICLE: usize = 20;
+
+/// Shorter sentences are mostly captions and bylines.
+const MIN_SENTENCE_WORDS: usize = 6;
+
+/// Sentences at least this similar to one already picked are left out as
+/// saying the same.
+const MAX_SIMILARITY: f32 = 0.85;
+
+/// Picks the [`SUMMARY_SENTENCES`] sentences of `articles` whose embeddings
+/// are closest to the centroid of all of them, skipping near repeats, and
+/// lists them in article order with a link to their article. The summary
+/// says it is extractive.
+pub async fn summarize(app: &Encrawl, articles: &[Article]) -> anyhow::Result<String> {
+    let mut candidates = vec![];
+    for (i, article) in articles.iter().enumerate() {
+        candidates.extend(
+            sentences(&article.content)
+                .into_iter()
+                .filter(|sentence| sentence.split_whitespace().count() >= MIN_SENTENCE_WORDS)
+                .take(SENTENCES_PER_ARTICLE)
+                .enumerate()
+                .map(|(position, sentence)| (i, position, sentence.to_string())),
+        );
+    }
+    let texts = candidates
+        .iter()
+        .map(|(_, _, sentence)| sentence.clone())
+        .collect::<Vec<_>>();
+    let embeddings = app.embed(&texts).await?;
+    let mut picked = pick(&embeddings, SUMMARY_SENTENCES);
+    picked.sort_by_key(|i| (candidates[*i].0, candidates[*i].1));
+
+    let mut summary = "*Extractive summary, no language model was available.*\n\n".to_string();
+    if picked.is_empty() {
+        summary.push_str("The articles have no sentences to quote.");
+    }
+    for i in picked {
+        let (article, _, sentence) = &candidates[i];
+        let article = &articles[*article];
+        summary.push_str(&format!(
+            "{sentence} ([{}]({})) ",
+            article.title, article.url
+        ));
+    }
+    Ok(summary.trim_end().to_string())
+}
+
+/// The `count` sentences of `content` closest to the centroid of its first
+/// ones, skipping near repeats, in the order they are written.
+pub async fn key_sentences(
+    app: &Encrawl,
+    content: &str,
+    count: usize,
+) -> anyhow::Result<Vec<String>> {
+    let candidates = sentences(content)
+        .into_iter()
+        .filter(|sentence| sentence.split_whitespace().count() >= MIN_SENTENCE_WORDS)
+        .take(SENTENCES_PER_ARTICLE)
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Ok(vec![]);
+    }
+    let embeddings = app.embed(&candidates).await?;
+    let mut picked = pick(&embeddings, count);
+    picked.sort();
+    Ok(picked.into_iter().map(|i| candidates[i].clone()).collect())
+}
+
+/// Indices of the up to `count` `embeddings` closest to their centroid, none
+/// [`MAX_SIMILARITY`] similar to another, closest first.
+fn pick(embeddings: &[Vec<f32>], count: usize) -> Vec<usize> {
+    let dim = embeddings.first().map_or(0, Vec::len);
+    let mut centroid = vec![0f32; dim];
+    for embedding in embeddings {
+        for (c, x) in centroid.iter_mut().zip(embedding) {
+            *c += x / embeddings.len() as f32;
+        }
+    }
+
+    let mut ranked = (0..embeddings.len()).collect::<Vec<_>>();
+    ranked.sort_by(|a, b| {
+        cosine_similarity(&embeddings[*b], &centroid)
+            .total_cmp(&cosine_similarity(&embeddings[*a], &centroid))
+    });
+    let mut picked: Vec<usize> = vec![];
+    for i in ranked {
+        if picked.len() >= count {
+            break;
+        }
+        let repeat = picked
+            .iter()
+            .any(|j| cosine_similarity(&embeddings[i], &embeddings[*j]) >= MAX_SIMILARITY);
+        if !repeat {
+            picked.push(i);
+        }
+    }
+    picked
+}
@@ -0,0 +1,250 @@
+//! The links a crawl has yet to scrape. Sources hand their links over as
+//! they are discovered and the scrapers take them in order. Up to a limit
+//! the frontier and the keys of every link seen are kept in memory; past it
+//! both spill over into a temporary SQLite file, so a crawl discovering
+//! hundreds of thousands of links, e.g. of a site's whole sitemap, runs in
+//! bounded memory. Extracted articles don't pile up either way, they are
+//! stored in batches as they come.
+
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::{Pool, QueryBuilder, Sqlite};
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Mutex, Notify};
+
+use crate::candidate::CandidateUrl;
+
+/// Links read back from the spill file at once.
+const SPILL_READ_BATCH: i64 = 256;
+
+/// Rows written to the spill file by a single insert.
+const SPILL_WRITE_BATCH: usize = 500;
+
+/// Numbers the spill files of the process.
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Links waiting to be scraped, without the ones seen before.
+pub struct Frontier {
+    state: Mutex<State>,
+    pushed: Notify,
+    /// Links and seen keys kept in memory before spilling to disk.
+    memory_limit: usize,
+}
+
+struct State {
+    /// Next links to scrape, all of them until the frontier spills.
+    queue: VecDeque<CandidateUrl>,
+    /// Keys of the links seen, until the frontier spills.
+    seen: HashSet<String>,
+    spill: Option<Spill>,
+    /// Set once no more links will be pushed.
+    closed: bool,
+}
+
+impl Frontier {
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                seen: HashSet::new(),
+                spill: None,
+                closed: false,
+            }),
+            pushed: Notify::new(),
+            memory_limit: memory_limit.max(1),
+        }
+    }
+
+    /// Adds the links of `candidates` that weren't seen before and returns
+    /// the ones that were. Spills the frontier to disk once it holds more
+    /// than its memory limit.
+    pub async fn push(&self, candidates: Vec<CandidateUrl>) -> anyhow::Result<Vec<CandidateUrl>> {
+        let mut guard = self.state.lock().await;
+        let state = &mut *guard;
+        let seen_earlier = match &state.spill {
+            Some(spill) => spill.push(candidates).await?,
+            None => {
+                let mut seen_earlier = vec![];
+                for candidate in candidates {
+                    if state.seen.insert(candidate.dedup_key()) {
+                        state.queue.push_back(candidate);
+                    } else {
+                        log::debug!("Skipping {}, found earlier", candidate);
+                        seen_earlier.push(candidate);
+                    }
+                }
+                if state.seen.len() + state.queue.len() > self.memory_limit {
+                    let spill = Spill::create().await?;
+                    log::info!(
+                        "Spilling {} links to crawl to {}",
+                        state.queue.len(),
+                        spill.path.display()
+                    );
+                    spill.absorb(&mut state.seen, &mut state.queue).await?;
+                    state.spill = Some(spill);
+                }
+                seen_earlier
+            }
+        };
+        drop(guard);
+        self.pushed.notify_waiters();
+        Ok(seen_earlier)
+    }
+
+    /// Takes the next link to scrape, waiting for one to be pushed while the
+    /// frontier is empty. Returns `None` once it is empty and closed.
+    pub async fn pop(&self) -> Option<CandidateUrl> {
+        loop {
+            // Created before checking, so a push in between isn't missed.
+            let pushed = self.pushed.notified();
+            {
+                let mut state = self.state.lock().await;
+                if state.queue.is_empty() {
+                    if let Some(spill) = &state.spill {
+                        match spill.pop(SPILL_READ_BATCH).await {
+                            Ok(candidates) => state.queue.extend(candidates),
+                            Err(e) => log::error!("Failed to read links to crawl back: {}", e),
+                        }
+                    }
+                }
+                if let Some(candidate) = state.queue.pop_front() {
+                    return Some(candidate);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            pushed.await;
+        }
+    }
+
+    /// Marks that no more links will be pushed, so [`Self::pop`] returns
+    /// `None` once the frontier is empty.
+    pub async fn close(&self) {
+        self.state.lock().await.closed = true;
+        self.pushed.notify_waiters();
+    }
+}
+
+/// The frontier in a temporary SQLite file, deleted when dropped.
+struct Spill {
+    db: Pool<Sqlite>,
+    path: PathBuf,
+}
+
+impl Spill {
+    async fn create() -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "encrawl-frontier-{}-{}.sqlite",
+            std::process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Off)
+            .synchronous(SqliteSynchronous::Off);
+        // One connection, the frontier's lock serializes access anyway.
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        let spill = Self { db, path };
+        sqlx::query("CREATE TABLE seen (key TEXT PRIMARY KEY) WITHOUT ROWID")
+            .execute(&spill.db)
+            .await?;
+        sqlx::query(
+            "CREATE TABLE queue (seq INTEGER PRIMARY KEY AUTOINCREMENT, candidate TEXT NOT NULL)",
+        )
+        .execute(&spill.db)
+        .await?;
+        Ok(spill)
+    }
+
+    /// Moves the keys of `seen` and the links of `queue` into the file.
+    async fn absorb(
+        &self,
+        seen: &mut HashSet<String>,
+        queue: &mut VecDeque<CandidateUrl>,
+    ) -> anyhow::Result<()> {
+        let keys = seen.drain().collect::<Vec<_>>();
+        for keys in keys.chunks(SPILL_WRITE_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new("INSERT OR IGNORE INTO seen (key) ");
+            query.push_values(keys, |mut row, key| {
+                row.push_bind(key);
+            });
+            query.build().execute(&self.db).await?;
+        }
+        let candidates = queue.drain(..).collect::<Vec<_>>();
+        self.enqueue(&candidates).await
+    }
+
+    /// Adds the links of `candidates` not seen before, returning the others.
+    async fn push(&self, candidates: Vec<CandidateUrl>) -> anyhow::Result<Vec<CandidateUrl>> {
+        let mut new = vec![];
+        let mut seen_earlier = vec![];
+        for candidate in candidates {
+            let inserted = sqlx::query("INSERT OR IGNORE INTO seen (key) VALUES (?)")
+                .bind(candidate.dedup_key())
+                .execute(&self.db)
+                .await?
+                .rows_affected();
+            if inserted == 0 {
+                log::debug!("Skipping {}, found earlier", candidate);
+                seen_earlier.push(candidate);
+            } else {
+                new.push(candidate);
+            }
+        }
+        self.enqueue(&new).await?;
+        Ok(seen_earlier)
+    }
+
+    async fn enqueue(&self, candidates: &[CandidateUrl]) -> anyhow::Result<()> {
+        for candidates in candidates.chunks(SPILL_WRITE_BATCH) {
+            let rows = candidates
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO queue (candidate) ");
+            query.push_values(rows, |mut row, candidate| {
+                row.push_bind(candidate);
+            });
+            query.build().execute(&self.db).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the `limit` links queued first.
+    async fn pop(&self, limit: i64) -> anyhow::Result<Vec<CandidateUrl>> {
+        let rows: Vec<(i64, String)> =
+            sqlx::query_as("SELECT seq, candidate FROM queue ORDER BY seq LIMIT ?")
+                .bind(limit)
+                .fetch_all(&self.db)
+                .await?;
+        let Some((last, _)) = rows.last() else {
+            return Ok(vec![]);
+        };
+        sqlx::query("DELETE FROM queue WHERE seq <= ?")
+            .bind(last)
+            .execute(&self.db)
+            .await?;
+        Ok(rows
+            .iter()
+            .filter_map(|(_, candidate)| {
+                serde_json::from_str(candidate)
+                    .inspect_err(|e| log::error!("Failed to read a link to crawl back: {}", e))
+                    .ok()
+            })
+            .collect())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
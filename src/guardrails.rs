@@ -0,0 +1,93 @@
+//! Checks run on generated text before it is handed out.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Markers of the prompt format that should never show up in an answer.
+const PROMPT_MARKERS: &[&str] = &["User:", "Response:", "Article:"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Guardrails {
+    pub max_chars: usize,
+    /// Require at least one Markdown link, e.g. `[Title](https://...)`.
+    pub require_links: bool,
+    /// Case-insensitive phrases the text may not contain.
+    pub banned_phrases: Vec<String>,
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Self {
+            max_chars: 4000,
+            require_links: true,
+            banned_phrases: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TooLong(usize),
+    MissingLinks,
+    PromptEcho(&'static str),
+    BannedPhrase(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLong(len) => write!(f, "too long ({len} characters)"),
+            Self::MissingLinks => write!(f, "no markdown links"),
+            Self::PromptEcho(marker) => write!(f, "echoes the prompt ({marker:?})"),
+            Self::BannedPhrase(phrase) => write!(f, "contains banned phrase {phrase:?}"),
+        }
+    }
+}
+
+impl Guardrails {
+    /// Reads banned phrases from a file with one phrase per line.
+    pub fn read_banned_phrases(path: &std::path::Path) -> anyhow::Result<Vec<String>> {
+        Ok(std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Returns every rule `text` breaks.
+    pub fn check(&self, text: &str) -> Vec<Violation> {
+        let mut violations = vec![];
+        let len = text.chars().count();
+        if len > self.max_chars {
+            violations.push(Violation::TooLong(len));
+        }
+        if self.require_links && !has_markdown_link(text) {
+            violations.push(Violation::MissingLinks);
+        }
+        violations.extend(
+            PROMPT_MARKERS
+                .iter()
+                .filter(|marker| text.contains(*marker))
+                .map(|marker| Violation::PromptEcho(marker)),
+        );
+        let lower = text.to_lowercase();
+        violations.extend(
+            self.banned_phrases
+                .iter()
+                .filter(|phrase| lower.contains(&phrase.to_lowercase()))
+                .map(|phrase| Violation::BannedPhrase(phrase.clone())),
+        );
+        violations
+    }
+}
+
+fn has_markdown_link(text: &str) -> bool {
+    text.match_indices("](").any(|(i, _)| {
+        text[..i].contains('[')
+            && text[i + 2..]
+                .split(')')
+                .next()
+                .is_some_and(|url| url.starts_with("http"))
+    })
+}
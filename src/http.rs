@@ -0,0 +1,247 @@
+//! Outbound HTTP with per-domain rate limiting and retries.
+
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::chaos::{Chaos, InjectedTimeout, MALFORMED_HTML};
+
+/// User agent sent with every request unless another one is configured.
+pub const USER_AGENT: &str = concat!("encrawl/", env!("CARGO_PKG_VERSION"));
+
+/// Longest a single backoff or `Retry-After` wait may last.
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+
+/// Token bucket per domain, refilled at `rate` requests per second.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> Self {
+        Self::with_burst(rate, rate.max(1.0))
+    }
+
+    /// Allows `burst` requests at once before limiting them to `rate`.
+    pub fn with_burst(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a request to `key` if one is allowed now, without waiting.
+    pub fn try_acquire(&self, key: &str) -> bool {
+        if self.rate <= 0.0 {
+            return true;
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            last: now,
+        });
+        let refill = now.duration_since(bucket.last).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.last = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Waits until a request to `domain` is allowed.
+    pub async fn acquire(&self, domain: &str) {
+        if self.rate <= 0.0 {
+            return;
+        }
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let now = Instant::now();
+            let bucket = buckets.entry(domain.to_string()).or_insert(Bucket {
+                tokens: self.burst,
+                last: now,
+            });
+            let refill = now.duration_since(bucket.last).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.burst) - 1.0;
+            bucket.last = now;
+            // A negative balance reserves a slot in the future for this request.
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / self.rate))
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Cheaply clonable HTTP client shared by everything that talks to the network.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    limiter: Arc<RateLimiter>,
+    max_retries: u32,
+    backoff: Duration,
+    chaos: Option<Arc<Chaos>>,
+}
+
+impl HttpClient {
+    /// Allows `rate` requests per second to every domain, `0` disables the limit,
+    /// and retries failed requests up to `max_retries` times.
+    pub fn new(rate: f64, max_retries: u32, user_agent: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::ClientBuilder::default()
+                .user_agent(user_agent)
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            limiter: Arc::new(RateLimiter::new(rate)),
+            max_retries,
+            backoff: Duration::from_millis(500),
+            chaos: None,
+        })
+    }
+
+    /// Fails requests and malforms pages as `chaos` draws them, see
+    /// [`crate::chaos`].
+    pub fn with_chaos(mut self, chaos: Option<Arc<Chaos>>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn put(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn request(&self, method: reqwest::Method, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    /// Sends `request` once the rate limit of its domain allows it.
+    ///
+    /// Timeouts, connection errors, 429 and 5xx responses are retried with
+    /// exponential backoff, waiting as long as `Retry-After` asks when it is
+    /// given. The last response or error is returned when retries run out.
+    ///
+    /// With chaos, attempts time out and HTML pages come back malformed at
+    /// its rates.
+    pub async fn send(&self, request: RequestBuilder) -> anyhow::Result<Response> {
+        self.send_with(|| {
+            request
+                .try_clone()
+                .ok_or_else(|| anyhow::anyhow!("request with a streaming body can't be retried"))
+        })
+        .await
+    }
+
+    /// Like [`Self::send`], building the request anew for every attempt, so
+    /// requests with a streaming body are retried too.
+    pub async fn send_with(
+        &self,
+        request: impl Fn() -> anyhow::Result<RequestBuilder>,
+    ) -> anyhow::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let req = request()?.build()?;
+            let url = req.url().clone();
+            self.limiter
+                .acquire(url.host_str().unwrap_or_default())
+                .await;
+            let injected = self
+                .chaos
+                .as_ref()
+                .and_then(|chaos| chaos.fetch_timeout(url.as_str()));
+            let result = match injected {
+                Some(timeout) => Err(anyhow::Error::new(timeout)),
+                None => self.client.execute(req).await.map_err(anyhow::Error::from),
+            };
+            let retry_after = match &result {
+                Ok(resp) if is_retryable(resp.status()) => retry_after(resp),
+                Err(e) if is_retryable_error(e) => None,
+                _ => return result.map(|resp| self.malform(resp)),
+            };
+            if attempt >= self.max_retries {
+                return result;
+            }
+            let delay = retry_after
+                .unwrap_or_else(|| self.backoff * 2u32.saturating_pow(attempt))
+                .min(MAX_BACKOFF);
+            match &result {
+                Ok(resp) => log::warn!(
+                    "{} answered {}, retrying in {:?}",
+                    url,
+                    resp.status(),
+                    delay
+                ),
+                Err(e) => log::warn!("Request to {} failed ({}), retrying in {:?}", url, e, delay),
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// `resp` with its body replaced by [`MALFORMED_HTML`] when it is an HTML
+    /// page and chaos draws it to be.
+    fn malform(&self, resp: Response) -> Response {
+        let is_html = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+        let Some(chaos) = &self.chaos else {
+            return resp;
+        };
+        if !resp.status().is_success() || !is_html || !chaos.malformed_html() {
+            return resp;
+        }
+        let mut malformed = http::Response::new(MALFORMED_HTML);
+        *malformed.status_mut() = resp.status();
+        *malformed.headers_mut() = resp.headers().clone();
+        malformed.headers_mut().remove(CONTENT_LENGTH);
+        malformed.into()
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_retryable_error(e: &anyhow::Error) -> bool {
+    e.is::<InjectedTimeout>()
+        || e.downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_timeout() || e.is_connect())
+}
+
+/// Parses `Retry-After` given either in seconds or as an HTTP date.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
@@ -0,0 +1,178 @@
+//! Approximate nearest neighbour indexes over the article and chunk embeddings.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use sqlx::{Pool, Postgres};
+
+use crate::app::Encrawl;
+
+/// Tables with an `embedding` column.
+pub const EMBEDDING_TABLES: [&str; 2] = ["articles", "article_chunks"];
+
+/// Name of the index on the `embedding` column of `table`.
+fn index_name(table: &str) -> String {
+    format!("{table}_embedding_idx")
+}
+
+/// Kind of pgvector index.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexKind {
+    /// Graph index, slower to build but more accurate and fine on an empty table.
+    #[default]
+    Hnsw,
+    /// Clustered index, fast to build but it should be rebuilt once the data changes a lot.
+    Ivfflat,
+}
+
+/// How the embeddings are compressed in the indexes. The table keeps the
+/// full embeddings, searches take candidates from the compressed index and
+/// rank the best of them by their full embeddings again.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quantization {
+    #[default]
+    None,
+    /// Half precision floats, halving the index at almost no loss of recall.
+    Half,
+    /// One bit per dimension, a 32nd of the index but far coarser, so more
+    /// candidates are rescored.
+    Binary,
+}
+
+/// Most dimensions pgvector can index as half precision floats.
+const MAX_HALFVEC_DIM: usize = 4000;
+
+impl Quantization {
+    /// The indexed expression and its operator class for `dim` dimensions.
+    fn indexed(self, dim: usize) -> String {
+        match self {
+            Self::None => "embedding vector_cosine_ops".to_string(),
+            Self::Half => format!("(embedding::halfvec({dim})) halfvec_cosine_ops"),
+            Self::Binary => format!("(binary_quantize(embedding)::bit({dim})) bit_hamming_ops"),
+        }
+    }
+
+    /// Distance between `column` and the vector `query` that the index on
+    /// `column` can order by.
+    pub(crate) fn distance(self, column: &str, query: &str, dim: usize) -> String {
+        match self {
+            Self::None => format!("{column} <=> {query}"),
+            Self::Half => format!("{column}::halfvec({dim}) <=> {query}::halfvec({dim})"),
+            Self::Binary => {
+                format!("binary_quantize({column})::bit({dim}) <~> binary_quantize({query})")
+            }
+        }
+    }
+
+    /// Candidates taken from the index per result that is wanted.
+    pub(crate) fn oversampling(self) -> i32 {
+        match self {
+            Self::None => 1,
+            Self::Half => 2,
+            Self::Binary => 8,
+        }
+    }
+}
+
+/// Parameters the embedding indexes are built with.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct IndexParams {
+    pub kind: IndexKind,
+    #[serde(default)]
+    pub quantization: Quantization,
+    /// Connections per node of an HNSW index.
+    pub m: u32,
+    /// Candidates considered while building an HNSW index.
+    pub ef_construction: u32,
+    /// Clusters of an IVFFlat index, about rows / 1000 up to a million rows.
+    pub lists: u32,
+}
+
+impl Default for IndexParams {
+    fn default() -> Self {
+        Self {
+            kind: IndexKind::Hnsw,
+            quantization: Quantization::None,
+            m: 16,
+            ef_construction: 64,
+            lists: 100,
+        }
+    }
+}
+
+impl IndexParams {
+    fn using(&self, dim: usize) -> String {
+        let indexed = self.quantization.indexed(dim);
+        match self.kind {
+            IndexKind::Hnsw => format!(
+                "hnsw ({indexed}) WITH (m = {}, ef_construction = {})",
+                self.m, self.ef_construction
+            ),
+            IndexKind::Ivfflat => format!("ivfflat ({indexed}) WITH (lists = {})", self.lists),
+        }
+    }
+}
+
+/// Drops and rebuilds the embedding indexes with `params`, e.g. after a bulk
+/// import. Each table is locked while its index is built.
+pub async fn reindex(app: &Encrawl, params: &IndexParams) -> anyhow::Result<()> {
+    match params.kind {
+        IndexKind::Hnsw => anyhow::ensure!(
+            (2..=100).contains(&params.m) && params.ef_construction >= 2 * params.m,
+            "m has to be between 2 and 100 and ef_construction at least twice m"
+        ),
+        IndexKind::Ivfflat => anyhow::ensure!(params.lists >= 1, "lists has to be at least 1"),
+    }
+    let dim = app.embedding_dim();
+    anyhow::ensure!(
+        params.quantization != Quantization::Half || dim <= MAX_HALFVEC_DIM,
+        "half precision indexes take at most {} dimensions, the model has {}",
+        MAX_HALFVEC_DIM,
+        dim
+    );
+    for table in EMBEDDING_TABLES {
+        let index = index_name(table);
+        log::info!(
+            "Rebuilding {} as {:?} with {:?} quantization",
+            index,
+            params.kind,
+            params.quantization
+        );
+        let mut tx = app.postgres()?.begin().await?;
+        sqlx::query(&format!("DROP INDEX IF EXISTS {index}"))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX {index} ON {table} USING {}",
+            params.using(dim)
+        ))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+/// Drops the embedding indexes, e.g. before the dimension of the embeddings changes.
+pub async fn drop_indexes(app: &Encrawl) -> anyhow::Result<()> {
+    for table in EMBEDDING_TABLES {
+        sqlx::query(&format!("DROP INDEX IF EXISTS {}", index_name(table)))
+            .execute(app.postgres()?)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Quantization of the current embedding index, read from its definition.
+pub async fn quantization(db: &Pool<Postgres>) -> anyhow::Result<Quantization> {
+    let definition: Option<(String,)> =
+        sqlx::query_as("SELECT indexdef FROM pg_indexes WHERE indexname = $1")
+            .bind(index_name(EMBEDDING_TABLES[0]))
+            .fetch_optional(db)
+            .await?;
+    Ok(match definition {
+        Some((definition,)) if definition.contains("binary_quantize") => Quantization::Binary,
+        Some((definition,)) if definition.contains("halfvec") => Quantization::Half,
+        _ => Quantization::None,
+    })
+}
@@ -0,0 +1,156 @@
+//! Append-only log of what crawls decided about every URL, kept in the
+//! `events` table so "why isn't this article stored?" can be answered with
+//! `encrawl-rust events --url`. Crawls buffer their events in an [`EventLog`]
+//! and write them out with every batch of articles stored.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::store::canonicalize_url;
+
+/// What happened to a URL.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Not scraped, or not stored, for the reason given in the detail.
+    Skipped,
+    /// The article was extracted, by the extractor given in the detail.
+    Extracted,
+    /// Not stored, the same content or URL already is.
+    Duplicate,
+    /// Stored as a new article.
+    Stored,
+    /// Stored over the article at the same URL.
+    Updated,
+    /// Fetching, extracting or storing failed with the error in the detail.
+    Failed,
+}
+
+impl Action {
+    const ALL: [Action; 6] = [
+        Action::Skipped,
+        Action::Extracted,
+        Action::Duplicate,
+        Action::Stored,
+        Action::Updated,
+        Action::Failed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Skipped => "skipped",
+            Action::Extracted => "extracted",
+            Action::Duplicate => "duplicate",
+            Action::Stored => "stored",
+            Action::Updated => "updated",
+            Action::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.as_str() == name)
+            .ok_or_else(|| format!("unknown event action {name:?}"))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A decision about a URL, as fetched.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    pub url: String,
+    #[sqlx(try_from = "String")]
+    pub action: Action,
+    pub detail: Option<String>,
+    /// The article stored or updated.
+    pub article_id: Option<i64>,
+}
+
+impl Event {
+    pub fn new(url: impl Into<String>, action: Action, detail: Option<String>) -> Self {
+        Self {
+            at: Utc::now(),
+            url: url.into(),
+            action,
+            detail,
+            article_id: None,
+        }
+    }
+}
+
+/// Events recorded but not written to the database yet.
+pub struct EventLog {
+    pending: Mutex<Vec<Event>>,
+    /// Unset without Postgres to write to, when nothing is recorded.
+    enabled: bool,
+}
+
+impl EventLog {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            pending: Mutex::new(vec![]),
+            enabled,
+        }
+    }
+
+    pub fn record(&self, event: Event) {
+        if self.enabled {
+            self.pending.lock().unwrap().push(event);
+        }
+    }
+
+    /// Writes out the events recorded since the last flush.
+    pub async fn flush(&self, db: &Pool<Postgres>) -> anyhow::Result<()> {
+        let events = std::mem::take(&mut *self.pending.lock().unwrap());
+        // Stays below the bind parameter limit with 5 per row.
+        for events in events.chunks(1000) {
+            let mut query = sqlx::QueryBuilder::<Postgres>::new(
+                "INSERT INTO events (at, url, action, detail, article_id) ",
+            );
+            query.push_values(events, |mut row, event| {
+                row.push_bind(event.at)
+                    .push_bind(&event.url)
+                    .push_bind(event.action.as_str())
+                    .push_bind(&event.detail)
+                    .push_bind(event.article_id);
+            });
+            query.build().execute(db).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The last `limit` events of `url`, given as fetched or canonicalised, or of
+/// all URLs without one, oldest first.
+pub async fn query(
+    db: &Pool<Postgres>,
+    url: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<Vec<Event>> {
+    let urls = url.map(|url| vec![url.to_string(), canonicalize_url(url)]);
+    let mut events: Vec<Event> = sqlx::query_as(
+        "SELECT at, url, action, detail, article_id FROM events
+        WHERE $1::text[] IS NULL OR url = ANY($1)
+        ORDER BY id DESC LIMIT $2",
+    )
+    .bind(urls)
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+    events.reverse();
+    Ok(events)
+}
@@ -0,0 +1,303 @@
+//! `init`, which asks a new user for what a first crawl needs, checks it and
+//! writes the settings, the scraper rules and the source lists.
+
+use sqlx::postgres::PgPoolOptions;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::author;
+use crate::embedding::{EmbeddingModel, REMOTE_MODELS};
+use crate::http::{HttpClient, USER_AGENT};
+use crate::reddit::RedditClient;
+use crate::scrape::{ScraperConfig, Selectors};
+use crate::settings::Settings;
+use crate::title::TitleRules;
+
+/// How long connecting to the database may take before it counts as down.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The files `init` writes.
+pub struct InitFiles {
+    pub config: PathBuf,
+    pub scrapers: PathBuf,
+    pub subs: PathBuf,
+    pub feeds: PathBuf,
+}
+
+/// Reads answers from stdin, offering a default in brackets.
+struct Prompter {
+    stdin: std::io::StdinLock<'static>,
+}
+
+impl Prompter {
+    fn ask(&mut self, question: &str, default: Option<&str>) -> anyhow::Result<String> {
+        match default {
+            Some(default) if !default.is_empty() => print!("{question} [{default}]: "),
+            _ => print!("{question}: "),
+        }
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if self.stdin.read_line(&mut answer)? == 0 {
+            anyhow::bail!("stdin closed before init finished");
+        }
+        let answer = answer.trim();
+        Ok(match (answer.is_empty(), default) {
+            (true, Some(default)) => default.to_string(),
+            _ => answer.to_string(),
+        })
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> anyhow::Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.ask(&format!("{question} ({hint})"), None)?;
+            match answer.to_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => println!("Please answer yes or no"),
+            }
+        }
+    }
+}
+
+/// Walks through the database, the Reddit app, the embedding model and the
+/// sites to scrape, checking each answer, and writes `files` once all of
+/// them are given. Existing settings are offered as defaults and existing
+/// scraper rules are kept.
+pub async fn run(files: &InitFiles) -> anyhow::Result<()> {
+    let mut prompter = Prompter {
+        stdin: std::io::stdin().lock(),
+    };
+    println!(
+        "This sets up {}. Press enter to keep the value in brackets.\n",
+        files.config.display()
+    );
+    let mut settings = Settings::read(&files.config)?;
+
+    println!("Postgres needs the pgvector extension installed.");
+    let mut database_url = settings
+        .database_url
+        .clone()
+        .unwrap_or_else(|| "postgres://postgres@localhost/encrawl".to_string());
+    loop {
+        database_url = prompter.ask("Database URL", Some(&database_url))?;
+        match check_database(&database_url).await {
+            Ok(()) => {
+                println!("Connected.\n");
+                break;
+            }
+            Err(e) => {
+                println!("Couldn't use {database_url}: {e}");
+                if !prompter.confirm("Try another URL?", true)? {
+                    break;
+                }
+            }
+        }
+    }
+    settings.database_url = Some(database_url);
+
+    println!(
+        "Subreddits are read with a Reddit app, create a \"script\" one at \
+        https://www.reddit.com/prefs/apps. Leave the id empty to skip this."
+    );
+    loop {
+        let client_id = prompter.ask("Reddit client id", settings.reddit.client_id.as_deref())?;
+        if client_id.is_empty() {
+            println!();
+            break;
+        }
+        let client_secret = prompter.ask(
+            "Reddit client secret, or empty to set ENCRAWL_REDDIT_CLIENT_SECRET instead",
+            settings.reddit.client_secret.as_deref(),
+        )?;
+        settings.reddit.client_id = Some(client_id.clone());
+        settings.reddit.client_secret = Some(client_secret.clone()).filter(|s| !s.is_empty());
+        if client_secret.is_empty() {
+            println!();
+            break;
+        }
+        let http = HttpClient::new(0.0, 0, USER_AGENT)?;
+        match RedditClient::new(http, client_id, client_secret).await {
+            Ok(_) => {
+                println!("Authenticated.\n");
+                break;
+            }
+            Err(e) => {
+                println!("Reddit refused the credentials: {e}");
+                if !prompter.confirm("Enter them again?", true)? {
+                    break;
+                }
+            }
+        }
+    }
+
+    println!("Articles are embedded with one of these models, or one in a local directory:");
+    for (i, name) in REMOTE_MODELS.iter().enumerate() {
+        println!("  {}. {name}", i + 1);
+    }
+    println!("Switching models later means running `re-embed` over every article.");
+    let current = settings
+        .models
+        .embedding
+        .clone()
+        .unwrap_or_else(|| EmbeddingModel::default().to_string());
+    loop {
+        let answer = prompter.ask("Embedding model", Some(&current))?;
+        let answer = match answer.parse::<usize>() {
+            Ok(n) if (1..=REMOTE_MODELS.len()).contains(&n) => REMOTE_MODELS[n - 1].to_string(),
+            _ => answer,
+        };
+        match answer.parse::<EmbeddingModel>() {
+            Ok(model) => {
+                settings.models.embedding = Some(model.to_string());
+                println!();
+                break;
+            }
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    let mut scrapers = if files.scrapers.exists() {
+        ScraperConfig::from_file(files.scrapers.clone())?
+    } else {
+        vec![]
+    };
+    println!(
+        "Sites without a scraper are read with a generic extractor. Starter scrapers \
+        with common selectors can be added for the sites you read most and tuned later \
+        in {}.",
+        files.scrapers.display()
+    );
+    let domains = prompter.ask("Domains to add scrapers for, comma separated", None)?;
+    for domain in domains.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let domain = domain.trim_start_matches("www.").to_string();
+        if scrapers.iter().any(|scraper| scraper.domain == domain) {
+            println!("{domain} already has a scraper");
+            continue;
+        }
+        let scraper = starter_scraper(domain.clone());
+        let sample = prompter.ask(
+            &format!("An article on {domain} to try the selectors on, or empty to skip"),
+            None,
+        )?;
+        if !sample.is_empty() {
+            match try_scraper(&scraper, sample).await {
+                Ok(summary) => println!("{summary}"),
+                Err(e) => {
+                    println!("The starter selectors don't work there yet: {e}");
+                    if !prompter.confirm("Add the scraper anyway?", true)? {
+                        continue;
+                    }
+                }
+            }
+        }
+        scrapers.push(scraper);
+    }
+    println!();
+
+    let subs = if files.subs.exists() {
+        None
+    } else {
+        let subs = prompter.ask("Subreddits to crawl, comma separated", Some("finance"))?;
+        Some(
+            subs.split(',')
+                .map(|sub| sub.trim().trim_start_matches("r/"))
+                .filter(|sub| !sub.is_empty())
+                .map(|sub| format!("{sub}\n"))
+                .collect::<String>(),
+        )
+    };
+
+    let mut table = toml::Table::try_from(&settings)?;
+    table.retain(|_, value| !value.as_table().is_some_and(toml::Table::is_empty));
+    let settings_text = toml::to_string_pretty(&table)?;
+    toml::from_str::<Settings>(&settings_text)
+        .map_err(|e| anyhow::anyhow!("the settings don't read back: {}", e))?;
+    let scrapers_text = ron::ser::to_string_pretty(&scrapers, ron::ser::PrettyConfig::default())?;
+    ron::from_str::<Vec<ScraperConfig>>(&scrapers_text)
+        .map_err(|e| anyhow::anyhow!("the scrapers don't read back: {}", e))?;
+    if files.config.exists()
+        && !prompter.confirm(&format!("Overwrite {}?", files.config.display()), false)?
+    {
+        println!("Nothing written.");
+        return Ok(());
+    }
+
+    std::fs::write(&files.config, settings_text)?;
+    println!("Wrote {}", files.config.display());
+    std::fs::write(&files.scrapers, scrapers_text)?;
+    println!("Wrote {}", files.scrapers.display());
+    if let Some(subs) = subs {
+        std::fs::write(&files.subs, subs)?;
+        println!("Wrote {}", files.subs.display());
+    }
+    if !files.feeds.exists() {
+        std::fs::write(&files.feeds, "# One RSS or Atom feed URL per line\n")?;
+        println!("Wrote {}", files.feeds.display());
+    }
+    println!("\nRun `encrawl-rust crawl` to fetch the first articles.");
+    Ok(())
+}
+
+/// Connects to `url` and checks that pgvector can be installed there.
+async fn check_database(url: &str) -> anyhow::Result<()> {
+    let db = PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(CONNECT_TIMEOUT)
+        .connect(url)
+        .await?;
+    let (available,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector')",
+    )
+    .fetch_one(&db)
+    .await?;
+    db.close().await;
+    if !available {
+        anyhow::bail!("the pgvector extension isn't installed on the server");
+    }
+    Ok(())
+}
+
+/// A scraper for `domain` with the selectors most news sites match.
+fn starter_scraper(domain: String) -> ScraperConfig {
+    let selectors =
+        |selectors: &[&str]| Selectors::Many(selectors.iter().map(|s| s.to_string()).collect());
+    ScraperConfig {
+        domain,
+        author_selector: selectors(author::GENERIC_SELECTORS),
+        content_selector: selectors(&["article p", "main p", "[itemprop='articleBody'] p"]),
+        title_selector: selectors(&["article h1", "h1"]),
+        published_selector: None,
+        annotation: None,
+        sitemap_url: None,
+        index_url: None,
+        link_selector: None,
+        render: false,
+        license: None,
+        title_rules: TitleRules::default(),
+    }
+}
+
+/// Extracts `url` with `scraper`, describing what it found.
+async fn try_scraper(scraper: &ScraperConfig, url: String) -> anyhow::Result<String> {
+    let http = HttpClient::new(0.0, 0, USER_AGENT)?;
+    let html = http
+        .send(http.get(&url))
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let article = scraper.extract(url, &html)?;
+    Ok(format!(
+        "Found \"{}\" by {}, {} words",
+        article.title,
+        if article.author.is_empty() {
+            "no author"
+        } else {
+            &article.author
+        },
+        article.content.split_whitespace().count()
+    ))
+}
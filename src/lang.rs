@@ -0,0 +1,171 @@
+//! Guessing the language articles are written in, from the script of their
+//! letters and, for Latin script, from how often each language's most common
+//! words occur.
+
+use sqlx::{Pool, Postgres};
+
+/// Most common words of the Latin script languages told apart, which are
+/// rarely words in the others.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "in", "that", "for", "with", "was", "on", "are",
+            "this", "by", "have", "from", "which", "will", "has", "it",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "und", "das", "ist", "nicht", "mit", "den", "ein", "eine", "auf", "sich",
+            "auch", "dem", "wird", "werden", "zu", "von", "im", "für",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "des", "est", "une", "du", "dans", "qui", "que", "pour",
+            "pas", "sur", "au", "avec", "sont", "ce", "par", "plus",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "del", "que", "en", "por", "con", "una", "para", "es", "se",
+            "su", "como", "más", "pero", "al", "lo", "fue",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "e", "della", "per", "non", "sono", "gli", "una", "nel", "anche",
+            "delle", "alla", "ha", "dei", "come", "più", "questo", "lo",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "da", "do", "que", "em", "não", "uma", "para", "com", "por", "as", "dos",
+            "mais", "das", "foi", "ao", "são", "à", "seu",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "van", "en", "niet", "dat", "is", "op", "voor", "met", "zijn",
+            "ook", "aan", "wordt", "bij", "er", "naar", "om", "dit",
+        ],
+    ),
+    (
+        "sv",
+        &[
+            "och", "att", "det", "som", "är", "på", "för", "med", "inte", "av", "till", "den",
+            "har", "en", "ett", "om", "var", "de", "men", "vi",
+        ],
+    ),
+    (
+        "pl",
+        &[
+            "i", "w", "się", "na", "nie", "z", "do", "że", "jest", "to", "o", "jak", "ale", "po",
+            "od", "przez", "dla", "są", "jego", "oraz",
+        ],
+    ),
+];
+
+/// Fewest common words that have to be found before a Latin script language is named.
+const MIN_STOPWORDS: usize = 3;
+
+/// Languages with a script of their own, by the first and last code point of its main block.
+const SCRIPTS: &[(&str, char, char)] = &[
+    ("ru", '\u{0400}', '\u{04FF}'),
+    ("el", '\u{0370}', '\u{03FF}'),
+    ("he", '\u{0590}', '\u{05FF}'),
+    ("ar", '\u{0600}', '\u{06FF}'),
+    ("hi", '\u{0900}', '\u{097F}'),
+    ("th", '\u{0E00}', '\u{0E7F}'),
+    ("ko", '\u{AC00}', '\u{D7AF}'),
+    ("ja", '\u{3040}', '\u{30FF}'),
+    ("zh", '\u{4E00}', '\u{9FFF}'),
+];
+
+/// ISO 639-1 code of the language `text` is most likely written in, `None`
+/// when it is too short or unlike any of the languages known here.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut scripts = [0usize; SCRIPTS.len()];
+    let mut latin = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        if c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c) {
+            latin += 1;
+        } else if let Some(i) = SCRIPTS
+            .iter()
+            .position(|(_, first, last)| (*first..=*last).contains(&c))
+        {
+            scripts[i] += 1;
+        }
+    }
+    let (script, count) = scripts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, count)| **count)
+        .map(|(i, count)| (SCRIPTS[i].0, *count))?;
+    if count > latin {
+        // Japanese mixes kana with Chinese characters, a little kana is enough.
+        let kana = scripts[SCRIPTS.iter().position(|(lang, ..)| *lang == "ja")?];
+        return Some(if kana > 0 && script == "zh" {
+            "ja"
+        } else {
+            script
+        });
+    }
+
+    let mut hits = [0usize; STOPWORDS.len()];
+    for word in text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .take(2000)
+    {
+        let word = word.to_lowercase();
+        for (i, (_, words)) in STOPWORDS.iter().enumerate() {
+            if words.contains(&word.as_str()) {
+                hits[i] += 1;
+            }
+        }
+    }
+    let (i, hits) = hits.iter().enumerate().max_by_key(|(_, hits)| **hits)?;
+    (*hits >= MIN_STOPWORDS).then_some(STOPWORDS[i].0)
+}
+
+/// Detects the language of the articles stored without one, `batch_size` at
+/// a time. Returns the number of articles whose language was detected.
+pub async fn backfill(db: &Pool<Postgres>, batch_size: i64) -> anyhow::Result<usize> {
+    let mut last_id = 0;
+    let mut total = 0;
+    loop {
+        let articles: Vec<(i64, String, String)> = sqlx::query_as(
+            "SELECT id, title, content FROM articles WHERE id > $1 AND lang IS NULL
+            ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(batch_size.max(1))
+        .fetch_all(db)
+        .await?;
+        let Some((id, ..)) = articles.last() else {
+            return Ok(total);
+        };
+        last_id = *id;
+        let (ids, langs): (Vec<i64>, Vec<&str>) = articles
+            .iter()
+            .filter_map(|(id, title, content)| Some((*id, detect(&format!("{title}\n{content}"))?)))
+            .unzip();
+        sqlx::query(
+            "UPDATE articles SET lang = d.lang FROM unnest($1::bigint[], $2::text[]) AS d(id, lang)
+            WHERE articles.id = d.id",
+        )
+        .bind(&ids)
+        .bind(&langs)
+        .execute(db)
+        .await?;
+        total += ids.len();
+        log::info!("Detected the language of {} articles", total);
+    }
+}
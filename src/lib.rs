@@ -1 +1,93 @@
+//! Crawls news linked from Reddit, feeds and other sources into Postgres,
+//! and searches and summarises it.
+//!
+//! Everything is `async` and runs on the caller's Tokio runtime, the models
+//! on its blocking threads:
+//!
+//! ```no_run
+//! use encrawl_rust::{crawl, report, Config, Encrawl};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let config = Config::load(
+//!     "scrapers.ron".into(),
+//!     "finance_subs.list".into(),
+//!     "feeds.list".into(),
+//!     "sources.ron".into(),
+//!     "sinks.ron".into(),
+//!     "policy.ron".into(),
+//! )?;
+//! let app = Encrawl::new("postgres://localhost/encrawl", None, config).await?;
+//! let report = crawl::run(&app, None).await;
+//! report::save(app.postgres()?, &report).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod app;
+pub mod ask;
+pub mod author;
+pub mod bootstrap;
+pub mod breaking;
+pub mod candidate;
+pub mod chaos;
+pub mod check;
+pub mod chunk;
+pub mod corpus;
+pub mod crawl;
+pub mod daemon;
+pub mod device;
+pub mod digest;
+pub mod embed_queue;
+pub mod embed_text;
+pub mod embedding;
+pub mod enrich;
+pub mod error;
+pub mod events;
+pub mod extractive;
+pub mod frontier;
+pub mod guardrails;
+pub mod http;
+pub mod index;
+pub mod ingest;
+pub mod init;
+pub mod lang;
 pub mod mamba;
+pub mod metrics;
+pub mod notify;
+pub mod openai;
+pub mod page_cache;
+pub mod pipeline;
+pub mod policy;
+pub mod publish;
+pub mod reddit;
+pub mod render;
+pub mod report;
+pub mod rerank;
+pub mod robots;
+pub mod routing;
+pub mod schedule;
+pub mod scrape;
+pub mod server;
+pub mod settings;
+pub mod share;
+pub mod simulate;
+pub mod sink;
+pub mod site;
+pub mod source;
+pub mod sqlite;
+pub mod stats;
+pub mod store;
+pub mod summaries;
+pub mod summarise;
+pub mod telegram;
+pub mod title;
+pub mod topics;
+pub mod usage;
+pub mod watchlist;
+
+pub use app::{Config, Encrawl};
+pub use error::{EncrawlError, ErrorKind};
+pub use reddit::RedditClient;
+pub use scrape::ScraperConfig;
+pub use store::{search, search_filtered, Article, SearchFilter, Stored};
+pub use summarise::{Summarisable, Summarizer};
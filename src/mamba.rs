@@ -9,20 +9,55 @@ use clap::{Parser, ValueEnum};
 
 use candle_transformers::models::mamba::{Config, Model, State};
 
-use candle_core::{DType, Device, Tensor};
+use candle_core::{Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::generation::LogitsProcessor;
 use hf_hub::{api::sync::Api, Repo, RepoType};
 use tokenizers::Tokenizer;
 
+use crate::device::{self, ComputeDType, ComputeDevice};
+use crate::summarise::Summarizer;
+
+/// Number of tokens consumed and produced by a generation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// Token counts and timing of a generation.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerationStats {
+    pub usage: TokenUsage,
+    /// Time spent generating, for the local model without processing the prompt.
+    pub elapsed: std::time::Duration,
+}
+
+impl GenerationStats {
+    pub fn tokens_per_second(&self) -> f64 {
+        self.usage.completion_tokens as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
 pub struct TextGeneration {
     model: Model,
     config: Config,
     device: Device,
     tokenizer: Tokenizer,
     logits_processor: LogitsProcessor,
+    seed: u64,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
     repeat_penalty: f32,
     repeat_last_n: usize,
+    last_stats: GenerationStats,
 }
 
 impl TextGeneration {
@@ -44,14 +79,38 @@ impl TextGeneration {
             config,
             tokenizer,
             logits_processor,
+            seed,
+            temperature: temp,
+            top_p,
             repeat_penalty,
             repeat_last_n,
             device: device.clone(),
+            last_stats: GenerationStats::default(),
         }
     }
+}
+
+impl Summarizer for TextGeneration {
+    fn sampling(&self) -> (u64, Option<f64>) {
+        (self.seed, self.temperature)
+    }
 
-    pub fn run(&mut self, prompt: &str, sample_len: usize) -> Result<String> {
-        use std::io::Write;
+    fn set_sampling(&mut self, seed: u64, temperature: Option<f64>) {
+        self.seed = seed;
+        self.temperature = temperature;
+        self.logits_processor = LogitsProcessor::new(seed, temperature, self.top_p);
+    }
+
+    fn last_stats(&self) -> GenerationStats {
+        self.last_stats
+    }
+
+    fn run_stream(
+        &mut self,
+        prompt: &str,
+        sample_len: usize,
+        on_text: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<GenerationStats> {
         let dtype = self.model.dtype();
         let mut tokens = self
             .tokenizer
@@ -73,6 +132,8 @@ impl TextGeneration {
             next_logits = Some(logits);
         }
 
+        let prompt_len = tokens.len();
+        let mut emitted = 0usize;
         let start_gen = std::time::Instant::now();
         for _ in 0..sample_len {
             let logits = match next_logits.as_ref() {
@@ -96,25 +157,31 @@ impl TextGeneration {
             if next_token == *eos_token {
                 break;
             }
+            let text = self
+                .tokenizer
+                .decode(&tokens[prompt_len..], true)
+                .map_err(E::msg)?;
+            if let Some(new) = text.get(emitted..).filter(|new| !new.is_empty()) {
+                on_text(new)?;
+                emitted = text.len();
+            }
 
             let input = Tensor::new(&[next_token], &self.device)?;
             next_logits = Some(self.model.forward(&input, &mut state)?)
         }
-        let dt = start_gen.elapsed();
-        std::io::stdout().flush()?;
-        println!(
-            "\n{generated_tokens} tokens generated ({:.2} token/s)",
-            generated_tokens as f64 / dt.as_secs_f64(),
-        );
-        Ok(self
-            .tokenizer
-            .decode(tokens.as_slice(), true)
-            .map_err(|_| std::fmt::Error::default())?)
+        self.last_stats = GenerationStats {
+            usage: TokenUsage {
+                prompt_tokens: prompt_len,
+                completion_tokens: generated_tokens,
+            },
+            elapsed: start_gen.elapsed(),
+        };
+        Ok(self.last_stats)
     }
 }
 
 #[derive(Parser, ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
-enum Which {
+pub enum Which {
     Mamba130m,
     Mamba370m,
     Mamba790m,
@@ -153,76 +220,85 @@ impl Which {
     }
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Run on CPU rather than on GPU.
-    #[arg(long)]
-    cpu: bool,
-
-    /// Enable tracing (generates a trace-timestamp.json file).
-    #[arg(long)]
-    tracing: bool,
+/// How the text generator is loaded and samples, see [`init`].
+#[derive(clap::Args, Clone, Debug)]
+pub struct InitConfig {
+    /// Device text generation runs on, the CPU when it isn't available
+    #[arg(long = "generation-device", global = true, value_enum, default_value_t = ComputeDevice::Cpu)]
+    pub device: ComputeDevice,
 
-    #[arg(long)]
-    prompt: String,
+    /// Type the generation model's weights are loaded as, f16 and bf16 need a GPU
+    #[arg(long = "generation-dtype", global = true, value_enum, default_value_t = ComputeDType::Auto)]
+    pub dtype: ComputeDType,
 
-    /// The temperature used to generate samples.
-    #[arg(long)]
-    temperature: Option<f64>,
+    /// Size of the Mamba model used for generation
+    #[arg(long = "generation-model", global = true, value_enum, default_value_t = Which::Mamba2_8bSlimPj)]
+    pub which: Which,
 
-    /// Nucleus sampling probability cutoff.
-    #[arg(long)]
-    top_p: Option<f64>,
+    /// Hugging Face repository to load instead of the one of `--generation-model`
+    #[arg(long = "generation-model-id", global = true)]
+    pub model_id: Option<String>,
 
-    /// The seed to use when generating random samples.
-    #[arg(long, default_value_t = 299792458)]
-    seed: u64,
+    /// Revision of the repository
+    #[arg(long = "generation-revision", global = true)]
+    pub revision: Option<String>,
 
-    /// The length of the sample to generate (in tokens).
-    #[arg(long, short = 'n', default_value_t = 5000)]
-    sample_len: usize,
+    /// The temperature used to generate samples, greedy when unset
+    #[arg(long = "generation-temperature", global = true)]
+    pub temperature: Option<f64>,
 
-    #[arg(long, default_value = "mamba130m")]
-    which: Which,
+    /// Nucleus sampling probability cutoff
+    #[arg(long, global = true)]
+    pub top_p: Option<f64>,
 
-    #[arg(long)]
-    model_id: Option<String>,
+    /// The seed to use when generating random samples
+    #[arg(long, global = true, default_value_t = 299792458)]
+    pub seed: u64,
 
-    #[arg(long)]
-    revision: Option<String>,
+    /// Penalty to be applied for repeating tokens, 1. means no penalty
+    #[arg(long, global = true, default_value_t = 1.1)]
+    pub repeat_penalty: f32,
 
-    #[arg(long)]
-    tokenizer_file: Option<String>,
-
-    #[arg(long)]
-    weight_files: Option<String>,
-
-    #[arg(long)]
-    config_file: Option<String>,
-
-    #[arg(long, default_value = "f32")]
-    dtype: String,
-
-    /// Penalty to be applied for repeating tokens, 1. means no penalty.
-    #[arg(long, default_value_t = 1.1)]
-    repeat_penalty: f32,
-
-    /// The context size to consider for the repeat penalty.
-    #[arg(long, default_value_t = 64)]
-    repeat_last_n: usize,
+    /// The context size to consider for the repeat penalty
+    #[arg(long, global = true, default_value_t = 64)]
+    pub repeat_last_n: usize,
 }
 
-pub fn init() -> Result<TextGeneration> {
-    use std::str::FromStr;
+impl Default for InitConfig {
+    fn default() -> Self {
+        Self {
+            device: ComputeDevice::Cpu,
+            dtype: ComputeDType::Auto,
+            which: Which::Mamba2_8bSlimPj,
+            model_id: None,
+            revision: None,
+            temperature: None,
+            top_p: None,
+            seed: 299792458,
+            repeat_penalty: 1.1,
+            repeat_last_n: 64,
+        }
+    }
+}
 
+impl InitConfig {
+    /// Hugging Face repository and revision the weights are loaded from.
+    pub fn repo(&self) -> (String, String) {
+        (
+            self.model_id
+                .clone()
+                .unwrap_or_else(|| self.which.model_id().to_string()),
+            self.revision
+                .clone()
+                .unwrap_or_else(|| self.which.revision().to_string()),
+        )
+    }
+}
 
+pub fn init(config: InitConfig) -> Result<TextGeneration> {
     let api = Api::new()?;
-    let repo = api.repo(Repo::with_revision(
-        Which::Mamba2_8bSlimPj.model_id().to_string(),
-        RepoType::Model,
-        Which::Mamba2_8bSlimPj.revision().to_string(),
-    ));
+    let (model_id, revision) = config.repo();
+    let repo = api.repo(Repo::with_revision(model_id, RepoType::Model, revision));
     let tokenizer_filename = api
         .model("EleutherAI/gpt-neox-20b".to_string())
         .get("tokenizer.json")?;
@@ -230,23 +306,20 @@ pub fn init() -> Result<TextGeneration> {
     let filenames = repo.get("model.safetensors")?;
     let tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
 
-    let config: Config = serde_json::from_slice(&std::fs::read(config_filename)?)?;
-    let device = Device::Cpu;
-    let dtype = DType::from_str("f32")?;
+    let model_config: Config = serde_json::from_slice(&std::fs::read(config_filename)?)?;
+    let (device, dtype) = device::select("text generator", config.device, config.dtype);
     let vb = unsafe { VarBuilder::from_mmaped_safetensors(&vec![filenames], dtype, &device)? };
-    let model = Model::new(&config, vb.pp("backbone"))?;
+    let model = Model::new(&model_config, vb.pp("backbone"))?;
 
     Ok(TextGeneration::new(
         model,
-        config,
+        model_config,
         tokenizer,
-        299792458,
-        None,
-        None,
-        1.1,
-        64,
+        config.seed,
+        config.temperature,
+        config.top_p,
+        config.repeat_penalty,
+        config.repeat_last_n,
         &device,
     ))
 }
-
-
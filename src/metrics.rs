@@ -0,0 +1,236 @@
+//! Counters of what crawls do, kept in memory for as long as the process
+//! runs and rendered in the Prometheus text format for `/metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::store::Stored;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations in each of [`LATENCY_BUCKETS`], not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {}", self.count);
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    posts: BTreeMap<String, u64>,
+    /// By domain and `success` or `failure`.
+    scrapes: BTreeMap<(String, &'static str), u64>,
+    /// By `new`, `updated`, `duplicate` or `failed`.
+    articles: BTreeMap<&'static str, u64>,
+    embedded_texts: u64,
+    embedding: Histogram,
+    /// By table.
+    inserted_rows: BTreeMap<&'static str, u64>,
+    inserts: BTreeMap<&'static str, Histogram>,
+    crawl_runs: u64,
+    last_crawl: Option<SystemTime>,
+}
+
+/// Embedding and insert work done since the process started, so the share of
+/// a crawl can be told by subtracting the totals from before it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WorkTotals {
+    pub embedded_texts: u64,
+    pub embed_secs: f64,
+    pub insert_secs: f64,
+}
+
+/// Metrics shared by everything holding the same [`crate::Encrawl`].
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+/// Escapes a label value as the text format requires.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// Counts the posts fetched from `source` that link to articles.
+    pub fn posts_fetched(&self, source: &str, posts: usize) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.posts.entry(source.to_string()).or_default() += posts as u64;
+    }
+
+    pub fn scraped(&self, domain: &str, success: bool) {
+        let result = if success { "success" } else { "failure" };
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .scrapes
+            .entry((domain.to_string(), result))
+            .or_default() += 1;
+    }
+
+    /// Counts what storing an article did, `None` when it failed.
+    pub fn stored(&self, stored: Option<Stored>) {
+        let result = match stored {
+            Some(Stored::New(_)) => "new",
+            Some(Stored::Updated(_)) => "updated",
+            Some(Stored::Duplicate) => "duplicate",
+            None => "failed",
+        };
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .articles
+            .entry(result)
+            .or_default() += 1;
+    }
+
+    /// Records one call to the embedding model with `texts` texts.
+    pub fn embedded(&self, texts: usize, elapsed: Duration) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.embedded_texts += texts as u64;
+        counters.embedding.observe(elapsed.as_secs_f64());
+    }
+
+    /// Records one insert of `rows` rows into `table`.
+    pub fn inserted(&self, table: &'static str, rows: usize, elapsed: Duration) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.inserted_rows.entry(table).or_default() += rows as u64;
+        counters
+            .inserts
+            .entry(table)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn crawl_finished(&self) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.crawl_runs += 1;
+        counters.last_crawl = Some(SystemTime::now());
+    }
+
+    pub fn work_totals(&self) -> WorkTotals {
+        let counters = self.counters.lock().unwrap();
+        WorkTotals {
+            embedded_texts: counters.embedded_texts,
+            embed_secs: counters.embedding.sum,
+            insert_secs: counters.inserts.values().map(|inserts| inserts.sum).sum(),
+        }
+    }
+
+    /// Everything counted so far in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# HELP encrawl_posts_fetched_total Posts linking to articles fetched from each source.\n");
+        out.push_str("# TYPE encrawl_posts_fetched_total counter\n");
+        for (source, count) in &counters.posts {
+            let _ = writeln!(
+                out,
+                "encrawl_posts_fetched_total{{source=\"{}\"}} {count}",
+                label(source)
+            );
+        }
+        out.push_str("# HELP encrawl_scrapes_total Pages scraped from each domain, by result.\n");
+        out.push_str("# TYPE encrawl_scrapes_total counter\n");
+        for ((domain, result), count) in &counters.scrapes {
+            let _ = writeln!(
+                out,
+                "encrawl_scrapes_total{{domain=\"{}\",result=\"{result}\"}} {count}",
+                label(domain)
+            );
+        }
+        out.push_str("# HELP encrawl_articles_total Scraped articles by what storing them did.\n");
+        out.push_str("# TYPE encrawl_articles_total counter\n");
+        for (result, count) in &counters.articles {
+            let _ = writeln!(out, "encrawl_articles_total{{result=\"{result}\"}} {count}");
+        }
+        out.push_str("# HELP encrawl_embedded_texts_total Texts embedded.\n");
+        out.push_str("# TYPE encrawl_embedded_texts_total counter\n");
+        let _ = writeln!(
+            out,
+            "encrawl_embedded_texts_total {}",
+            counters.embedded_texts
+        );
+        out.push_str(
+            "# HELP encrawl_embedding_seconds Time taken by each call to the embedding model.\n",
+        );
+        out.push_str("# TYPE encrawl_embedding_seconds histogram\n");
+        counters
+            .embedding
+            .render(&mut out, "encrawl_embedding_seconds", "");
+        out.push_str("# HELP encrawl_inserted_rows_total Rows inserted into each table.\n");
+        out.push_str("# TYPE encrawl_inserted_rows_total counter\n");
+        for (table, count) in &counters.inserted_rows {
+            let _ = writeln!(
+                out,
+                "encrawl_inserted_rows_total{{table=\"{table}\"}} {count}"
+            );
+        }
+        out.push_str("# HELP encrawl_insert_seconds Time taken by each insert into a table.\n");
+        out.push_str("# TYPE encrawl_insert_seconds histogram\n");
+        for (table, histogram) in &counters.inserts {
+            histogram.render(
+                &mut out,
+                "encrawl_insert_seconds",
+                &format!("table=\"{table}\""),
+            );
+        }
+        out.push_str("# HELP encrawl_crawl_runs_total Crawl runs finished.\n");
+        out.push_str("# TYPE encrawl_crawl_runs_total counter\n");
+        let _ = writeln!(out, "encrawl_crawl_runs_total {}", counters.crawl_runs);
+        if let Some(last) = counters.last_crawl {
+            let secs = last.duration_since(UNIX_EPOCH).unwrap_or_default();
+            out.push_str(
+                "# HELP encrawl_last_crawl_timestamp_seconds When the last crawl run finished.\n",
+            );
+            out.push_str("# TYPE encrawl_last_crawl_timestamp_seconds gauge\n");
+            let _ = writeln!(
+                out,
+                "encrawl_last_crawl_timestamp_seconds {}",
+                secs.as_secs_f64()
+            );
+        }
+        out
+    }
+}
@@ -0,0 +1,152 @@
+//! Outbound notifications, batched so a burst of hits becomes one message per sink.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::app::Encrawl;
+use crate::sink::{self, ConfiguredSink};
+
+/// Something worth telling the user about, e.g. a watchlist hit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub title: String,
+    pub url: String,
+    /// Why the article was reported, e.g. the watch term it matched.
+    pub reason: String,
+    /// Watch term the article matched, used by push sinks to pick a topic and priority.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchlist: Option<String>,
+}
+
+/// Collects notifications for `window` after the first one arrives and then
+/// delivers them to every sink as a single combined digest.
+///
+/// Every sink is flushed by its own task, so a slow or strictly limited sink
+/// only delays its own messages. Sinks without a
+/// [`ConfiguredSink::min_interval`] get at most one message per `min_interval`.
+pub struct Batcher {
+    senders: Vec<mpsc::UnboundedSender<Notification>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Batcher {
+    pub fn spawn(
+        app: &Encrawl,
+        topic: String,
+        window: Duration,
+        min_interval: Duration,
+        sinks: Vec<ConfiguredSink>,
+    ) -> Self {
+        let (senders, tasks) = sinks
+            .into_iter()
+            .map(|sink| {
+                let (tx, rx) = mpsc::unbounded_channel();
+                let min_interval = sink.min_interval.unwrap_or(min_interval);
+                let batches = Batches {
+                    app: app.clone(),
+                    topic: topic.clone(),
+                    window,
+                    min_interval,
+                };
+                (tx, tokio::spawn(batches.flush(sink, rx)))
+            })
+            .unzip();
+        Self { senders, tasks }
+    }
+
+    /// Queues `notification` for every sink.
+    pub fn push(&self, notification: Notification) {
+        for sender in &self.senders {
+            // The task only stops once the sender is dropped.
+            let _ = sender.send(notification.clone());
+        }
+    }
+
+    /// Sends whatever is still queued and waits for the sinks to finish.
+    pub async fn shutdown(self) {
+        drop(self.senders);
+        for task in self.tasks {
+            if let Err(e) = task.await {
+                log::error!("Notification task failed: {}", e);
+            }
+        }
+    }
+}
+
+struct Batches {
+    app: Encrawl,
+    topic: String,
+    window: Duration,
+    min_interval: Duration,
+}
+
+impl Batches {
+    async fn flush(self, sink: ConfiguredSink, mut rx: mpsc::UnboundedReceiver<Notification>) {
+        let mut last_sent: Option<Instant> = None;
+        while let Some(first) = rx.recv().await {
+            let mut batch = vec![first];
+            let mut deadline = Instant::now() + self.window;
+            if let Some(last_sent) = last_sent {
+                deadline = deadline.max(last_sent + self.min_interval);
+            }
+            let sleep = tokio::time::sleep_until(deadline);
+            tokio::pin!(sleep);
+            let closed = loop {
+                tokio::select! {
+                    _ = &mut sleep => break false,
+                    notification = rx.recv() => match notification {
+                        Some(notification) => batch.push(notification),
+                        None => break true,
+                    },
+                }
+            };
+            self.send(&sink, &batch).await;
+            last_sent = Some(Instant::now());
+            if closed {
+                break;
+            }
+        }
+    }
+
+    /// Saves `batch` as a digest and delivers it, so failures end up in the
+    /// delivery log.
+    async fn send(&self, sink: &ConfiguredSink, batch: &[Notification]) {
+        let result = async {
+            let mut digest = sink::save(
+                self.app.postgres()?,
+                &self.topic,
+                &format_batch(batch),
+                None,
+            )
+            .await?;
+            digest.events = batch.to_vec();
+            sink::deliver(&self.app, &digest, std::slice::from_ref(sink)).await
+        };
+        if let Err(e) = result.await {
+            log::error!(
+                "Failed to send {} notifications to {}: {}",
+                batch.len(),
+                sink.sink.name(),
+                e
+            );
+        }
+    }
+}
+
+/// Renders `batch` as a markdown list of links.
+pub fn format_batch(batch: &[Notification]) -> String {
+    let mut message = match batch.len() {
+        1 => String::new(),
+        n => format!("{n} new articles\n"),
+    };
+    for notification in batch {
+        message.push_str(&format!(
+            "- [{}]({}) ({})\n",
+            notification.title, notification.url, notification.reason
+        ));
+    }
+    message.trim_end().to_string()
+}
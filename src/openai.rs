@@ -0,0 +1,184 @@
+//! Summarisation on any server implementing the OpenAI chat completions API,
+//! such as llama.cpp, vLLM or Ollama.
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use crate::mamba::{GenerationStats, InitConfig, TokenUsage};
+use crate::summarise::Summarizer;
+
+/// How long a single generation may take, including reading the whole stream.
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Where the OpenAI-compatible server is and which model it runs.
+#[derive(clap::Args, Clone, Debug)]
+pub struct OpenAiConfig {
+    /// Base URL of the OpenAI-compatible API used with `--summarizer openai`
+    #[arg(
+        long = "openai-url",
+        global = true,
+        default_value = "http://localhost:8080/v1"
+    )]
+    pub url: String,
+
+    /// Model requested from the OpenAI-compatible API
+    #[arg(long = "openai-model", global = true, default_value = "default")]
+    pub model: String,
+
+    /// Bearer token of the OpenAI-compatible API
+    #[arg(long = "openai-api-key", global = true)]
+    pub api_key: Option<String>,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080/v1".to_string(),
+            model: "default".to_string(),
+            api_key: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    delta: Delta,
+}
+
+#[derive(Deserialize)]
+struct Delta {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    prompt_tokens: usize,
+    completion_tokens: usize,
+}
+
+/// Streams completions from an OpenAI-compatible server.
+///
+/// Requests block, so like the local model this must not be used directly
+/// on an async task.
+pub struct OpenAiSummarizer {
+    client: reqwest::blocking::Client,
+    config: OpenAiConfig,
+    seed: u64,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    last_stats: GenerationStats,
+}
+
+impl OpenAiSummarizer {
+    /// Samples with the seed, temperature and top-p of `generation`.
+    pub fn new(config: OpenAiConfig, generation: &InitConfig, user_agent: &str) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::blocking::Client::builder()
+                .user_agent(user_agent)
+                .timeout(GENERATION_TIMEOUT)
+                .build()?,
+            config,
+            seed: generation.seed,
+            temperature: generation.temperature,
+            top_p: generation.top_p,
+            last_stats: GenerationStats::default(),
+        })
+    }
+}
+
+impl Summarizer for OpenAiSummarizer {
+    fn sampling(&self) -> (u64, Option<f64>) {
+        (self.seed, self.temperature)
+    }
+
+    fn set_sampling(&mut self, seed: u64, temperature: Option<f64>) {
+        self.seed = seed;
+        self.temperature = temperature;
+    }
+
+    fn last_stats(&self) -> GenerationStats {
+        self.last_stats
+    }
+
+    fn run_stream(
+        &mut self,
+        prompt: &str,
+        sample_len: usize,
+        on_text: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<GenerationStats> {
+        let url = format!("{}/chat/completions", self.config.url.trim_end_matches('/'));
+        let body = serde_json::json!({
+            "model": self.config.model,
+            "messages": [{ "role": "user", "content": prompt }],
+            "max_tokens": sample_len,
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "seed": self.seed,
+            // OpenAI has no greedy mode, a temperature of 0 comes closest.
+            "temperature": self.temperature.unwrap_or(0.0),
+            "top_p": self.top_p,
+        });
+        let mut request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+        let start = Instant::now();
+        let resp = request.send()?;
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("{} answered {}: {}", url, status, resp.text()?);
+        }
+
+        let mut usage = None;
+        let mut chunks = 0;
+        for line in std::io::BufReader::new(resp).lines() {
+            let line = line?;
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break;
+            }
+            let chunk: Chunk = serde_json::from_str(data)?;
+            usage = chunk.usage.or(usage);
+            if let Some(text) = chunk
+                .choices
+                .into_iter()
+                .filter_map(|choice| choice.delta.content)
+                .find(|text| !text.is_empty())
+            {
+                chunks += 1;
+                on_text(&text)?;
+            }
+        }
+        // Servers that don't report usage stream about one token per chunk.
+        let usage = usage.map_or(
+            TokenUsage {
+                prompt_tokens: 0,
+                completion_tokens: chunks,
+            },
+            |usage| TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+            },
+        );
+        self.last_stats = GenerationStats {
+            usage,
+            elapsed: start.elapsed(),
+        };
+        Ok(self.last_stats)
+    }
+}
@@ -0,0 +1,114 @@
+//! When every stored page was last fetched and the validators it came with,
+//! so pages are only downloaded again once they may have changed, and the
+//! [`page_key`] of its HTML, so pages downloaded again unchanged aren't
+//! extracted and stored again.
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, Pool, Postgres};
+use std::time::Duration;
+
+use crate::store::{content_hash, Article};
+
+/// A page as it was last fetched.
+#[derive(Debug, FromRow)]
+pub struct CachedPage {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    /// [`page_key`] of the HTML the page's article was stored from.
+    pub page_key: Option<String>,
+}
+
+/// Key of a page's HTML, its [`content_hash`].
+pub fn page_key(html: &str) -> String {
+    content_hash(html)
+}
+
+impl CachedPage {
+    /// Whether the page was fetched less than `max_age` ago.
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        chrono::Duration::from_std(max_age)
+            .is_ok_and(|max_age| Utc::now() - self.fetched_at < max_age)
+    }
+}
+
+/// The `page_cache` table. Without a database nothing is cached and every
+/// page is fetched in full.
+pub struct PageCache {
+    db: Option<Pool<Postgres>>,
+    /// How long a fetched page is taken to be unchanged without asking.
+    max_age: Duration,
+}
+
+impl PageCache {
+    pub fn new(db: Option<Pool<Postgres>>, max_age: Duration) -> Self {
+        Self { db, max_age }
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    pub async fn get(&self, url: &str) -> anyhow::Result<Option<CachedPage>> {
+        let Some(db) = &self.db else {
+            return Ok(None);
+        };
+        Ok(sqlx::query_as(
+            "SELECT etag, last_modified, fetched_at, page_key FROM page_cache WHERE url = $1",
+        )
+        .bind(url)
+        .fetch_optional(db)
+        .await?)
+    }
+
+    /// Marks `url` as fetched again now, after the site said it is unchanged.
+    pub async fn touch(&self, url: &str) -> anyhow::Result<()> {
+        let Some(db) = &self.db else {
+            return Ok(());
+        };
+        sqlx::query("UPDATE page_cache SET fetched_at = now() WHERE url = $1")
+            .bind(url)
+            .execute(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Remembers the validators and keys of the pages `articles` were
+    /// scraped from.
+    /// Only called once they are stored, so a page whose article failed is
+    /// fetched in full again next time.
+    pub async fn record(&self, articles: &[Article]) -> anyhow::Result<()> {
+        let Some(db) = &self.db else {
+            return Ok(());
+        };
+        let mut urls = vec![];
+        let mut etags = vec![];
+        let mut last_modified = vec![];
+        let mut fetched_at = vec![];
+        let mut page_keys = vec![];
+        for article in articles {
+            let metadata = article.metadata.as_ref();
+            urls.push(article.url.as_str());
+            etags.push(metadata.and_then(|metadata| metadata.etag.as_deref()));
+            last_modified.push(metadata.and_then(|metadata| metadata.last_modified.as_deref()));
+            fetched_at.push(article.fetched_at.unwrap_or_else(Utc::now));
+            page_keys.push(metadata.and_then(|metadata| metadata.page_key.as_deref()));
+        }
+        sqlx::query(
+            "INSERT INTO page_cache (url, etag, last_modified, fetched_at, page_key)
+            SELECT DISTINCT ON (url) * FROM unnest($1::text[], $2::text[], $3::text[],
+                $4::timestamptz[], $5::text[]) AS p(url, etag, last_modified, fetched_at, page_key)
+            ON CONFLICT (url) DO UPDATE SET etag = EXCLUDED.etag,
+                last_modified = EXCLUDED.last_modified, fetched_at = EXCLUDED.fetched_at,
+                page_key = EXCLUDED.page_key",
+        )
+        .bind(&urls)
+        .bind(&etags)
+        .bind(&last_modified)
+        .bind(&fetched_at)
+        .bind(&page_keys)
+        .execute(db)
+        .await?;
+        Ok(())
+    }
+}
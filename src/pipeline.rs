@@ -0,0 +1,133 @@
+//! The stages a crawl runs every link through, as functions of their own so
+//! library users can run part of a crawl or put their own stages in between:
+//!
+//! 1. [`discover`] the links of a source,
+//! 2. [`fetch`] the page a link points to,
+//! 3. [`extract`] the article from the page,
+//! 4. [`enrich()`] a batch of articles with the configured enrichers,
+//! 5. [`store`] the batch.
+//!
+//! Stages skip work whose output already exists, addressed by the URL of a
+//! page in the page cache, the [`page_key`] of its HTML and the
+//! [`embedding_key`] of an article with the model, so running a crawl again
+//! after it failed part way only does what is missing: pages stored before
+//! aren't extracted again when they come back the same, and articles stored
+//! without their embeddings or chunks are embedded again.
+//!
+//! [`crate::crawl::crawl`] chains them with reporting and concurrency. Every
+//! stage returns what the next one takes, and all of it serializes, so a
+//! pipeline can be stopped after any stage and resumed from its output:
+//!
+//! ```no_run
+//! use encrawl_rust::{pipeline, Encrawl};
+//! use encrawl_rust::candidate::CandidateUrl;
+//!
+//! # async fn example(app: &Encrawl) -> anyhow::Result<()> {
+//! // Extract only, without storing anything.
+//! let candidate = CandidateUrl::new("https://example.com/news/1", "manual")?;
+//! if let Some(page) = pipeline::fetch(app, candidate).await? {
+//!     std::fs::write("page.json", serde_json::to_string(&page)?)?;
+//!     let article = pipeline::extract(app, page)?;
+//!     println!("{}", article.title);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`page_key`]: crate::page_cache::page_key
+//! [`embedding_key`]: crate::store::embedding_key
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::Encrawl;
+use crate::candidate::CandidateUrl;
+use crate::crawl::find_scraper;
+use crate::enrich;
+use crate::error::EncrawlError;
+use crate::scrape::{extract_page, fetch_page, Page};
+use crate::source::Source;
+use crate::store::{store_batch, Article, Stored};
+
+/// A page downloaded for a link, ready to be extracted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchedPage {
+    pub candidate: CandidateUrl,
+    pub page: Page,
+}
+
+/// Fetches the posts of `source` and returns the links to pages outside of
+/// Reddit. Links that don't parse are skipped.
+pub async fn discover(source: &dyn Source) -> anyhow::Result<Vec<CandidateUrl>> {
+    Ok(source
+        .fetch_posts()
+        .await?
+        .into_iter()
+        .filter_map(|post| {
+            let url = post.url.clone();
+            CandidateUrl::try_from(post)
+                .inspect_err(|e| log::debug!("Skipping invalid URL {:?}: {}", url, e))
+                .ok()
+        })
+        .filter(CandidateUrl::is_external)
+        .collect())
+}
+
+/// Downloads the page `candidate` links to, or loads it in a browser for
+/// scrapers with `render`, unless the crawl policy disallows it. Returns
+/// `None` when the page is unchanged since it was last fetched, see
+/// [`fetch_page`].
+pub async fn fetch(
+    app: &Encrawl,
+    candidate: CandidateUrl,
+) -> Result<Option<FetchedPage>, EncrawlError> {
+    let config = app.config();
+    let url = candidate.as_str();
+    let page = match find_scraper(&config.scrapers, url) {
+        Some(scraper) => {
+            scraper
+                .fetch(
+                    app.http(),
+                    app.policy(),
+                    app.page_cache(),
+                    app.renderer(),
+                    url,
+                )
+                .await?
+        }
+        None => fetch_page(app.http(), app.policy(), app.page_cache(), url).await?,
+    };
+    Ok(page.map(|page| FetchedPage { candidate, page }))
+}
+
+/// Extracts the article of a fetched page with the scraper configured for
+/// its domain or the generic extractor, attributed to the source of its link.
+pub fn extract(app: &Encrawl, fetched: FetchedPage) -> Result<Article, EncrawlError> {
+    let FetchedPage { candidate, page } = fetched;
+    let config = app.config();
+    let mut article = extract_page(
+        &config.scrapers,
+        &config.title_rules,
+        candidate.as_str().to_string(),
+        &page.html,
+    )?;
+    page.validators_into(&mut article);
+    article.source = Some(candidate.source);
+    Ok(article)
+}
+
+/// Runs the enrichers configured for the sources of a batch of articles,
+/// by default detecting their language and generating the missing titles,
+/// see [`crate::enrich`].
+pub async fn enrich(app: &Encrawl, articles: &mut [Article]) {
+    enrich::run(app, articles).await
+}
+
+/// Embeds and stores `articles`, see [`store_batch`], and records the pages
+/// they came from in the page cache once they are stored.
+pub async fn store(app: &Encrawl, articles: &[Article]) -> Result<Vec<Stored>, EncrawlError> {
+    let stored = store_batch(app, articles).await?;
+    if let Err(e) = app.page_cache().record(articles).await {
+        log::error!("Failed to update the page cache: {}", e);
+    }
+    Ok(stored)
+}
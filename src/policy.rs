@@ -0,0 +1,207 @@
+//! Site policies checked before every page is fetched, for crawls that have
+//! to stay within legal or contractual limits.
+
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::http::HttpClient;
+use crate::robots::{pattern_matches, RobotsCache};
+
+/// How robots.txt is treated on a domain, regardless of `--ignore-robots`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RobotsOverride {
+    Obey,
+    Ignore,
+}
+
+/// Allows or denies the paths of a domain matching `pattern`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PathRule {
+    pub domain: String,
+    /// A robots.txt style pattern, `*` matches anything and a trailing `$`
+    /// anchors it at the end. The longest matching pattern wins.
+    pub pattern: String,
+    pub allow: bool,
+}
+
+/// The contents of `policy.ron`. Domains match themselves and their subdomains.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PolicyConfig {
+    /// When not empty, only pages on these domains are fetched.
+    #[serde(default)]
+    pub allow_domains: Vec<String>,
+    #[serde(default)]
+    pub deny_domains: Vec<String>,
+    #[serde(default)]
+    pub path_rules: Vec<PathRule>,
+    /// Most pages fetched from any one domain per day.
+    #[serde(default)]
+    pub max_pages_per_day: Option<u32>,
+    /// Daily page limits of single domains, replacing `max_pages_per_day`.
+    #[serde(default)]
+    pub domain_page_limits: HashMap<String, u32>,
+    #[serde(default)]
+    pub robots: HashMap<String, RobotsOverride>,
+}
+
+impl PolicyConfig {
+    /// Reads a policy, e.g. `policy.ron`.
+    pub fn from_file(path: PathBuf) -> anyhow::Result<Self> {
+        Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Fails with the reason when the domain and path rules deny `url`,
+    /// without looking at robots.txt or the daily limits. Returns the
+    /// lowercase host of `url` otherwise.
+    pub fn check_rules(&self, url: &str) -> anyhow::Result<String> {
+        let parsed = url::Url::parse(url)?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("{} has no host", url))?
+            .to_lowercase();
+        if let Some(domain) = self
+            .deny_domains
+            .iter()
+            .find(|domain| matches_domain(&host, domain))
+        {
+            anyhow::bail!("{} is denied by the policy for {}", url, domain);
+        }
+        if !self.allow_domains.is_empty()
+            && !self
+                .allow_domains
+                .iter()
+                .any(|domain| matches_domain(&host, domain))
+        {
+            anyhow::bail!("{} is not on a domain the policy allows", url);
+        }
+        let path = match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        };
+        let rule = self
+            .path_rules
+            .iter()
+            .filter(|rule| {
+                matches_domain(&host, &rule.domain) && pattern_matches(&rule.pattern, &path)
+            })
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow));
+        if let Some(rule) = rule.filter(|rule| !rule.allow) {
+            anyhow::bail!("{} is denied by the policy rule {}", url, rule.pattern);
+        }
+        Ok(host)
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn matches_domain(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches('.').to_lowercase();
+    host == domain
+        || host
+            .strip_suffix(&domain)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// The entry of `map` for the most specific domain `host` belongs to.
+fn most_specific<'a, T>(map: &'a HashMap<String, T>, host: &str) -> Option<(&'a str, &'a T)> {
+    map.iter()
+        .filter(|(domain, _)| matches_domain(host, domain))
+        .max_by_key(|(domain, _)| domain.len())
+        .map(|(domain, value)| (domain.as_str(), value))
+}
+
+/// Enforces a [`PolicyConfig`] and robots.txt, counting the pages fetched
+/// per domain in the database so daily limits hold across runs. Without a
+/// database they are counted in memory and only hold within the process.
+pub struct Policy {
+    db: Option<Pool<Postgres>>,
+    /// Pages fetched per domain and day when there is no database.
+    counts: Mutex<HashMap<(String, NaiveDate), u32>>,
+    robots: RobotsCache,
+    config: RwLock<Arc<PolicyConfig>>,
+}
+
+impl Policy {
+    pub fn new(db: Option<Pool<Postgres>>, robots: RobotsCache, config: PolicyConfig) -> Self {
+        Self {
+            db,
+            counts: Mutex::new(HashMap::new()),
+            robots,
+            config: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    /// Replaces the policy, e.g. after the config was reloaded.
+    pub fn set_config(&self, config: PolicyConfig) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    /// Fails with the reason when `url` may not be fetched. Otherwise the page
+    /// is counted against its domain's daily limit, after waiting out the
+    /// crawl delay of the site.
+    pub async fn check(&self, http: &HttpClient, url: &str) -> anyhow::Result<()> {
+        let config = self.config.read().unwrap().clone();
+        let host = config.check_rules(url)?;
+
+        let allowed = match most_specific(&config.robots, &host) {
+            Some((_, RobotsOverride::Ignore)) => true,
+            Some((_, RobotsOverride::Obey)) => self.robots.check_rules(http, url).await?,
+            None => self.robots.check(http, url).await?,
+        };
+        if !allowed {
+            anyhow::bail!("robots.txt disallows {}", url);
+        }
+
+        let limit = match most_specific(&config.domain_page_limits, &host) {
+            Some((domain, limit)) => Some((domain.to_lowercase(), *limit)),
+            None => config.max_pages_per_day.map(|limit| {
+                (
+                    host.strip_prefix("www.").unwrap_or(&host).to_string(),
+                    limit,
+                )
+            }),
+        };
+        if let Some((domain, limit)) = limit {
+            if !self.count_page(&domain, limit).await? {
+                anyhow::bail!(
+                    "the daily limit of {} pages from {} is reached",
+                    limit,
+                    domain
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a page fetched from `domain` today, unless `limit` pages already were.
+    async fn count_page(&self, domain: &str, limit: u32) -> anyhow::Result<bool> {
+        if limit == 0 {
+            return Ok(false);
+        }
+        let Some(db) = &self.db else {
+            let mut counts = self.counts.lock().unwrap();
+            let pages = counts
+                .entry((domain.to_string(), Utc::now().date_naive()))
+                .or_default();
+            if *pages >= limit {
+                return Ok(false);
+            }
+            *pages += 1;
+            return Ok(true);
+        };
+        let counted: Option<(i32,)> = sqlx::query_as(
+            "INSERT INTO domain_fetches (domain, day, pages) VALUES ($1, current_date, 1)
+            ON CONFLICT (domain, day) DO UPDATE SET pages = domain_fetches.pages + 1
+            WHERE domain_fetches.pages < $2
+            RETURNING pages",
+        )
+        .bind(domain)
+        .bind(limit as i32)
+        .fetch_optional(db)
+        .await?;
+        Ok(counted.is_some())
+    }
+}
@@ -0,0 +1,588 @@
+//! Publishing the corpus as a Hugging Face dataset, for research building on
+//! the crawled news: the articles in Parquet shards under `data/` and a
+//! dataset card, `README.md`, whose metadata tells the Hub how to load them.
+//!
+//! Articles can be limited to the domains whose [`ScraperConfig::license`]
+//! allows it. Shards are uploaded through Git LFS and committed together with
+//! the card in a single commit, which also removes the shards of earlier
+//! publishes.
+
+use base64::Engine;
+use futures::TryStreamExt;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::io::ReaderStream;
+
+use crate::app::Encrawl;
+use crate::crawl::find_scraper;
+use crate::http::HttpClient;
+use crate::store::Article;
+use crate::ScraperConfig;
+
+/// Where datasets are pushed unless told otherwise.
+pub const HUB_URL: &str = "https://huggingface.co";
+
+/// Directory of the dataset the shards are in.
+const DATA_DIR: &str = "data";
+
+/// Articles read from the database, and written as a row group, at a time.
+const PUBLISH_BATCH_SIZE: i64 = 5000;
+
+/// How long uploading a shard may take.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Columns of the shards, one row per article.
+const SCHEMA: &str = "message article {
+    required binary url (UTF8);
+    required binary title (UTF8);
+    required binary content (UTF8);
+    required binary author (UTF8);
+    optional binary annotation (UTF8);
+    optional binary source (UTF8);
+    optional binary domain (UTF8);
+    optional binary lang (UTF8);
+    optional int64 published_at (TIMESTAMP(MICROS, true));
+    optional int64 fetched_at (TIMESTAMP(MICROS, true));
+}";
+
+/// What goes into a dataset.
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    /// Only articles of domains published under one of these licenses,
+    /// every article when empty.
+    pub licenses: Vec<String>,
+    /// Most articles in a shard.
+    pub shard_size: usize,
+    /// Name shown on the dataset card.
+    pub pretty_name: String,
+}
+
+/// A dataset written to a directory, ready to be pushed.
+#[derive(Debug)]
+pub struct Dataset {
+    pub dir: PathBuf,
+    /// Paths of the shards, relative to [`Self::dir`].
+    pub shards: Vec<String>,
+    pub articles: usize,
+}
+
+/// Writes the stored articles allowed by `options` to `dir` as Parquet
+/// shards, and their dataset card.
+pub async fn package(
+    app: &Encrawl,
+    dir: &Path,
+    options: &PublishOptions,
+) -> anyhow::Result<Dataset> {
+    std::fs::create_dir_all(dir.join(DATA_DIR))?;
+    let config = app.config();
+    let shard_size = options.shard_size.max(1);
+    let mut shards = vec![];
+    let mut writer: Option<(SerializedFileWriter<File>, usize)> = None;
+    let mut langs = BTreeSet::new();
+    let mut domains = BTreeSet::new();
+    let mut last_id = 0;
+    let mut total = 0;
+    loop {
+        let articles: Vec<Article> = sqlx::query_as(
+            "SELECT id, title, content, url, author, annotation, extractor, source, domain, lang,
+                published_at, fetched_at
+            FROM articles WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(PUBLISH_BATCH_SIZE)
+        .fetch_all(app.postgres()?)
+        .await?;
+        let Some(last) = articles.last() else {
+            break;
+        };
+        last_id = last.id.unwrap_or(last_id);
+        let mut articles = articles
+            .into_iter()
+            .filter(|article| licensed(&config.scrapers, &article.url, &options.licenses))
+            .collect::<Vec<_>>();
+        while !articles.is_empty() {
+            let (shard, rows) = match &mut writer {
+                Some(open) => open,
+                None => {
+                    let path = format!("{DATA_DIR}/train-{:05}.parquet", shards.len());
+                    let shard = shard_writer(&dir.join(&path))?;
+                    shards.push(path);
+                    writer.insert((shard, 0))
+                }
+            };
+            let rest = articles.split_off(articles.len().min(shard_size - *rows));
+            write_rows(shard, &articles)?;
+            *rows += articles.len();
+            total += articles.len();
+            langs.extend(articles.iter().filter_map(|article| article.lang.clone()));
+            domains.extend(articles.iter().filter_map(|article| article.domain.clone()));
+            if *rows >= shard_size {
+                if let Some((shard, _)) = writer.take() {
+                    shard.close()?;
+                }
+            }
+            articles = rest;
+        }
+        log::info!("Packaged {} articles", total);
+    }
+    if let Some((shard, _)) = writer.take() {
+        shard.close()?;
+    }
+
+    // The Hub reads the split and shard count off the names.
+    let count = shards.len();
+    for (i, shard) in shards.iter_mut().enumerate() {
+        let name = format!("{DATA_DIR}/train-{i:05}-of-{count:05}.parquet");
+        std::fs::rename(dir.join(&*shard), dir.join(&name))?;
+        *shard = name;
+    }
+    let card = dataset_card(options, total, &langs, &domains);
+    std::fs::write(dir.join("README.md"), card)?;
+    Ok(Dataset {
+        dir: dir.to_path_buf(),
+        shards,
+        articles: total,
+    })
+}
+
+/// Whether the article at `url` may be published under one of `licenses`,
+/// by the license of its domain's scraper. Any article may without licenses.
+fn licensed(scrapers: &[ScraperConfig], url: &str, licenses: &[String]) -> bool {
+    licenses.is_empty()
+        || find_scraper(scrapers, url)
+            .and_then(|scraper| scraper.license.as_ref())
+            .is_some_and(|license| licenses.contains(license))
+}
+
+fn shard_writer(path: &Path) -> anyhow::Result<SerializedFileWriter<File>> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    Ok(SerializedFileWriter::new(
+        File::create(path)?,
+        schema,
+        Arc::new(properties),
+    )?)
+}
+
+/// Writes `articles` to `shard` as a row group, in the order of [`SCHEMA`].
+fn write_rows(shard: &mut SerializedFileWriter<File>, articles: &[Article]) -> anyhow::Result<()> {
+    let text = |get: fn(&Article) -> &str| {
+        articles
+            .iter()
+            .map(|article| ByteArray::from(get(article)))
+            .collect::<Vec<_>>()
+    };
+    let optional_text = |get: fn(&Article) -> Option<&str>| {
+        let values = articles.iter().map(get);
+        (
+            values
+                .clone()
+                .flatten()
+                .map(ByteArray::from)
+                .collect::<Vec<_>>(),
+            values
+                .map(|value| value.is_some() as i16)
+                .collect::<Vec<_>>(),
+        )
+    };
+    let optional_time = |get: fn(&Article) -> Option<i64>| {
+        let values = articles.iter().map(get);
+        (
+            values.clone().flatten().collect::<Vec<_>>(),
+            values
+                .map(|value| value.is_some() as i16)
+                .collect::<Vec<_>>(),
+        )
+    };
+    let required = [
+        text(|article| &article.url),
+        text(|article| &article.title),
+        text(|article| &article.content),
+        text(|article| &article.author),
+    ];
+    let optional = [
+        optional_text(|article| article.annotation.as_deref()),
+        optional_text(|article| article.source.as_deref()),
+        optional_text(|article| article.domain.as_deref()),
+        optional_text(|article| article.lang.as_deref()),
+    ];
+    let times = [
+        optional_time(|article| article.published_at.map(|at| at.timestamp_micros())),
+        optional_time(|article| article.fetched_at.map(|at| at.timestamp_micros())),
+    ];
+
+    let mut row_group = shard.next_row_group()?;
+    let mut column = 0;
+    while let Some(mut writer) = row_group.next_column()? {
+        match column {
+            0..=3 => {
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&required[column], None, None)?;
+            }
+            4..=7 => {
+                let (values, defined) = &optional[column - 4];
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(values, Some(defined), None)?;
+            }
+            _ => {
+                let (values, defined) = &times[column - 8];
+                writer
+                    .typed::<Int64Type>()
+                    .write_batch(values, Some(defined), None)?;
+            }
+        }
+        writer.close()?;
+        column += 1;
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+/// `README.md` of the dataset, its metadata followed by a description.
+fn dataset_card(
+    options: &PublishOptions,
+    articles: usize,
+    langs: &BTreeSet<String>,
+    domains: &BTreeSet<String>,
+) -> String {
+    let license = match options.licenses.as_slice() {
+        [license] => license.as_str(),
+        _ => "other",
+    };
+    let size = match articles {
+        0..=999 => "n<1K",
+        1_000..=9_999 => "1K<n<10K",
+        10_000..=99_999 => "10K<n<100K",
+        100_000..=999_999 => "100K<n<1M",
+        _ => "1M<n<10M",
+    };
+    let mut card = format!(
+        "---\nlicense: {license}\npretty_name: {}\n",
+        options.pretty_name
+    );
+    if !langs.is_empty() {
+        card.push_str("language:\n");
+        for lang in langs {
+            card.push_str(&format!("- {lang}\n"));
+        }
+    }
+    card.push_str(&format!(
+        "size_categories:\n- {size}\ntags:\n- finance\n- news\nconfigs:\n- config_name: default\n  data_files:\n  - split: train\n    path: {DATA_DIR}/train-*\n---\n\n"
+    ));
+    card.push_str(&format!(
+        "# {}\n\n{} news articles crawled by encrawl-rust from {} domains, as of {}.\n",
+        options.pretty_name,
+        articles,
+        domains.len(),
+        chrono::Utc::now().format("%Y-%m-%d")
+    ));
+    if !options.licenses.is_empty() {
+        card.push_str(&format!(
+            "\nOnly articles of sites published under {} are included.\n",
+            options.licenses.join(", ")
+        ));
+    }
+    card.push_str(
+        "\n## Columns\n\n\
+        - `url`: where the article was crawled, without tracking parameters\n\
+        - `title`, `content`, `author`: as extracted from the page\n\
+        - `annotation`: what kind of source the site is, e.g. \"opinion\"\n\
+        - `source`: the feed or subreddit that linked to the article\n\
+        - `domain`: host of the URL without `www.`\n\
+        - `lang`: ISO 639-1 code of the language, when detected\n\
+        - `published_at`, `fetched_at`: UTC timestamps\n",
+    );
+    card
+}
+
+/// Where a dataset is pushed to.
+#[derive(Debug, Clone)]
+pub struct Destination {
+    /// Hub server, [`HUB_URL`] unless self-hosted.
+    pub endpoint: String,
+    /// `owner/name` of the dataset repository, created when missing.
+    pub repo_id: String,
+    pub revision: String,
+    /// Create the repository as private.
+    pub private: bool,
+    /// Access token with write access to the repository.
+    pub token: String,
+}
+
+/// Object of a Git LFS batch response.
+#[derive(Deserialize)]
+struct LfsObject {
+    oid: String,
+    #[serde(default)]
+    actions: Option<LfsActions>,
+}
+
+#[derive(Deserialize)]
+struct LfsActions {
+    upload: Option<LfsAction>,
+    verify: Option<LfsAction>,
+}
+
+#[derive(Deserialize)]
+struct LfsAction {
+    href: String,
+    #[serde(default)]
+    header: BTreeMap<String, String>,
+}
+
+/// Entry of a repository tree listing.
+#[derive(Deserialize)]
+struct TreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Pushes `dataset` to `to` in one commit, creating the repository when it
+/// doesn't exist. Returns the URL of the commit.
+pub async fn push(
+    http: &HttpClient,
+    dataset: &Dataset,
+    to: &Destination,
+) -> anyhow::Result<String> {
+    let repo = hf_hub::Repo::with_revision(
+        to.repo_id.clone(),
+        hf_hub::RepoType::Dataset,
+        to.revision.clone(),
+    );
+    let endpoint = to.endpoint.trim_end_matches('/');
+    create_repo(http, endpoint, to).await?;
+
+    // Shards are content addressed, only the ones the Hub lacks are sent.
+    let mut shards = vec![];
+    for path in &dataset.shards {
+        let (oid, size) = hash_file(&dataset.dir.join(path)).await?;
+        shards.push((path.as_str(), oid, size));
+    }
+    let objects = shards
+        .iter()
+        .map(|(_, oid, size)| json!({"oid": oid, "size": size}))
+        .collect::<Vec<_>>();
+    let batch = json!({
+        "operation": "upload",
+        "transfers": ["basic"],
+        "hash_algo": "sha256",
+        "objects": objects,
+    });
+    let resp = http
+        .send(
+            http.post(format!(
+                "{endpoint}/{}.git/info/lfs/objects/batch",
+                repo.url()
+            ))
+            .bearer_auth(&to.token)
+            .header("Accept", "application/vnd.git-lfs+json")
+            .header("Content-Type", "application/vnd.git-lfs+json")
+            .body(batch.to_string()),
+        )
+        .await?
+        .error_for_status()?;
+    #[derive(Deserialize)]
+    struct LfsBatch {
+        objects: Vec<LfsObject>,
+    }
+    let batch: LfsBatch = serde_json::from_slice(&resp.bytes().await?)?;
+    for object in batch.objects {
+        let Some(actions) = object.actions else {
+            continue;
+        };
+        let Some((path, oid, size)) = shards.iter().find(|(_, oid, _)| *oid == object.oid) else {
+            continue;
+        };
+        if let Some(upload) = actions.upload {
+            log::info!("Uploading {} ({} bytes)", path, size);
+            upload_file(http, &upload, &dataset.dir.join(path), oid, *size).await?;
+        }
+        if let Some(verify) = actions.verify {
+            let mut request = http.post(&verify.href).bearer_auth(&to.token);
+            for (name, value) in &verify.header {
+                request = request.header(name, value);
+            }
+            let body = json!({"oid": object.oid, "size": size});
+            http.send(request.body(body.to_string()))
+                .await?
+                .error_for_status()?;
+        }
+    }
+
+    let mut operations = vec![json!({
+        "key": "header",
+        "value": {
+            "summary": format!("Publish {} articles", dataset.articles),
+            "description": "",
+        },
+    })];
+    for stale in existing_shards(http, endpoint, &repo, &to.token).await? {
+        if !dataset.shards.contains(&stale) {
+            operations.push(json!({"key": "deletedFile", "value": {"path": stale}}));
+        }
+    }
+    for (path, oid, size) in &shards {
+        operations.push(json!({
+            "key": "lfsFile",
+            "value": {"path": path, "algo": "sha256", "oid": oid, "size": size},
+        }));
+    }
+    let card = std::fs::read(dataset.dir.join("README.md"))?;
+    operations.push(json!({
+        "key": "file",
+        "value": {
+            "path": "README.md",
+            "encoding": "base64",
+            "content": base64::engine::general_purpose::STANDARD.encode(card),
+        },
+    }));
+    let body = operations
+        .iter()
+        .map(|operation| operation.to_string() + "\n")
+        .collect::<String>();
+    let resp = http
+        .send(
+            http.post(format!(
+                "{endpoint}/api/{}/commit/{}",
+                repo.url(),
+                repo.url_revision()
+            ))
+            .bearer_auth(&to.token)
+            .header("Content-Type", "application/x-ndjson")
+            .body(body),
+        )
+        .await?;
+    let status = resp.status();
+    let body = resp.bytes().await?;
+    if !status.is_success() {
+        anyhow::bail!(
+            "the Hub refused the commit with {}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        );
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Commit {
+        commit_url: String,
+    }
+    Ok(serde_json::from_slice::<Commit>(&body)?.commit_url)
+}
+
+/// Creates the dataset repository unless it exists.
+/// SHA-256 in hex and size of the file at `path`, read a buffer at a time.
+async fn hash_file(path: &Path) -> anyhow::Result<(String, u64)> {
+    let mut chunks = ReaderStream::new(tokio::fs::File::open(path).await?);
+    let (mut hasher, mut size) = (Sha256::new(), 0);
+    while let Some(chunk) = chunks.try_next().await? {
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+    }
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+/// Streams the file at `path` to the LFS `upload` action, hashing it on the
+/// way to make sure what was sent is the object `oid` of `size` bytes.
+async fn upload_file(
+    http: &HttpClient,
+    upload: &LfsAction,
+    path: &Path,
+    oid: &str,
+    size: u64,
+) -> anyhow::Result<()> {
+    let hasher = Arc::new(Mutex::new(Sha256::new()));
+    let request = || {
+        let file = tokio::fs::File::from_std(File::open(path)?);
+        *hasher.lock().unwrap() = Sha256::new();
+        let hasher = hasher.clone();
+        let body =
+            ReaderStream::new(file).inspect_ok(move |chunk| hasher.lock().unwrap().update(chunk));
+        // Stores behind LFS want the length up front, not a chunked body.
+        let mut request = http
+            .put(&upload.href)
+            .timeout(UPLOAD_TIMEOUT)
+            .header(CONTENT_LENGTH, size)
+            .body(reqwest::Body::wrap_stream(body));
+        for (name, value) in &upload.header {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    };
+    http.send_with(request).await?.error_for_status()?;
+    let sent = hex::encode(hasher.lock().unwrap().clone().finalize());
+    anyhow::ensure!(sent == oid, "{} changed while uploading it", path.display());
+    Ok(())
+}
+
+async fn create_repo(http: &HttpClient, endpoint: &str, to: &Destination) -> anyhow::Result<()> {
+    let (organization, name) = match to.repo_id.split_once('/') {
+        Some((organization, name)) => (Some(organization), name),
+        None => (None, to.repo_id.as_str()),
+    };
+    let body = json!({
+        "type": "dataset",
+        "name": name,
+        "organization": organization,
+        "private": to.private,
+    });
+    let resp = http
+        .send(
+            http.post(format!("{endpoint}/api/repos/create"))
+                .bearer_auth(&to.token)
+                .header("Content-Type", "application/json")
+                .body(body.to_string()),
+        )
+        .await?;
+    if resp.status() == StatusCode::CONFLICT {
+        return Ok(());
+    }
+    resp.error_for_status()?;
+    log::info!("Created the dataset {}", to.repo_id);
+    Ok(())
+}
+
+/// Paths of the shards in the repository, none when it has no data yet.
+async fn existing_shards(
+    http: &HttpClient,
+    endpoint: &str,
+    repo: &hf_hub::Repo,
+    token: &str,
+) -> anyhow::Result<Vec<String>> {
+    let resp = http
+        .send(
+            http.get(format!(
+                "{endpoint}/api/{}/tree/{}/{DATA_DIR}",
+                repo.url(),
+                repo.url_revision()
+            ))
+            .bearer_auth(token),
+        )
+        .await?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(vec![]);
+    }
+    let entries: Vec<TreeEntry> = serde_json::from_slice(&resp.error_for_status()?.bytes().await?)?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.kind == "file" && entry.path.ends_with(".parquet"))
+        .map(|entry| entry.path)
+        .collect())
+}
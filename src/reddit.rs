@@ -0,0 +1,559 @@
+//! Minimal Reddit API client used to discover links to news articles.
+
+use clap::ValueEnum;
+use futures::{stream, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::http::HttpClient;
+
+/// Most posts Reddit returns for a single listing request.
+const PAGE_SIZE: usize = 100;
+
+/// Tokens are refreshed this long before Reddit says they expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Posts of a listing page, or comments of a post, whose shortened links
+/// are resolved at the same time.
+const RESOLVE_CONCURRENCY: usize = 4;
+
+/// Hosts of link shorteners, whose links are followed to where they redirect.
+const SHORTENERS: &[&str] = &[
+    "t.co",
+    "bit.ly",
+    "buff.ly",
+    "dlvr.it",
+    "goo.gl",
+    "ow.ly",
+    "tinyurl.com",
+    "trib.al",
+    "amzn.to",
+    "bloom.bg",
+    "cnb.cx",
+    "nyti.ms",
+    "reut.rs",
+    "wapo.st",
+    "on.ft.com",
+    "on.wsj.com",
+];
+
+/// A Markdown link, `[text](url)` or `[text](<url>)`, capturing the URL.
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[[^\]]*\]\(\s*<?(https?://[^\s<>]+?)>?\s*\)").unwrap());
+
+/// A bare URL, trailing punctuation is trimmed after matching.
+static BARE_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>()\[\]"'`]+"#).unwrap());
+
+/// Every distinct URL in `text`, which is Reddit flavoured Markdown, in the
+/// order they appear. Markdown links give their target, HTML escapes and
+/// backslash escapes Reddit adds to URLs are undone.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let text = text.replace("&amp;", "&").replace("\\_", "_");
+    let mut urls = vec![];
+    let mut push = |url: &str| {
+        let url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '*']);
+        if url::Url::parse(url).is_ok() && !urls.iter().any(|seen| seen == url) {
+            urls.push(url.to_string());
+        }
+    };
+    let mut rest = String::with_capacity(text.len());
+    let mut last = 0;
+    for link in MARKDOWN_LINK.captures_iter(&text) {
+        let whole = link.get(0).unwrap();
+        rest.push_str(&text[last..whole.start()]);
+        rest.push(' ');
+        last = whole.end();
+        push(&link[1]);
+    }
+    rest.push_str(&text[last..]);
+    for url in BARE_URL.find_iter(&rest) {
+        push(url.as_str());
+    }
+    urls
+}
+
+/// Order of a subreddit listing.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sort {
+    #[default]
+    Hot,
+    New,
+    Top,
+}
+
+impl Sort {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hot => "hot",
+            Self::New => "new",
+            Self::Top => "top",
+        }
+    }
+}
+
+/// Time window of a [`Sort::Top`] listing.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeWindow {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl TimeWindow {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Year => "year",
+            Self::All => "all",
+        }
+    }
+}
+
+/// Which posts of a subreddit to fetch and how many.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Listing {
+    pub sort: Sort,
+    pub time: Option<TimeWindow>,
+    /// Maximum number of pages to request.
+    pub pages: usize,
+    /// Stop once this many posts have been fetched.
+    pub max_posts: Option<usize>,
+    /// Also fetch the top-level comments of every post scoring at least
+    /// this, see [`RedditClient::get_comments`]. No comments when unset.
+    #[serde(default)]
+    pub comment_min_score: Option<i64>,
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        Self {
+            sort: Sort::Hot,
+            time: None,
+            pages: 1,
+            max_posts: None,
+            comment_min_score: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TopLevelResp {
+    kind: String,
+    data: TopLevelData,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TopLevelData {
+    after: Option<String>,
+    dist: isize,
+    modhash: String,
+    before: Option<String>,
+    children: Vec<Children>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Children {
+    kind: String,
+    data: RedditPost,
+}
+
+/// One of the two listings of a comments page, the post or its comments.
+#[derive(Deserialize)]
+struct CommentsListing {
+    data: CommentsData,
+}
+
+#[derive(Deserialize)]
+struct CommentsData {
+    children: Vec<CommentChild>,
+}
+
+/// A comment (`t1`), or a `more` stub for comments left out of the page,
+/// which is skipped.
+#[derive(Deserialize)]
+struct CommentChild {
+    kind: String,
+    data: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RedditAuthResp {
+    access_token: String,
+    token_type: String,
+    expires_in: i64,
+    scope: String,
+}
+
+/// A post from a subreddit listing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RedditPost {
+    /// Fullname of the post, e.g. `t3_1abcde`.
+    #[serde(default)]
+    pub name: String,
+    /// Seconds since the epoch.
+    #[serde(default)]
+    pub created_utc: f64,
+    pub title: String,
+    pub url: String,
+    /// Path of the post's comments, e.g. `/r/finance/comments/1abcde/title/`.
+    #[serde(default)]
+    pub permalink: String,
+    #[serde(default)]
+    pub score: i64,
+    /// Text of the post's flair, which the flairs of a subreddit match.
+    #[serde(default)]
+    pub link_flair_text: Option<String>,
+    pub selftext: String,
+    pub over_18: bool,
+    pub stickied: bool,
+    pub body: Option<String>,
+    /// URLs found in the selftext or body of the post, with links of
+    /// [`SHORTENERS`] resolved to where they redirect.
+    #[serde(skip_deserializing)]
+    pub referenced_urls: Vec<String>,
+}
+
+/// A top-level comment of a post.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RedditComment {
+    /// Fullname of the comment, e.g. `t1_abcdef`.
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub score: i64,
+    /// Seconds since the epoch.
+    #[serde(default)]
+    pub created_utc: f64,
+    /// URLs found in the body, with links of [`SHORTENERS`] resolved.
+    #[serde(skip_deserializing)]
+    pub referenced_urls: Vec<String>,
+}
+
+/// A flair template of a subreddit, as listed by `link_flair_v2`.
+#[derive(Deserialize)]
+struct FlairTemplate {
+    text: String,
+}
+
+/// An access token and when it stops being valid.
+struct Token {
+    authorization: String,
+    expires_at: Instant,
+}
+
+/// Application-only OAuth client for the Reddit API.
+pub struct RedditClient {
+    http: HttpClient,
+    client_id: String,
+    client_secret: String,
+    token: RwLock<Token>,
+}
+
+impl RedditClient {
+    pub async fn new(
+        http: HttpClient,
+        client_id: String,
+        client_secret: String,
+    ) -> Result<Self, anyhow::Error> {
+        let token = Self::authenticate(&http, &client_id, &client_secret).await?;
+        Ok(Self {
+            http,
+            client_id,
+            client_secret,
+            token: RwLock::new(token),
+        })
+    }
+
+    async fn authenticate(
+        http: &HttpClient,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<Token, anyhow::Error> {
+        let base_url = "https://www.reddit.com/";
+        let req = http
+            .post(format!("{base_url}api/v1/access_token"))
+            .body("grant_type=client_credentials&username=&password=")
+            .basic_auth(client_id, Some(client_secret))
+            .header("User-Agent", "encrawl by Striking_Director_64");
+        let req = http.send(req).await?.error_for_status()?;
+        let auth_resp: RedditAuthResp = serde_json::from_slice(&req.bytes().await?)?;
+        Ok(Token {
+            authorization: format!("bearer {}", auth_resp.access_token),
+            expires_at: Instant::now() + Duration::from_secs(auth_resp.expires_in.max(0) as u64),
+        })
+    }
+
+    /// Re-authenticates when the access token has expired or is about to.
+    /// Every request does this itself, so it is only needed to fail early,
+    /// e.g. when a long-running service checks its credentials.
+    pub async fn ensure_authenticated(&self) -> Result<(), anyhow::Error> {
+        self.ensure_token().await.map(|_| ())
+    }
+
+    /// Fetches a new access token even though the current one hasn't expired,
+    /// e.g. after the app's credentials were rotated or it was revoked.
+    pub async fn refresh(&self) -> Result<(), anyhow::Error> {
+        let mut token = self.token.write().await;
+        log::info!("Refreshing Reddit access token");
+        *token = Self::authenticate(&self.http, &self.client_id, &self.client_secret).await?;
+        Ok(())
+    }
+
+    /// Returns the `Authorization` header value, re-authenticating first when
+    /// the token is about to expire.
+    async fn ensure_token(&self) -> Result<String, anyhow::Error> {
+        {
+            let token = self.token.read().await;
+            if Instant::now() + TOKEN_EXPIRY_MARGIN < token.expires_at {
+                return Ok(token.authorization.clone());
+            }
+        }
+        self.refresh_token(None).await
+    }
+
+    /// Fetches a new token unless another task already replaced `stale`.
+    async fn refresh_token(&self, stale: Option<&str>) -> Result<String, anyhow::Error> {
+        let mut token = self.token.write().await;
+        let still_valid = Instant::now() + TOKEN_EXPIRY_MARGIN < token.expires_at;
+        let replaced = stale.is_some_and(|stale| stale != token.authorization);
+        if (stale.is_none() && still_valid) || replaced {
+            return Ok(token.authorization.clone());
+        }
+        log::info!("Refreshing Reddit access token");
+        *token = Self::authenticate(&self.http, &self.client_id, &self.client_secret).await?;
+        Ok(token.authorization.clone())
+    }
+
+    /// Sends an authenticated GET request, re-authenticating and retrying once
+    /// if Reddit answers with 401.
+    async fn get<Q: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        query: &Q,
+    ) -> Result<reqwest::Response, anyhow::Error> {
+        let authorization = self.ensure_token().await?;
+        let request = |authorization: &str| {
+            self.http.send(
+                self.http
+                    .get(url)
+                    .header("Authorization", authorization)
+                    .header(
+                        "User-Agent",
+                        "telegram-integration-bot by Striking_Director_64",
+                    )
+                    .query(query),
+            )
+        };
+        let resp = request(&authorization).await?;
+        if resp.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+        let authorization = self.refresh_token(Some(&authorization)).await?;
+        request(&authorization).await
+    }
+
+    /// Fetches the first page of hot posts of `subreddit`, restricted to
+    /// `flairs` when any are given.
+    pub async fn get_posts(
+        &self,
+        subreddit: String,
+        flairs: Vec<String>,
+    ) -> Result<Vec<RedditPost>, anyhow::Error> {
+        self.get_listing(&subreddit, &flairs, &Listing::default())
+            .await
+    }
+
+    /// Fetches posts of `subreddit` page by page, following the `after`
+    /// cursor until `listing` says to stop or the listing runs out.
+    pub async fn get_listing(
+        &self,
+        subreddit: &str,
+        flairs: &[String],
+        listing: &Listing,
+    ) -> Result<Vec<RedditPost>, anyhow::Error> {
+        let base_url = "https://www.reddit.com";
+        let request_url = format!("{base_url}/r/{subreddit}/{}.json", listing.sort.as_str());
+        let search_param = if flairs.is_empty() {
+            None
+        } else {
+            Some(
+                flairs
+                    .iter()
+                    .map(|flair| format!("flair:{flair}"))
+                    .collect::<Vec<String>>()
+                    .join(" OR "),
+            )
+        };
+        let mut posts = vec![];
+        let mut after: Option<String> = None;
+        for _ in 0..listing.pages.max(1) {
+            let remaining = listing
+                .max_posts
+                .map_or(PAGE_SIZE, |max| max.saturating_sub(posts.len()));
+            let limit = remaining.min(PAGE_SIZE).to_string();
+            let mut query_param = vec![("limit", limit.as_str())];
+            if let Some(search_param) = &search_param {
+                query_param.push(("q", search_param.as_str()));
+            }
+            if let (Sort::Top, Some(time)) = (listing.sort, listing.time) {
+                query_param.push(("t", time.as_str()));
+            }
+            if let Some(after) = &after {
+                query_param.push(("after", after.as_str()));
+            }
+            let resp = self.get(&request_url, &query_param).await?;
+            let resp_parsed: TopLevelResp = serde_json::from_slice(&resp.bytes().await?)?;
+            let page = resp_parsed
+                .data
+                .children
+                .into_iter()
+                .map(|child| self.with_referenced_urls(child.data))
+                .collect::<Vec<_>>();
+            posts.extend(
+                stream::iter(page)
+                    .buffered(RESOLVE_CONCURRENCY)
+                    .collect::<Vec<_>>()
+                    .await,
+            );
+            after = resp_parsed.data.after;
+            let full = listing.max_posts.is_some_and(|max| posts.len() >= max);
+            if after.is_none() || full {
+                break;
+            }
+        }
+        if let Some(max) = listing.max_posts {
+            posts.truncate(max);
+        }
+        Ok(posts)
+    }
+
+    /// Texts of the link flairs of `subreddit`, sorted. When Reddit doesn't
+    /// list its flair templates, which needs more than application-only
+    /// access on many subreddits, they are taken from the flairs of the
+    /// subreddit's top posts of the month, which may miss rare ones.
+    pub async fn link_flairs(&self, subreddit: &str) -> Result<Vec<String>, anyhow::Error> {
+        let url = format!("https://www.reddit.com/r/{subreddit}/api/link_flair_v2.json");
+        let templates = async {
+            let resp = self.get(&url, &()).await?.error_for_status()?;
+            anyhow::Ok(serde_json::from_slice::<Vec<FlairTemplate>>(
+                &resp.bytes().await?,
+            )?)
+        };
+        let mut flairs = match templates.await {
+            Ok(templates) if !templates.is_empty() => templates
+                .into_iter()
+                .map(|template| template.text)
+                .collect::<Vec<_>>(),
+            result => {
+                if let Err(e) = result {
+                    log::debug!("Failed to list the flairs of r/{}: {}", subreddit, e);
+                }
+                log::info!(
+                    "r/{} doesn't list its flairs, taking them from its top posts",
+                    subreddit
+                );
+                let listing = Listing {
+                    sort: Sort::Top,
+                    time: Some(TimeWindow::Month),
+                    pages: 1,
+                    max_posts: None,
+                    comment_min_score: None,
+                };
+                self.get_listing(subreddit, &[], &listing)
+                    .await?
+                    .into_iter()
+                    .filter_map(|post| post.link_flair_text)
+                    .collect()
+            }
+        };
+        flairs.retain(|flair| !flair.trim().is_empty());
+        flairs.sort();
+        flairs.dedup();
+        Ok(flairs)
+    }
+
+    /// Top-level comments of the post `post_id`, given with or without its
+    /// `t3_` prefix, scoring at least `min_score`, the best first. Only the
+    /// comments Reddit puts on the first page are considered.
+    pub async fn get_comments(
+        &self,
+        post_id: &str,
+        min_score: i64,
+    ) -> Result<Vec<RedditComment>, anyhow::Error> {
+        let id = post_id.trim_start_matches("t3_");
+        let url = format!("https://www.reddit.com/comments/{id}.json");
+        let query = [("depth", "1"), ("sort", "top"), ("limit", "100")];
+        let resp = self.get(&url, &query).await?.error_for_status()?;
+        let listings: Vec<CommentsListing> = serde_json::from_slice(&resp.bytes().await?)?;
+        // The first listing is the post itself.
+        let comments = listings
+            .into_iter()
+            .nth(1)
+            .map(|listing| listing.data.children)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|child| child.kind == "t1")
+            .filter_map(|child| serde_json::from_value::<RedditComment>(child.data).ok())
+            .filter(|comment| comment.score >= min_score)
+            .map(|mut comment| async move {
+                for url in extract_urls(&comment.body) {
+                    let url = self.resolve_shortened(url).await;
+                    if !comment.referenced_urls.contains(&url) {
+                        comment.referenced_urls.push(url);
+                    }
+                }
+                comment
+            });
+        Ok(stream::iter(comments)
+            .buffered(RESOLVE_CONCURRENCY)
+            .collect()
+            .await)
+    }
+
+    async fn with_referenced_urls(&self, mut post: RedditPost) -> RedditPost {
+        let mut urls = extract_urls(&post.selftext);
+        if let Some(body) = &post.body {
+            urls.extend(extract_urls(body));
+        }
+        for url in urls {
+            let url = self.resolve_shortened(url).await;
+            if !post.referenced_urls.contains(&url) {
+                post.referenced_urls.push(url);
+            }
+        }
+        post
+    }
+
+    /// Where `url` redirects to when it is a link of [`SHORTENERS`], `url`
+    /// itself otherwise or when following it fails.
+    async fn resolve_shortened(&self, url: String) -> String {
+        let host = url::Url::parse(&url).ok().and_then(|parsed| {
+            parsed
+                .host_str()
+                .map(|host| host.trim_start_matches("www.").to_string())
+        });
+        if !host.is_some_and(|host| SHORTENERS.contains(&host.as_str())) {
+            return url;
+        }
+        match self.http.send(self.http.get(&url)).await {
+            Ok(resp) => resp.url().to_string(),
+            Err(e) => {
+                log::warn!("Failed to follow the shortened link {}: {}", url, e);
+                url
+            }
+        }
+    }
+}
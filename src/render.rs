@@ -0,0 +1,151 @@
+//! Rendering of pages that build their article with JavaScript, in a
+//! headless browser driven over the WebDriver protocol, e.g. chromedriver
+//! or geckodriver, for the scrapers with `render: true`.
+
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+use crate::http::HttpClient;
+
+/// How long a page may take to show its content before it is read anyway.
+const CONTENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Wait between two looks for the content.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Browser sessions open at once, each of which is a browser process.
+const MAX_SESSIONS: usize = 2;
+
+/// Client of a WebDriver server, starting a headless browser session for
+/// every page.
+pub struct Renderer {
+    http: HttpClient,
+    url: String,
+    sessions: Semaphore,
+}
+
+impl Renderer {
+    /// Talks to the WebDriver server at `url`, e.g. `http://localhost:4444`.
+    pub fn new(url: &str, user_agent: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            http: HttpClient::new(0.0, 0, user_agent)?,
+            url: url.trim_end_matches('/').to_string(),
+            sessions: Semaphore::new(MAX_SESSIONS),
+        })
+    }
+
+    /// Loads `url` in a new session and returns the HTML of the page once
+    /// one of `selectors` matches, or once [`CONTENT_TIMEOUT`] has passed
+    /// so the extraction can tell what is missing.
+    pub async fn render(&self, url: &str, selectors: &[String]) -> anyhow::Result<String> {
+        let _permit = self.sessions.acquire().await?;
+        let session = self.new_session().await?;
+        let html = self.render_in(&session, url, selectors).await;
+        if let Err(e) = self
+            .command(
+                reqwest::Method::DELETE,
+                &format!("/session/{session}"),
+                None,
+            )
+            .await
+        {
+            log::warn!("Failed to close the WebDriver session {}: {}", session, e);
+        }
+        html
+    }
+
+    async fn render_in(
+        &self,
+        session: &str,
+        url: &str,
+        selectors: &[String],
+    ) -> anyhow::Result<String> {
+        self.command(
+            reqwest::Method::POST,
+            &format!("/session/{session}/url"),
+            Some(json!({ "url": url })),
+        )
+        .await?;
+        let deadline = Instant::now() + CONTENT_TIMEOUT;
+        'wait: loop {
+            for selector in selectors {
+                let found = self
+                    .command(
+                        reqwest::Method::POST,
+                        &format!("/session/{session}/element"),
+                        Some(json!({ "using": "css selector", "value": selector })),
+                    )
+                    .await;
+                if found.is_ok() {
+                    break 'wait;
+                }
+            }
+            if Instant::now() + POLL_INTERVAL > deadline {
+                log::debug!("{} showed no content within {:?}", url, CONTENT_TIMEOUT);
+                break;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        let source = self
+            .command(
+                reqwest::Method::GET,
+                &format!("/session/{session}/source"),
+                None,
+            )
+            .await?;
+        source
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("the WebDriver server returned no page source"))
+    }
+
+    /// Starts a headless Chrome or Firefox, whichever the server drives.
+    async fn new_session(&self) -> anyhow::Result<String> {
+        let capabilities = json!({
+            "capabilities": {
+                "alwaysMatch": {
+                    "pageLoadStrategy": "normal",
+                    "goog:chromeOptions": { "args": ["--headless=new", "--disable-gpu"] },
+                    "moz:firefoxOptions": { "args": ["-headless"] },
+                }
+            }
+        });
+        let value = self
+            .command(reqwest::Method::POST, "/session", Some(capabilities))
+            .await?;
+        value["sessionId"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("the WebDriver server returned no session id"))
+    }
+
+    /// Sends a WebDriver command and returns the `value` of its answer,
+    /// failing with the error the server describes.
+    async fn command(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> anyhow::Result<Value> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.url, path))
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(body) = body {
+            request = request.body(serde_json::to_string(&body)?);
+        }
+        let resp = self.http.send(request).await?;
+        let status = resp.status();
+        let mut answer: Value = serde_json::from_str(&resp.text().await?)?;
+        let value = answer["value"].take();
+        if !status.is_success() {
+            anyhow::bail!(
+                "WebDriver answered {}: {}",
+                value["error"].as_str().unwrap_or(status.as_str()),
+                value["message"].as_str().unwrap_or_default()
+            );
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,533 @@
+//! Machine-readable reports of crawl runs, kept in the `crawl_runs` table,
+//! with every URL that failed also in `crawl_errors`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::chaos::InjectedFailures;
+use crate::error::{EncrawlError, ErrorKind};
+
+/// What crawling one source yielded.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SourceReport {
+    /// Posts linking outside of Reddit.
+    pub posts: usize,
+    pub new: usize,
+    pub updated: usize,
+    pub duplicates: usize,
+    /// Posts whose article could not be scraped or stored.
+    pub failed: usize,
+    /// Pages skipped for being unchanged since they were last fetched.
+    #[serde(default)]
+    pub unchanged: usize,
+    /// Articles skipped for being written in a language not crawled.
+    #[serde(default)]
+    pub other_language: usize,
+    /// Links skipped for having been found earlier in the same run.
+    #[serde(default)]
+    pub seen_earlier: usize,
+    pub fetch_secs: f64,
+    /// Why the posts of the source could not be fetched.
+    pub error: Option<String>,
+}
+
+/// A page that could not be scraped or stored.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UrlError {
+    pub url: String,
+    /// Unknown in reports saved before errors were told apart.
+    #[serde(default)]
+    pub kind: Option<ErrorKind>,
+    pub error: String,
+}
+
+impl UrlError {
+    pub fn new(url: String, error: &EncrawlError) -> Self {
+        Self {
+            url,
+            kind: Some(error.kind()),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Time spent in each stage, summed over everything done concurrently, so
+/// the stages together usually take longer than the whole run.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Timings {
+    pub fetch_posts_secs: f64,
+    pub scrape_secs: f64,
+    /// Downloading pages, part of scraping.
+    #[serde(default)]
+    pub fetch_secs: f64,
+    /// Extracting articles from pages, part of scraping.
+    #[serde(default)]
+    pub extract_secs: f64,
+    /// Embedding and inserting articles.
+    pub store_secs: f64,
+    /// Calls to the embedding model, while storing or by the embedding
+    /// worker next to the crawl.
+    #[serde(default)]
+    pub embed_secs: f64,
+    /// Inserts into the database.
+    #[serde(default)]
+    pub insert_secs: f64,
+    pub total_secs: f64,
+}
+
+/// How many links, pages and articles went through each stage of a run.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct StageCounts {
+    /// Links found by the sources.
+    pub discovered: usize,
+    /// Links left once the ones found earlier in the run are taken out.
+    pub queued: usize,
+    /// Pages downloaded, without the unchanged ones.
+    pub fetched: usize,
+    pub extracted: usize,
+    /// Titles, leads and chunks embedded.
+    pub embedded_texts: u64,
+    /// Articles stored new or updated.
+    pub stored: usize,
+    pub failed: usize,
+}
+
+/// Where the time of a run went, printed and logged at its end.
+#[derive(Serialize, Debug, Clone)]
+pub struct CrawlSummary {
+    pub timings: Timings,
+    pub stages: StageCounts,
+    /// The domains that took longest to scrape, slowest first.
+    pub slowest_domains: Vec<(String, DomainTimings)>,
+}
+
+impl CrawlSummary {
+    /// Where the time of the run went, stage by stage, as printed at its end.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let timings = &self.timings;
+        let stages = &self.stages;
+        let _ = writeln!(
+            out,
+            "Crawl finished in {:.1}s, stages summed over concurrent work:",
+            timings.total_secs
+        );
+        let rows = [
+            (
+                "sources",
+                timings.fetch_posts_secs,
+                stages.discovered,
+                "links",
+            ),
+            ("fetch", timings.fetch_secs, stages.fetched, "pages"),
+            (
+                "extract",
+                timings.extract_secs,
+                stages.extracted,
+                "articles",
+            ),
+            (
+                "embed",
+                timings.embed_secs,
+                stages.embedded_texts as usize,
+                "texts",
+            ),
+            ("insert", timings.insert_secs, stages.stored, "stored"),
+        ];
+        for (stage, secs, count, unit) in rows {
+            let _ = writeln!(out, "  {:<8} {:>9.1}s {:>8} {}", stage, secs, count, unit);
+        }
+        let _ = writeln!(
+            out,
+            "  {} links queued after taking out the ones found twice, {} failed",
+            stages.queued, stages.failed
+        );
+        if !self.slowest_domains.is_empty() {
+            let _ = writeln!(out, "Slowest domains:");
+        }
+        for (domain, timings) in &self.slowest_domains {
+            let _ = writeln!(
+                out,
+                "  {:<30} {:>5} pages {:>8.1}s, fetch p90 {:.2}s",
+                domain, timings.pages, timings.total_secs, timings.fetch.p90
+            );
+        }
+        out
+    }
+}
+
+/// Latency percentiles of some operation, in seconds.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `secs`, all zero when it is empty.
+    pub fn of(secs: &mut [f64]) -> Self {
+        secs.sort_by(f64::total_cmp);
+        let rank = |p: f64| {
+            let i = (p * secs.len() as f64).ceil() as usize;
+            secs.get(i.saturating_sub(1)).copied().unwrap_or_default()
+        };
+        Self {
+            p50: rank(0.5),
+            p90: rank(0.9),
+            p99: rank(0.99),
+        }
+    }
+}
+
+/// How long the pages of one domain took to scrape in a run.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DomainTimings {
+    /// Pages fetched, including the ones that failed.
+    pub pages: usize,
+    pub fetch: Percentiles,
+    /// Of the pages that were fetched and not unchanged.
+    pub extract: Percentiles,
+    /// Fetching and extracting all pages, summed over concurrent work.
+    pub total_secs: f64,
+}
+
+impl DomainTimings {
+    pub fn new(fetch_secs: &mut [f64], extract_secs: &mut [f64]) -> Self {
+        Self {
+            pages: fetch_secs.len(),
+            total_secs: fetch_secs.iter().chain(extract_secs.iter()).sum(),
+            fetch: Percentiles::of(fetch_secs),
+            extract: Percentiles::of(extract_secs),
+        }
+    }
+}
+
+/// Everything a crawl run did, as written to the `crawl_runs` table.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrawlReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub sources: BTreeMap<String, SourceReport>,
+    /// Pages that could not be scraped or stored, by domain.
+    pub domain_errors: BTreeMap<String, Vec<UrlError>>,
+    /// Batches of articles that could not be stored.
+    pub store_errors: Vec<String>,
+    pub new_article_ids: Vec<i64>,
+    pub timings: Timings,
+    /// Scrape latency of every domain, empty in reports saved before it was
+    /// recorded.
+    #[serde(default)]
+    pub domain_timings: BTreeMap<String, DomainTimings>,
+    /// Failures injected on purpose, see [`crate::chaos`].
+    #[serde(default, skip_serializing_if = "InjectedFailures::is_empty")]
+    pub injected: InjectedFailures,
+    /// Empty in reports saved before it was recorded.
+    #[serde(default)]
+    pub stages: StageCounts,
+}
+
+impl CrawlReport {
+    pub fn new(started_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            finished_at: started_at,
+            sources: BTreeMap::new(),
+            domain_errors: BTreeMap::new(),
+            store_errors: vec![],
+            new_article_ids: vec![],
+            timings: Timings::default(),
+            domain_timings: BTreeMap::new(),
+            injected: InjectedFailures::default(),
+            stages: StageCounts::default(),
+        }
+    }
+
+    /// Timings and stage counts of the run with its `top` slowest domains.
+    pub fn summary(&self, top: usize) -> CrawlSummary {
+        let mut slowest_domains = self
+            .domain_timings
+            .iter()
+            .map(|(domain, timings)| (domain.clone(), timings.clone()))
+            .collect::<Vec<_>>();
+        slowest_domains.sort_by(|a, b| b.1.total_secs.total_cmp(&a.1.total_secs));
+        slowest_domains.truncate(top);
+        CrawlSummary {
+            timings: self.timings.clone(),
+            stages: self.stages.clone(),
+            slowest_domains,
+        }
+    }
+
+    /// Counts of all sources added up.
+    pub fn totals(&self) -> SourceReport {
+        let mut totals = SourceReport::default();
+        for source in self.sources.values() {
+            totals.posts += source.posts;
+            totals.new += source.new;
+            totals.updated += source.updated;
+            totals.duplicates += source.duplicates;
+            totals.failed += source.failed;
+            totals.unchanged += source.unchanged;
+            totals.other_language += source.other_language;
+            totals.seen_earlier += source.seen_earlier;
+            totals.fetch_secs += source.fetch_secs;
+        }
+        totals
+    }
+
+    /// Number of new articles stored from every source, by name.
+    pub fn new_articles(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.sources
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.new))
+    }
+
+    /// The report of run `id` as `encrawl-rust report` prints it: timings,
+    /// counts by source, failures and the slowest domains.
+    pub fn render(&self, id: i64) -> String {
+        let mut out = String::new();
+        let timings = &self.timings;
+        let _ = writeln!(
+            out,
+            "Crawl run {} from {} to {} ({:.1}s)",
+            id, self.started_at, self.finished_at, timings.total_secs
+        );
+        let _ = writeln!(
+            out,
+            "Fetching posts {:.1}s, scraping {:.1}s (fetching {:.1}s, extracting {:.1}s), \
+            storing {:.1}s (embedding {:.1}s, inserting {:.1}s), summed over concurrent work",
+            timings.fetch_posts_secs,
+            timings.scrape_secs,
+            timings.fetch_secs,
+            timings.extract_secs,
+            timings.store_secs,
+            timings.embed_secs,
+            timings.insert_secs
+        );
+        let _ = writeln!(
+            out,
+            "\n{:<30} {:>6} {:>5} {:>5} {:>8} {:>10} {:>9} {:>6} {:>9} {:>8}",
+            "source",
+            "posts",
+            "seen",
+            "new",
+            "updated",
+            "duplicates",
+            "unchanged",
+            "failed",
+            "language",
+            "fetch"
+        );
+        for (name, source) in &self.sources {
+            let _ = writeln!(
+                out,
+                "{:<30} {:>6} {:>5} {:>5} {:>8} {:>10} {:>9} {:>6} {:>9} {:>7.1}s",
+                name,
+                source.posts,
+                source.seen_earlier,
+                source.new,
+                source.updated,
+                source.duplicates,
+                source.unchanged,
+                source.failed,
+                source.other_language,
+                source.fetch_secs
+            );
+            if let Some(error) = &source.error {
+                let _ = writeln!(out, "  {error}");
+            }
+        }
+        let injected = &self.injected;
+        if !injected.is_empty() {
+            let _ = writeln!(
+                out,
+                "\nInjected failures: {} fetch timeouts, {} malformed pages, \
+                {} store errors ({} articles)",
+                injected.fetch_timeouts,
+                injected.malformed_html,
+                injected.db_errors,
+                injected.db_error_articles
+            );
+        }
+        if !self.domain_errors.is_empty() {
+            let _ = writeln!(out, "\nFailed URLs:");
+        }
+        for (domain, errors) in &self.domain_errors {
+            let _ = writeln!(out, "{} ({})", domain, errors.len());
+            for error in errors {
+                let _ = writeln!(out, "  {}: {}", error.url, error.error);
+            }
+        }
+        for error in &self.store_errors {
+            let _ = writeln!(out, "\nFailed to store {error}");
+        }
+        let mut slowest = self.domain_timings.iter().collect::<Vec<_>>();
+        slowest.sort_by(|a, b| b.1.total_secs.total_cmp(&a.1.total_secs));
+        if !slowest.is_empty() {
+            let _ = writeln!(
+                out,
+                "\n{:<30} {:>6} {:>9} {:>9} {:>9} {:>11} {:>9}",
+                "domain", "pages", "fetch p50", "fetch p90", "fetch p99", "extract p90", "total"
+            );
+        }
+        for (domain, timings) in slowest.into_iter().take(10) {
+            let _ = writeln!(
+                out,
+                "{:<30} {:>6} {:>8.2}s {:>8.2}s {:>8.2}s {:>10.3}s {:>8.1}s",
+                domain,
+                timings.pages,
+                timings.fetch.p50,
+                timings.fetch.p90,
+                timings.fetch.p99,
+                timings.extract.p90,
+                timings.total_secs
+            );
+        }
+        let _ = writeln!(out, "\n{} new articles", self.new_article_ids.len());
+        out
+    }
+}
+
+/// Saves `report`, its failed URLs and its domain timings and returns the id
+/// of its run.
+pub async fn save(db: &Pool<Postgres>, report: &CrawlReport) -> anyhow::Result<i64> {
+    let totals = report.totals();
+    let mut tx = db.begin().await?;
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO crawl_runs (started_at, finished_at, report, posts, new_articles, updated,
+            duplicates, failed)
+        VALUES ($1, $2, $3::jsonb, $4, $5, $6, $7, $8)
+        RETURNING id",
+    )
+    .bind(report.started_at)
+    .bind(report.finished_at)
+    .bind(serde_json::to_string(report)?)
+    .bind(totals.posts as i32)
+    .bind(totals.new as i32)
+    .bind(totals.updated as i32)
+    .bind(totals.duplicates as i32)
+    .bind(totals.failed as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+    let errors = report
+        .domain_errors
+        .iter()
+        .flat_map(|(domain, errors)| errors.iter().map(move |error| (domain, error)))
+        .collect::<Vec<_>>();
+    // Well below the bind parameter limit for any realistic run.
+    for errors in errors.chunks(1000) {
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO crawl_errors (run_id, url, domain, kind, error) ",
+        );
+        query.push_values(errors, |mut row, (domain, error)| {
+            row.push_bind(id)
+                .push_bind(&error.url)
+                .push_bind(domain.as_str())
+                .push_bind(error.kind.map_or("unknown", ErrorKind::as_str))
+                .push_bind(&error.error);
+        });
+        query.build().execute(&mut *tx).await?;
+    }
+    let timings = report.domain_timings.iter().collect::<Vec<_>>();
+    for timings in timings.chunks(1000) {
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO crawl_domain_timings (run_id, domain, pages, fetch_p50, fetch_p90,
+                fetch_p99, extract_p50, extract_p90, extract_p99, total_secs) ",
+        );
+        query.push_values(timings, |mut row, (domain, timings)| {
+            row.push_bind(id)
+                .push_bind(domain.as_str())
+                .push_bind(timings.pages as i32)
+                .push_bind(timings.fetch.p50)
+                .push_bind(timings.fetch.p90)
+                .push_bind(timings.fetch.p99)
+                .push_bind(timings.extract.p50)
+                .push_bind(timings.extract.p90)
+                .push_bind(timings.extract.p99)
+                .push_bind(timings.total_secs);
+        });
+        query.build().execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// The report of run `id`, or of the latest run when it is `None`.
+pub async fn load(db: &Pool<Postgres>, id: Option<i64>) -> anyhow::Result<(i64, CrawlReport)> {
+    let row: Option<(i64, String)> = sqlx::query_as(
+        "SELECT id, report::text FROM crawl_runs WHERE $1::bigint IS NULL OR id = $1
+        ORDER BY id DESC LIMIT 1",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    match row {
+        Some((id, report)) => Ok((id, serde_json::from_str(&report)?)),
+        None => match id {
+            Some(id) => anyhow::bail!("there is no crawl run {}", id),
+            None => anyhow::bail!("nothing has been crawled yet"),
+        },
+    }
+}
+
+/// Scrape latency of a domain over recent crawl runs.
+#[derive(Serialize, Debug, FromRow)]
+pub struct SlowDomain {
+    pub domain: String,
+    /// Runs the domain was scraped in.
+    pub runs: i64,
+    pub pages: i64,
+    /// Medians over the runs of the per-run percentiles.
+    pub fetch_p50: f64,
+    pub fetch_p90: f64,
+    pub extract_p90: f64,
+    /// Share of the scrape time of all the runs spent on the domain.
+    pub share: f64,
+    /// Runs in which the domain took at least the flagging share of the
+    /// scrape time.
+    pub dominated: i64,
+    /// Whether it did so in at least half of the runs, making it a candidate
+    /// for a lower concurrency or a stricter rate limit.
+    pub slow: bool,
+}
+
+/// The `top` domains taking the largest share of the scrape time of the
+/// last `runs` crawl runs with domain timings, the largest first. Domains
+/// taking at least `share` of the time of half of the runs are flagged slow.
+pub async fn slow_domains(
+    db: &Pool<Postgres>,
+    runs: i64,
+    share: f64,
+    top: i64,
+) -> anyhow::Result<Vec<SlowDomain>> {
+    Ok(sqlx::query_as(
+        "WITH runs AS (
+            SELECT id FROM crawl_runs r
+            WHERE EXISTS (SELECT 1 FROM crawl_domain_timings t WHERE t.run_id = r.id)
+            ORDER BY id DESC LIMIT $1
+        ), timings AS (
+            SELECT t.*, t.total_secs / NULLIF(SUM(t.total_secs) OVER (PARTITION BY t.run_id), 0)
+                AS share
+            FROM crawl_domain_timings t JOIN runs r ON r.id = t.run_id
+        ), domains AS (
+            SELECT domain, COUNT(*) AS runs, SUM(pages)::bigint AS pages,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY fetch_p50) AS fetch_p50,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY fetch_p90) AS fetch_p90,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY extract_p90) AS extract_p90,
+                COALESCE(SUM(share), 0) / (SELECT COUNT(*) FROM runs) AS share,
+                COUNT(*) FILTER (WHERE share >= $2) AS dominated
+            FROM timings GROUP BY domain
+        )
+        SELECT *, dominated * 2 >= (SELECT COUNT(*) FROM runs) AS slow
+        FROM domains ORDER BY share DESC, domain LIMIT $3",
+    )
+    .bind(runs)
+    .bind(share)
+    .bind(top)
+    .fetch_all(db)
+    .await?)
+}
@@ -0,0 +1,175 @@
+//! Reranking of search results with a cross-encoder, which reads the query
+//! together with each candidate instead of comparing their embeddings.
+
+use candle_core::{DType, Device, Module, Tensor, D};
+use candle_nn::{Linear, VarBuilder};
+use candle_transformers::models::bert::{self, BertModel};
+use hf_hub::api::sync::Api;
+use serde::Deserialize;
+use tokenizers::{Tokenizer, TruncationParams};
+
+use crate::app::Encrawl;
+use crate::device::{self, ComputeDType, ComputeDevice};
+use crate::store::{search_filtered, Article, SearchFilter};
+
+/// Cross-encoder used by `--rerank` unless `--rerank-model` says otherwise.
+pub const DEFAULT_MODEL: &str = "cross-encoder/ms-marco-MiniLM-L-6-v2";
+
+/// Candidates retrieved from the vector search and reranked.
+pub const CANDIDATES: i32 = 50;
+
+/// Sentences of an article scored together with the query.
+const WINDOW_SENTENCES: usize = 3;
+
+/// Windows of every article scored, from its start, on top of its title.
+const MAX_WINDOWS: usize = 4;
+
+/// A BERT cross-encoder with a classification head, scoring how relevant a
+/// passage is to a query, such as the `cross-encoder/ms-marco-*` models.
+pub struct CrossEncoder {
+    model: BertModel,
+    pooler: Linear,
+    classifier: Linear,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+/// The fields of a model's `config.json` that [`bert::Config`] keeps private.
+#[derive(Deserialize)]
+struct ModelSize {
+    hidden_size: usize,
+    max_position_embeddings: usize,
+}
+
+impl CrossEncoder {
+    /// Downloads the model with the Hugging Face name `name`, or reads it
+    /// from the directory `name` when it exists. This blocks.
+    pub fn load(name: &str, device: ComputeDevice, dtype: ComputeDType) -> anyhow::Result<Self> {
+        let dir = std::path::Path::new(name);
+        let (config, tokenizer, weights) = if dir.is_dir() {
+            (
+                dir.join("config.json"),
+                dir.join("tokenizer.json"),
+                dir.join("model.safetensors"),
+            )
+        } else {
+            let repo = Api::new()?.model(name.to_string());
+            (
+                repo.get("config.json")?,
+                repo.get("tokenizer.json")?,
+                repo.get("model.safetensors")?,
+            )
+        };
+        let config = std::fs::read(config)?;
+        let size: ModelSize = serde_json::from_slice(&config)?;
+        let config: bert::Config = serde_json::from_slice(&config)
+            .map_err(|e| anyhow::anyhow!("only BERT cross-encoders are supported: {}", e))?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer).map_err(anyhow::Error::msg)?;
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: size.max_position_embeddings,
+                ..Default::default()
+            }))
+            .map_err(anyhow::Error::msg)?;
+        let (device, dtype) = device::select("rerank model", device, dtype);
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], dtype, &device)? };
+        Ok(Self {
+            model: BertModel::load(vb.clone(), &config)?,
+            pooler: candle_nn::linear(
+                size.hidden_size,
+                size.hidden_size,
+                vb.pp("bert.pooler.dense"),
+            )?,
+            classifier: candle_nn::linear(size.hidden_size, 1, vb.pp("classifier"))?,
+            tokenizer,
+            device,
+        })
+    }
+
+    /// Relevance of every passage to `query`, higher is more relevant. Pairs
+    /// are scored one at a time, since this version of the model can't mask
+    /// the padding of a batch.
+    pub fn score(&self, query: &str, passages: &[String]) -> anyhow::Result<Vec<f32>> {
+        passages
+            .iter()
+            .map(|passage| {
+                let encoding = self
+                    .tokenizer
+                    .encode((query, passage.as_str()), true)
+                    .map_err(anyhow::Error::msg)?;
+                let input_ids = Tensor::new(encoding.get_ids(), &self.device)?.unsqueeze(0)?;
+                let token_type_ids =
+                    Tensor::new(encoding.get_type_ids(), &self.device)?.unsqueeze(0)?;
+                let tokens = self.model.forward(&input_ids, &token_type_ids)?;
+                let cls = tokens.narrow(1, 0, 1)?.squeeze(1)?;
+                let pooled = self.pooler.forward(&cls)?.tanh()?;
+                let logit = self.classifier.forward(&pooled)?.squeeze(D::Minus1)?;
+                Ok(logit.to_dtype(DType::F32)?.squeeze(0)?.to_scalar::<f32>()?)
+            })
+            .collect()
+    }
+}
+
+/// Returns the `limit` articles matching `filter` most relevant to `query`,
+/// reranking the best [`CANDIDATES`] of [`search_filtered`] with the
+/// cross-encoder. Every article is scored by its title and its best window
+/// of a few sentences, and comes with that score.
+pub async fn search(
+    app: &Encrawl,
+    query: String,
+    limit: i32,
+    filter: &SearchFilter,
+) -> anyhow::Result<Vec<(Article, f32)>> {
+    let candidates = search_filtered(app, query.clone(), CANDIDATES.max(limit), filter).await?;
+    let mut passages = vec![];
+    let mut owners = vec![];
+    for (i, article) in candidates.iter().enumerate() {
+        for passage in passages_of(article) {
+            passages.push(passage);
+            owners.push(i);
+        }
+    }
+    let scores = {
+        let reranker = app.reranker().await?;
+        tokio::task::spawn_blocking(move || reranker.score(&query, &passages)).await??
+    };
+    let mut best = vec![f32::NEG_INFINITY; candidates.len()];
+    for (owner, score) in owners.into_iter().zip(scores) {
+        best[owner] = best[owner].max(score);
+    }
+    let mut reranked = candidates.into_iter().zip(best).collect::<Vec<_>>();
+    reranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    reranked.truncate(limit.max(0) as usize);
+    Ok(reranked)
+}
+
+/// The title of `article` followed by the first [`MAX_WINDOWS`] windows of
+/// [`WINDOW_SENTENCES`] sentences of its content, each prefixed by the title.
+fn passages_of(article: &Article) -> Vec<String> {
+    let sentences = sentences(&article.content);
+    let mut passages = vec![article.title.clone()];
+    passages.extend(
+        sentences
+            .chunks(WINDOW_SENTENCES)
+            .take(MAX_WINDOWS)
+            .map(|window| format!("{}. {}", article.title, window.join(" "))),
+    );
+    passages
+}
+
+/// Splits `text` after every `.`, `!` or `?` followed by whitespace.
+pub(crate) fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_end = chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+        if matches!(c, '.' | '!' | '?') && at_end {
+            sentences.push(text[start..=i].trim());
+            start = i + 1;
+        }
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
@@ -0,0 +1,248 @@
+//! robots.txt fetching, caching and matching for the article scraper.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::http::HttpClient;
+
+/// How long a fetched robots.txt is trusted before it is fetched again.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest crawl delay honoured, sites asking for more are still crawled this often.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60);
+
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The part of a robots.txt that applies to our user agent.
+pub struct Rules {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Rules {
+    fn allow_all() -> Self {
+        Self {
+            rules: vec![],
+            crawl_delay: None,
+        }
+    }
+
+    fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".to_string(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    /// Parses `body`, keeping the groups for `user_agent` or, when there are
+    /// none, the groups for `*`.
+    pub fn parse(body: &str, user_agent: &str) -> Self {
+        let product = user_agent
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let mut specific = Self::allow_all();
+        let mut wildcard = Self::allow_all();
+        let (mut has_specific, mut has_wildcard) = (false, false);
+        let mut agents: Vec<String> = vec![];
+        let mut in_rules = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_lowercase());
+                }
+                key @ ("allow" | "disallow" | "crawl-delay") => {
+                    in_rules = true;
+                    let target = if agents.contains(&product) {
+                        has_specific = true;
+                        &mut specific
+                    } else if agents.iter().any(|agent| agent == "*") {
+                        has_wildcard = true;
+                        &mut wildcard
+                    } else {
+                        continue;
+                    };
+                    if key == "crawl-delay" {
+                        target.crawl_delay = value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                            .map(|secs| Duration::from_secs_f64(secs).min(MAX_CRAWL_DELAY));
+                    } else if !value.is_empty() {
+                        target.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        if has_specific || !has_wildcard {
+            specific
+        } else {
+            wildcard
+        }
+    }
+
+    /// Whether `path` may be fetched, the longest matching rule wins and
+    /// `Allow` wins ties.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` matches anything and a
+/// trailing `$` anchors the pattern at the end of the path.
+pub(crate) fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Fetches and caches the robots.txt of every site and spaces out requests
+/// to sites that ask for a crawl delay.
+pub struct RobotsCache {
+    user_agent: String,
+    ignore: bool,
+    rules: Mutex<HashMap<String, (Instant, Arc<Rules>)>>,
+    next_fetch: Mutex<HashMap<String, Instant>>,
+}
+
+impl RobotsCache {
+    /// Checks rules for `user_agent`, or lets everything through when `ignore` is set.
+    pub fn new(user_agent: &str, ignore: bool) -> Self {
+        Self {
+            user_agent: user_agent.to_string(),
+            ignore,
+            rules: Mutex::new(HashMap::new()),
+            next_fetch: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether `url` may be fetched, first waiting out the crawl
+    /// delay of its site when it is.
+    pub async fn check(&self, http: &HttpClient, url: &str) -> anyhow::Result<bool> {
+        if self.ignore {
+            return Ok(true);
+        }
+        self.check_rules(http, url).await
+    }
+
+    /// Like [`Self::check`], but also when robots.txt is ignored otherwise.
+    pub async fn check_rules(&self, http: &HttpClient, url: &str) -> anyhow::Result<bool> {
+        let url = url::Url::parse(url)?;
+        let origin = url.origin().ascii_serialization();
+        let rules = self.rules_for(http, &origin).await;
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        if !rules.is_allowed(&path) {
+            return Ok(false);
+        }
+        if let Some(delay) = rules.crawl_delay {
+            let wait = {
+                let mut next_fetch = self.next_fetch.lock().unwrap();
+                let now = Instant::now();
+                let slot = next_fetch.get(&origin).map_or(now, |next| (*next).max(now));
+                next_fetch.insert(origin, slot + delay);
+                slot - now
+            };
+            tokio::time::sleep(wait).await;
+        }
+        Ok(true)
+    }
+
+    async fn rules_for(&self, http: &HttpClient, origin: &str) -> Arc<Rules> {
+        if let Some((fetched_at, rules)) = self.rules.lock().unwrap().get(origin) {
+            if fetched_at.elapsed() < CACHE_TTL {
+                return rules.clone();
+            }
+        }
+        let rules = Arc::new(self.fetch(http, origin).await);
+        self.rules
+            .lock()
+            .unwrap()
+            .insert(origin.to_string(), (Instant::now(), rules.clone()));
+        rules
+    }
+
+    /// Downloads the robots.txt of `origin`. A missing file allows
+    /// everything, while an unreachable one disallows everything.
+    async fn fetch(&self, http: &HttpClient, origin: &str) -> Rules {
+        let url = format!("{origin}/robots.txt");
+        let resp = match http.send(http.get(&url)).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                log::warn!(
+                    "Failed to fetch {}, treating the site as disallowed: {}",
+                    url,
+                    e
+                );
+                return Rules::disallow_all();
+            }
+        };
+        let status = resp.status();
+        if status.is_client_error() {
+            return Rules::allow_all();
+        }
+        if !status.is_success() {
+            log::warn!(
+                "{} answered {}, treating the site as disallowed",
+                url,
+                status
+            );
+            return Rules::disallow_all();
+        }
+        match resp.text().await {
+            Ok(body) => Rules::parse(&body, &self.user_agent),
+            Err(e) => {
+                log::warn!(
+                    "Failed to read {}, treating the site as disallowed: {}",
+                    url,
+                    e
+                );
+                Rules::disallow_all()
+            }
+        }
+    }
+}
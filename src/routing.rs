@@ -0,0 +1,133 @@
+//! Routing of stored articles to the configured digest topics. Every article
+//! is assigned to the topic whose query its title is closest to as soon as it
+//! is stored, so topics can be counted and digested without searching.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres};
+
+use crate::app::Encrawl;
+use crate::store::{check_embedding_dim, Article};
+
+/// Articles routed again at once by [`reroute_all`].
+const REROUTE_BATCH: i64 = 1000;
+
+/// Articles routed to one topic.
+#[derive(Debug, Serialize, FromRow)]
+pub struct TopicArticles {
+    pub topic: String,
+    pub articles: i64,
+}
+
+/// Assigns the articles with `ids` to the configured topic their title is
+/// closest to, replacing their earlier assignment. Articles whose similarity
+/// to every topic is below the routing threshold are left unassigned.
+/// Returns the number of articles assigned.
+pub async fn route(app: &Encrawl, ids: &[i64]) -> anyhow::Result<u64> {
+    let config = app.config();
+    if config.topics.is_empty() || ids.is_empty() {
+        return Ok(0);
+    }
+    check_embedding_dim(app).await?;
+    let embeddings = app.embed(&config.topics).await?;
+    let mut tx = app.postgres()?.begin().await?;
+    sqlx::query("DELETE FROM article_topics WHERE article_id = ANY($1)")
+        .bind(ids)
+        .execute(&mut *tx)
+        .await?;
+    // Every topic takes the articles it is closer to than the topics before.
+    let mut routed = 0;
+    for (topic, embedding) in config.topics.iter().zip(embeddings) {
+        let result = sqlx::query(
+            "INSERT INTO article_topics (article_id, topic, similarity)
+            SELECT id, $2, 1 - (embedding <=> $3) FROM articles
+            WHERE id = ANY($1) AND embedding_model = $4 AND 1 - (embedding <=> $3) >= $5
+            ON CONFLICT (article_id) DO UPDATE
+                SET topic = EXCLUDED.topic, similarity = EXCLUDED.similarity, routed_at = now()
+                WHERE EXCLUDED.similarity > article_topics.similarity",
+        )
+        .bind(ids)
+        .bind(topic)
+        .bind(pgvector::Vector::from(embedding))
+        .bind(app.embedding_model())
+        .bind(config.route_threshold)
+        .execute(&mut *tx)
+        .await?;
+        routed += result.rows_affected();
+    }
+    tx.commit().await?;
+    let (assigned,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM article_topics WHERE article_id = ANY($1)")
+            .bind(ids)
+            .fetch_one(app.postgres()?)
+            .await?;
+    log::debug!(
+        "Routed {} of {} articles to topics ({} assignments)",
+        assigned,
+        ids.len(),
+        routed
+    );
+    Ok(assigned as u64)
+}
+
+/// Routes every stored article again, after the topics or the threshold
+/// changed. Assignments to topics that are no longer configured are dropped.
+pub async fn reroute_all(app: &Encrawl) -> anyhow::Result<u64> {
+    sqlx::query("DELETE FROM article_topics WHERE NOT topic = ANY($1)")
+        .bind(&app.config().topics)
+        .execute(app.postgres()?)
+        .await?;
+    let mut after = 0;
+    let mut routed = 0;
+    loop {
+        let ids: Vec<(i64,)> =
+            sqlx::query_as("SELECT id FROM articles WHERE id > $1 ORDER BY id LIMIT $2")
+                .bind(after)
+                .bind(REROUTE_BATCH)
+                .fetch_all(app.postgres()?)
+                .await?;
+        let Some((last,)) = ids.last() else {
+            return Ok(routed);
+        };
+        after = *last;
+        let ids = ids.into_iter().map(|(id,)| id).collect::<Vec<_>>();
+        routed += route(app, &ids).await?;
+        log::info!("Routed articles up to id {}", after);
+    }
+}
+
+/// Number of articles routed to each of `topics` and published since
+/// `since`, the fetch date for articles without one, the most first.
+pub async fn counts(
+    db: &Pool<Postgres>,
+    topics: &[String],
+    since: Option<DateTime<Utc>>,
+) -> anyhow::Result<Vec<TopicArticles>> {
+    Ok(sqlx::query_as(
+        "SELECT topic, COUNT(a.id) AS articles
+        FROM unnest($1::text[]) topic
+        LEFT JOIN article_topics t USING (topic)
+        LEFT JOIN articles a ON a.id = t.article_id
+            AND ($2::timestamptz IS NULL OR COALESCE(a.published_at, a.fetched_at) >= $2)
+        GROUP BY topic ORDER BY articles DESC, topic",
+    )
+    .bind(topics)
+    .bind(since)
+    .fetch_all(db)
+    .await?)
+}
+
+/// The `limit` articles routed to `topic`, the closest first.
+pub async fn articles(app: &Encrawl, topic: &str, limit: i32) -> anyhow::Result<Vec<Article>> {
+    Ok(sqlx::query_as(
+        "SELECT a.id, title, content, url, author, author_source, annotation, extractor, source,
+            domain, lang, synthetic_title, published_at, fetched_at
+        FROM article_topics t JOIN articles a ON a.id = t.article_id
+        WHERE t.topic = $1
+        ORDER BY t.similarity DESC LIMIT $2",
+    )
+    .bind(topic)
+    .bind(limit)
+    .fetch_all(app.read_db().await?)
+    .await?)
+}
@@ -0,0 +1,157 @@
+//! Adaptive poll intervals for crawling sources continuously.
+//!
+//! Every source starts at the shortest interval. Polls that yield new
+//! articles halve it and polls that don't stretch it by half, so active
+//! subreddits are polled often and dormant feeds are backed off.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::app::Encrawl;
+use crate::crawl::crawl;
+use crate::report::save;
+use crate::source::Source;
+
+/// Shortest and longest time between two polls of a source.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+/// What polling one source has yielded so far.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SourceActivity {
+    pub source: String,
+    pub polls: i64,
+    pub new_articles: i64,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    pub last_new_at: Option<DateTime<Utc>>,
+    pub interval_secs: i64,
+    pub next_poll_at: DateTime<Utc>,
+}
+
+/// Interval after a poll that found `new_articles`, within `bounds`.
+pub fn next_interval(current: Duration, new_articles: usize, bounds: Bounds) -> Duration {
+    let next = if new_articles > 0 {
+        current / 2
+    } else {
+        current + current / 2
+    };
+    next.clamp(bounds.min, bounds.max.max(bounds.min))
+}
+
+pub async fn activity(db: &Pool<Postgres>) -> anyhow::Result<Vec<SourceActivity>> {
+    Ok(sqlx::query_as(
+        "SELECT source, polls, new_articles, last_polled_at, last_new_at, interval_secs, next_poll_at
+        FROM source_activity ORDER BY next_poll_at",
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+/// Records a poll of `source` that found `new_articles` and schedules the next one in `interval`.
+pub async fn record(
+    db: &Pool<Postgres>,
+    source: &str,
+    new_articles: usize,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO source_activity (source, polls, new_articles, last_polled_at, last_new_at,
+            interval_secs, next_poll_at)
+        VALUES ($1, 1, $2, now(), CASE WHEN $2 > 0 THEN now() END, $3, now() + make_interval(secs => $3))
+        ON CONFLICT (source) DO UPDATE SET polls = source_activity.polls + 1,
+            new_articles = source_activity.new_articles + EXCLUDED.new_articles,
+            last_polled_at = EXCLUDED.last_polled_at,
+            last_new_at = COALESCE(EXCLUDED.last_new_at, source_activity.last_new_at),
+            interval_secs = EXCLUDED.interval_secs, next_poll_at = EXCLUDED.next_poll_at",
+    )
+    .bind(source)
+    .bind(new_articles as i64)
+    .bind(interval.as_secs() as i64)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Crawls every source whenever it is due, forever, adapting its interval
+/// to how many new articles it yields. The schedule is kept in the database,
+/// so restarts carry on with the learned intervals.
+pub async fn run(
+    app: &Encrawl,
+    mut sources: Vec<Box<dyn Source>>,
+    bounds: Bounds,
+) -> anyhow::Result<()> {
+    loop {
+        let schedule = match activity(app.postgres()?).await {
+            Ok(activity) => activity
+                .into_iter()
+                .map(|activity| (activity.source.clone(), activity))
+                .collect::<HashMap<_, _>>(),
+            Err(e) => {
+                log::error!("Failed to load the crawl schedule: {}", e);
+                tokio::time::sleep(bounds.min).await;
+                continue;
+            }
+        };
+        let now = Utc::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = sources.into_iter().partition(|source| {
+            schedule
+                .get(&source.name())
+                .is_none_or(|activity| activity.next_poll_at <= now)
+        });
+        if !due.is_empty() {
+            log::info!("Crawling {} due sources", due.len());
+            let report = crawl(app, &due).await;
+            match save(app.postgres()?, &report).await {
+                Ok(id) => log::info!("Saved the report of crawl run {}", id),
+                Err(e) => log::error!("Failed to save the crawl report: {}", e),
+            }
+            let found = report.new_articles().collect::<HashMap<_, _>>();
+            for source in &due {
+                let name = source.name();
+                let new_articles = found.get(name.as_str()).copied().unwrap_or_default();
+                let interval = match schedule.get(&name) {
+                    Some(activity) => {
+                        let current = Duration::from_secs(activity.interval_secs.max(0) as u64);
+                        next_interval(current, new_articles, bounds)
+                    }
+                    None => bounds.min,
+                };
+                log::debug!(
+                    "{} yielded {} new articles, polling again in {}",
+                    name,
+                    new_articles,
+                    humantime::format_duration(interval)
+                );
+                if let Err(e) = record(app.postgres()?, &name, new_articles, interval).await {
+                    log::error!("Failed to record the activity of {}: {}", name, e);
+                }
+            }
+        }
+        sources = due.into_iter().chain(waiting).collect();
+
+        // Sleep until the next source is due, sources that were never polled
+        // successfully are retried after the shortest interval.
+        let schedule = activity(app.postgres()?).await?;
+        let now = Utc::now();
+        let next = sources
+            .iter()
+            .map(|source| {
+                let name = source.name();
+                schedule
+                    .iter()
+                    .find(|activity| activity.source == name)
+                    .map_or(bounds.min, |activity| {
+                        (activity.next_poll_at - now).to_std().unwrap_or_default()
+                    })
+            })
+            .min()
+            .unwrap_or(bounds.max);
+        tokio::time::sleep(next.clamp(Duration::from_secs(1), bounds.max)).await;
+    }
+}
@@ -0,0 +1,642 @@
+//! Extraction of articles from news sites, with per-domain CSS selector
+//! rules and a generic fallback for every other site.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::author;
+use crate::candidate::CandidateUrl;
+use crate::crawl::find_scraper;
+use crate::error::EncrawlError;
+use crate::http::HttpClient;
+use crate::page_cache::{page_key, CachedPage, PageCache};
+use crate::policy::Policy;
+use crate::render::Renderer;
+use crate::store::Article;
+use crate::title::{self, TitleRules};
+
+/// Stored as [`Article::extractor`] for articles extracted with a [`ScraperConfig`].
+pub const SCRAPER_EXTRACTOR: &str = "scraper";
+
+/// Stored as [`Article::extractor`] for articles extracted by [`extract_generic`].
+pub const GENERIC_EXTRACTOR: &str = "generic";
+
+/// Paragraphs shorter than this are usually captions, bylines or buttons.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Where pages usually put their publication date.
+const PUBLISHED_SELECTORS: &[&str] = &[
+    "meta[property='article:published_time']",
+    "meta[property='og:published_time']",
+    "meta[itemprop='datePublished']",
+    "meta[name='date']",
+    "time[datetime]",
+];
+
+/// Elements whose paragraphs are never part of the article body.
+const BOILERPLATE_TAGS: &[&str] = &["nav", "header", "footer", "aside", "form"];
+const BOILERPLATE_CLASSES: &[&str] = &["comment", "share", "related", "newsletter", "promo"];
+
+/// One CSS selector or an ordered list of them, tried until one matches.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Selectors {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Selectors {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Self::One(selector) => std::slice::from_ref(selector),
+            Self::Many(selectors) => selectors,
+        }
+    }
+}
+
+/// Selectors used to pull the parts of an article out of a page on `domain`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScraperConfig {
+    pub domain: String,
+    pub author_selector: Selectors,
+    pub content_selector: Selectors,
+    pub title_selector: Selectors,
+    /// Where the publication date is, read from the `datetime` or `content`
+    /// attribute when there is one. The usual `<meta>` tags are tried after.
+    #[serde(default)]
+    pub published_selector: Option<Selectors>,
+    /// What kind of source the domain is, e.g. "official statement",
+    /// "opinion" or "blog". Stored with its articles and shown to the summariser.
+    #[serde(default)]
+    pub annotation: Option<String>,
+    /// Sitemap listing the site's articles, read by `crawl --sites`.
+    #[serde(default)]
+    pub sitemap_url: Option<String>,
+    /// Page linking to the site's latest articles, read by `crawl --sites`.
+    #[serde(default)]
+    pub index_url: Option<String>,
+    /// Links to articles on the index page, any link on the domain when unset.
+    #[serde(default)]
+    pub link_selector: Option<Selectors>,
+    /// Load pages in a headless browser before extracting them, for sites
+    /// that build the article with JavaScript. Needs `--webdriver-url`.
+    #[serde(default)]
+    pub render: bool,
+    /// License the site publishes its articles under, e.g. `cc-by-4.0`, which
+    /// `publish --license` picks the articles of a dataset by.
+    #[serde(default)]
+    pub license: Option<String>,
+    /// How titles are cleaned of site names and labels, see [`TitleRules`].
+    #[serde(default)]
+    pub title_rules: TitleRules,
+}
+
+/// Fields of a page none of the configured selectors matched.
+#[derive(Debug)]
+pub struct ExtractError {
+    pub url: String,
+    pub missing: Vec<&'static str>,
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no selector for {} matched on {}",
+            self.missing.join(", "),
+            self.url
+        )
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// Page metadata found next to the article body.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageMetadata {
+    /// Publication date as written on the page, usually RFC 3339.
+    pub published_at: Option<String>,
+    /// Target of `<link rel="canonical">` or `og:url`.
+    pub canonical_url: Option<String>,
+    /// OpenGraph, Twitter card, `article:*` and description `<meta>` tags.
+    pub meta: BTreeMap<String, String>,
+    /// `ETag` header of the response, kept in the [`PageCache`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the response, kept in the [`PageCache`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// [`page_key`] of the HTML, kept in the [`PageCache`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_key: Option<String>,
+}
+
+impl PageMetadata {
+    /// Parses [`Self::published_at`] as RFC 3339, RFC 2822 or a plain date.
+    pub fn published_at(&self) -> Option<DateTime<Utc>> {
+        let value = self.published_at.as_deref()?;
+        DateTime::parse_from_rfc3339(value)
+            .or_else(|_| DateTime::parse_from_rfc2822(value))
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                let date = NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+                Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+            })
+    }
+}
+
+impl ScraperConfig {
+    /// Reads a RON list of scraper configs, e.g. `scrapers.ron`.
+    pub fn from_file(path: PathBuf) -> anyhow::Result<Vec<Self>> {
+        Ok(ron::from_str(&String::from_utf8(std::fs::read(path)?)?)?)
+    }
+
+    /// Downloads `url` and extracts an article from it with this config's
+    /// selectors, unless `policy` disallows it. Returns `None` when the page
+    /// is unchanged since it was last fetched, see [`fetch_page`]. Pages of
+    /// scrapers with [`Self::render`] are loaded with `renderer` instead.
+    pub async fn get_article(
+        &self,
+        http: &HttpClient,
+        policy: &Policy,
+        cache: &PageCache,
+        renderer: Option<&Renderer>,
+        url: String,
+    ) -> Result<Option<Article>, EncrawlError> {
+        let Some(page) = self.fetch(http, policy, cache, renderer, &url).await? else {
+            return Ok(None);
+        };
+        let mut article = self.extract(url, &page.html)?;
+        page.validators_into(&mut article);
+        Ok(Some(article))
+    }
+
+    /// Downloads `url`, or loads it with `renderer` for scrapers with
+    /// [`Self::render`], like [`Self::get_article`] does before extracting.
+    pub async fn fetch(
+        &self,
+        http: &HttpClient,
+        policy: &Policy,
+        cache: &PageCache,
+        renderer: Option<&Renderer>,
+        url: &str,
+    ) -> Result<Option<Page>, EncrawlError> {
+        if !self.render {
+            return fetch_page(http, policy, cache, url).await;
+        }
+        let renderer = renderer.ok_or_else(|| {
+            EncrawlError::Network(anyhow::anyhow!(
+                "{} is rendered in a browser, which needs --webdriver-url",
+                self.domain
+            ))
+        })?;
+        render_page(
+            http,
+            policy,
+            cache,
+            renderer,
+            url,
+            self.content_selector.as_slice(),
+        )
+        .await
+    }
+
+    /// Extracts an article from `html` with this config's selectors, failing
+    /// when no content selector matches.
+    ///
+    /// A missing title is left empty to be generated later, see
+    /// [`crate::summarise::generate_headlines`]. A missing author is only
+    /// logged, plenty of articles don't name one.
+    pub fn extract(&self, url: String, html: &str) -> Result<Article, ExtractError> {
+        let document = scraper::Html::parse_document(html);
+        let title = select_first(&document, self.title_selector.as_slice());
+        let content = select_first(&document, self.content_selector.as_slice());
+        if content.is_none() {
+            return Err(ExtractError {
+                url,
+                missing: vec!["content"],
+            });
+        }
+        if title.is_none() {
+            log::debug!("No title selector matched on {}", url);
+        }
+        let content = content.unwrap_or_default();
+        let author = author::find(&document, self.author_selector.as_slice(), &content);
+        if author.is_none() {
+            log::debug!("No author found on {}", url);
+        }
+        let published = self
+            .published_selector
+            .as_ref()
+            .map_or(&[][..], Selectors::as_slice);
+        let metadata = page_metadata(&document, published);
+        Ok(Article {
+            id: None,
+            title: self.title_rules.clean(&title.unwrap_or_default()),
+            author: author
+                .as_ref()
+                .map(|(author, _)| author.clone())
+                .unwrap_or_default(),
+            author_source: author.map(|(_, source)| source.to_string()),
+            content,
+            url,
+            annotation: self.annotation.clone(),
+            extractor: Some(SCRAPER_EXTRACTOR.to_string()),
+            source: None,
+            domain: None,
+            lang: None,
+            synthetic_title: false,
+            published_at: metadata.published_at(),
+            fetched_at: Some(Utc::now()),
+            enrichments: BTreeMap::new(),
+            metadata: Some(metadata),
+        })
+    }
+}
+
+/// Text of every element matched by the first of `selectors` that matches
+/// something other than whitespace.
+fn select_first(document: &scraper::Html, selectors: &[String]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = match scraper::Selector::parse(selector) {
+            Ok(selector) => selector,
+            Err(e) => {
+                log::warn!("Skipping invalid selector {:?}: {}", selector, e);
+                return None;
+            }
+        };
+        let text = document
+            .select(&selector)
+            .map(|e| e.text().to_owned().collect::<Vec<&str>>().join("\n"))
+            .collect::<Vec<String>>()
+            .join("\n");
+        (!text.trim().is_empty()).then_some(text)
+    })
+}
+
+/// Collects the metadata of a page, looking for the publication date with
+/// `published_selectors` first.
+pub fn page_metadata(document: &scraper::Html, published_selectors: &[String]) -> PageMetadata {
+    let meta_selector = scraper::Selector::parse("meta").unwrap();
+    let mut meta = BTreeMap::new();
+    for element in document.select(&meta_selector) {
+        let element = element.value();
+        let Some(key) = element.attr("property").or_else(|| element.attr("name")) else {
+            continue;
+        };
+        let tracked = ["og:", "twitter:", "article:"]
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+            || key == "description";
+        if let (true, Some(content)) = (tracked, element.attr("content")) {
+            meta.entry(key.to_string())
+                .or_insert_with(|| content.trim().to_string());
+        }
+    }
+    let published_at = published_selectors
+        .iter()
+        .map(String::as_str)
+        .chain(PUBLISHED_SELECTORS.iter().copied())
+        .find_map(|selector| {
+            let selector = scraper::Selector::parse(selector).ok()?;
+            let element = document.select(&selector).next()?;
+            let value = element
+                .value()
+                .attr("datetime")
+                .or_else(|| element.value().attr("content"))
+                .map(str::to_string)
+                .unwrap_or_else(|| element.text().collect::<String>());
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        });
+    let canonical_selector = scraper::Selector::parse("link[rel='canonical']").unwrap();
+    let canonical_url = document
+        .select(&canonical_selector)
+        .find_map(|e| e.value().attr("href"))
+        .map(str::to_string)
+        .or_else(|| meta.get("og:url").cloned());
+    PageMetadata {
+        published_at,
+        canonical_url,
+        meta,
+        ..Default::default()
+    }
+}
+
+/// A downloaded page and the validators to ask whether it changed with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page {
+    pub html: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Page {
+    /// Keeps the validators and key in the metadata of `article`, so they can
+    /// be recorded in the [`PageCache`] once it is stored.
+    pub(crate) fn validators_into(self, article: &mut Article) {
+        let metadata = article.metadata.get_or_insert_with(Default::default);
+        metadata.page_key = Some(page_key(&self.html));
+        metadata.etag = self.etag;
+        metadata.last_modified = self.last_modified;
+    }
+}
+
+/// Whether `html` has the key of the page `cached` was stored from, in which
+/// case the page is marked as fetched again in `cache`.
+async fn unchanged(cache: &PageCache, cached: Option<&CachedPage>, url: &str, html: &str) -> bool {
+    let Some(key) = cached.and_then(|cached| cached.page_key.as_deref()) else {
+        return false;
+    };
+    if key != page_key(html) {
+        return false;
+    }
+    log::debug!("Skipping {}, same content as when it was last stored", url);
+    if let Err(e) = cache.touch(url).await {
+        log::warn!("Failed to update {} in the page cache: {}", url, e);
+    }
+    true
+}
+
+/// Downloads `url` unless the crawl policy or the site's robots.txt disallows it.
+///
+/// Returns `None` without downloading it when the page is in `cache` and
+/// either was fetched within its max age or the site answers that it is
+/// unchanged since, and after downloading it when its HTML is the same as
+/// when it was last stored.
+pub async fn fetch_page(
+    http: &HttpClient,
+    policy: &Policy,
+    cache: &PageCache,
+    url: &str,
+) -> Result<Option<Page>, EncrawlError> {
+    let cached = cache.get(url).await.unwrap_or_else(|e| {
+        log::warn!("Failed to look up {} in the page cache: {}", url, e);
+        None
+    });
+    if cached
+        .as_ref()
+        .is_some_and(|cached| cached.is_fresh(cache.max_age()))
+    {
+        log::debug!("Skipping {}, fetched within {:?}", url, cache.max_age());
+        return Ok(None);
+    }
+    policy
+        .check(http, url)
+        .await
+        .map_err(EncrawlError::Blocked)?;
+    let mut request = http.get(url);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let resp = http.send(request).await.map_err(EncrawlError::Network)?;
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        log::debug!("Skipping {}, unchanged since it was last fetched", url);
+        if let Err(e) = cache.touch(url).await {
+            log::warn!("Failed to update {} in the page cache: {}", url, e);
+        }
+        return Ok(None);
+    }
+    let resp = resp
+        .error_for_status()
+        .map_err(|e| EncrawlError::Network(e.into()))?;
+    let validator = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = validator(header::ETAG);
+    let last_modified = validator(header::LAST_MODIFIED);
+    let html = resp
+        .text()
+        .await
+        .map_err(|e| EncrawlError::Network(e.into()))?;
+    if unchanged(cache, cached.as_ref(), url, &html).await {
+        return Ok(None);
+    }
+    Ok(Some(Page {
+        html,
+        etag,
+        last_modified,
+    }))
+}
+
+/// Loads `url` in a headless browser and returns its HTML once one of
+/// `selectors` shows up, unless the crawl policy or robots.txt disallows it.
+///
+/// Returns `None` when the page is in `cache` and was fetched within its max
+/// age, or its HTML is the same as when it was last stored. Rendered pages
+/// come without validators, so older ones are always loaded again.
+pub async fn render_page(
+    http: &HttpClient,
+    policy: &Policy,
+    cache: &PageCache,
+    renderer: &Renderer,
+    url: &str,
+    selectors: &[String],
+) -> Result<Option<Page>, EncrawlError> {
+    let cached = cache.get(url).await.unwrap_or_else(|e| {
+        log::warn!("Failed to look up {} in the page cache: {}", url, e);
+        None
+    });
+    if cached
+        .as_ref()
+        .is_some_and(|cached| cached.is_fresh(cache.max_age()))
+    {
+        log::debug!("Skipping {}, fetched within {:?}", url, cache.max_age());
+        return Ok(None);
+    }
+    policy
+        .check(http, url)
+        .await
+        .map_err(EncrawlError::Blocked)?;
+    let html = renderer
+        .render(url, selectors)
+        .await
+        .map_err(EncrawlError::Network)?;
+    if unchanged(cache, cached.as_ref(), url, &html).await {
+        return Ok(None);
+    }
+    Ok(Some(Page {
+        html,
+        etag: None,
+        last_modified: None,
+    }))
+}
+
+/// Extracts the page `candidate` links to with the scraper configured for
+/// its domain, falling back to [`extract_generic`] with the `title_rules` of
+/// the domain for domains without one. Returns `None` when the page is
+/// unchanged since it was last fetched, see [`fetch_page`].
+pub async fn get_article(
+    scrapers: &[ScraperConfig],
+    title_rules: &BTreeMap<String, TitleRules>,
+    http: &HttpClient,
+    policy: &Policy,
+    cache: &PageCache,
+    renderer: Option<&Renderer>,
+    candidate: &CandidateUrl,
+) -> Result<Option<Article>, EncrawlError> {
+    let url = candidate.as_str().to_string();
+    if let Some(scraper) = find_scraper(scrapers, &url) {
+        return scraper
+            .get_article(http, policy, cache, renderer, url)
+            .await;
+    }
+    let Some(page) = fetch_page(http, policy, cache, &url).await? else {
+        return Ok(None);
+    };
+    let mut article = extract_page(scrapers, title_rules, url, &page.html)?;
+    page.validators_into(&mut article);
+    Ok(Some(article))
+}
+
+/// Extracts the article of `url` from its `html` like [`get_article`] does
+/// once the page is fetched, failing when there is no text.
+pub fn extract_page(
+    scrapers: &[ScraperConfig],
+    title_rules: &BTreeMap<String, TitleRules>,
+    url: String,
+    html: &str,
+) -> Result<Article, EncrawlError> {
+    if let Some(scraper) = find_scraper(scrapers, &url) {
+        return Ok(scraper.extract(url, html)?);
+    }
+    let rules = title::rules_for(title_rules, &url);
+    let article = extract_generic(url, html, rules);
+    if article.content.is_empty() {
+        return Err(EncrawlError::Parse(format!(
+            "no article text found in {}",
+            article.url
+        )));
+    }
+    Ok(article)
+}
+
+/// Extracts an article from any page, for domains without a [`ScraperConfig`].
+///
+/// The title and author come from the usual `<meta>` tags and markup, the body
+/// is the paragraphs of the element holding the most paragraph text that
+/// isn't made of links, outside of navigation, headers and footers. The title
+/// is cleaned with `title_rules`.
+pub fn extract_generic(url: String, html: &str, title_rules: &TitleRules) -> Article {
+    let document = scraper::Html::parse_document(html);
+    let title = first_meta(
+        &document,
+        &["meta[property='og:title']", "meta[name='twitter:title']"],
+    )
+    .or_else(|| first_text(&document, &["article h1", "h1", "title"]))
+    .map(|title| title_rules.clean(&title))
+    .unwrap_or_default();
+    let content = main_text(&document);
+    let author = author::find(&document, author::GENERIC_SELECTORS, &content);
+    let metadata = page_metadata(&document, &[]);
+    Article {
+        id: None,
+        title,
+        author: author
+            .as_ref()
+            .map(|(author, _)| author.clone())
+            .unwrap_or_default(),
+        author_source: author.map(|(_, source)| source.to_string()),
+        content,
+        url,
+        annotation: None,
+        extractor: Some(GENERIC_EXTRACTOR.to_string()),
+        source: None,
+        domain: None,
+        lang: None,
+        synthetic_title: false,
+        published_at: metadata.published_at(),
+        fetched_at: Some(Utc::now()),
+        enrichments: BTreeMap::new(),
+        metadata: Some(metadata),
+    }
+}
+
+fn first_meta(document: &scraper::Html, selectors: &[&str]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("content"))
+            .map(str::trim)
+            .find(|content| !content.is_empty())
+            .map(str::to_string)
+    })
+}
+
+fn first_text(document: &scraper::Html, selectors: &[&str]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .map(|e| collapse_whitespace(&e.text().collect::<String>()))
+            .find(|text| !text.is_empty())
+    })
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_boilerplate(element: scraper::ElementRef) -> bool {
+    let tag = element.value().name();
+    let class = element
+        .value()
+        .attr("class")
+        .unwrap_or_default()
+        .to_lowercase();
+    BOILERPLATE_TAGS.contains(&tag) || BOILERPLATE_CLASSES.iter().any(|c| class.contains(c))
+}
+
+/// Joins the paragraphs of the element with the highest text density.
+fn main_text(document: &scraper::Html) -> String {
+    let paragraph = scraper::Selector::parse("p").unwrap();
+    let link = scraper::Selector::parse("a").unwrap();
+    let mut candidates: Vec<(_, usize, Vec<String>)> = vec![];
+    for p in document.select(&paragraph) {
+        if p.ancestors()
+            .filter_map(scraper::ElementRef::wrap)
+            .any(is_boilerplate)
+        {
+            continue;
+        }
+        let text = collapse_whitespace(&p.text().collect::<String>());
+        if text.chars().count() < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let link_chars = p
+            .select(&link)
+            .flat_map(|a| a.text())
+            .map(|t| t.chars().count())
+            .sum::<usize>();
+        let text_chars = text.chars().count();
+        let score = text_chars.saturating_sub(link_chars);
+        let Some(parent) = p.parent() else {
+            continue;
+        };
+        match candidates.iter_mut().find(|(id, _, _)| *id == parent.id()) {
+            Some((_, total, paragraphs)) => {
+                *total += score;
+                paragraphs.push(text);
+            }
+            None => candidates.push((parent.id(), score, vec![text])),
+        }
+    }
+    candidates
+        .into_iter()
+        .max_by_key(|(_, score, _)| *score)
+        .map(|(_, _, paragraphs)| paragraphs.join("\n"))
+        .unwrap_or_default()
+}
@@ -0,0 +1,374 @@
+//! HTTP API over the crawled corpus.
+
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+
+use crate::app::Encrawl;
+use crate::ask::{ask, Answer};
+use crate::events;
+use crate::http::RateLimiter;
+use crate::share::{self, ShareError, SHARE_BURST, SHARE_PATH, SHARE_RATE};
+use crate::sink;
+use crate::stats::{self, Stats};
+use crate::store::{search, search_filtered, Article, SearchFilter};
+use crate::summarise::Summarisable;
+use crate::usage;
+
+#[derive(Serialize, Deserialize)]
+struct NewsQuery {
+    topic: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchQuery {
+    q: String,
+    #[serde(default = "default_limit")]
+    limit: i32,
+    #[serde(default)]
+    domain: Option<String>,
+    /// RFC 3339 timestamp.
+    #[serde(default)]
+    since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    author: Option<String>,
+    /// Comma-separated terms every article has to contain.
+    #[serde(default)]
+    must_contain: Option<String>,
+    /// Comma-separated terms no article may mention.
+    #[serde(default)]
+    exclude: Option<String>,
+    /// ISO 639-1 code of the language articles are written in.
+    #[serde(default)]
+    lang: Option<String>,
+}
+
+/// Expiry and signature of a share link, see [`share::link`].
+#[derive(Serialize, Deserialize)]
+struct ShareQuery {
+    expires: i64,
+    sig: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StatsQuery {
+    /// Days covered by the daily series.
+    #[serde(default = "default_stats_days")]
+    days: u64,
+    #[serde(default = "default_stats_top")]
+    top: i64,
+}
+
+fn default_stats_days() -> u64 {
+    30
+}
+
+fn default_stats_top() -> i64 {
+    10
+}
+
+#[derive(Serialize, Deserialize)]
+struct AskRequest {
+    question: String,
+    #[serde(default = "default_limit")]
+    limit: i32,
+    #[serde(default = "default_chunks")]
+    chunks: usize,
+}
+
+fn default_chunks() -> usize {
+    6
+}
+
+#[derive(Serialize, Deserialize)]
+struct SummarizeRequest {
+    query: String,
+    #[serde(default = "default_limit")]
+    limit: i32,
+}
+
+/// How far back `/calendar.ics` looks for articles mentioning events.
+const CALENDAR_LOOKBACK_DAYS: i64 = 30;
+
+fn default_limit() -> i32 {
+    5
+}
+
+pub fn router(app: Encrawl) -> Router {
+    Router::new()
+        .route("/", get(|| async { "Hello, World!" }))
+        .route("/news", get(get_news))
+        .route("/search", get(get_search))
+        .route("/summarize", post(post_summarize))
+        .route("/ask", post(post_ask))
+        .route("/calendar.ics", get(get_calendar))
+        .route("/stats", get(get_stats))
+        .route("/admin/reload", post(reload))
+        .route(&format!("{SHARE_PATH}/:id"), get(get_shared_digest))
+        .layer(Extension(Arc::new(RateLimiter::with_burst(
+            SHARE_RATE,
+            SHARE_BURST,
+        ))))
+        .with_state(app)
+}
+
+/// Serves the crawl metrics of `app` on `/metrics` for Prometheus to scrape.
+pub fn metrics_router(app: Encrawl) -> Router {
+    Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(app)
+}
+
+async fn get_metrics(State(app): State<Encrawl>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        app.metrics().render(),
+    )
+        .into_response()
+}
+
+#[axum::debug_handler]
+async fn get_news(State(app): State<Encrawl>, q: Query<NewsQuery>) -> Result<String, StatusCode> {
+    let articles = search(&app, q.topic.clone(), 5)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let config = app.config();
+    let (summary, tokens) = app
+        .with_generator(move |generator| {
+            articles.get_checked_summary(&config.guardrails, &config.summary_budget, generator)
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Err(e) = usage::record(app.db(), &q.topic, app.config().summarizer.name(), tokens).await
+    {
+        log::error!("Failed to record usage: {}", e);
+    }
+    Ok(summary)
+}
+
+async fn get_search(
+    State(app): State<Encrawl>,
+    q: Query<SearchQuery>,
+) -> Result<Json<Vec<Article>>, StatusCode> {
+    let filter = SearchFilter {
+        domain: q.domain.clone(),
+        since: q.since,
+        author: q.author.clone(),
+        must_contain: q
+            .must_contain
+            .iter()
+            .flat_map(|terms| terms.split(','))
+            .map(str::to_string)
+            .collect(),
+        exclude: q
+            .exclude
+            .iter()
+            .flat_map(|terms| terms.split(','))
+            .map(str::to_string)
+            .collect(),
+        lang: q.lang.clone(),
+    };
+    search_filtered(&app, q.q.clone(), q.limit, &filter)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            log::error!("Search for {:?} failed: {}", q.q, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Streams the summary as plain text while it is being generated.
+async fn post_summarize(
+    State(app): State<Encrawl>,
+    Json(req): Json<SummarizeRequest>,
+) -> Result<Response, StatusCode> {
+    let articles = search(&app, req.query.clone(), req.limit)
+        .await
+        .map_err(|e| {
+            log::error!("Search failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    // Load the generator before answering so a failure is still a proper 500.
+    drop(app.generator().await.map_err(|e| {
+        log::error!("Failed to load the text generator: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?);
+    let (tx, rx) = futures::channel::mpsc::unbounded::<Result<String, std::io::Error>>();
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let result = handle.block_on(app.generator()).and_then(|mut generator| {
+            let budget = &app.config().summary_budget;
+            let tokens = articles.stream_summary(budget, generator.as_mut(), |text| {
+                Ok(tx.unbounded_send(Ok(text.to_string()))?)
+            })?;
+            let stats = generator.last_stats();
+            log::info!(
+                "Streamed {} tokens ({:.2} token/s)",
+                stats.usage.completion_tokens,
+                stats.tokens_per_second()
+            );
+            Ok(tokens)
+        });
+        match result {
+            Ok(tokens) => {
+                let recorded =
+                    usage::record(app.db(), &req.query, app.config().summarizer.name(), tokens);
+                if let Err(e) = handle.block_on(recorded) {
+                    log::error!("Failed to record usage: {}", e);
+                }
+            }
+            Err(e) => {
+                log::error!("Summarisation failed: {}", e);
+                let _ = tx.unbounded_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        Body::from_stream(rx),
+    )
+        .into_response())
+}
+
+/// Answers a question as JSON together with the chunks it was based on.
+async fn post_ask(
+    State(app): State<Encrawl>,
+    Json(req): Json<AskRequest>,
+) -> Result<Json<Answer>, StatusCode> {
+    let answer = ask(&app, &req.question, req.limit, req.chunks)
+        .await
+        .map_err(|e| {
+            log::error!("Answering {:?} failed: {}", req.question, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if let Err(e) = usage::record(
+        app.db(),
+        &req.question,
+        app.config().summarizer.name(),
+        answer.usage,
+    )
+    .await
+    {
+        log::error!("Failed to record usage: {}", e);
+    }
+    Ok(Json(answer))
+}
+
+/// Upcoming events found in the articles of the last [`CALENDAR_LOOKBACK_DAYS`] days.
+async fn get_calendar(State(app): State<Encrawl>) -> Result<Response, StatusCode> {
+    let lookback = chrono::Duration::days(CALENDAR_LOOKBACK_DAYS);
+    let events = events::upcoming(&app, lookback).await.map_err(|e| {
+        log::error!("Failed to collect events: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        events::to_ics(&events, Utc::now()),
+    )
+        .into_response())
+}
+
+/// Daily series and totals for a dashboard, the same as `encrawl-rust stats`.
+async fn get_stats(
+    State(app): State<Encrawl>,
+    q: Query<StatsQuery>,
+) -> Result<Json<Stats>, StatusCode> {
+    let since = Duration::from_secs(q.days.saturating_mul(24 * 60 * 60));
+    let db = app.read_db().await.map_err(|e| {
+        log::error!("Failed to collect stats: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    stats::collect(db, since, q.top)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            log::error!("Failed to collect stats: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// A digest as markdown, to anyone with a share link to it that hasn't
+/// expired. Each digest is served at most [`SHARE_RATE`] times a second.
+async fn get_shared_digest(
+    State(app): State<Encrawl>,
+    Extension(limiter): Extension<Arc<RateLimiter>>,
+    Path(id): Path<i64>,
+    q: Query<ShareQuery>,
+) -> Result<Response, StatusCode> {
+    let Some(secret) = app.config().share_secret.clone() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    share::verify(&secret, id, q.expires, &q.sig, Utc::now()).map_err(|e| {
+        log::debug!("Refused a share link to digest {}: {}", id, e);
+        match e {
+            ShareError::Expired => StatusCode::GONE,
+            ShareError::InvalidSignature => StatusCode::FORBIDDEN,
+        }
+    })?;
+    if !limiter.try_acquire(&id.to_string()) {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+    let db = app.read_db().await.map_err(|e| {
+        log::error!("Failed to load digest {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let digest = sink::load(db, id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load digest {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/markdown; charset=utf-8"),
+            (header::CACHE_CONTROL, "private, no-store"),
+        ],
+        digest.body,
+    )
+        .into_response())
+}
+
+async fn reload(State(app): State<Encrawl>, headers: HeaderMap) -> StatusCode {
+    let Some(token) = app.config().admin_token.clone() else {
+        return StatusCode::NOT_FOUND;
+    };
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        // Compared in constant time, so the token can't be guessed byte by byte
+        // from how long a rejection takes.
+        .is_some_and(|given| bool::from(given.as_bytes().ct_eq(token.as_bytes())));
+    if !authorized {
+        return StatusCode::UNAUTHORIZED;
+    }
+    match app.reload().await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(e) => {
+            log::error!("Failed to reload config: {}", e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+    }
+}
+
+/// Reloads the config of `app` whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub async fn reload_on_sighup(app: Encrawl) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        if let Err(e) = app.reload().await {
+            log::error!("Failed to reload config: {}", e);
+        }
+    }
+    Ok(())
+}
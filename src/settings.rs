@@ -0,0 +1,217 @@
+//! Settings read from `config.toml` and the environment.
+//!
+//! Every setting is optional and stands in for the default of the matching
+//! CLI flag, so flags still override the file. Secrets can be left out of
+//! the file and the command line and given as environment variables instead,
+//! which take precedence over the file:
+//!
+//! - `ENCRAWL_DATABASE_URL`, or `DATABASE_URL`
+//! - `ENCRAWL_READ_DATABASE_URL`
+//! - `ENCRAWL_REDDIT_CLIENT_ID` and `ENCRAWL_REDDIT_CLIENT_SECRET`
+//! - `ENCRAWL_OPENAI_API_KEY`
+//! - `ENCRAWL_ADMIN_TOKEN`
+//! - `ENCRAWL_SHARE_SECRET`
+//! - `ENCRAWL_TELEGRAM_BOT_TOKEN`
+//! - `ENCRAWL_HF_TOKEN`
+//!
+//! ```toml
+//! database_url = "postgres://encrawl@localhost/encrawl"
+//! user_agent = "encrawl (+https://example.com/bot)"
+//!
+//! [paths]
+//! subs = "finance_subs.list"
+//! scrapers = "scrapers.ron"
+//!
+//! [models]
+//! embedding = "all-MiniLM-L12-v2"
+//! summarizer = "openai"
+//! generation_seed = 42
+//!
+//! [models.embed_text_sources]
+//! longform = "chunks"
+//!
+//! [openai]
+//! url = "http://localhost:8080/v1"
+//!
+//! [digest]
+//! topics = ["interest rates", "crypto regulation"]
+//!
+//! [enrich.sources]
+//! "r/stocks" = ["language", "tickers", "sentiment"]
+//!
+//! [title_rules."example.com"]
+//! prefixes = ["Opinion:"]
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::embed_text::EmbedText;
+use crate::enrich::EnricherKind;
+use crate::title::TitleRules;
+
+/// Where the settings are read from unless `--config` says otherwise.
+pub const DEFAULT_PATH: &str = "config.toml";
+
+/// Files the configuration is read from.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paths {
+    pub subs: Option<PathBuf>,
+    pub scrapers: Option<PathBuf>,
+    pub feeds: Option<PathBuf>,
+    pub sources: Option<PathBuf>,
+    pub sinks: Option<PathBuf>,
+    pub policy: Option<PathBuf>,
+    pub banned_phrases: Option<PathBuf>,
+}
+
+/// Credentials of the Reddit app subreddits are fetched with.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedditSettings {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Models and where they run, named like the values of the CLI flags.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelSettings {
+    pub embedding: Option<String>,
+    pub embedding_backend: Option<String>,
+    pub embedding_device: Option<String>,
+    pub embedding_dtype: Option<String>,
+    /// Copies of the embedding model embedding in parallel.
+    pub embedding_instances: Option<usize>,
+    /// Cross-encoder of `search --rerank`.
+    pub rerank: Option<String>,
+    pub embed_text: Option<String>,
+    /// Text embedded for the articles of these sources instead of
+    /// `embed_text`, by source name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub embed_text_sources: BTreeMap<String, EmbedText>,
+    pub summarizer: Option<String>,
+    pub generation: Option<String>,
+    pub generation_model_id: Option<String>,
+    pub generation_device: Option<String>,
+    pub generation_dtype: Option<String>,
+    /// Seed summaries are sampled with, recorded with every digest.
+    pub generation_seed: Option<u64>,
+}
+
+/// Server used with `summarizer = "openai"`.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenAiSettings {
+    pub url: Option<String>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+}
+
+/// Analyses run on crawled articles, see [`crate::enrich`].
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnrichSettings {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub enrichers: Vec<EnricherKind>,
+    /// Enrichers of the articles of these sources instead, by source name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, Vec<EnricherKind>>,
+}
+
+/// Topics `digest` summarises when none are given.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestSettings {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+}
+
+/// Account datasets are published with.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HuggingFaceSettings {
+    pub token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelegramSettings {
+    pub bot_token: Option<String>,
+}
+
+/// Contents of `config.toml`, with the secrets given in the environment applied.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub database_url: Option<String>,
+    /// Read-only replica used for searches and stats.
+    pub read_database_url: Option<String>,
+    pub user_agent: Option<String>,
+    /// WebDriver server scrapers with `render: true` load pages through.
+    pub webdriver_url: Option<String>,
+    /// Bearer token of the admin endpoints of `serve`.
+    pub admin_token: Option<String>,
+    /// Key share links of digests are signed with.
+    pub share_secret: Option<String>,
+    /// URL `serve` is reached at, which share links point to.
+    pub public_url: Option<String>,
+    pub paths: Paths,
+    pub reddit: RedditSettings,
+    pub models: ModelSettings,
+    pub openai: OpenAiSettings,
+    pub telegram: TelegramSettings,
+    pub huggingface: HuggingFaceSettings,
+    pub digest: DigestSettings,
+    pub enrich: EnrichSettings,
+    /// How the titles of sites read with the generic extractor are cleaned,
+    /// by domain.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub title_rules: BTreeMap<String, TitleRules>,
+}
+
+impl Settings {
+    /// Reads `path` when it exists and applies the environment on top.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut settings = Self::read(path)?;
+        settings.apply_env();
+        Ok(settings)
+    }
+
+    /// Reads `path` when it exists, without the environment.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// Replaces the secrets with the environment variables that are set.
+    fn apply_env(&mut self) {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let secrets = [
+            (&mut self.read_database_url, "ENCRAWL_READ_DATABASE_URL"),
+            (&mut self.reddit.client_id, "ENCRAWL_REDDIT_CLIENT_ID"),
+            (
+                &mut self.reddit.client_secret,
+                "ENCRAWL_REDDIT_CLIENT_SECRET",
+            ),
+            (&mut self.openai.api_key, "ENCRAWL_OPENAI_API_KEY"),
+            (&mut self.admin_token, "ENCRAWL_ADMIN_TOKEN"),
+            (&mut self.share_secret, "ENCRAWL_SHARE_SECRET"),
+            (&mut self.telegram.bot_token, "ENCRAWL_TELEGRAM_BOT_TOKEN"),
+            (&mut self.huggingface.token, "ENCRAWL_HF_TOKEN"),
+        ];
+        for (value, name) in secrets {
+            if let Some(set) = var(name) {
+                *value = Some(set);
+            }
+        }
+        if let Some(url) = var("ENCRAWL_DATABASE_URL").or_else(|| var("DATABASE_URL")) {
+            self.database_url = Some(url);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Links showing a single digest to anyone holding them until they expire,
+//! so a summary can be passed on outside the team. A link carries its expiry
+//! and an HMAC-SHA256 of the digest id and expiry under the configured
+//! `share_secret`, so nothing is stored per link and changing the secret
+//! revokes all of them. `serve` answers them on [`SHARE_PATH`], at most
+//! [`SHARE_RATE`] requests per second per digest after a burst of
+//! [`SHARE_BURST`].
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Path shared digests are served under, followed by their id.
+pub const SHARE_PATH: &str = "/share/digests";
+
+/// Requests per second a shared digest is served at once its burst is spent.
+pub const SHARE_RATE: f64 = 0.2;
+
+/// Requests a shared digest is served at once.
+pub const SHARE_BURST: f64 = 10.0;
+
+/// Why a share link was refused.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ShareError {
+    #[error("the link expired")]
+    Expired,
+    #[error("the link isn't signed with the share secret")]
+    InvalidSignature,
+}
+
+fn mac(secret: &str, digest_id: i64, expires: i64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{digest_id}.{expires}").as_bytes());
+    mac
+}
+
+/// Hex encoded signature of a link to `digest_id` expiring at the Unix
+/// timestamp `expires`.
+pub fn sign(secret: &str, digest_id: i64, expires: i64) -> String {
+    hex::encode(mac(secret, digest_id, expires).finalize().into_bytes())
+}
+
+/// Checks that `signature` was made by [`sign`] with `secret`, in constant
+/// time, and that the link hasn't expired by `now`.
+pub fn verify(
+    secret: &str,
+    digest_id: i64,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Utc>,
+) -> Result<(), ShareError> {
+    let signature = hex::decode(signature).map_err(|_| ShareError::InvalidSignature)?;
+    mac(secret, digest_id, expires)
+        .verify_slice(&signature)
+        .map_err(|_| ShareError::InvalidSignature)?;
+    if now.timestamp() >= expires {
+        return Err(ShareError::Expired);
+    }
+    Ok(())
+}
+
+/// Link to `digest_id` on the server at `base_url`, valid until `expires`.
+pub fn link(base_url: &str, secret: &str, digest_id: i64, expires: DateTime<Utc>) -> String {
+    let expires = expires.timestamp();
+    format!(
+        "{}{SHARE_PATH}/{digest_id}?expires={expires}&sig={}",
+        base_url.trim_end_matches('/'),
+        sign(secret, digest_id, expires)
+    )
+}
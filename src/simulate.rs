@@ -0,0 +1,101 @@
+//! Dry runs of the first stages of a crawl, for tuning sources and filters
+//! without fetching a single article page.
+
+use futures::{stream, StreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::app::Encrawl;
+use crate::candidate::CandidateUrl;
+use crate::crawl::find_scraper;
+use crate::source::Source;
+
+/// Name [`Simulation::scrapers`] counts the generic extractor under.
+pub const GENERIC: &str = "generic";
+
+/// What crawling one source would do with the posts it lists now.
+#[derive(Serialize, Debug, Default)]
+pub struct Simulation {
+    /// Posts listed by the source.
+    pub posts: usize,
+    /// Posts linking to Reddit itself, which are never scraped.
+    pub internal: usize,
+    /// URLs the policy denies, with the reason.
+    pub denied: Vec<(String, String)>,
+    /// URLs already stored, which a crawl would only update.
+    pub stored: usize,
+    /// URLs a crawl would scrape.
+    pub candidates: usize,
+    /// Candidates per scraper domain, [`GENERIC`] for the generic extractor.
+    pub scrapers: BTreeMap<String, usize>,
+    /// Why the posts of the source could not be fetched.
+    pub error: Option<String>,
+}
+
+/// Fetches the posts of every source and runs them through the filters a
+/// crawl applies before scraping: links to Reddit, the domain and path rules
+/// of the policy and the URLs already stored. robots.txt and the daily page
+/// limits aren't checked, since they need requests or would count the pages.
+pub async fn simulate(
+    app: &Encrawl,
+    sources: &[Box<dyn Source>],
+) -> anyhow::Result<BTreeMap<String, Simulation>> {
+    let config = app.config();
+    let fetched = stream::iter(sources)
+        .map(|source| async move { (source.name(), source.fetch_posts().await) })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    let mut simulations = BTreeMap::new();
+    for (name, posts) in fetched {
+        let simulation: &mut Simulation = simulations.entry(name.clone()).or_default();
+        let posts = match posts {
+            Ok(posts) => posts,
+            Err(e) => {
+                log::error!("Failed to fetch posts from {}: {}", name, e);
+                simulation.error = Some(e.to_string());
+                continue;
+            }
+        };
+        simulation.posts = posts.len();
+        let mut allowed = vec![];
+        for post in posts {
+            let url = post.url.clone();
+            let candidate = match CandidateUrl::try_from(post) {
+                Ok(candidate) => candidate,
+                Err(e) => {
+                    simulation.denied.push((url, e.to_string()));
+                    continue;
+                }
+            };
+            if !candidate.is_external() {
+                simulation.internal += 1;
+                continue;
+            }
+            match config.policy.check_rules(candidate.as_str()) {
+                Ok(_) => allowed.push(candidate),
+                Err(e) => simulation.denied.push((url, e.to_string())),
+            }
+        }
+        let canonical = allowed
+            .iter()
+            .map(CandidateUrl::dedup_key)
+            .collect::<Vec<_>>();
+        let stored: Vec<(String,)> = sqlx::query_as("SELECT url FROM articles WHERE url = ANY($1)")
+            .bind(&canonical)
+            .fetch_all(app.postgres()?)
+            .await?;
+        let stored = stored.into_iter().map(|(url,)| url).collect::<HashSet<_>>();
+        for (candidate, canonical) in allowed.iter().zip(&canonical) {
+            if stored.contains(canonical) {
+                simulation.stored += 1;
+                continue;
+            }
+            simulation.candidates += 1;
+            let scraper = find_scraper(&config.scrapers, candidate.as_str())
+                .map_or(GENERIC, |scraper| scraper.domain.as_str());
+            *simulation.scrapers.entry(scraper.to_string()).or_default() += 1;
+        }
+    }
+    Ok(simulations)
+}
@@ -0,0 +1,947 @@
+//! Delivery of digests to chat and push services, mail, webhooks and files, with retries
+//! and a delivery log so failed deliveries can be retried later.
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use futures::future::join_all;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use crate::app::{Config, Encrawl};
+use crate::http::HttpClient;
+use crate::notify::{format_batch, Notification};
+use crate::store::content_hash;
+use crate::summarise::Provenance;
+
+/// Longest message Discord accepts.
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// Longest message Telegram accepts.
+pub(crate) const TELEGRAM_MAX_CHARS: usize = 4096;
+
+/// A generated text ready to be sent out, e.g. a topic summary or a batch of
+/// watchlist hits.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Digest {
+    /// Row id, set once the digest is saved.
+    #[sqlx(default)]
+    pub id: Option<i64>,
+    pub topic: String,
+    pub body: String,
+    /// Watchlist hits the digest was made from, empty for summaries.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<Notification>,
+}
+
+/// A destination for digests.
+#[async_trait]
+pub trait DigestSink: Send + Sync {
+    /// Identifies the sink in the delivery log, so it has to be stable across runs.
+    fn name(&self) -> String;
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()>;
+}
+
+/// Prints digests to stdout.
+pub struct StdoutSink;
+
+#[async_trait]
+impl DigestSink for StdoutSink {
+    fn name(&self) -> String {
+        "stdout".to_string()
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        println!("{}", digest.body);
+        Ok(())
+    }
+}
+
+/// Appends digests to a markdown file.
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl DigestSink for FileSink {
+    fn name(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        let path = self.path.clone();
+        let entry = format!("## {}\n\n{}\n\n", digest.topic, digest.body);
+        tokio::task::spawn_blocking(move || {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            file.write_all(entry.as_bytes())
+        })
+        .await??;
+        Ok(())
+    }
+}
+
+/// Sends digests to a Telegram chat through a bot.
+pub struct TelegramSink {
+    http: HttpClient,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl TelegramSink {
+    pub fn new(http: HttpClient, bot_token: String, chat_id: String) -> Self {
+        Self {
+            http,
+            bot_token,
+            chat_id,
+        }
+    }
+}
+
+#[async_trait]
+impl DigestSink for TelegramSink {
+    fn name(&self) -> String {
+        format!("telegram:{}", self.chat_id)
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        let body = serde_json::json!({
+            "chat_id": self.chat_id,
+            "text": truncate(&format!("*{}*\n\n{}", digest.topic, digest.body), TELEGRAM_MAX_CHARS),
+            "parse_mode": "Markdown",
+        });
+        let req = self
+            .http
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.bot_token
+            ))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?);
+        self.http.send(req).await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Posts digests to a Discord channel webhook.
+pub struct DiscordSink {
+    http: HttpClient,
+    webhook_url: String,
+}
+
+impl DiscordSink {
+    pub fn new(http: HttpClient, webhook_url: String) -> Self {
+        Self { http, webhook_url }
+    }
+}
+
+#[async_trait]
+impl DigestSink for DiscordSink {
+    fn name(&self) -> String {
+        // The webhook URL contains its secret token, so only the id is logged.
+        let id = self
+            .webhook_url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .nth(1)
+            .unwrap_or_default();
+        format!("discord:{id}")
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        let body = serde_json::json!({
+            "content": truncate(&format!("**{}**\n\n{}", digest.topic, digest.body), DISCORD_MAX_CHARS),
+        });
+        let req = self
+            .http
+            .post(&self.webhook_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?);
+        self.http.send(req).await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Mails digests through an SMTP relay.
+pub struct EmailSink {
+    transport: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
+    from: lettre::message::Mailbox,
+    to: Vec<lettre::message::Mailbox>,
+}
+
+impl EmailSink {
+    pub fn new(
+        smtp_host: &str,
+        username: String,
+        password: String,
+        from: &str,
+        to: &[String],
+    ) -> anyhow::Result<Self> {
+        let transport = lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::relay(smtp_host)?
+            .credentials(lettre::transport::smtp::authentication::Credentials::new(
+                username, password,
+            ))
+            .build();
+        Ok(Self {
+            transport,
+            from: from.parse()?,
+            to: to.iter().map(|to| to.parse()).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl DigestSink for EmailSink {
+    fn name(&self) -> String {
+        let to = self
+            .to
+            .iter()
+            .map(|to| to.email.to_string())
+            .collect::<Vec<_>>();
+        format!("email:{}", to.join(","))
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        use lettre::AsyncTransport;
+        let mut message = lettre::Message::builder()
+            .from(self.from.clone())
+            .subject(format!("encrawl: {}", digest.topic));
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        self.transport
+            .send(message.body(digest.body.clone())?)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Posts digests to a Matrix room as formatted messages.
+pub struct MatrixSink {
+    http: HttpClient,
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+}
+
+impl MatrixSink {
+    pub fn new(
+        http: HttpClient,
+        homeserver: String,
+        access_token: String,
+        room_id: String,
+    ) -> Self {
+        Self {
+            http,
+            homeserver,
+            access_token,
+            room_id,
+        }
+    }
+}
+
+#[async_trait]
+impl DigestSink for MatrixSink {
+    fn name(&self) -> String {
+        format!("matrix:{}", self.room_id)
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        use ruma::events::room::message::RoomMessageEventContent;
+        let content = RoomMessageEventContent::text_markdown(format!(
+            "## {}\n\n{}",
+            digest.topic, digest.body
+        ));
+        // Retries of the same delivery reuse the transaction id, so the
+        // homeserver drops them if an earlier attempt went through.
+        let txn_id = format!(
+            "encrawl-{}-{}",
+            digest.id.unwrap_or_default(),
+            content_hash(&digest.body)
+        );
+        let mut url = url::Url::parse(&self.homeserver)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("{} can't be a homeserver URL", self.homeserver))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room_id,
+                "send",
+                "m.room.message",
+                &txn_id,
+            ]);
+        let req = self
+            .http
+            .put(url)
+            .bearer_auth(&self.access_token)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&content)?);
+        self.http.send(req).await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Header carrying the HMAC-SHA256 of the timestamp and body of a webhook request.
+pub const SIGNATURE_HEADER: &str = "X-Encrawl-Signature";
+
+/// Header carrying the Unix timestamp the webhook request was signed at.
+pub const TIMESTAMP_HEADER: &str = "X-Encrawl-Timestamp";
+
+/// POSTs digests as JSON to any URL.
+///
+/// With a secret, every request is signed: [`SIGNATURE_HEADER`] is
+/// `sha256=` followed by the hex HMAC-SHA256 of `"{timestamp}.{body}"`, where
+/// the timestamp is the value of [`TIMESTAMP_HEADER`], so receivers can
+/// reject forged and replayed requests.
+pub struct WebhookSink {
+    http: HttpClient,
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookSink {
+    pub fn new(http: HttpClient, url: String, secret: Option<String>) -> Self {
+        Self { http, url, secret }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `"{timestamp}.{body}"` under `secret`.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    use hmac::Mac;
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[async_trait]
+impl DigestSink for WebhookSink {
+    fn name(&self) -> String {
+        // Only the host is logged, the path and query often hold a token.
+        let host = url::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        format!("webhook:{host}")
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        let event = if digest.events.is_empty() {
+            "digest"
+        } else {
+            "watchlist"
+        };
+        let body = serde_json::to_vec(&serde_json::json!({
+            "event": event,
+            "digest": digest,
+        }))?;
+        let mut req = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs();
+            req = req.header(TIMESTAMP_HEADER, timestamp.to_string()).header(
+                SIGNATURE_HEADER,
+                format!("sha256={}", sign(secret, timestamp, &body)),
+            );
+        }
+        self.http.send(req.body(body)).await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Where and how urgently ntfy notifications for one watchlist are published.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NtfyRoute {
+    /// Replaces the topic of the sink.
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Replaces the priority of the sink, from 1 (min) to 5 (max).
+    #[serde(default)]
+    pub priority: Option<u8>,
+}
+
+/// Publishes digests to an ntfy topic, so they reach phones subscribed to it.
+pub struct NtfySink {
+    http: HttpClient,
+    server: String,
+    topic: String,
+    token: Option<String>,
+    priority: Option<u8>,
+    watchlists: HashMap<String, NtfyRoute>,
+}
+
+impl NtfySink {
+    pub fn new(
+        http: HttpClient,
+        server: String,
+        topic: String,
+        token: Option<String>,
+        priority: Option<u8>,
+        watchlists: HashMap<String, NtfyRoute>,
+    ) -> Self {
+        Self {
+            http,
+            server,
+            topic,
+            token,
+            priority,
+            watchlists,
+        }
+    }
+}
+
+#[async_trait]
+impl DigestSink for NtfySink {
+    fn name(&self) -> String {
+        format!("ntfy:{}", self.topic)
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        for (watchlist, message) in by_watchlist(digest) {
+            let route = watchlist
+                .and_then(|watchlist| self.watchlists.get(watchlist))
+                .cloned()
+                .unwrap_or_default();
+            let mut body = serde_json::json!({
+                "topic": route.topic.as_ref().unwrap_or(&self.topic),
+                "title": digest.topic,
+                "message": message,
+                "markdown": true,
+            });
+            if let Some(priority) = route.priority.or(self.priority) {
+                body["priority"] = priority.clamp(1, 5).into();
+            }
+            let mut req = self
+                .http
+                .post(&self.server)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&body)?);
+            if let Some(token) = &self.token {
+                req = req.bearer_auth(token);
+            }
+            self.http.send(req).await?.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Pushes digests to a Gotify server as messages of the application owning the token.
+pub struct GotifySink {
+    http: HttpClient,
+    server: String,
+    app_token: String,
+    priority: u8,
+    priorities: HashMap<String, u8>,
+}
+
+impl GotifySink {
+    pub fn new(
+        http: HttpClient,
+        server: String,
+        app_token: String,
+        priority: u8,
+        priorities: HashMap<String, u8>,
+    ) -> Self {
+        Self {
+            http,
+            server,
+            app_token,
+            priority,
+            priorities,
+        }
+    }
+}
+
+#[async_trait]
+impl DigestSink for GotifySink {
+    fn name(&self) -> String {
+        let host = url::Url::parse(&self.server)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        format!("gotify:{host}")
+    }
+
+    async fn send(&self, digest: &Digest) -> anyhow::Result<()> {
+        let url = format!("{}/message", self.server.trim_end_matches('/'));
+        for (watchlist, message) in by_watchlist(digest) {
+            let priority = watchlist
+                .and_then(|watchlist| self.priorities.get(watchlist))
+                .copied()
+                .unwrap_or(self.priority);
+            let body = serde_json::json!({
+                "title": digest.topic,
+                "message": message,
+                "priority": priority,
+                "extras": {
+                    "client::display": { "contentType": "text/markdown" },
+                },
+            });
+            let req = self
+                .http
+                .post(&url)
+                .header("X-Gotify-Key", &self.app_token)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&body)?);
+            self.http.send(req).await?.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits the events of `digest` by the watchlist they matched and renders
+/// each group, so every group can be pushed with its own settings. Digests
+/// without events are kept whole.
+fn by_watchlist(digest: &Digest) -> Vec<(Option<&str>, String)> {
+    if digest.events.is_empty() {
+        return vec![(None, digest.body.clone())];
+    }
+    let mut groups = BTreeMap::<_, Vec<_>>::new();
+    for event in &digest.events {
+        groups
+            .entry(event.watchlist.as_deref())
+            .or_default()
+            .push(event.clone());
+    }
+    groups
+        .into_iter()
+        .map(|(watchlist, events)| (watchlist, format_batch(&events)))
+        .collect()
+}
+
+/// A Markdown link to a web page, with its text and its URL.
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]+)\]\((https?://[^)\s]+)\)").unwrap());
+
+/// How digests cite the articles they link to.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Citations {
+    /// Markdown links, as the summaries are generated.
+    #[default]
+    Inline,
+    /// Numbers such as `[1]` after the cited text and a list of the titles
+    /// and URLs at the end, for email and chat clients that render Markdown
+    /// links poorly.
+    Numbered,
+}
+
+impl Citations {
+    /// Rewrites the links of `body` in this style. Every URL keeps the
+    /// number it was first cited with.
+    pub fn apply(self, body: &str) -> String {
+        if self == Self::Inline {
+            return body.to_string();
+        }
+        let mut references: Vec<(String, String)> = vec![];
+        let cited = MARKDOWN_LINK.replace_all(body, |link: &regex::Captures| {
+            let (text, url) = (&link[1], &link[2]);
+            let n = match references.iter().position(|(cited, _)| cited == url) {
+                Some(i) => i + 1,
+                None => {
+                    references.push((url.to_string(), text.to_string()));
+                    references.len()
+                }
+            };
+            format!("{text} [{n}]")
+        });
+        if references.is_empty() {
+            return cited.into_owned();
+        }
+        let mut body = format!("{}\n\nReferences:", cited.trim_end());
+        for (i, (url, title)) in references.iter().enumerate() {
+            body.push_str(&format!("\n[{}] {title}: {url}", i + 1));
+        }
+        body
+    }
+}
+
+pub(crate) fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars.saturating_sub(1)) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// A kind of sink and its settings, as written in `sinks.ron`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SinkKind {
+    Stdout,
+    File {
+        path: PathBuf,
+    },
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+    },
+    Discord {
+        webhook_url: String,
+    },
+    Matrix {
+        /// Base URL of the homeserver, e.g. `https://matrix.org`.
+        homeserver: String,
+        access_token: String,
+        /// Internal id of the room, e.g. `!abcdef:matrix.org`.
+        room_id: String,
+    },
+    Webhook {
+        url: String,
+        /// Signs requests when set, see [`WebhookSink`].
+        #[serde(default)]
+        secret: Option<String>,
+    },
+    Ntfy {
+        /// Base URL of the ntfy server.
+        #[serde(default = "default_ntfy_server")]
+        server: String,
+        topic: String,
+        /// Access token for protected topics.
+        #[serde(default)]
+        token: Option<String>,
+        /// From 1 (min) to 5 (max), the server default when unset.
+        #[serde(default)]
+        priority: Option<u8>,
+        /// Topic and priority per watch term, e.g. to make some hits ring louder.
+        #[serde(default)]
+        watchlists: HashMap<String, NtfyRoute>,
+    },
+    Gotify {
+        /// Base URL of the Gotify server.
+        server: String,
+        /// Token of the Gotify application the messages are posted as.
+        app_token: String,
+        /// From 0 to 10.
+        #[serde(default = "default_gotify_priority")]
+        priority: u8,
+        /// Priority per watch term.
+        #[serde(default)]
+        priorities: HashMap<String, u8>,
+    },
+    Email {
+        smtp_host: String,
+        username: String,
+        password: String,
+        from: String,
+        to: Vec<String>,
+    },
+}
+
+/// A configured sink along with how persistently to deliver to it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SinkConfig {
+    pub kind: SinkKind,
+    /// Attempts per delivery before it is logged as failed.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled on every further one.
+    #[serde(default = "default_backoff_secs")]
+    pub backoff_secs: u64,
+    /// Least seconds between two batched notifications, see [`crate::notify::Batcher`].
+    #[serde(default)]
+    pub min_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub citations: Citations,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+fn default_gotify_priority() -> u8 {
+    5
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_backoff_secs() -> u64 {
+    5
+}
+
+impl SinkConfig {
+    /// Reads a RON list of sinks, e.g. `sinks.ron`.
+    pub fn from_file(path: PathBuf) -> anyhow::Result<Vec<Self>> {
+        Ok(ron::from_str(&String::from_utf8(std::fs::read(path)?)?)?)
+    }
+}
+
+/// A sink ready to deliver to, with its retry policy.
+#[derive(Clone)]
+pub struct ConfiguredSink {
+    pub sink: Arc<dyn DigestSink>,
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub min_interval: Option<Duration>,
+    /// Style the links of digests are rewritten in before they are sent.
+    pub citations: Citations,
+}
+
+impl ConfiguredSink {
+    /// Wraps `sink` with the default retry policy.
+    pub fn new(sink: Arc<dyn DigestSink>) -> Self {
+        Self {
+            sink,
+            max_attempts: default_max_attempts(),
+            backoff: Duration::from_secs(default_backoff_secs()),
+            min_interval: None,
+            citations: Citations::default(),
+        }
+    }
+
+    /// Sends `digest`, retrying with exponential backoff. Returns the number
+    /// of attempts made and the last error if every attempt failed.
+    pub async fn send_with_retry(&self, digest: &Digest) -> (u32, Option<anyhow::Error>) {
+        let cited;
+        let digest = if self.citations == Citations::Inline {
+            digest
+        } else {
+            cited = Digest {
+                body: self.citations.apply(&digest.body),
+                ..digest.clone()
+            };
+            &cited
+        };
+        let mut attempt = 1;
+        loop {
+            match self.sink.send(digest).await {
+                Ok(()) => return (attempt, None),
+                Err(e) if attempt >= self.max_attempts.max(1) => return (attempt, Some(e)),
+                Err(e) => {
+                    let delay = self.backoff * 2u32.saturating_pow(attempt - 1);
+                    log::warn!(
+                        "Delivery to {} failed ({}), retrying in {:?}",
+                        self.sink.name(),
+                        e,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Builds every sink in the config, or just stdout when none is configured.
+pub fn from_config(config: &Config, http: &HttpClient) -> anyhow::Result<Vec<ConfiguredSink>> {
+    if config.sinks.is_empty() {
+        return Ok(vec![ConfiguredSink::new(Arc::new(StdoutSink))]);
+    }
+    config
+        .sinks
+        .iter()
+        .map(|entry| {
+            let sink: Arc<dyn DigestSink> = match entry.kind.clone() {
+                SinkKind::Stdout => Arc::new(StdoutSink),
+                SinkKind::File { path } => Arc::new(FileSink::new(path)),
+                SinkKind::Telegram { bot_token, chat_id } => {
+                    Arc::new(TelegramSink::new(http.clone(), bot_token, chat_id))
+                }
+                SinkKind::Discord { webhook_url } => {
+                    Arc::new(DiscordSink::new(http.clone(), webhook_url))
+                }
+                SinkKind::Matrix {
+                    homeserver,
+                    access_token,
+                    room_id,
+                } => Arc::new(MatrixSink::new(
+                    http.clone(),
+                    homeserver,
+                    access_token,
+                    room_id,
+                )),
+                SinkKind::Ntfy {
+                    server,
+                    topic,
+                    token,
+                    priority,
+                    watchlists,
+                } => Arc::new(NtfySink::new(
+                    http.clone(),
+                    server,
+                    topic,
+                    token,
+                    priority,
+                    watchlists,
+                )),
+                SinkKind::Gotify {
+                    server,
+                    app_token,
+                    priority,
+                    priorities,
+                } => Arc::new(GotifySink::new(
+                    http.clone(),
+                    server,
+                    app_token,
+                    priority,
+                    priorities,
+                )),
+                SinkKind::Webhook { url, secret } => {
+                    Arc::new(WebhookSink::new(http.clone(), url, secret))
+                }
+                SinkKind::Email {
+                    smtp_host,
+                    username,
+                    password,
+                    from,
+                    to,
+                } => Arc::new(EmailSink::new(&smtp_host, username, password, &from, &to)?),
+            };
+            Ok(ConfiguredSink {
+                sink,
+                max_attempts: entry.max_attempts,
+                backoff: Duration::from_secs(entry.backoff_secs),
+                min_interval: entry.min_interval_secs.map(Duration::from_secs),
+                citations: entry.citations,
+            })
+        })
+        .collect()
+}
+
+/// Outcome of delivering a digest to one sink.
+#[derive(Debug, FromRow)]
+pub struct Delivery {
+    pub digest_id: i64,
+    pub sink: String,
+    pub attempts: i32,
+    pub delivered: bool,
+    pub error: Option<String>,
+}
+
+/// Saves `topic` and `body` as a new digest, with what it was generated
+/// with when it was.
+pub async fn save(
+    db: &Pool<Postgres>,
+    topic: &str,
+    body: &str,
+    provenance: Option<&Provenance>,
+) -> anyhow::Result<Digest> {
+    let provenance = provenance.map(serde_json::to_string).transpose()?;
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO digests (topic, body, provenance) VALUES ($1, $2, $3::jsonb) RETURNING id",
+    )
+    .bind(topic)
+    .bind(body)
+    .bind(provenance)
+    .fetch_one(db)
+    .await?;
+    Ok(Digest {
+        id: Some(id),
+        topic: topic.to_string(),
+        body: body.to_string(),
+        events: vec![],
+    })
+}
+
+/// Sends a saved `digest` to all `sinks` at once and logs the outcome for each.
+pub async fn deliver(
+    app: &Encrawl,
+    digest: &Digest,
+    sinks: &[ConfiguredSink],
+) -> anyhow::Result<Vec<Delivery>> {
+    let digest_id = digest
+        .id
+        .ok_or_else(|| anyhow::anyhow!("digest has to be saved before it is delivered"))?;
+    let results = join_all(sinks.iter().map(|sink| async move {
+        let (attempts, error) = sink.send_with_retry(digest).await;
+        if let Some(e) = &error {
+            log::error!(
+                "Failed to deliver digest {} to {}: {}",
+                digest_id,
+                sink.sink.name(),
+                e
+            );
+        }
+        Delivery {
+            digest_id,
+            sink: sink.sink.name(),
+            attempts: attempts as i32,
+            delivered: error.is_none(),
+            error: error.map(|e| e.to_string()),
+        }
+    }))
+    .await;
+    for delivery in &results {
+        sqlx::query(
+            "INSERT INTO deliveries (digest_id, sink, attempts, delivered, error) VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (digest_id, sink) DO UPDATE SET attempts = deliveries.attempts + EXCLUDED.attempts,
+                delivered = EXCLUDED.delivered, error = EXCLUDED.error, updated_at = now()",
+        )
+        .bind(delivery.digest_id)
+        .bind(&delivery.sink)
+        .bind(delivery.attempts)
+        .bind(delivery.delivered)
+        .bind(&delivery.error)
+        .execute(app.postgres()?)
+        .await?;
+    }
+    Ok(results)
+}
+
+/// The saved digest `digest_id`.
+pub async fn load(db: &Pool<Postgres>, digest_id: i64) -> anyhow::Result<Option<Digest>> {
+    Ok(
+        sqlx::query_as("SELECT id, topic, body FROM digests WHERE id = $1")
+            .bind(digest_id)
+            .fetch_optional(db)
+            .await?,
+    )
+}
+
+/// Sends the digest `digest_id` again to the `sinks` whose delivery of it
+/// failed, or to all of them when `all` is set.
+pub async fn redeliver(
+    app: &Encrawl,
+    digest_id: i64,
+    sinks: &[ConfiguredSink],
+    all: bool,
+) -> anyhow::Result<Vec<Delivery>> {
+    let digest = load(app.postgres()?, digest_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no digest with id {}", digest_id))?;
+    let failed: Vec<(String,)> =
+        sqlx::query_as("SELECT sink FROM deliveries WHERE digest_id = $1 AND NOT delivered")
+            .bind(digest_id)
+            .fetch_all(app.postgres()?)
+            .await?;
+    let pending = sinks
+        .iter()
+        .filter(|sink| all || failed.iter().any(|(name,)| *name == sink.sink.name()))
+        .cloned()
+        .collect::<Vec<_>>();
+    deliver(app, &digest, &pending).await
+}
+
+/// Deliveries of the last `limit` digests, newest first.
+pub async fn log(app: &Encrawl, limit: i64) -> anyhow::Result<Vec<Delivery>> {
+    Ok(sqlx::query_as(
+        "SELECT digest_id, sink, attempts, delivered, error FROM deliveries
+        WHERE digest_id IN (SELECT id FROM digests ORDER BY id DESC LIMIT $1)
+        ORDER BY digest_id DESC, sink",
+    )
+    .bind(limit)
+    .fetch_all(app.postgres()?)
+    .await?)
+}
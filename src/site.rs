@@ -0,0 +1,226 @@
+//! Links to articles read straight from the sitemaps and index pages of the
+//! sites there is a [`ScraperConfig`] for, instead of waiting for someone to
+//! post them.
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::collections::{HashSet, VecDeque};
+use std::sync::LazyLock;
+
+use crate::app::Encrawl;
+use crate::scrape::{ScraperConfig, Selectors};
+
+/// Links followed on index pages without a [`ScraperConfig::link_selector`].
+const DEFAULT_LINK_SELECTOR: &str = "a[href]";
+
+static SITEMAP_ENTRY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(?:url|sitemap)>(.*?)</(?:url|sitemap)>").unwrap());
+static LOC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<loc>\s*(.*?)\s*</loc>").unwrap());
+static LASTMOD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<lastmod>\s*(.*?)\s*</lastmod>").unwrap());
+
+/// How far a site is crawled for links on every run.
+#[derive(Clone, Debug)]
+pub struct SiteLimits {
+    /// Levels of nested sitemaps, or of links from the index page, followed.
+    /// `1` reads only the configured sitemap or index page.
+    pub max_depth: usize,
+    /// Sitemaps and index pages fetched per site.
+    pub max_pages: usize,
+    /// Links returned per site, the most recently modified first when the
+    /// sitemap says.
+    pub max_links: usize,
+}
+
+/// A link to what is probably an article.
+#[derive(Debug, Clone)]
+pub struct SiteLink {
+    pub url: String,
+    /// When the sitemap says the page last changed.
+    pub modified: Option<DateTime<Utc>>,
+    /// Sitemap or index page the link was found on.
+    pub found_on: String,
+}
+
+/// Links to the articles of the site of `scraper`, from its sitemap and its
+/// index page, whichever are configured. Every page fetched goes through the
+/// crawl policy like an article.
+pub async fn links(
+    app: &Encrawl,
+    scraper: &ScraperConfig,
+    limits: &SiteLimits,
+) -> anyhow::Result<Vec<SiteLink>> {
+    let mut links = vec![];
+    let mut pages = 0;
+    if let Some(sitemap) = &scraper.sitemap_url {
+        links.extend(sitemap_links(app, sitemap, limits, &mut pages).await?);
+    }
+    if let Some(index) = &scraper.index_url {
+        let selectors = scraper
+            .link_selector
+            .as_ref()
+            .map_or(&[][..], Selectors::as_slice);
+        links.extend(index_links(app, scraper, index, selectors, limits, &mut pages).await?);
+    }
+    let mut seen = HashSet::new();
+    links.retain(|link| seen.insert(link.url.clone()));
+    // Links without a date sort after dated ones, in the order found.
+    links.sort_by_key(|link| std::cmp::Reverse(link.modified));
+    links.truncate(limits.max_links);
+    Ok(links)
+}
+
+async fn fetch(app: &Encrawl, url: &str) -> anyhow::Result<String> {
+    app.policy().check(app.http(), url).await?;
+    let resp = app.http().send(app.http().get(url)).await?;
+    Ok(resp.error_for_status()?.text().await?)
+}
+
+/// Reads the `<url>` entries of `sitemap`, following sitemap indexes as deep
+/// as `limits` allow, their most recently modified sitemaps first.
+async fn sitemap_links(
+    app: &Encrawl,
+    sitemap: &str,
+    limits: &SiteLimits,
+    pages: &mut usize,
+) -> anyhow::Result<Vec<SiteLink>> {
+    let mut links = vec![];
+    let mut queue = VecDeque::from([(sitemap.to_string(), 1)]);
+    while let Some((url, depth)) = queue.pop_front() {
+        if *pages >= limits.max_pages {
+            break;
+        }
+        if url.ends_with(".gz") {
+            log::warn!("Skipping the compressed sitemap {}", url);
+            continue;
+        }
+        *pages += 1;
+        let xml = match fetch(app, &url).await {
+            Ok(xml) => xml,
+            // Only the configured sitemap failing fails the site.
+            Err(e) if depth > 1 => {
+                log::warn!("Failed to read the sitemap {}: {}", url, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let mut entries = SITEMAP_ENTRY
+            .captures_iter(&xml)
+            .filter_map(|entry| {
+                let loc = LOC.captures(&entry[1])?[1].replace("&amp;", "&");
+                let modified = LASTMOD
+                    .captures(&entry[1])
+                    .and_then(|lastmod| parse_lastmod(&lastmod[1]));
+                Some(SiteLink {
+                    url: loc,
+                    modified,
+                    found_on: url.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if xml.contains("<sitemapindex") {
+            if depth < limits.max_depth {
+                entries.sort_by_key(|entry| std::cmp::Reverse(entry.modified));
+                queue.extend(entries.into_iter().map(|entry| (entry.url, depth + 1)));
+            }
+        } else {
+            links.extend(entries);
+        }
+    }
+    Ok(links)
+}
+
+/// `<lastmod>` is a W3C datetime, either a full timestamp or only a date.
+fn parse_lastmod(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            let date = chrono::NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+            Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+        })
+}
+
+/// Collects the links matching `selectors` on `index`, and on the pages they
+/// lead to as deep as `limits` allow, that stay on the scraper's domain.
+async fn index_links(
+    app: &Encrawl,
+    scraper: &ScraperConfig,
+    index: &str,
+    selectors: &[String],
+    limits: &SiteLimits,
+    pages: &mut usize,
+) -> anyhow::Result<Vec<SiteLink>> {
+    let default = [DEFAULT_LINK_SELECTOR.to_string()];
+    let selectors = if selectors.is_empty() {
+        &default[..]
+    } else {
+        selectors
+    };
+    let mut links = vec![];
+    let mut seen = HashSet::from([index.to_string()]);
+    let mut queue = VecDeque::from([(index.to_string(), 1)]);
+    while let Some((url, depth)) = queue.pop_front() {
+        if *pages >= limits.max_pages {
+            break;
+        }
+        *pages += 1;
+        let html = match fetch(app, &url).await {
+            Ok(html) => html,
+            Err(e) if depth > 1 => {
+                log::warn!("Failed to read {}: {}", url, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let base = url::Url::parse(&url)?;
+        for link in links_on(&html, &base, selectors) {
+            if !on_domain(&link, &scraper.domain) || !seen.insert(link.clone()) {
+                continue;
+            }
+            if depth < limits.max_depth {
+                queue.push_back((link.clone(), depth + 1));
+            }
+            links.push(SiteLink {
+                url: link,
+                modified: None,
+                found_on: url.clone(),
+            });
+        }
+    }
+    Ok(links)
+}
+
+/// Targets of the links matching `selectors` in `html`, resolved against
+/// `base` and without fragments.
+fn links_on(html: &str, base: &url::Url, selectors: &[String]) -> Vec<String> {
+    let document = scraper::Html::parse_document(html);
+    selectors
+        .iter()
+        .filter_map(|selector| match scraper::Selector::parse(selector) {
+            Ok(selector) => Some(selector),
+            Err(e) => {
+                log::warn!("Skipping invalid selector {:?}: {}", selector, e);
+                None
+            }
+        })
+        .flat_map(|selector| {
+            document
+                .select(&selector)
+                .filter_map(|e| e.value().attr("href"))
+                .filter_map(|href| base.join(href).ok())
+                .map(|mut url| {
+                    url.set_fragment(None);
+                    url.to_string()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn on_domain(url: &str, domain: &str) -> bool {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .is_some_and(|host| host == domain || host.ends_with(&format!(".{domain}")))
+}
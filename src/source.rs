@@ -0,0 +1,469 @@
+//! Places the crawler discovers links to articles from.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::app::Encrawl;
+use crate::crawl::Subreddit;
+use crate::http::HttpClient;
+use crate::reddit::{Listing, RedditClient, RedditComment, RedditPost};
+use crate::scrape::ScraperConfig;
+use crate::site::{self, SiteLimits};
+use crate::store::canonicalize_url;
+
+/// A link found by a [`Source`] that may point to an article.
+#[derive(Debug, Clone)]
+pub struct PostCandidate {
+    pub url: String,
+    pub title: String,
+    /// Name of the source that found the link.
+    pub source: String,
+    /// Id of the post within its source, when it has one.
+    pub id: Option<String>,
+    /// When the post was published, when the source says.
+    pub posted_at: Option<DateTime<Utc>>,
+    /// Page the link was found on, e.g. the post's comments.
+    pub referrer: Option<String>,
+    /// Upvotes or points of the post, when the source has them.
+    pub score: Option<f32>,
+}
+
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Human readable name used in logs, e.g. `r/finance`.
+    fn name(&self) -> String;
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>>;
+}
+
+/// An entry of `sources.ron`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SourceConfig {
+    Reddit {
+        name: String,
+        #[serde(default)]
+        flairs: Vec<String>,
+    },
+    HackerNews {
+        #[serde(default)]
+        list: HnList,
+        /// Full text search query, every story of the list is used when unset.
+        #[serde(default)]
+        query: Option<String>,
+        #[serde(default)]
+        min_score: u32,
+    },
+    Feed {
+        url: String,
+    },
+}
+
+impl SourceConfig {
+    /// Reads a RON list of sources, e.g. `sources.ron`.
+    pub fn from_file(path: PathBuf) -> anyhow::Result<Vec<Self>> {
+        Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Builds a source for every configured subreddit, feed and `sources.ron`
+/// entry of `app`. Subreddits are skipped when there is no Reddit client.
+pub fn from_config(app: &Encrawl, reddit: Option<Arc<RedditClient>>) -> Vec<Box<dyn Source>> {
+    let (config, http) = (&*app.config(), app.http());
+    let entries = config
+        .subs
+        .iter()
+        .map(|sub| SourceConfig::Reddit {
+            name: sub.name.clone(),
+            flairs: sub.flairs.clone(),
+        })
+        .chain(
+            config
+                .feeds
+                .iter()
+                .map(|url| SourceConfig::Feed { url: url.clone() }),
+        )
+        .chain(config.sources.iter().cloned());
+    let mut skipped_reddit = false;
+    let mut sources: Vec<Box<dyn Source>> = vec![];
+    for entry in entries {
+        match entry {
+            SourceConfig::Reddit { name, flairs } => match &reddit {
+                Some(reddit) => sources.push(Box::new(RedditSource::new(
+                    app.clone(),
+                    reddit.clone(),
+                    Subreddit { name, flairs },
+                    config.listing.clone(),
+                ))),
+                None => skipped_reddit = true,
+            },
+            SourceConfig::HackerNews {
+                list,
+                query,
+                min_score,
+            } => sources.push(Box::new(HackerNewsSource {
+                client: http.clone(),
+                list,
+                query,
+                min_score,
+            })),
+            SourceConfig::Feed { url } => {
+                sources.push(Box::new(FeedSource::new(http.clone(), url)))
+            }
+        }
+    }
+    if skipped_reddit {
+        log::warn!("No Reddit credentials given, skipping subreddits");
+    }
+    sources
+}
+
+/// Posts of a subreddit, and the top-level comments of every post when the
+/// listing has a `comment_min_score`.
+pub struct RedditSource {
+    app: Encrawl,
+    client: Arc<RedditClient>,
+    sub: Subreddit,
+    listing: Listing,
+}
+
+impl RedditSource {
+    pub fn new(app: Encrawl, client: Arc<RedditClient>, sub: Subreddit, listing: Listing) -> Self {
+        Self {
+            app,
+            client,
+            sub,
+            listing,
+        }
+    }
+
+    /// Top-level comments of `post` scoring at least `min_score`, stored
+    /// linked to the post when there is Postgres. Failures are logged, the
+    /// post's own links are crawled regardless.
+    async fn comments(&self, post: &RedditPost, min_score: i64) -> Vec<RedditComment> {
+        if post.name.is_empty() {
+            return vec![];
+        }
+        let comments = match self.client.get_comments(&post.name, min_score).await {
+            Ok(comments) => comments,
+            Err(e) => {
+                log::warn!("Failed to fetch the comments of {}: {}", post.name, e);
+                return vec![];
+            }
+        };
+        if self.app.has_postgres() && !comments.is_empty() {
+            if let Err(e) = self.store_comments(post, &comments).await {
+                log::error!("Failed to store the comments of {}: {}", post.name, e);
+            }
+        }
+        comments
+    }
+
+    async fn store_comments(
+        &self,
+        post: &RedditPost,
+        comments: &[RedditComment],
+    ) -> anyhow::Result<()> {
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO reddit_comments \
+             (name, post_name, post_url, subreddit, author, body, score, created_at, urls, \
+             article_id) ",
+        );
+        // The article is only there when the post's link was crawled before,
+        // otherwise it is linked once stored, see `store_batch`.
+        let article_url = canonicalize_url(&post.url);
+        query.push_values(comments, |mut row, comment| {
+            let created_at = DateTime::from_timestamp(comment.created_utc as i64, 0)
+                .filter(|_| comment.created_utc > 0.0);
+            row.push_bind(&comment.name)
+                .push_bind(&post.name)
+                .push_bind(&post.url)
+                .push_bind(&self.sub.name)
+                .push_bind(&comment.author)
+                .push_bind(&comment.body)
+                .push_bind(comment.score)
+                .push_bind(created_at)
+                .push_bind(&comment.referenced_urls)
+                .push("(SELECT id FROM articles WHERE url = ")
+                .push_bind_unseparated(&article_url)
+                .push_unseparated(")");
+        });
+        query.push(
+            " ON CONFLICT (name) DO UPDATE SET body = EXCLUDED.body, \
+             score = EXCLUDED.score, urls = EXCLUDED.urls, fetched_at = NOW(), \
+             article_id = COALESCE(EXCLUDED.article_id, reddit_comments.article_id)",
+        );
+        query.build().execute(self.app.postgres()?).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Source for RedditSource {
+    fn name(&self) -> String {
+        format!("r/{}", self.sub.name)
+    }
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>> {
+        let posts = self
+            .client
+            .get_listing(&self.sub.name, &self.sub.flairs, &self.listing)
+            .await?;
+        let comments = match self.listing.comment_min_score {
+            // Each post's comments resolve their own shortened links, so
+            // only as many posts as a crawl fetches pages at once are done
+            // at the same time.
+            Some(min_score) => {
+                let concurrency = self.app.config().concurrency.max(1);
+                // Collected first, the lazy iterator isn't `Send` for `async_trait`.
+                let comments = posts
+                    .iter()
+                    .map(|post| self.comments(post, min_score))
+                    .collect::<Vec<_>>();
+                stream::iter(comments).buffered(concurrency).collect().await
+            }
+            None => vec![vec![]; posts.len()],
+        };
+        // A post links to its URL, to everything referenced in its text and
+        // to the links of its comments, self posts only to the latter two
+        // once internal links are dropped.
+        Ok(posts
+            .into_iter()
+            .zip(comments)
+            .flat_map(|(post, comments)| {
+                let id = Some(post.name).filter(|name| !name.is_empty());
+                let referrer = Some(post.permalink)
+                    .filter(|permalink| !permalink.is_empty())
+                    .map(|permalink| format!("https://www.reddit.com{permalink}"));
+                let score = Some(post.score as f32);
+                let posted_at = DateTime::from_timestamp(post.created_utc as i64, 0)
+                    .filter(|_| post.created_utc > 0.0);
+                let mut urls = vec![post.url];
+                let comment_urls = comments
+                    .into_iter()
+                    .flat_map(|comment| comment.referenced_urls);
+                for url in post.referenced_urls.into_iter().chain(comment_urls) {
+                    if !urls.contains(&url) {
+                        urls.push(url);
+                    }
+                }
+                let (title, source) = (post.title, self.name());
+                urls.into_iter().map(move |url| PostCandidate {
+                    url,
+                    title: title.clone(),
+                    source: source.clone(),
+                    id: id.clone(),
+                    posted_at,
+                    referrer: referrer.clone(),
+                    score,
+                })
+            })
+            .collect())
+    }
+}
+
+/// A source for every scraper with a sitemap or index page, when the
+/// config says to crawl sites.
+pub fn sites(app: &Encrawl) -> Vec<Box<dyn Source>> {
+    let config = app.config();
+    let Some(limits) = &config.sites else {
+        return vec![];
+    };
+    config
+        .scrapers
+        .iter()
+        .filter(|scraper| scraper.sitemap_url.is_some() || scraper.index_url.is_some())
+        .map(|scraper| {
+            Box::new(SiteSource {
+                app: app.clone(),
+                scraper: scraper.clone(),
+                limits: limits.clone(),
+            }) as Box<dyn Source>
+        })
+        .collect()
+}
+
+/// Articles listed by the sitemap or index page of a scraper's site.
+pub struct SiteSource {
+    app: Encrawl,
+    scraper: ScraperConfig,
+    limits: SiteLimits,
+}
+
+#[async_trait]
+impl Source for SiteSource {
+    fn name(&self) -> String {
+        format!("site:{}", self.scraper.domain)
+    }
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>> {
+        let links = site::links(&self.app, &self.scraper, &self.limits).await?;
+        Ok(links
+            .into_iter()
+            .map(|link| PostCandidate {
+                url: link.url,
+                title: String::new(),
+                source: self.name(),
+                id: None,
+                posted_at: link.modified,
+                referrer: Some(link.found_on),
+                score: None,
+            })
+            .collect())
+    }
+}
+
+/// Entries of an RSS or Atom feed.
+pub struct FeedSource {
+    client: HttpClient,
+    url: String,
+}
+
+impl FeedSource {
+    pub fn new(client: HttpClient, url: String) -> Self {
+        Self { client, url }
+    }
+
+    /// Reads a feeds list with one feed URL per line, `#` starts a comment.
+    pub fn read_list(path: PathBuf) -> anyhow::Result<Vec<String>> {
+        let file = BufReader::new(std::fs::File::open(path)?);
+        let mut feeds = vec![];
+        for line in file.lines() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if !line.is_empty() {
+                feeds.push(line.to_string());
+            }
+        }
+        Ok(feeds)
+    }
+}
+
+#[async_trait]
+impl Source for FeedSource {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>> {
+        let resp = self.client.send(self.client.get(&self.url)).await?;
+        let body = resp.error_for_status()?.bytes().await?;
+        let feed = feed_rs::parser::parse(body.as_ref())?;
+        Ok(feed
+            .entries
+            .into_iter()
+            .filter_map(|entry| {
+                let link = entry.links.into_iter().next()?;
+                Some(PostCandidate {
+                    url: link.href,
+                    title: entry.title.map(|t| t.content).unwrap_or_default(),
+                    source: self.name(),
+                    id: Some(entry.id).filter(|id| !id.is_empty()),
+                    posted_at: entry.published.or(entry.updated),
+                    referrer: Some(self.url.clone()),
+                    score: None,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Which Hacker News listing a [`HackerNewsSource`] reads.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HnList {
+    #[default]
+    FrontPage,
+    New,
+}
+
+#[derive(Deserialize)]
+struct HnSearchResp {
+    hits: Vec<HnHit>,
+}
+
+#[derive(Deserialize)]
+struct HnHit {
+    #[serde(rename = "objectID")]
+    object_id: Option<String>,
+    title: Option<String>,
+    url: Option<String>,
+    created_at_i: Option<i64>,
+    points: Option<i64>,
+}
+
+/// Stories from Hacker News through the Algolia search API.
+pub struct HackerNewsSource {
+    client: HttpClient,
+    list: HnList,
+    query: Option<String>,
+    min_score: u32,
+}
+
+impl HackerNewsSource {
+    pub fn new(client: HttpClient, list: HnList, query: Option<String>, min_score: u32) -> Self {
+        Self {
+            client,
+            list,
+            query,
+            min_score,
+        }
+    }
+}
+
+#[async_trait]
+impl Source for HackerNewsSource {
+    fn name(&self) -> String {
+        let list = match self.list {
+            HnList::FrontPage => "front page",
+            HnList::New => "new",
+        };
+        match &self.query {
+            Some(query) => format!("HN {list} {query:?}"),
+            None => format!("HN {list}"),
+        }
+    }
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>> {
+        let (endpoint, tags) = match self.list {
+            HnList::FrontPage => ("search", "front_page"),
+            HnList::New => ("search_by_date", "story"),
+        };
+        let points = format!("points>={}", self.min_score);
+        let mut query_param = vec![("tags", tags), ("numericFilters", points.as_str())];
+        if let Some(query) = &self.query {
+            query_param.push(("query", query.as_str()));
+        }
+        let req = self
+            .client
+            .get(format!("https://hn.algolia.com/api/v1/{endpoint}"))
+            .query(&query_param);
+        let resp = self.client.send(req).await?.error_for_status()?;
+        let resp: HnSearchResp = serde_json::from_slice(&resp.bytes().await?)?;
+        Ok(resp
+            .hits
+            .into_iter()
+            .filter_map(|hit| {
+                Some(PostCandidate {
+                    url: hit.url?,
+                    title: hit.title.unwrap_or_default(),
+                    source: self.name(),
+                    referrer: hit
+                        .object_id
+                        .as_ref()
+                        .map(|id| format!("https://news.ycombinator.com/item?id={id}")),
+                    score: hit.points.map(|points| points as f32),
+                    id: hit.object_id,
+                    posted_at: hit
+                        .created_at_i
+                        .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+                })
+            })
+            .collect())
+    }
+}
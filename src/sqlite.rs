@@ -0,0 +1,397 @@
+//! Articles stored in a SQLite file, for trying encrawl out without a
+//! Postgres server, e.g. `--database-url sqlite://encrawl.db`.
+//!
+//! Only storing and searching articles is supported. Crawls skip the page
+//! cache, event log and rollups and count daily page limits in memory,
+//! everything else such as crawl reports and digests still needs Postgres.
+//! Embeddings are kept as blobs and searched by brute force, which is fast
+//! enough for the few thousand articles of a local corpus.
+
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Executor, Pool, QueryBuilder, Sqlite};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::app::Encrawl;
+use crate::embed_text::{self, EmbedText};
+use crate::error::EncrawlError;
+use crate::store::{
+    chunk_embeddings, configured_key, content_hash, cosine_similarity, reusable, Article,
+    ArticleEmbeddings, ArticleStore, ChunkEmbedding, SearchFilter, Stored,
+    RRF_CANDIDATES_PER_RESULT, RRF_K,
+};
+
+/// Tables of the articles and their content chunks, created when missing.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS articles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    url TEXT NOT NULL UNIQUE,
+    content TEXT NOT NULL,
+    author TEXT NOT NULL,
+    author_source TEXT,
+    content_hash TEXT NOT NULL,
+    annotation TEXT,
+    extractor TEXT,
+    source TEXT,
+    domain TEXT,
+    lang TEXT,
+    synthetic_title INTEGER NOT NULL DEFAULT 0,
+    published_at TEXT,
+    fetched_at TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    embedding_model TEXT NOT NULL,
+    embedding_key TEXT
+);
+CREATE INDEX IF NOT EXISTS articles_content_hash_idx ON articles (content_hash);
+CREATE TABLE IF NOT EXISTS article_chunks (
+    article_id INTEGER NOT NULL REFERENCES articles (id) ON DELETE CASCADE,
+    seq INTEGER NOT NULL,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    embedding BLOB NOT NULL,
+    PRIMARY KEY (article_id, seq)
+);
+";
+
+const ARTICLE_COLUMNS: &str =
+    "id, title, content, url, author, author_source, annotation, extractor,
+    source, domain, lang, synthetic_title, published_at, fetched_at";
+
+/// Whether `url` points to a SQLite database rather than Postgres.
+pub fn is_sqlite(url: &str) -> bool {
+    url.starts_with("sqlite:")
+}
+
+/// Articles in a SQLite database.
+pub struct SqliteStore {
+    db: Pool<Sqlite>,
+}
+
+impl SqliteStore {
+    /// Opens the database at `url`, creating the file and its tables when
+    /// they are missing.
+    pub async fn open(url: &str) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+        let db = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await?;
+        db.execute(SCHEMA).await?;
+        // Files created before articles had embedding keys are embedded again
+        // once, when their articles are next stored.
+        let columns: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM pragma_table_info('articles')")
+                .fetch_all(&db)
+                .await?;
+        if !columns.iter().any(|(name,)| name == "embedding_key") {
+            db.execute("ALTER TABLE articles ADD COLUMN embedding_key TEXT")
+                .await?;
+        }
+        Ok(Self { db })
+    }
+}
+
+/// Embeddings are stored as their little-endian `f32`s.
+fn to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+#[async_trait]
+impl ArticleStore for SqliteStore {
+    async fn store_batch(
+        &self,
+        app: &Encrawl,
+        articles: &[Article],
+        embeddings: &[Option<ArticleEmbeddings>],
+    ) -> Result<Vec<Stored>, EncrawlError> {
+        let reusable = |i: usize| reusable(app, embeddings.get(i)?.as_ref());
+        let urls = articles
+            .iter()
+            .map(Article::canonical_url)
+            .collect::<Vec<_>>();
+        let hashes = articles
+            .iter()
+            .map(|article| content_hash(&article.content))
+            .collect::<Vec<_>>();
+        let keys = articles
+            .iter()
+            .map(|article| {
+                configured_key(
+                    app,
+                    article.source.as_deref(),
+                    &article.title,
+                    &article.content,
+                )
+            })
+            .collect::<Vec<_>>();
+        // Like Postgres, the same content under another URL is a duplicate,
+        // and an article at the same URL is only stored again when it isn't
+        // embedded as it is now with the configured model.
+        let mut seen_hashes = HashSet::new();
+        let mut seen_urls = HashSet::new();
+        let mut results = vec![Stored::Duplicate; articles.len()];
+        let mut pending = vec![];
+        for (i, (url, hash)) in urls.iter().zip(&hashes).enumerate() {
+            let elsewhere: Option<(i64,)> = sqlx::query_as(
+                "SELECT id FROM articles WHERE content_hash = ? AND url <> ? LIMIT 1",
+            )
+            .bind(hash)
+            .bind(url)
+            .fetch_optional(&self.db)
+            .await?;
+            let done: Option<(i64,)> =
+                sqlx::query_as("SELECT id FROM articles WHERE url = ? AND embedding_key = ?")
+                    .bind(url)
+                    .bind(&keys[i])
+                    .fetch_optional(&self.db)
+                    .await?;
+            if elsewhere.is_some()
+                || done.is_some()
+                || !seen_hashes.insert(hash)
+                || !seen_urls.insert(url)
+            {
+                log::debug!(
+                    "{} has the same content or URL as an article already stored",
+                    url
+                );
+                continue;
+            }
+            pending.push(i);
+        }
+        if pending.is_empty() {
+            return Ok(results);
+        }
+
+        let config = app.config();
+        let texts = pending
+            .iter()
+            .filter(|&&i| reusable(i).is_none())
+            .map(|&i| {
+                let article = &articles[i];
+                let composition = EmbedText::for_source(&config, article.source.as_deref());
+                (
+                    composition,
+                    article.title.as_str(),
+                    article.content.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut embedded = embed_text::embed(app, &texts)
+            .await
+            .map_err(EncrawlError::Model)?
+            .into_iter();
+        // Chunks are embedded before the transaction, tagged with the index
+        // of their article until it has an id.
+        let contents = pending
+            .iter()
+            .filter(|&&i| reusable(i).is_none())
+            .map(|&i| (i as i64, articles[i].content.as_str()))
+            .collect::<Vec<_>>();
+        let mut chunks = HashMap::<usize, Vec<ChunkEmbedding>>::new();
+        for (i, chunk) in chunk_embeddings(app, &contents).await? {
+            chunks.entry(i as usize).or_default().push(chunk);
+        }
+
+        let mut tx = self.db.begin().await?;
+        for &i in &pending {
+            let article = &articles[i];
+            let (embedding, article_chunks) = match reusable(i) {
+                Some(embeddings) => (embeddings.title.clone(), embeddings.chunks.clone()),
+                None => (
+                    embedded.next().unwrap_or_default(),
+                    chunks.remove(&i).unwrap_or_default(),
+                ),
+            };
+            let existing: Option<(i64,)> = sqlx::query_as("SELECT id FROM articles WHERE url = ?")
+                .bind(&urls[i])
+                .fetch_optional(&mut *tx)
+                .await?;
+            let (id,): (i64,) = sqlx::query_as(
+                "INSERT INTO articles (title, url, content, author, author_source, content_hash,
+                    annotation, extractor, source, domain, lang, synthetic_title, published_at,
+                    fetched_at, embedding, embedding_model, embedding_key)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (url) DO UPDATE SET title = excluded.title, content = excluded.content,
+                    author = excluded.author, author_source = excluded.author_source,
+                    content_hash = excluded.content_hash, annotation = excluded.annotation,
+                    extractor = excluded.extractor, source = COALESCE(excluded.source, source),
+                    domain = excluded.domain, lang = excluded.lang,
+                    synthetic_title = excluded.synthetic_title,
+                    published_at = COALESCE(excluded.published_at, published_at),
+                    fetched_at = excluded.fetched_at, embedding = excluded.embedding,
+                    embedding_model = excluded.embedding_model,
+                    embedding_key = excluded.embedding_key
+                RETURNING id",
+            )
+            .bind(&article.title)
+            .bind(&urls[i])
+            .bind(&article.content)
+            .bind(&article.author)
+            .bind(&article.author_source)
+            .bind(&hashes[i])
+            .bind(&article.annotation)
+            .bind(&article.extractor)
+            .bind(&article.source)
+            .bind(article.domain())
+            .bind(article.language())
+            .bind(article.synthetic_title)
+            .bind(article.published_at)
+            .bind(article.fetched_at.unwrap_or_else(chrono::Utc::now))
+            .bind(to_blob(&embedding))
+            .bind(app.embedding_model())
+            .bind(&keys[i])
+            .fetch_one(&mut *tx)
+            .await?;
+            sqlx::query("DELETE FROM article_chunks WHERE article_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            for chunk in article_chunks {
+                sqlx::query(
+                    "INSERT INTO article_chunks (article_id, seq, start_byte, end_byte, embedding)
+                    VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(id)
+                .bind(chunk.seq)
+                .bind(chunk.start as i64)
+                .bind(chunk.end as i64)
+                .bind(to_blob(&chunk.embedding))
+                .execute(&mut *tx)
+                .await?;
+            }
+            results[i] = if existing.is_some() {
+                Stored::Updated(id)
+            } else {
+                Stored::New(id)
+            };
+        }
+        tx.commit().await?;
+        Ok(results)
+    }
+
+    /// Ranks the articles embedded with the configured model like Postgres
+    /// does, fusing the ranking by the closest of their title and content
+    /// chunks with the ranking by how many of the query's words they contain.
+    async fn search(
+        &self,
+        app: &Encrawl,
+        query: &str,
+        embedding: Vec<f32>,
+        exclude: &[String],
+        limit: i32,
+        filter: &SearchFilter,
+    ) -> anyhow::Result<Vec<Article>> {
+        let mut select = QueryBuilder::<Sqlite>::new(
+            "SELECT id, title, content, embedding FROM articles WHERE embedding_model = ",
+        );
+        select.push_bind(app.embedding_model());
+        if let Some(domain) = &filter.domain {
+            let domain = domain.to_lowercase();
+            select
+                .push(" AND domain = ")
+                .push_bind(domain.strip_prefix("www.").unwrap_or(&domain).to_string());
+        }
+        if let Some(since) = filter.since {
+            select
+                .push(" AND COALESCE(published_at, fetched_at) >= ")
+                .push_bind(since);
+        }
+        if let Some(author) = filter.author_pattern() {
+            select
+                .push(" AND author LIKE ")
+                .push_bind(author)
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(lang) = &filter.lang {
+            select.push(" AND lang = ").push_bind(lang.to_lowercase());
+        }
+        let rows: Vec<(i64, String, String, Vec<u8>)> =
+            select.build_query_as().fetch_all(&self.db).await?;
+
+        let lowercase = |terms: &[String]| {
+            terms
+                .iter()
+                .map(|term| term.trim().to_lowercase())
+                .filter(|term| !term.is_empty())
+                .collect::<Vec<_>>()
+        };
+        let must_contain = lowercase(&filter.must_contain);
+        let exclude = lowercase(exclude);
+        let words = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.chars().count() > 1)
+            .map(str::to_lowercase)
+            .collect::<HashSet<_>>();
+        let mut semantic = HashMap::new();
+        let mut keyword = vec![];
+        for (id, title, content, blob) in rows {
+            let text = format!("{title}\n{content}").to_lowercase();
+            if !must_contain.iter().all(|term| text.contains(term.as_str()))
+                || exclude.iter().any(|term| text.contains(term.as_str()))
+            {
+                continue;
+            }
+            semantic.insert(id, cosine_similarity(&embedding, &from_blob(&blob)));
+            let matches = words
+                .iter()
+                .filter(|word| text.contains(word.as_str()))
+                .count();
+            if matches > 0 {
+                keyword.push((id, matches));
+            }
+        }
+        let chunks: Vec<(i64, Vec<u8>)> = sqlx::query_as(
+            "SELECT c.article_id, c.embedding FROM article_chunks c
+            JOIN articles a ON a.id = c.article_id WHERE a.embedding_model = ?",
+        )
+        .bind(app.embedding_model())
+        .fetch_all(&self.db)
+        .await?;
+        for (id, blob) in chunks {
+            if let Some(best) = semantic.get_mut(&id) {
+                *best = best.max(cosine_similarity(&embedding, &from_blob(&blob)));
+            }
+        }
+
+        let candidates = (limit.max(1) * RRF_CANDIDATES_PER_RESULT) as usize;
+        let mut semantic = semantic.into_iter().collect::<Vec<_>>();
+        semantic.sort_by(|a, b| b.1.total_cmp(&a.1));
+        keyword.sort_by_key(|(_, matches)| std::cmp::Reverse(*matches));
+        let mut fused = HashMap::<i64, f64>::new();
+        let rankings = [
+            semantic.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            keyword.iter().map(|(id, _)| *id).collect(),
+        ];
+        for ranking in rankings {
+            for (rank, id) in ranking.into_iter().take(candidates).enumerate() {
+                *fused.entry(id).or_default() += 1.0 / (RRF_K as f64 + rank as f64 + 1.0);
+            }
+        }
+        let mut fused = fused.into_iter().collect::<Vec<_>>();
+        fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let mut articles = vec![];
+        for (id, _) in fused.into_iter().take(limit.max(0) as usize) {
+            articles.extend(self.find(app, id).await?);
+        }
+        Ok(articles)
+    }
+
+    async fn find(&self, _app: &Encrawl, id: i64) -> anyhow::Result<Option<Article>> {
+        Ok(sqlx::query_as(&format!(
+            "SELECT {ARTICLE_COLUMNS} FROM articles WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?)
+    }
+}
@@ -0,0 +1,152 @@
+//! Statistics about the corpus and the digests sent from it.
+//!
+//! Article counts are read from the `article_rollups` table rather than
+//! counted from `articles`, which gets slow with millions of rows. The
+//! rollups are refreshed after every crawl by [`refresh_rollups`], which only
+//! recounts the days since its last run.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres};
+use std::time::Duration;
+
+/// Articles fetched from one source on one day.
+#[derive(Debug, Serialize, FromRow)]
+pub struct DailyArticles {
+    pub day: NaiveDate,
+    pub source: String,
+    pub articles: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct DomainArticles {
+    pub domain: String,
+    pub articles: i64,
+}
+
+/// Articles embedded by one model.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ModelArticles {
+    pub model: String,
+    pub dim: i32,
+    pub articles: i64,
+}
+
+/// How much of the corpus is searchable by content and by which models.
+#[derive(Debug, Serialize)]
+pub struct EmbeddingCoverage {
+    pub articles: i64,
+    /// Articles whose content is embedded in chunks, the rest only match by title.
+    pub chunked: i64,
+    pub models: Vec<ModelArticles>,
+}
+
+/// Digests created on one day and how their deliveries went.
+#[derive(Debug, Serialize, FromRow)]
+pub struct DailyDigests {
+    pub day: NaiveDate,
+    pub digests: i64,
+    pub delivered: i64,
+    pub failed: i64,
+}
+
+/// Everything reported by `encrawl-rust stats` and `/stats`.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub articles_per_day: Vec<DailyArticles>,
+    pub top_domains: Vec<DomainArticles>,
+    pub embeddings: EmbeddingCoverage,
+    pub digests_per_day: Vec<DailyDigests>,
+}
+
+/// Recounts the articles of every day since the last refresh into
+/// `article_rollups`, or of all days when `full` is set.
+///
+/// Older days are left alone, so an article refetched today moves to today's
+/// count only after a full refresh.
+pub async fn refresh_rollups(db: &Pool<Postgres>, full: bool) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    let last: Option<(DateTime<Utc>,)> = sqlx::query_as(
+        "SELECT refreshed_at FROM rollup_refreshes WHERE name = 'articles' FOR UPDATE",
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let from = last.filter(|_| !full).map(|(refreshed_at,)| refreshed_at);
+    sqlx::query("DELETE FROM article_rollups WHERE $1::timestamptz IS NULL OR day >= $1::date")
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+    let rows = sqlx::query(
+        "INSERT INTO article_rollups (day, source, domain, articles)
+        SELECT fetched_at::date, COALESCE(source, 'unknown'), COALESCE(domain, ''), COUNT(*)
+        FROM articles WHERE $1::timestamptz IS NULL OR fetched_at >= $1::date
+        GROUP BY 1, 2, 3",
+    )
+    .bind(from)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query(
+        "INSERT INTO rollup_refreshes (name, refreshed_at) VALUES ('articles', now())
+        ON CONFLICT (name) DO UPDATE SET refreshed_at = EXCLUDED.refreshed_at",
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    log::debug!("Refreshed {} article rollups", rows);
+    Ok(())
+}
+
+/// Collects the daily series of the last `since` and the `top` domains with the most articles.
+pub async fn collect(db: &Pool<Postgres>, since: Duration, top: i64) -> anyhow::Result<Stats> {
+    let since = since.as_secs_f64();
+    let articles_per_day = sqlx::query_as(
+        "SELECT day, source, SUM(articles)::bigint AS articles FROM article_rollups
+        WHERE day >= (now() - make_interval(secs => $1))::date
+        GROUP BY 1, 2 ORDER BY 1, 2",
+    )
+    .bind(since)
+    .fetch_all(db)
+    .await?;
+    let top_domains = sqlx::query_as(
+        "SELECT domain, SUM(articles)::bigint AS articles FROM article_rollups WHERE domain <> ''
+        GROUP BY domain ORDER BY 2 DESC, domain LIMIT $1",
+    )
+    .bind(top)
+    .fetch_all(db)
+    .await?;
+    let (articles, chunked): (i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*),
+            COUNT(*) FILTER (WHERE EXISTS (SELECT 1 FROM article_chunks c WHERE c.article_id = a.id))
+        FROM articles a",
+    )
+    .fetch_one(db)
+    .await?;
+    let models = sqlx::query_as(
+        "SELECT embedding_model AS model, embedding_dim AS dim, COUNT(*) AS articles
+        FROM articles GROUP BY 1, 2 ORDER BY 3 DESC",
+    )
+    .fetch_all(db)
+    .await?;
+    let digests_per_day = sqlx::query_as(
+        "SELECT d.created_at::date AS day, COUNT(DISTINCT d.id) AS digests,
+            COUNT(*) FILTER (WHERE l.delivered) AS delivered,
+            COUNT(*) FILTER (WHERE NOT l.delivered) AS failed
+        FROM digests d LEFT JOIN deliveries l ON l.digest_id = d.id
+        WHERE d.created_at >= now() - make_interval(secs => $1)
+        GROUP BY 1 ORDER BY 1",
+    )
+    .bind(since)
+    .fetch_all(db)
+    .await?;
+    Ok(Stats {
+        articles_per_day,
+        top_domains,
+        embeddings: EmbeddingCoverage {
+            articles,
+            chunked,
+            models,
+        },
+        digests_per_day,
+    })
+}
@@ -0,0 +1,1044 @@
+//! Storage and semantic search of articles in Postgres with pgvector, or
+//! in SQLite through [`crate::sqlite`].
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Pool, Postgres};
+use std::collections::{BTreeMap, HashSet};
+use std::time::Instant;
+
+use crate::app::Encrawl;
+use crate::chunk::{self, CHUNK_WORDS, OVERLAP_WORDS};
+use crate::embed_queue;
+use crate::embed_text::{self, EmbedText};
+use crate::error::EncrawlError;
+use crate::index;
+use crate::routing;
+use crate::scrape::PageMetadata;
+
+/// Query parameters that only track where a visitor came from.
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "mc_cid", "mc_eid", "ref_src"];
+
+/// What [`Article::store`] did with an article.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stored {
+    /// Inserted with this id.
+    New(i64),
+    /// The URL was already stored with this id, but its content changed or
+    /// wasn't embedded with the configured model.
+    Updated(i64),
+    /// The same content is already stored, under this or another URL.
+    Duplicate,
+}
+
+/// Removes tracking parameters and the fragment from `url` so the same page
+/// always maps to the same string. Unparseable URLs are returned unchanged.
+pub fn canonicalize_url(url: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    parsed.set_fragment(None);
+    let query = parsed
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_ref()))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    parsed.into()
+}
+
+/// Cosine similarity of two embeddings.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b)).max(f32::EPSILON)
+}
+
+/// Hex encoded SHA-256 of `content` with whitespace normalised.
+pub fn content_hash(content: &str) -> String {
+    let normalized = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// Key of the embeddings of an article, the hash of the model and
+/// composition, its [`content_hash`] and title. Articles stored with the key
+/// of the configured model and composition aren't embedded again.
+pub fn embedding_key(model: &str, composition: EmbedText, title: &str, content: &str) -> String {
+    // Title keys are the ones from before compositions could be configured.
+    let model = match composition {
+        EmbedText::Title => model.to_string(),
+        composition => format!("{model}+{composition}"),
+    };
+    let key = format!("{}:{}:{}", model, content_hash(content), title);
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// The [`embedding_key`] of an article of `source` under the configuration
+/// of `app`.
+pub(crate) fn configured_key(
+    app: &Encrawl,
+    source: Option<&str>,
+    title: &str,
+    content: &str,
+) -> String {
+    let composition = EmbedText::for_source(&app.config(), source);
+    embedding_key(app.embedding_model(), composition, title, content)
+}
+
+/// Sets the [`embedding_key`] of every `(article id, key)`, once the
+/// articles and their chunks are embedded.
+pub(crate) async fn mark_embedded(
+    db: &mut sqlx::PgConnection,
+    keys: &[(i64, String)],
+) -> Result<(), EncrawlError> {
+    let (ids, keys): (Vec<_>, Vec<_>) = keys.iter().cloned().unzip();
+    sqlx::query(
+        "UPDATE articles SET embedding_key = k.key
+        FROM unnest($1::bigint[], $2::text[]) AS k (id, key) WHERE articles.id = k.id",
+    )
+    .bind(ids)
+    .bind(keys)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// A scraped news article.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, FromRow)]
+pub struct Article {
+    /// Row id, only set for articles read back from the database.
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub title: String,
+    pub url: String,
+    pub content: String,
+    pub author: String,
+    /// How the author was found, see [`crate::author::AuthorSource`].
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_source: Option<String>,
+    /// Kind of source the article comes from, see [`crate::ScraperConfig::annotation`].
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<String>,
+    /// Which extractor produced the article, see [`crate::scrape::GENERIC_EXTRACTOR`].
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extractor: Option<String>,
+    /// Name of the source that linked to the article, e.g. `r/stocks`.
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Host of the article's URL without a leading `www.`, set when stored.
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// ISO 639-1 code of the language the article is written in, detected
+    /// when stored unless already set.
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Whether the title was generated from the content since the page had
+    /// none, see [`crate::summarise::generate_headlines`].
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic_title: bool,
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Results of the enrichers run on the article by their name, see
+    /// [`crate::enrich`]. Written with the article, not read back.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub enrichments: BTreeMap<String, serde_json::Value>,
+    /// Everything else found while scraping, not stored.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<PageMetadata>,
+}
+
+impl Article {
+    /// Embeds the title of the article.
+    pub async fn get_embedding(&self, app: &Encrawl) -> anyhow::Result<Vec<f32>> {
+        Ok(app
+            .embed(std::slice::from_ref(&self.title))
+            .await?
+            .remove(0))
+    }
+
+    /// URL the article is stored under: the canonical URL the page declares
+    /// when it is on the same host, otherwise the fetched URL, without
+    /// tracking parameters either way.
+    pub fn canonical_url(&self) -> String {
+        let host = |url: &str| url::Url::parse(url).ok()?.host_str().map(str::to_string);
+        let declared = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.canonical_url.as_deref())
+            .filter(|declared| host(declared).is_some() && host(declared) == host(&self.url));
+        canonicalize_url(declared.unwrap_or(&self.url))
+    }
+
+    /// Host of the article's URL without a leading `www.`.
+    pub fn domain(&self) -> Option<String> {
+        Self::domain_of(&self.url)
+    }
+
+    /// Host of `url` without a leading `www.`.
+    pub fn domain_of(url: &str) -> Option<String> {
+        let url = url::Url::parse(url).ok()?;
+        let host = url.host_str()?.to_lowercase();
+        Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+    }
+
+    /// Language the article is written in, see [`crate::lang::detect`].
+    pub fn language(&self) -> Option<String> {
+        self.lang.clone().or_else(|| {
+            crate::lang::detect(&format!("{}\n{}", self.title, self.content)).map(str::to_string)
+        })
+    }
+
+    /// Embeds the article and upserts it into the `articles` table under its
+    /// canonical URL, skipping it when the same content is already stored.
+    pub async fn store(&self, app: &Encrawl) -> anyhow::Result<Stored> {
+        Ok(store_batch(app, std::slice::from_ref(self))
+            .await?
+            .remove(0))
+    }
+}
+
+/// A window of an article's content and its embedding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkEmbedding {
+    pub seq: i32,
+    /// Byte offsets of the window in the content.
+    pub start: usize,
+    pub end: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// Embeddings of an article computed before, e.g. read from an export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArticleEmbeddings {
+    /// Model that produced them, they are only reused with the same one.
+    pub model: String,
+    pub title: Vec<f32>,
+    /// Embedding of the start of the content, see [`lead`]. Missing in
+    /// exports from before it was stored.
+    #[serde(default)]
+    pub content: Option<Vec<f32>>,
+    pub chunks: Vec<ChunkEmbedding>,
+}
+
+/// Where articles are stored and searched, chosen by the scheme of the
+/// database URL: [`PgStore`] unless it is a `sqlite:` one.
+#[async_trait]
+pub trait ArticleStore: Send + Sync {
+    /// Stores `articles`, see [`store_batch_with`].
+    async fn store_batch(
+        &self,
+        app: &Encrawl,
+        articles: &[Article],
+        embeddings: &[Option<ArticleEmbeddings>],
+    ) -> Result<Vec<Stored>, EncrawlError>;
+
+    /// Returns the `limit` articles matching `filter` most relevant to
+    /// `query`, which is embedded as `embedding`, leaving out the ones that
+    /// mention any of `exclude`. See [`search_filtered`].
+    async fn search(
+        &self,
+        app: &Encrawl,
+        query: &str,
+        embedding: Vec<f32>,
+        exclude: &[String],
+        limit: i32,
+        filter: &SearchFilter,
+    ) -> anyhow::Result<Vec<Article>>;
+
+    /// The stored article with `id`.
+    async fn find(&self, app: &Encrawl, id: i64) -> anyhow::Result<Option<Article>>;
+}
+
+/// Articles in the Postgres database of [`Encrawl::db`], searched with pgvector.
+pub struct PgStore;
+
+#[async_trait]
+impl ArticleStore for PgStore {
+    async fn store_batch(
+        &self,
+        app: &Encrawl,
+        articles: &[Article],
+        embeddings: &[Option<ArticleEmbeddings>],
+    ) -> Result<Vec<Stored>, EncrawlError> {
+        let stored = pg_store_batch(app, articles, embeddings).await?;
+        let ids = stored
+            .iter()
+            .filter_map(|stored| match stored {
+                Stored::New(id) | Stored::Updated(id) => Some(*id),
+                Stored::Duplicate => None,
+            })
+            .collect::<Vec<_>>();
+        if let Err(e) = routing::route(app, &ids).await {
+            log::error!("Failed to route {} articles to topics: {}", ids.len(), e);
+        }
+        Ok(stored)
+    }
+
+    async fn search(
+        &self,
+        app: &Encrawl,
+        query: &str,
+        embedding: Vec<f32>,
+        exclude: &[String],
+        limit: i32,
+        filter: &SearchFilter,
+    ) -> anyhow::Result<Vec<Article>> {
+        pg_search(app, query, embedding, exclude, limit, filter).await
+    }
+
+    async fn find(&self, app: &Encrawl, id: i64) -> anyhow::Result<Option<Article>> {
+        Ok(sqlx::query_as(
+            "SELECT id, title, content, url, author, author_source, annotation, extractor, source, domain,
+                lang, synthetic_title, published_at, fetched_at
+            FROM articles WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(app.read_db().await?)
+        .await?)
+    }
+}
+
+/// Stores `articles` like [`Article::store`], embedding all of their titles
+/// with a single call to the model and inserting them with a single query.
+/// Their content is stored in chunks with [`store_chunks`].
+///
+/// Returns what happened to every article, in order. Of several articles
+/// with the same URL or content only the first one is stored.
+pub async fn store_batch(app: &Encrawl, articles: &[Article]) -> Result<Vec<Stored>, EncrawlError> {
+    store_batch_with(app, articles, &vec![None; articles.len()]).await
+}
+
+/// Stores `articles` like [`store_batch`], reusing the embeddings given for
+/// an article, at the same index, when they come from the configured model.
+///
+/// With [`Encrawl::chaos`], batches fail with a database error at its rate.
+pub async fn store_batch_with(
+    app: &Encrawl,
+    articles: &[Article],
+    embeddings: &[Option<ArticleEmbeddings>],
+) -> Result<Vec<Stored>, EncrawlError> {
+    if let Some(e) = app.chaos().and_then(|chaos| chaos.db_error(articles.len())) {
+        return Err(e);
+    }
+    app.articles().store_batch(app, articles, embeddings).await
+}
+
+/// Whether the `embeddings` of an article can be stored as they are,
+/// because they come from the configured model.
+pub(crate) fn reusable<'a>(
+    app: &Encrawl,
+    embeddings: Option<&'a ArticleEmbeddings>,
+) -> Option<&'a ArticleEmbeddings> {
+    embeddings.filter(|embeddings| {
+        embeddings.model == app.embedding_model()
+            && embeddings.title.len() == app.embedding_dim()
+            && embeddings
+                .content
+                .as_ref()
+                .is_none_or(|content| content.len() == app.embedding_dim())
+            && embeddings
+                .chunks
+                .iter()
+                .all(|chunk| chunk.embedding.len() == app.embedding_dim())
+    })
+}
+
+async fn pg_store_batch(
+    app: &Encrawl,
+    articles: &[Article],
+    embeddings: &[Option<ArticleEmbeddings>],
+) -> Result<Vec<Stored>, EncrawlError> {
+    let reusable = |i: usize| reusable(app, embeddings.get(i)?.as_ref());
+    check_embedding_dim(app)
+        .await
+        .map_err(EncrawlError::Model)?;
+    let urls = articles
+        .iter()
+        .map(Article::canonical_url)
+        .collect::<Vec<_>>();
+    let hashes = articles
+        .iter()
+        .map(|article| content_hash(&article.content))
+        .collect::<Vec<_>>();
+    // The same content under another URL, and the articles at these URLs
+    // already embedded as they are now or queued for it. Others at these URLs
+    // were stored by a crawl failing part way and are stored again.
+    let existing: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT content_hash FROM articles
+        WHERE content_hash = ANY($1) AND url <> ALL($2)",
+    )
+    .bind(&hashes)
+    .bind(&urls)
+    .fetch_all(app.postgres()?)
+    .await?;
+    let mut seen_hashes = existing
+        .into_iter()
+        .map(|(hash,)| hash)
+        .collect::<HashSet<_>>();
+    let keys = articles
+        .iter()
+        .map(|article| {
+            configured_key(
+                app,
+                article.source.as_deref(),
+                &article.title,
+                &article.content,
+            )
+        })
+        .collect::<Vec<_>>();
+    let done: Vec<(String,)> = sqlx::query_as(
+        "SELECT url FROM articles WHERE url = ANY($1)
+            AND (embedding_key = ANY($2) OR (pending_embedding AND content_hash = ANY($3)))",
+    )
+    .bind(&urls)
+    .bind(&keys)
+    .bind(&hashes)
+    .fetch_all(app.postgres()?)
+    .await?;
+    let done = done.into_iter().map(|(url,)| url).collect::<HashSet<_>>();
+    let mut seen_urls = HashSet::new();
+    let mut results = vec![Stored::Duplicate; articles.len()];
+    let mut pending = vec![];
+    for (i, (url, hash)) in urls.iter().zip(&hashes).enumerate() {
+        if done.contains(url) || !seen_hashes.insert(hash.clone()) || !seen_urls.insert(url) {
+            log::debug!(
+                "{} has the same content or URL as an article already stored",
+                url
+            );
+            continue;
+        }
+        pending.push(i);
+    }
+    if pending.is_empty() {
+        return Ok(results);
+    }
+
+    // Deferred articles are stored without embeddings and queued for the
+    // embedding worker, see [`crate::embed_queue`].
+    let config = app.config();
+    let defer = config.defer_embedding;
+    let to_embed = pending
+        .iter()
+        .filter(|&&i| !defer && reusable(i).is_none())
+        .map(|&i| {
+            let article = &articles[i];
+            let composition = EmbedText::for_source(&config, article.source.as_deref());
+            (
+                composition,
+                article.title.as_str(),
+                article.content.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut embedded = embed_articles(app, &to_embed)
+        .await
+        .map_err(EncrawlError::Model)?
+        .into_iter();
+    let embeddings = pending
+        .iter()
+        .map(|&i| match reusable(i) {
+            Some(embeddings) => (Some(embeddings.title.clone()), embeddings.content.clone()),
+            None if defer => (None, None),
+            None => {
+                let (title, content) = embedded.next().unwrap_or_default();
+                (Some(title), Some(content))
+            }
+        })
+        .collect::<Vec<_>>();
+    let values = pending.iter().zip(embeddings).collect::<Vec<_>>();
+    let mut rows = vec![];
+    for values in values.chunks(MAX_ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO articles (title, url, content, author, author_source, content_hash, annotation, extractor,
+                source, domain, lang, synthetic_title, published_at, fetched_at, embedding, embedding_model,
+                embedding_dim, pending_embedding, content_embedding, enrichments) ",
+        );
+        query.push_values(values, |mut row, (&i, (embedding, content_embedding))| {
+            let article = &articles[i];
+            row.push_bind(&article.title)
+                .push_bind(&urls[i])
+                .push_bind(&article.content)
+                .push_bind(&article.author)
+                .push_bind(&article.author_source)
+                .push_bind(&hashes[i])
+                .push_bind(&article.annotation)
+                .push_bind(&article.extractor)
+                .push_bind(&article.source)
+                .push_bind(article.domain())
+                .push_bind(article.language())
+                .push_bind(article.synthetic_title)
+                .push_bind(article.published_at)
+                .push_bind(article.fetched_at.unwrap_or_else(Utc::now))
+                .push_bind(embedding.clone().map(pgvector::Vector::from))
+                .push_bind(app.embedding_model())
+                .push_bind(app.embedding_dim() as i32)
+                .push_bind(embedding.is_none())
+                .push_bind(content_embedding.clone().map(pgvector::Vector::from))
+                .push_bind(serde_json::to_string(&article.enrichments).unwrap_or_default())
+                .push_unseparated("::jsonb");
+        });
+        query.push(
+            " ON CONFLICT (url) DO UPDATE SET title = EXCLUDED.title, content = EXCLUDED.content, author = EXCLUDED.author,
+                author_source = EXCLUDED.author_source,
+                content_hash = EXCLUDED.content_hash, annotation = EXCLUDED.annotation,
+                extractor = EXCLUDED.extractor, source = COALESCE(EXCLUDED.source, articles.source),
+                domain = EXCLUDED.domain, lang = EXCLUDED.lang, synthetic_title = EXCLUDED.synthetic_title,
+                published_at = COALESCE(EXCLUDED.published_at, articles.published_at),
+                fetched_at = EXCLUDED.fetched_at, embedding = EXCLUDED.embedding,
+                embedding_model = EXCLUDED.embedding_model, embedding_dim = EXCLUDED.embedding_dim,
+                pending_embedding = EXCLUDED.pending_embedding, embedding_key = NULL,
+                content_embedding = EXCLUDED.content_embedding, enrichments = EXCLUDED.enrichments
+            RETURNING id, url, (xmax = 0)",
+        );
+        let insert_start = Instant::now();
+        let inserted: Vec<(i64, String, bool)> =
+            query.build_query_as().fetch_all(app.postgres()?).await?;
+        app.metrics()
+            .inserted("articles", inserted.len(), insert_start.elapsed());
+        rows.extend(inserted);
+    }
+    link_comments(app.postgres()?, articles, &urls, &rows).await?;
+    let mut stored = vec![];
+    let mut deferred = vec![];
+    let mut reused_ids = vec![];
+    let mut reused_chunks = vec![];
+    let mut embedded = vec![];
+    for i in pending {
+        let Some((id, _, inserted)) = rows.iter().find(|(_, url, _)| *url == urls[i]) else {
+            return Err(EncrawlError::Db(sqlx::Error::Protocol(format!(
+                "{} was not returned by the insert",
+                urls[i]
+            ))));
+        };
+        results[i] = if *inserted {
+            Stored::New(*id)
+        } else {
+            Stored::Updated(*id)
+        };
+        match reusable(i) {
+            Some(embeddings) => {
+                reused_ids.push(*id);
+                reused_chunks.extend(embeddings.chunks.iter().map(|chunk| (*id, chunk.clone())));
+            }
+            None if defer => {
+                deferred.push(*id);
+                continue;
+            }
+            None => stored.push((*id, articles[i].content.as_str())),
+        }
+        embedded.push((*id, keys[i].clone()));
+    }
+    store_chunks(app, &stored).await?;
+    if !deferred.is_empty() {
+        embed_queue::enqueue(app.postgres()?, &deferred).await?;
+    }
+    if !reused_ids.is_empty() {
+        replace_chunks(app, &reused_ids, &reused_chunks).await?;
+    }
+    mark_embedded(&mut *app.postgres()?.acquire().await?, &embedded).await?;
+    Ok(results)
+}
+
+/// Links the Reddit comments stored before the articles at `urls` were, see
+/// [`crate::source::RedditSource`], to the `rows` they were stored as. The
+/// comments know the link of their post, which is the URL an article was
+/// fetched from, not always the one it is stored under.
+async fn link_comments(
+    db: &Pool<Postgres>,
+    articles: &[Article],
+    urls: &[String],
+    rows: &[(i64, String, bool)],
+) -> sqlx::Result<()> {
+    let (mut ids, mut fetched) = (vec![], vec![]);
+    for (article, url) in articles.iter().zip(urls) {
+        if let Some((id, _, _)) = rows.iter().find(|(_, stored, _)| stored == url) {
+            ids.push(*id);
+            fetched.push(article.url.as_str());
+        }
+    }
+    sqlx::query(
+        "UPDATE reddit_comments c SET article_id = v.id
+        FROM UNNEST($1::BIGINT[], $2::TEXT[]) AS v(id, url)
+        WHERE c.post_url = v.url AND c.article_id IS DISTINCT FROM v.id",
+    )
+    .bind(&ids)
+    .bind(&fetched)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Most rows inserted by a single query, keeping below the bind parameter limit.
+const MAX_ROWS_PER_INSERT: usize = 1000;
+
+/// Words of the start of an article embedded as [`ArticleEmbeddings::content`].
+/// News put the gist of a story first, so this is what the article is about,
+/// whatever its title says.
+const LEAD_WORDS: usize = 100;
+
+/// The first [`LEAD_WORDS`] of `content`.
+pub(crate) fn lead(content: &str) -> String {
+    content
+        .split_whitespace()
+        .take(LEAD_WORDS)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Embeds every `(composition, title, content)` as its composition says,
+/// see [`embed_text::embed`], and its [`lead`], and returns them in order.
+pub(crate) async fn embed_articles(
+    app: &Encrawl,
+    articles: &[(EmbedText, &str, &str)],
+) -> anyhow::Result<Vec<(Vec<f32>, Vec<f32>)>> {
+    if articles.is_empty() {
+        return Ok(vec![]);
+    }
+    let embeddings = embed_text::embed(app, articles).await?;
+    let leads = articles
+        .iter()
+        .map(|(_, _, content)| lead(content))
+        .collect::<Vec<_>>();
+    let leads = app.embed(&leads).await?;
+    Ok(embeddings.into_iter().zip(leads).collect())
+}
+
+/// Splits the content of every `(article id, content)` into overlapping
+/// windows, embeds them all at once and replaces the chunks stored for
+/// those articles.
+pub async fn store_chunks(app: &Encrawl, articles: &[(i64, &str)]) -> Result<(), EncrawlError> {
+    let ids = articles.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let chunks = chunk_embeddings(app, articles).await?;
+    replace_chunks(app, &ids, &chunks).await
+}
+
+/// Splits the content of every `(article id, content)` into overlapping
+/// windows and embeds them all at once, returning them with the id of their
+/// article.
+pub(crate) async fn chunk_embeddings(
+    app: &Encrawl,
+    articles: &[(i64, &str)],
+) -> Result<Vec<(i64, ChunkEmbedding)>, EncrawlError> {
+    let chunks = articles
+        .iter()
+        .flat_map(|&(id, content)| {
+            chunk::windows(content, CHUNK_WORDS, OVERLAP_WORDS)
+                .into_iter()
+                .enumerate()
+                .map(move |(seq, (start, end))| (id, seq as i32, start, end, &content[start..end]))
+        })
+        .collect::<Vec<_>>();
+    let texts = chunks
+        .iter()
+        .map(|(_, _, _, _, text)| text.to_string())
+        .collect::<Vec<_>>();
+    let embeddings = if texts.is_empty() {
+        vec![]
+    } else {
+        app.embed(&texts).await.map_err(EncrawlError::Model)?
+    };
+    Ok(chunks
+        .into_iter()
+        .zip(embeddings)
+        .map(|((id, seq, start, end, _), embedding)| {
+            (
+                id,
+                ChunkEmbedding {
+                    seq,
+                    start,
+                    end,
+                    embedding,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Replaces the chunks stored for the articles `ids` with `chunks`, given
+/// with the id of their article.
+async fn replace_chunks(
+    app: &Encrawl,
+    ids: &[i64],
+    chunks: &[(i64, ChunkEmbedding)],
+) -> Result<(), EncrawlError> {
+    let mut tx = app.postgres()?.begin().await?;
+    replace_chunks_in(app, &mut tx, ids, chunks).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Replaces chunks like [`replace_chunks`] as part of the transaction `tx`.
+pub(crate) async fn replace_chunks_in(
+    app: &Encrawl,
+    tx: &mut sqlx::PgConnection,
+    ids: &[i64],
+    chunks: &[(i64, ChunkEmbedding)],
+) -> Result<(), EncrawlError> {
+    sqlx::query("DELETE FROM article_chunks WHERE article_id = ANY($1)")
+        .bind(ids)
+        .execute(&mut *tx)
+        .await?;
+    for rows in chunks.chunks(MAX_ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO article_chunks (article_id, seq, start_byte, end_byte, embedding,
+                embedding_model, embedding_dim) ",
+        );
+        query.push_values(rows, |mut row, (id, chunk)| {
+            row.push_bind(*id)
+                .push_bind(chunk.seq)
+                .push_bind(chunk.start as i32)
+                .push_bind(chunk.end as i32)
+                .push_bind(pgvector::Vector::from(chunk.embedding.clone()))
+                .push_bind(app.embedding_model())
+                .push_bind(app.embedding_dim() as i32);
+        });
+        let insert_start = Instant::now();
+        query.build().execute(&mut *tx).await?;
+        app.metrics()
+            .inserted("article_chunks", rows.len(), insert_start.elapsed());
+    }
+    Ok(())
+}
+
+/// Chunks and embeds the content of articles stored without chunks,
+/// `batch_size` articles at a time. Returns the number of articles processed.
+pub async fn backfill_chunks(app: &Encrawl, batch_size: i64) -> anyhow::Result<usize> {
+    let mut last_id = 0;
+    let mut total = 0;
+    loop {
+        let articles: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT id, content FROM articles a
+            WHERE id > $1 AND NOT EXISTS (SELECT 1 FROM article_chunks c WHERE c.article_id = a.id)
+            ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(batch_size.max(1))
+        .fetch_all(app.postgres()?)
+        .await?;
+        let Some((id, _)) = articles.last() else {
+            return Ok(total);
+        };
+        last_id = *id;
+        total += articles.len();
+        let articles = articles
+            .iter()
+            .map(|(id, content)| (*id, content.as_str()))
+            .collect::<Vec<_>>();
+        store_chunks(app, &articles).await?;
+        log::info!("Chunked {} articles", total);
+    }
+}
+
+/// Embeds the [`lead`] of the articles stored without a content embedding,
+/// `batch_size` at a time. Returns the number of articles processed.
+pub async fn backfill_content_embeddings(app: &Encrawl, batch_size: i64) -> anyhow::Result<usize> {
+    check_embedding_dim(app).await?;
+    let mut last_id = 0;
+    let mut total = 0;
+    loop {
+        let articles: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT id, content FROM articles
+            WHERE id > $1 AND content_embedding IS NULL AND NOT pending_embedding
+            ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(batch_size.max(1))
+        .fetch_all(app.postgres()?)
+        .await?;
+        let Some((id, _)) = articles.last() else {
+            return Ok(total);
+        };
+        last_id = *id;
+        let leads = articles
+            .iter()
+            .map(|(_, content)| lead(content))
+            .collect::<Vec<_>>();
+        let embeddings = app.embed(&leads).await?;
+        let mut query = sqlx::QueryBuilder::<Postgres>::new(
+            "UPDATE articles SET content_embedding = v.embedding FROM (",
+        );
+        query.push_values(
+            articles.iter().zip(embeddings),
+            |mut row, ((id, _), embedding)| {
+                row.push_bind(*id)
+                    .push_bind(pgvector::Vector::from(embedding));
+            },
+        );
+        query.push(") AS v (id, embedding) WHERE articles.id = v.id");
+        query.build().execute(app.postgres()?).await?;
+        total += articles.len();
+        log::info!("Embedded the content of {} articles", total);
+    }
+}
+
+/// Dimension the `embedding` column of `articles` is declared with, `None`
+/// while it accepts any, i.e. during [`crate::embedding::reembed`].
+pub async fn embedding_column_dim(db: &Pool<Postgres>) -> anyhow::Result<Option<usize>> {
+    // pgvector stores the dimension as the type modifier, -1 when there is none.
+    let (typmod,): (i32,) = sqlx::query_as(
+        "SELECT atttypmod FROM pg_attribute WHERE attrelid = 'articles'::regclass AND attname = 'embedding'",
+    )
+    .fetch_one(db)
+    .await?;
+    Ok((typmod > 0).then_some(typmod as usize))
+}
+
+/// Fails unless the stored embeddings have the dimension of the configured
+/// model, since they can't be compared with its embeddings otherwise.
+pub async fn check_embedding_dim(app: &Encrawl) -> anyhow::Result<()> {
+    match embedding_column_dim(app.postgres()?).await? {
+        Some(dim) if dim == app.embedding_dim() => Ok(()),
+        Some(dim) => anyhow::bail!(
+            "articles are embedded with {} dimensions but {} produces {}, run `re-embed` to migrate them",
+            dim,
+            app.embedding_model(),
+            app.embedding_dim()
+        ),
+        None => anyhow::bail!(
+            "embeddings are being migrated to another model, run `re-embed` until it finishes"
+        ),
+    }
+}
+
+/// Restricts which articles [`search_filtered`] considers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFilter {
+    /// Domain the article was published on, without `www.`.
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Earliest publication date, the fetch date for articles without one.
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Case-insensitive part of the author's name.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Terms the title or text has to contain literally, ignoring case, so
+    /// articles about a similar but different company don't match.
+    #[serde(default)]
+    pub must_contain: Vec<String>,
+    /// Terms articles may not mention, on top of the ones written as `-term`
+    /// in the query. Their embeddings are also subtracted from the query's,
+    /// pushing down articles about the same subtopic in other words.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// ISO 639-1 code of the language articles are written in.
+    #[serde(default)]
+    pub lang: Option<String>,
+}
+
+impl SearchFilter {
+    /// `ILIKE` patterns of [`Self::must_contain`], see [`contains_pattern`].
+    fn must_contain_patterns(&self) -> Vec<String> {
+        self.must_contain
+            .iter()
+            .map(|term| term.trim())
+            .filter(|term| !term.is_empty())
+            .map(contains_pattern)
+            .collect()
+    }
+
+    /// `LIKE` pattern of [`Self::author`], see [`contains_pattern`].
+    pub(crate) fn author_pattern(&self) -> Option<String> {
+        self.author
+            .as_deref()
+            .map(str::trim)
+            .filter(|author| !author.is_empty())
+            .map(contains_pattern)
+    }
+}
+
+/// `LIKE` pattern matching text containing `term`, with `%`, `_` and `\`
+/// matched literally when the pattern is used with `ESCAPE '\'`.
+fn contains_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// Splits the `-term` words off `query`, returning the rest of the query and
+/// the terms.
+fn split_exclusions(query: &str) -> (String, Vec<String>) {
+    let (excluded, rest): (Vec<&str>, Vec<&str>) = query
+        .split_whitespace()
+        .partition(|word| word.len() > 1 && word.starts_with('-'));
+    (
+        rest.join(" "),
+        excluded
+            .into_iter()
+            .map(|word| word[1..].to_string())
+            .collect(),
+    )
+}
+
+/// Full-text query matching any of `terms`, each as a phrase.
+fn any_term_query(terms: &[String]) -> Option<String> {
+    let phrases = terms
+        .iter()
+        .map(|term| term.replace('"', " "))
+        .filter(|term| !term.trim().is_empty())
+        .map(|term| format!("\"{}\"", term.trim()))
+        .collect::<Vec<_>>();
+    (!phrases.is_empty()).then(|| phrases.join(" or "))
+}
+
+/// How much of the mean embedding of the excluded terms is subtracted from
+/// the query's embedding.
+const EXCLUDE_WEIGHT: f32 = 0.5;
+
+/// Conditions on the `articles` columns applying a [`SearchFilter`] bound as
+/// $3 to $5, $10 and $11. They restrict the candidates before they are ranked.
+const SEARCH_FILTER: &str = "($3::text IS NULL OR domain = $3)
+    AND ($4::timestamptz IS NULL OR COALESCE(published_at, fetched_at) >= $4)
+    AND ($5::text IS NULL OR author ILIKE $5 ESCAPE '\\')
+    AND NOT EXISTS (SELECT 1 FROM unnest($10::text[]) term
+        WHERE title NOT ILIKE term ESCAPE '\\' AND content NOT ILIKE term ESCAPE '\\')
+    AND ($11::text IS NULL OR NOT search @@ websearch_to_tsquery('english', $11))
+    AND ($12::text IS NULL OR lang = $12)";
+
+/// Smoothing constant of reciprocal rank fusion, higher values flatten the
+/// advantage of the top ranks.
+pub(crate) const RRF_K: i32 = 60;
+
+/// Candidates taken from each ranking per requested result before fusing.
+pub(crate) const RRF_CANDIDATES_PER_RESULT: i32 = 10;
+
+/// The stored article with `id`.
+pub async fn find(app: &Encrawl, id: i64) -> anyhow::Result<Option<Article>> {
+    app.articles().find(app, id).await
+}
+
+/// Returns the `limit` articles most relevant to `query`, see [`search_filtered`].
+pub async fn search(app: &Encrawl, query: String, limit: i32) -> anyhow::Result<Vec<Article>> {
+    search_filtered(app, query, limit, &SearchFilter::default()).await
+}
+
+/// Returns the `limit` articles matching `filter` most relevant to `query`.
+///
+/// Articles are ranked twice, semantically by whichever is closer of their
+/// title and their best matching content chunk, and by full-text rank, so
+/// exact terms such as ticker symbols are not drowned out by related
+/// articles. Both rankings are combined with reciprocal rank fusion.
+pub async fn search_filtered(
+    app: &Encrawl,
+    query: String,
+    limit: i32,
+    filter: &SearchFilter,
+) -> anyhow::Result<Vec<Article>> {
+    let (query, mut exclude) = split_exclusions(&query);
+    exclude.extend(filter.exclude.iter().cloned());
+    let mut texts = vec![query.clone()];
+    texts.extend(exclude.iter().cloned());
+    let mut embeddings = app.embed(&texts).await?;
+    let mut embedding = embeddings.remove(0);
+    if !embeddings.is_empty() {
+        let weight = EXCLUDE_WEIGHT / embeddings.len() as f32;
+        for excluded in &embeddings {
+            for (value, excluded) in embedding.iter_mut().zip(excluded) {
+                *value -= weight * excluded;
+            }
+        }
+    }
+    app.articles()
+        .search(app, &query, embedding, &exclude, limit, filter)
+        .await
+}
+
+async fn pg_search(
+    app: &Encrawl,
+    query: &str,
+    embedding: Vec<f32>,
+    exclude: &[String],
+    limit: i32,
+    filter: &SearchFilter,
+) -> anyhow::Result<Vec<Article>> {
+    check_embedding_dim(app).await?;
+    let embedding = pgvector::Vector::from(embedding);
+    let domain = filter.domain.as_ref().map(|domain| {
+        let domain = domain.to_lowercase();
+        domain.strip_prefix("www.").unwrap_or(&domain).to_string()
+    });
+    // Titles and chunks are ranked separately, each ordered by plain distance
+    // so the embedding indexes can be used. With a quantized index, more
+    // candidates are taken from it and ranked again by full distance. Title
+    // candidates are ranked by their distance blended with the one of the
+    // start of their content, weighed by `title_weight`, which sinks
+    // articles whose title matches but whose content is about something else.
+    let db = app.read_db().await?;
+    let quantization = index::quantization(db).await?;
+    let title_distance = quantization.distance("embedding", "$1", app.embedding_dim());
+    let chunk_distance = quantization.distance("c.embedding", "$1", app.embedding_dim());
+    let candidates = limit.max(1) * RRF_CANDIDATES_PER_RESULT;
+    Ok(sqlx::query_as::<_, Article>(&format!(
+        "WITH semantic AS (
+            SELECT id, ROW_NUMBER() OVER (ORDER BY MIN(distance)) AS rank
+            FROM (
+                (SELECT id,
+                    $13 * (embedding <=> $1)
+                        + (1 - $13) * COALESCE(content_embedding <=> $1, embedding <=> $1)
+                    AS distance
+                FROM (
+                    SELECT id, embedding, content_embedding FROM articles
+                    WHERE NOT pending_embedding AND {SEARCH_FILTER}
+                    ORDER BY {title_distance} LIMIT $9
+                ) t ORDER BY distance LIMIT $6)
+                UNION ALL
+                (SELECT id, embedding <=> $1 FROM (
+                    SELECT c.article_id AS id, c.embedding FROM article_chunks c
+                    JOIN articles ON articles.id = c.article_id
+                    WHERE {SEARCH_FILTER} ORDER BY {chunk_distance} LIMIT $9
+                ) c ORDER BY 2 LIMIT $6)
+            ) d
+            GROUP BY id ORDER BY rank LIMIT $6
+        ), keyword AS (
+            SELECT id, ROW_NUMBER() OVER (ORDER BY ts_rank_cd(search, q) DESC) AS rank
+            FROM articles, websearch_to_tsquery('english', $2) q
+            WHERE search @@ q AND {SEARCH_FILTER}
+            ORDER BY rank LIMIT $6
+        ), fused AS (
+            SELECT COALESCE(s.id, k.id) AS id,
+                COALESCE(1.0 / ($7 + s.rank), 0) + COALESCE(1.0 / ($7 + k.rank), 0) AS score
+            FROM semantic s FULL OUTER JOIN keyword k ON k.id = s.id
+        )
+        SELECT a.id, title, content, url, author, author_source, annotation, extractor, source, domain,
+            lang, synthetic_title, published_at, fetched_at
+        FROM fused JOIN articles a ON a.id = fused.id
+        ORDER BY fused.score DESC LIMIT $8"
+    ))
+    .bind(embedding)
+    .bind(query)
+    .bind(domain)
+    .bind(filter.since)
+    .bind(filter.author_pattern())
+    .bind(candidates)
+    .bind(RRF_K)
+    .bind(limit)
+    .bind(candidates * quantization.oversampling())
+    .bind(filter.must_contain_patterns())
+    .bind(any_term_query(exclude))
+    .bind(filter.lang.as_ref().map(|lang| lang.to_lowercase()))
+    .bind(app.config().title_weight as f64)
+    .fetch_all(db)
+    .await?)
+}
@@ -0,0 +1,272 @@
+//! Generated summaries kept in the `summaries` table, with the articles they
+//! were generated from, so they can be read again and reused while those
+//! articles stay the same.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Pool, Postgres};
+use std::time::Duration;
+
+use crate::app::Encrawl;
+use crate::extractive::{self, EXTRACTIVE_BACKEND};
+use crate::store::{content_hash, Article};
+use crate::summarise::{self, Provenance, Summarisable};
+use crate::usage;
+
+/// Model extractive summaries are stored with.
+const EXTRACTIVE_MODEL: &str = "embedding-centroid";
+
+const COLUMNS: &str = "id, topic, body, backend, model, article_ids,
+    provenance::text AS provenance, created_at, last_used_at";
+
+/// A stored summary.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub id: i64,
+    /// Query or topic the summary was generated for.
+    pub topic: String,
+    /// The summary, in Markdown.
+    pub body: String,
+    pub backend: String,
+    pub model: String,
+    /// Articles that fed the prompt, in prompt order.
+    pub article_ids: Vec<i64>,
+    /// What the summary was generated with, unless it was stored without.
+    pub provenance: Option<Provenance>,
+    pub created_at: DateTime<Utc>,
+    /// When the summary was last generated or reused.
+    pub last_used_at: DateTime<Utc>,
+    /// Whether [`summarize`] reused the summary instead of generating it.
+    pub reused: bool,
+}
+
+impl Summary {
+    /// Whether the summary quotes the articles because no text generator
+    /// was available, see [`crate::extractive`].
+    pub fn is_extractive(&self) -> bool {
+        self.backend == EXTRACTIVE_BACKEND
+    }
+}
+
+#[derive(FromRow)]
+struct SummaryRow {
+    id: i64,
+    topic: String,
+    body: String,
+    backend: String,
+    model: String,
+    article_ids: Vec<i64>,
+    provenance: Option<String>,
+    created_at: DateTime<Utc>,
+    last_used_at: DateTime<Utc>,
+}
+
+impl From<SummaryRow> for Summary {
+    fn from(row: SummaryRow) -> Self {
+        Self {
+            id: row.id,
+            topic: row.topic,
+            body: row.body,
+            backend: row.backend,
+            model: row.model,
+            article_ids: row.article_ids,
+            provenance: row
+                .provenance
+                .and_then(|provenance| serde_json::from_str(&provenance).ok()),
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+            reused: false,
+        }
+    }
+}
+
+/// Hex encoded SHA-256 of the ids and contents of `articles`, in order, which
+/// changes when any of them is stored again with other content.
+pub fn fingerprint(articles: &[Article]) -> String {
+    let mut hasher = Sha256::new();
+    for article in articles {
+        hasher.update(article.id.unwrap_or_default().to_le_bytes());
+        hasher.update(content_hash(&article.content));
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Summarises `articles` for `topic` with the configured summariser and
+/// stores the summary, recording the tokens spent. A summary of the same
+/// topic generated from the same articles with the same model and prompt
+/// template, which differs between length presets, within `max_age` is
+/// returned instead, `0` never reuses one.
+///
+/// When the generator can't be loaded, the summary is made extractively
+/// instead, see [`crate::extractive`], and stored as such.
+pub async fn summarize(
+    app: &Encrawl,
+    topic: &str,
+    articles: &[Article],
+    max_age: Duration,
+) -> anyhow::Result<Summary> {
+    let config = app.config();
+    let fingerprint = fingerprint(articles);
+    if !max_age.is_zero() {
+        let model = summarise::model_of(&config);
+        let backend = config.summarizer.name();
+        let template = config.summary_budget.template(summarise::DEFAULT_PROMPT);
+        let template = hex::encode(Sha256::digest(template));
+        let reused = recent(
+            app.postgres()?,
+            topic,
+            &fingerprint,
+            backend,
+            &model,
+            &template,
+            max_age,
+        );
+        if let Some(summary) = reused.await? {
+            log::info!(
+                "Reusing summary {} generated at {}, its articles are unchanged",
+                summary.id,
+                summary.created_at
+            );
+            return Ok(summary);
+        }
+    }
+    let (body, tokens, provenance) = {
+        let mut generator = match app.generator().await {
+            Ok(generator) => generator,
+            Err(e) => {
+                log::warn!(
+                    "No text generator ({:#}), summarising {:?} extractively",
+                    e,
+                    topic
+                );
+                let body = extractive::summarize(app, articles).await?;
+                let (backend, model) = (EXTRACTIVE_BACKEND, EXTRACTIVE_MODEL);
+                return insert(
+                    app.postgres()?,
+                    topic,
+                    &body,
+                    articles,
+                    backend,
+                    model,
+                    None,
+                )
+                .await;
+            }
+        };
+        let (articles, config) = (articles.to_vec(), config.clone());
+        tokio::task::spawn_blocking(move || {
+            let generated = articles.get_reproducible_summary(&config, generator.as_mut());
+            let stats = generator.last_stats();
+            log::info!(
+                "{} tokens generated ({:.2} token/s)",
+                stats.usage.completion_tokens,
+                stats.tokens_per_second()
+            );
+            generated
+        })
+        .await??
+    };
+    usage::record(app.db(), topic, config.summarizer.name(), tokens).await?;
+    save(app.postgres()?, topic, &body, articles, Some(&provenance)).await
+}
+
+/// The latest summary of `topic` generated from articles with `fingerprint`
+/// by `backend` and `model` from the prompt template with the SHA-256
+/// `template` within `max_age`, marked as used again.
+async fn recent(
+    db: &Pool<Postgres>,
+    topic: &str,
+    fingerprint: &str,
+    backend: &str,
+    model: &str,
+    template: &str,
+    max_age: Duration,
+) -> anyhow::Result<Option<Summary>> {
+    let row: Option<SummaryRow> = sqlx::query_as(&format!(
+        "UPDATE summaries SET last_used_at = now() WHERE id = (
+            SELECT id FROM summaries
+            WHERE topic = $1 AND articles_sha256 = $2 AND backend = $3 AND model = $4
+                AND provenance->>'template_sha256' = $6
+                AND created_at >= now() - make_interval(secs => $5)
+            ORDER BY created_at DESC LIMIT 1
+        )
+        RETURNING {COLUMNS}"
+    ))
+    .bind(topic)
+    .bind(fingerprint)
+    .bind(backend)
+    .bind(model)
+    .bind(max_age.as_secs_f64())
+    .bind(template)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(|row| Summary {
+        reused: true,
+        ..row.into()
+    }))
+}
+
+/// Stores `body` as the summary of `articles` for `topic`.
+pub async fn save(
+    db: &Pool<Postgres>,
+    topic: &str,
+    body: &str,
+    articles: &[Article],
+    provenance: Option<&Provenance>,
+) -> anyhow::Result<Summary> {
+    let (backend, model) = provenance
+        .map(|provenance| (provenance.backend.as_str(), provenance.model.as_str()))
+        .unwrap_or_default();
+    insert(db, topic, body, articles, backend, model, provenance).await
+}
+
+async fn insert(
+    db: &Pool<Postgres>,
+    topic: &str,
+    body: &str,
+    articles: &[Article],
+    backend: &str,
+    model: &str,
+    provenance: Option<&Provenance>,
+) -> anyhow::Result<Summary> {
+    let ids = articles
+        .iter()
+        .filter_map(|article| article.id)
+        .collect::<Vec<_>>();
+    let row: SummaryRow = sqlx::query_as(&format!(
+        "INSERT INTO summaries (topic, body, backend, model, article_ids, articles_sha256, provenance)
+        VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb)
+        RETURNING {COLUMNS}"
+    ))
+    .bind(topic)
+    .bind(body)
+    .bind(backend)
+    .bind(model)
+    .bind(&ids)
+    .bind(fingerprint(articles))
+    .bind(provenance.map(serde_json::to_string).transpose()?)
+    .fetch_one(db)
+    .await?;
+    Ok(row.into())
+}
+
+/// The latest `limit` summaries, the newest first.
+pub async fn list(db: &Pool<Postgres>, limit: i64) -> anyhow::Result<Vec<Summary>> {
+    let rows: Vec<SummaryRow> = sqlx::query_as(&format!(
+        "SELECT {COLUMNS} FROM summaries ORDER BY created_at DESC LIMIT $1"
+    ))
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(Summary::from).collect())
+}
+
+/// The summary with `id`.
+pub async fn get(db: &Pool<Postgres>, id: i64) -> anyhow::Result<Option<Summary>> {
+    let row: Option<SummaryRow> =
+        sqlx::query_as(&format!("SELECT {COLUMNS} FROM summaries WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(db)
+            .await?;
+    Ok(row.map(Summary::from))
+}
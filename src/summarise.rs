@@ -0,0 +1,661 @@
+//! Summarisation of retrieved articles with the local Mamba model or an
+//! OpenAI-compatible server.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+use crate::app::{Config, Encrawl};
+use crate::chunk;
+use crate::guardrails::{Guardrails, Violation};
+use crate::mamba::{self, GenerationStats, TokenUsage};
+use crate::openai::OpenAiSummarizer;
+use crate::store::{cosine_similarity, Article};
+use crate::usage::{self, MAMBA_BACKEND, OPENAI_BACKEND};
+
+/// Prompt used by [`Summarisable::get_summary`], `{articles}` is replaced by the articles.
+pub const DEFAULT_PROMPT: &str = "You are an conversational AI model designed to create summaries of news given to you on a specific topic. Do NOT use lists, Just output in paragraphs in Markdown. When an article has a Source type, attribute its claims accordingly, e.g. \"according to an opinion piece\". When an article was also reported by others, say so once, e.g. \"reported by X, Y and Z\", instead of repeating the story.{articles}User: Summarize the given news. You MUST add the relevant links to the content using markdown links in the format of [<Title>](<Url>).{length}\nResponse: ";
+
+/// Where summaries are generated.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SummarizerBackend {
+    /// The Mamba model of `--generation-model`, run locally on candle.
+    #[default]
+    Mamba,
+    /// Any OpenAI-compatible chat completions API, e.g. llama.cpp, vLLM or Ollama.
+    #[value(name = "openai")]
+    OpenAi,
+}
+
+impl SummarizerBackend {
+    /// Name usage of this backend is recorded under.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mamba => MAMBA_BACKEND,
+            Self::OpenAi => OPENAI_BACKEND,
+        }
+    }
+}
+
+/// A text generator summaries are written with.
+pub trait Summarizer: Send {
+    /// Seed and temperature the sampler was last set up with.
+    fn sampling(&self) -> (u64, Option<f64>);
+
+    /// Restarts the sampler with `seed` and `temperature`, `None` samples greedily.
+    fn set_sampling(&mut self, seed: u64, temperature: Option<f64>);
+
+    /// Token counts and timing of the most recent call to [`Self::run`] or [`Self::run_stream`].
+    fn last_stats(&self) -> GenerationStats;
+
+    /// Token counts of the most recent call to [`Self::run`] or [`Self::run_stream`].
+    fn last_usage(&self) -> TokenUsage {
+        self.last_stats().usage
+    }
+
+    /// Continues `prompt` with up to `sample_len` tokens, calling `on_text`
+    /// with every new piece of text as soon as it is generated.
+    fn run_stream(
+        &mut self,
+        prompt: &str,
+        sample_len: usize,
+        on_text: &mut dyn FnMut(&str) -> anyhow::Result<()>,
+    ) -> anyhow::Result<GenerationStats>;
+
+    fn run(&mut self, prompt: &str, sample_len: usize) -> anyhow::Result<String> {
+        let mut text = String::new();
+        self.run_stream(prompt, sample_len, &mut |new| {
+            text.push_str(new);
+            Ok(())
+        })?;
+        Ok(text)
+    }
+}
+
+/// Loads the summariser `config` selects. This may block for a long time
+/// while model weights are downloaded.
+pub fn load(config: &Config) -> anyhow::Result<Box<dyn Summarizer>> {
+    Ok(match config.summarizer {
+        SummarizerBackend::Mamba => Box::new(mamba::init(config.generation.clone())?),
+        SummarizerBackend::OpenAi => Box::new(OpenAiSummarizer::new(
+            config.openai.clone(),
+            &config.generation,
+            &config.user_agent,
+        )?),
+    })
+}
+
+/// What a summary was generated with, stored with every digest so the same
+/// summary can be generated again when asking why it says what it says.
+///
+/// The sampler is restarted with `seed` before each summary, so the same
+/// articles, prompt and model give the same summary. Hashes tell whether the
+/// prompt template and the prompt with the articles filled in are the same.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Provenance {
+    pub backend: String,
+    /// Repository and revision of the local model, or the model requested from the server.
+    pub model: String,
+    pub seed: u64,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Index of the [`best_of`] candidate, which was sampled with `seed + candidate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<u64>,
+    /// Hex encoded SHA-256 of the prompt template.
+    pub template_sha256: String,
+    /// Hex encoded SHA-256 of the final prompt, after long articles were condensed.
+    pub prompt_sha256: String,
+}
+
+/// The model `config` generates with: the repository and revision of the
+/// local model, or the model requested from the server.
+pub fn model_of(config: &Config) -> String {
+    match config.summarizer {
+        SummarizerBackend::Mamba => {
+            let (model_id, revision) = config.generation.repo();
+            format!("{model_id}@{revision}")
+        }
+        SummarizerBackend::OpenAi => config.openai.model.clone(),
+    }
+}
+
+impl Provenance {
+    fn new(config: &Config, template: &str, prompt: &str) -> Self {
+        Self {
+            backend: config.summarizer.name().to_string(),
+            model: model_of(config),
+            seed: config.generation.seed,
+            temperature: config.generation.temperature,
+            top_p: config.generation.top_p,
+            candidate: None,
+            template_sha256: hex::encode(Sha256::digest(template)),
+            prompt_sha256: hex::encode(Sha256::digest(prompt)),
+        }
+    }
+}
+
+/// How many tokens each stage of summarisation may generate.
+#[derive(clap::Args, Clone, Debug)]
+pub struct SummaryBudget {
+    /// Articles are condensed part by part first when the prompt would be longer than this many words
+    #[arg(long, global = true, default_value_t = 1500)]
+    pub max_prompt_words: usize,
+
+    /// Tokens generated for every article, or part of a long one, before the final summary
+    #[arg(long, global = true, default_value_t = 120)]
+    pub map_tokens: usize,
+
+    /// Tokens generated for the final summary
+    #[arg(long, global = true, default_value_t = 400)]
+    pub reduce_tokens: usize,
+
+    /// Sets the three budgets above to fit summaries of this length and asks
+    /// the model for it
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        conflicts_with_all = ["max_prompt_words", "map_tokens", "reduce_tokens"]
+    )]
+    pub length: Option<SummaryLength>,
+}
+
+impl Default for SummaryBudget {
+    fn default() -> Self {
+        Self {
+            max_prompt_words: 1500,
+            map_tokens: 120,
+            reduce_tokens: 400,
+            length: None,
+        }
+    }
+}
+
+impl SummaryBudget {
+    /// The budget of summaries of `length`.
+    pub fn preset(length: SummaryLength) -> Self {
+        let (max_prompt_words, map_tokens, reduce_tokens) = match length {
+            SummaryLength::Short => (1000, 80, 200),
+            SummaryLength::Medium => (1500, 120, 400),
+            SummaryLength::Long => (3000, 200, 800),
+        };
+        Self {
+            max_prompt_words,
+            map_tokens,
+            reduce_tokens,
+            length: Some(length),
+        }
+    }
+
+    /// `template` asking for the length of the preset in place of
+    /// `{length}`, which is dropped without one.
+    pub fn template(&self, template: &str) -> String {
+        let instruction = self.length.map_or("", SummaryLength::instruction);
+        template.replace("{length}", instruction)
+    }
+
+    /// Summaries shorter or longer than this many characters are penalised
+    /// by [`best_of`].
+    fn target_chars(&self) -> std::ops::RangeInclusive<usize> {
+        match self.length {
+            Some(SummaryLength::Short) => 200..=800,
+            Some(SummaryLength::Medium) | None => 300..=1500,
+            Some(SummaryLength::Long) => 1000..=4000,
+        }
+    }
+}
+
+/// How long summaries are, from a few sentences to several paragraphs.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryLength {
+    Short,
+    Medium,
+    Long,
+}
+
+impl SummaryLength {
+    fn instruction(self) -> &'static str {
+        match self {
+            Self::Short => " Keep it to a single paragraph of a few sentences.",
+            Self::Medium => " Write two or three paragraphs.",
+            Self::Long => " Write several paragraphs covering every article in detail.",
+        }
+    }
+}
+
+/// Prompt every article or part of one is condensed with before the final
+/// summary, when all of them together are too long.
+pub const MAP_PROMPT: &str = "You are an conversational AI model designed to condense news articles. Keep names, numbers and dates.\nTitle: {title}\nContent: {content}\nUser: Summarize the given part of the article in a few sentences.\nResponse: ";
+
+/// Condenses `content` of the article titled `title` until it fits the
+/// budget: it is split into windows that are summarised one by one, and their
+/// summaries are split and summarised again for as long as they are too long
+/// together. Always condenses at least once.
+pub fn condense(
+    title: &str,
+    content: &str,
+    budget: &SummaryBudget,
+    text_generator: &mut dyn Summarizer,
+) -> anyhow::Result<(String, TokenUsage)> {
+    let mut usage = TokenUsage::default();
+    let mut text = content.to_string();
+    let mut words = text.split_whitespace().count();
+    for round in 1.. {
+        let mut parts = vec![];
+        for (start, end) in chunk::windows(&text, budget.max_prompt_words, 0) {
+            let prompt = MAP_PROMPT
+                .replace("{title}", title)
+                .replace("{content}", &text[start..end]);
+            parts.push(
+                text_generator
+                    .run(&prompt, budget.map_tokens)?
+                    .trim()
+                    .to_string(),
+            );
+            usage += text_generator.last_usage();
+        }
+        let condensed = parts.join(" ");
+        let condensed_words = condensed.split_whitespace().count();
+        log::debug!("Condensed {title:?} from {words} to {condensed_words} words in round {round}");
+        // Stop when a round no longer shortens it, rather than loop forever
+        // on a generator that answers at length.
+        let shrunk = condensed_words < words;
+        text = condensed;
+        words = condensed_words;
+        if words <= budget.max_prompt_words || !shrunk {
+            break;
+        }
+    }
+    Ok((text, usage))
+}
+
+/// Prompt the headline of an article without a title is generated with.
+pub const HEADLINE_PROMPT: &str = "You are an conversational AI model designed to write headlines for news articles.\nContent: {content}\nUser: Write a headline of at most 12 words for the given article.\nResponse: ";
+
+/// Tokens generated for a headline.
+const HEADLINE_TOKENS: usize = 32;
+
+/// Gives every article in `articles` without a title one generated from the
+/// start of its content, marking it as [`Article::synthetic_title`]. Articles
+/// the generator fails on keep their empty title.
+pub async fn generate_headlines(app: &Encrawl, articles: &mut [Article]) -> anyhow::Result<()> {
+    if articles.iter().all(|a| !a.title.trim().is_empty()) {
+        return Ok(());
+    }
+    let config = app.config();
+    let budget = &config.summary_budget;
+    let mut usage = TokenUsage::default();
+    // Failing to load the generator fails the whole batch, not each article.
+    drop(app.generator().await?);
+    for article in articles.iter_mut().filter(|a| a.title.trim().is_empty()) {
+        let end = chunk::windows(&article.content, budget.max_prompt_words, 0)
+            .first()
+            .map_or(0, |(_, end)| *end);
+        let prompt = HEADLINE_PROMPT.replace("{content}", &article.content[..end]);
+        let headline = app
+            .with_generator(move |generator| {
+                let headline = generator.run(&prompt, HEADLINE_TOKENS)?;
+                Ok((headline, generator.last_usage()))
+            })
+            .await;
+        match headline {
+            Ok((headline, tokens)) => {
+                usage += tokens;
+                let headline = headline.lines().find(|line| !line.trim().is_empty());
+                let headline = headline
+                    .unwrap_or_default()
+                    .trim_matches(|c: char| c == '"' || c == '#' || c == '*' || c.is_whitespace());
+                log::debug!("Generated the headline {headline:?} for {}", article.url);
+                article.title = headline.to_string();
+                article.synthetic_title = !headline.is_empty();
+            }
+            Err(e) => log::warn!("Failed to generate a headline for {}: {}", article.url, e),
+        }
+    }
+    usage::record(app.db(), "headlines", config.summarizer.name(), usage).await
+}
+
+/// Things that can be turned into a prose summary by a text generator.
+pub trait Summarisable {
+    /// Renders `template`, replacing `{articles}` with the formatted content.
+    fn prompt(&self, template: &str) -> String;
+
+    /// Like [`Self::prompt`], but when that is longer than the budget allows,
+    /// the parts are summarised one by one first and their summaries stand
+    /// in for the content. Returns the tokens spent on that as well.
+    fn reduce_prompt(
+        &self,
+        template: &str,
+        budget: &SummaryBudget,
+        text_generator: &mut dyn Summarizer,
+    ) -> anyhow::Result<(String, TokenUsage)>;
+
+    /// Summarises with `template` and returns the summary along with the
+    /// tokens spent on all stages.
+    fn get_summary_with(
+        &self,
+        template: &str,
+        budget: &SummaryBudget,
+        text_generator: &mut dyn Summarizer,
+    ) -> anyhow::Result<(String, TokenUsage)> {
+        let (prompt, mut usage) = self.reduce_prompt(template, budget, text_generator)?;
+        let summary = text_generator.run(&prompt, budget.reduce_tokens)?;
+        usage += text_generator.last_usage();
+        Ok((summary, usage))
+    }
+
+    fn get_summary(
+        &self,
+        budget: &SummaryBudget,
+        text_generator: &mut dyn Summarizer,
+    ) -> anyhow::Result<(String, TokenUsage)> {
+        self.get_summary_with(DEFAULT_PROMPT, budget, text_generator)
+    }
+
+    /// Like [`Self::get_summary`], but regenerates the summary once if it
+    /// breaks any of the `guardrails`.
+    fn get_checked_summary(
+        &self,
+        guardrails: &Guardrails,
+        budget: &SummaryBudget,
+        text_generator: &mut dyn Summarizer,
+    ) -> anyhow::Result<(String, TokenUsage)> {
+        let (prompt, usage) = self.reduce_prompt(DEFAULT_PROMPT, budget, text_generator)?;
+        checked_run(&prompt, usage, guardrails, budget, text_generator)
+    }
+
+    /// Like [`Self::get_checked_summary`] with the guardrails, budget and
+    /// sampling of `config`, restarting the sampler with the configured seed
+    /// first. Returns what the summary was generated with too.
+    fn get_reproducible_summary(
+        &self,
+        config: &Config,
+        text_generator: &mut dyn Summarizer,
+    ) -> anyhow::Result<(String, TokenUsage, Provenance)> {
+        let generation = &config.generation;
+        text_generator.set_sampling(generation.seed, generation.temperature);
+        let budget = &config.summary_budget;
+        let (prompt, usage) = self.reduce_prompt(DEFAULT_PROMPT, budget, text_generator)?;
+        let (summary, usage) =
+            checked_run(&prompt, usage, &config.guardrails, budget, text_generator)?;
+        Ok((
+            summary,
+            usage,
+            Provenance::new(config, &budget.template(DEFAULT_PROMPT), &prompt),
+        ))
+    }
+
+    /// Generates a summary with the default prompt, passing each new piece
+    /// of the final summary to `on_text`. Returns the tokens spent on all stages.
+    fn stream_summary(
+        &self,
+        budget: &SummaryBudget,
+        text_generator: &mut dyn Summarizer,
+        mut on_text: impl FnMut(&str) -> anyhow::Result<()>,
+    ) -> anyhow::Result<TokenUsage> {
+        let (prompt, mut usage) = self.reduce_prompt(DEFAULT_PROMPT, budget, text_generator)?;
+        usage += text_generator
+            .run_stream(&prompt, budget.reduce_tokens, &mut on_text)?
+            .usage;
+        Ok(usage)
+    }
+}
+
+/// Generates the final summary from `prompt`, once more if it breaks any of
+/// the `guardrails`. Returns it with `usage` plus the tokens spent.
+fn checked_run(
+    prompt: &str,
+    mut usage: TokenUsage,
+    guardrails: &Guardrails,
+    budget: &SummaryBudget,
+    text_generator: &mut dyn Summarizer,
+) -> anyhow::Result<(String, TokenUsage)> {
+    let summary = text_generator.run(prompt, budget.reduce_tokens)?;
+    usage += text_generator.last_usage();
+    let violations = guardrails.check(&summary);
+    if violations.is_empty() {
+        return Ok((summary, usage));
+    }
+    log::warn!("Regenerating summary that {}", join(&violations));
+    let summary = text_generator.run(prompt, budget.reduce_tokens)?;
+    usage += text_generator.last_usage();
+    let violations = guardrails.check(&summary);
+    if !violations.is_empty() {
+        log::warn!("Regenerated summary still {}", join(&violations));
+    }
+    Ok((summary, usage))
+}
+
+/// A summary sampled by [`best_of`] and how it was scored.
+#[derive(Debug)]
+pub struct Candidate {
+    pub summary: String,
+    /// Share of the source articles linked from the summary.
+    pub coverage: f32,
+    /// 1 inside the target length, falling off linearly outside of it.
+    pub length: f32,
+    /// Cosine similarity between the summary and the mean source embedding.
+    pub similarity: f32,
+    pub score: f32,
+    pub provenance: Provenance,
+}
+
+/// Samples `n` summaries of `articles` at `temperature` and returns all of
+/// them, best first, along with the tokens spent on generating them.
+///
+/// Candidates are scored by how many articles they link to, whether their
+/// length suits the configured [`SummaryLength`] and how close their
+/// embedding is to the sources.
+pub async fn best_of(
+    app: &Encrawl,
+    articles: &[Article],
+    n: usize,
+    temperature: f64,
+) -> anyhow::Result<(Vec<Candidate>, TokenUsage)> {
+    let config = app.config();
+    let budget = &config.summary_budget;
+    let (seed, original_temperature) = (config.generation.seed, config.generation.temperature);
+    let sampled = {
+        let articles = articles.to_vec();
+        let config = config.clone();
+        app.with_generator(move |generator| {
+            generator.set_sampling(seed, original_temperature);
+            let budget = &config.summary_budget;
+            let result = (|| {
+                // Long article sets are only condensed once for all candidates.
+                let (prompt, mut usage) =
+                    articles.reduce_prompt(DEFAULT_PROMPT, budget, generator)?;
+                let mut summaries = vec![];
+                for i in 0..n.max(1) {
+                    generator.set_sampling(seed.wrapping_add(i as u64), Some(temperature));
+                    summaries.push(generator.run(&prompt, budget.reduce_tokens)?);
+                    usage += generator.last_usage();
+                }
+                let template = budget.template(DEFAULT_PROMPT);
+                anyhow::Ok((
+                    summaries,
+                    usage,
+                    Provenance::new(&config, &template, &prompt),
+                ))
+            })();
+            generator.set_sampling(seed, original_temperature);
+            result
+        })
+    };
+    let (summaries, usage, provenance) = sampled.await?;
+
+    let sources = articles
+        .iter()
+        .map(|a| format!("{}\n{}", a.title, a.content))
+        .collect::<Vec<_>>();
+    let source_embeddings = app.embed(&sources).await?;
+    let dim = source_embeddings.first().map_or(0, Vec::len);
+    let mut centroid = vec![0f32; dim];
+    for embedding in &source_embeddings {
+        for (c, x) in centroid.iter_mut().zip(embedding) {
+            *c += x / source_embeddings.len() as f32;
+        }
+    }
+    let summary_embeddings = app.embed(&summaries).await?;
+    let target = budget.target_chars();
+
+    let mut candidates = summaries
+        .into_iter()
+        .zip(summary_embeddings)
+        .enumerate()
+        .map(|(i, (summary, embedding))| {
+            let linked = articles.iter().filter(|a| summary.contains(&a.url)).count();
+            let coverage = linked as f32 / articles.len().max(1) as f32;
+            let len = summary.chars().count();
+            let length = if target.contains(&len) {
+                1.0
+            } else if len < *target.start() {
+                len as f32 / *target.start() as f32
+            } else {
+                (*target.end() as f32 / len as f32).clamp(0.0, 1.0)
+            };
+            let similarity = cosine_similarity(&embedding, &centroid);
+            Candidate {
+                score: 0.4 * coverage + 0.2 * length + 0.4 * similarity,
+                summary,
+                coverage,
+                length,
+                similarity,
+                provenance: Provenance {
+                    temperature: Some(temperature),
+                    candidate: Some(i as u64),
+                    ..provenance.clone()
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok((candidates, usage))
+}
+
+fn join(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(Violation::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Share of the words two titles have in common, of all the words in
+/// either, from which they are taken to be the same story.
+const DUPLICATE_TITLE_OVERLAP: f32 = 0.6;
+
+/// Groups the indexes of `articles` whose titles are near-identical, so a
+/// story reported by several sites goes into the prompt once. Every group
+/// starts with the article with the longest content, which stands for it,
+/// and the groups are in the order of their first article.
+pub fn duplicate_groups(articles: &[Article]) -> Vec<Vec<usize>> {
+    let words = articles
+        .iter()
+        .map(|a| {
+            a.title
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| word.chars().count() > 2)
+                .map(str::to_lowercase)
+                .collect::<HashSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    let same_story = |a: usize, b: usize| {
+        let (a, b) = (&words[a], &words[b]);
+        let union = a.union(b).count();
+        union > 0 && a.intersection(b).count() as f32 / union as f32 >= DUPLICATE_TITLE_OVERLAP
+    };
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for i in 0..articles.len() {
+        match groups
+            .iter_mut()
+            .find(|group| group.iter().any(|j| same_story(i, *j)))
+        {
+            Some(group) => group.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+    for group in &mut groups {
+        let longest = (0..group.len())
+            .max_by_key(|k| articles[group[*k]].content.len())
+            .unwrap_or(0);
+        group.swap(0, longest);
+        group[1..].sort_unstable();
+    }
+    groups
+}
+
+/// Renders `template` with `articles`, using `contents` in place of their content.
+fn render(template: &str, articles: &[Article], contents: &[&str]) -> String {
+    let articles = duplicate_groups(articles)
+        .into_iter()
+        .enumerate()
+        .map(|(i, group)| {
+            let (a, content) = (&articles[group[0]], contents[group[0]]);
+            let source_type = a
+                .annotation
+                .as_ref()
+                .map(|annotation| format!("Source type: {annotation}\n"))
+                .unwrap_or_default();
+            let also_reported = group[1..]
+                .iter()
+                .map(|j| {
+                    let other = &articles[*j];
+                    let site = other.domain().unwrap_or_else(|| other.title.clone());
+                    format!("{site} ({})", other.url)
+                })
+                .collect::<Vec<_>>();
+            let also_reported = if also_reported.is_empty() {
+                String::new()
+            } else {
+                format!("Also reported by: {}\n", also_reported.join(", "))
+            };
+            format!(
+                "Article: {i}\nTitle: {}\nAuthor: {}\nUrl: {}\n{also_reported}{source_type}Content: {}\n",
+                a.title, a.author, a.url, content
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    template
+        .replace("{articles}", &articles)
+        .replace("{length}", "")
+}
+
+impl Summarisable for [Article] {
+    fn prompt(&self, template: &str) -> String {
+        let contents = self.iter().map(|a| a.content.as_str()).collect::<Vec<_>>();
+        render(template, self, &contents)
+    }
+
+    fn reduce_prompt(
+        &self,
+        template: &str,
+        budget: &SummaryBudget,
+        text_generator: &mut dyn Summarizer,
+    ) -> anyhow::Result<(String, TokenUsage)> {
+        let template = &budget.template(template);
+        let prompt = self.prompt(template);
+        let mut usage = TokenUsage::default();
+        if prompt.split_whitespace().count() <= budget.max_prompt_words {
+            return Ok((prompt, usage));
+        }
+        log::debug!(
+            "Summarising {} articles one by one, together they are too long",
+            self.len()
+        );
+        // Only the article standing for a group of duplicates is rendered.
+        let mut notes = vec![String::new(); self.len()];
+        for group in duplicate_groups(self) {
+            let a = &self[group[0]];
+            let (note, spent) = condense(&a.title, &a.content, budget, text_generator)?;
+            notes[group[0]] = note;
+            usage += spent;
+        }
+        let notes = notes.iter().map(String::as_str).collect::<Vec<_>>();
+        Ok((render(template, self, &notes), usage))
+    }
+}
@@ -0,0 +1,479 @@
+//! Interactive Telegram bot answering commands with the shared application state.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::app::Encrawl;
+use crate::digest::{self, Section};
+use crate::http::HttpClient;
+use crate::sink::{self, truncate, ConfiguredSink, TelegramSink, TELEGRAM_MAX_CHARS};
+use crate::store::search;
+use crate::summaries;
+
+/// Seconds a `getUpdates` call waits for new messages, below the HTTP client timeout.
+const POLL_TIMEOUT_SECS: u64 = 25;
+
+/// Articles listed by `/search` and summarised by `/summarize`.
+const RESULTS: i32 = 5;
+
+/// How often subscriptions are checked for being due.
+const SCHEDULE_TICK: Duration = Duration::from_secs(60);
+
+/// Shortest schedule a subscription may have, so a few chats can't keep the generator busy.
+const MIN_SUBSCRIPTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Schedule of subscriptions made without one.
+const DEFAULT_SUBSCRIPTION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const HELP: &str = "/search <query> - list the closest articles, words starting with - are left out, e.g. /search ai -crypto
+/summarize <query> - summarise the closest articles
+/add-source r/<subreddit> or <feed url> - crawl a new source
+/subscribe [interval] <topic> - get a summary every interval, e.g. /subscribe 12h crypto
+/unsubscribe <topic> - stop a subscription
+/subscriptions - list your subscriptions";
+
+/// A command sent to the bot.
+#[derive(Debug, PartialEq)]
+pub enum BotCommand {
+    Search(String),
+    Summarize(String),
+    /// A subreddit as `r/<name>` or the URL of an RSS or Atom feed.
+    AddSource(String),
+    Subscribe {
+        topic: String,
+        interval: Duration,
+    },
+    Unsubscribe(String),
+    Subscriptions,
+    Help,
+}
+
+impl BotCommand {
+    /// Parses a message such as `/search tax`, ignoring anything that isn't a
+    /// command. The `@botname` suffix Telegram adds in groups is accepted and,
+    /// since Telegram only links commands with underscores, so is `/add_source`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (command, argument) = text.trim().split_once(' ').unwrap_or((text.trim(), ""));
+        let command = command.strip_prefix('/')?;
+        let command = command
+            .split_once('@')
+            .map_or(command, |(command, _)| command);
+        let argument = argument.trim().to_string();
+        match command {
+            "search" if !argument.is_empty() => Some(Self::Search(argument)),
+            "summarize" | "summarise" if !argument.is_empty() => Some(Self::Summarize(argument)),
+            "add-source" | "add_source" if !argument.is_empty() => Some(Self::AddSource(argument)),
+            "subscribe" if !argument.is_empty() => {
+                let interval = argument.split_once(' ').and_then(|(interval, topic)| {
+                    Some((humantime::parse_duration(interval).ok()?, topic))
+                });
+                Some(match interval {
+                    Some((interval, topic)) => Self::Subscribe {
+                        topic: topic.trim().to_string(),
+                        interval,
+                    },
+                    None => Self::Subscribe {
+                        topic: argument,
+                        interval: DEFAULT_SUBSCRIPTION_INTERVAL,
+                    },
+                })
+            }
+            "unsubscribe" if !argument.is_empty() => Some(Self::Unsubscribe(argument)),
+            "subscriptions" => Some(Self::Subscriptions),
+            _ => Some(Self::Help),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+/// A topic a chat gets a summary of every `interval_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Subscription {
+    pub id: i64,
+    pub chat_id: i64,
+    pub topic: String,
+    pub interval_secs: i64,
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl Subscription {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(0) as u64)
+    }
+}
+
+/// Subscribes `chat_id` to `topic`, replacing the schedule of an existing subscription.
+pub async fn subscribe(
+    db: &Pool<Postgres>,
+    chat_id: i64,
+    topic: &str,
+    interval: Duration,
+) -> anyhow::Result<Subscription> {
+    Ok(sqlx::query_as(
+        "INSERT INTO telegram_subscriptions (chat_id, topic, interval_secs) VALUES ($1, $2, $3)
+        ON CONFLICT (chat_id, topic) DO UPDATE SET interval_secs = EXCLUDED.interval_secs
+        RETURNING id, chat_id, topic, interval_secs, last_sent_at",
+    )
+    .bind(chat_id)
+    .bind(topic)
+    .bind(interval.as_secs() as i64)
+    .fetch_one(db)
+    .await?)
+}
+
+/// Deletes the subscription of `chat_id` to `topic`, returning whether it existed.
+pub async fn unsubscribe(db: &Pool<Postgres>, chat_id: i64, topic: &str) -> anyhow::Result<bool> {
+    let result =
+        sqlx::query("DELETE FROM telegram_subscriptions WHERE chat_id = $1 AND topic = $2")
+            .bind(chat_id)
+            .bind(topic)
+            .execute(db)
+            .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn subscriptions(db: &Pool<Postgres>, chat_id: i64) -> anyhow::Result<Vec<Subscription>> {
+    Ok(sqlx::query_as(
+        "SELECT id, chat_id, topic, interval_secs, last_sent_at FROM telegram_subscriptions
+        WHERE chat_id = $1 ORDER BY topic",
+    )
+    .bind(chat_id)
+    .fetch_all(db)
+    .await?)
+}
+
+/// Subscriptions of every chat whose next summary is due.
+pub async fn due_subscriptions(db: &Pool<Postgres>) -> anyhow::Result<Vec<Subscription>> {
+    Ok(sqlx::query_as(
+        "SELECT id, chat_id, topic, interval_secs, last_sent_at FROM telegram_subscriptions
+        WHERE last_sent_at IS NULL OR last_sent_at + make_interval(secs => interval_secs) <= now()
+        ORDER BY last_sent_at NULLS FIRST",
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+/// Long polls a bot for messages and answers the commands in them, and
+/// sends every chat the summaries it subscribed to.
+pub struct TelegramBot {
+    http: HttpClient,
+    bot_token: String,
+    /// Chats allowed to use the bot, since it can change what is crawled.
+    allowed_chats: Vec<i64>,
+}
+
+impl TelegramBot {
+    pub fn new(http: HttpClient, bot_token: String, allowed_chats: Vec<i64>) -> Self {
+        Self {
+            http,
+            bot_token,
+            allowed_chats,
+        }
+    }
+
+    /// Answers commands and delivers subscriptions forever.
+    pub async fn run(&self, app: &Encrawl) -> anyhow::Result<()> {
+        tokio::try_join!(self.answer(app), self.deliver_subscriptions(app))?;
+        Ok(())
+    }
+
+    /// Answers commands, one at a time.
+    async fn answer(&self, app: &Encrawl) -> anyhow::Result<()> {
+        let mut offset = 0;
+        loop {
+            let updates = match self.updates(offset).await {
+                Ok(updates) => updates,
+                Err(e) => {
+                    log::error!("Failed to fetch Telegram updates: {}", e);
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+                let Some(Message {
+                    chat,
+                    text: Some(text),
+                }) = update.message
+                else {
+                    continue;
+                };
+                let Some(command) = BotCommand::parse(&text) else {
+                    continue;
+                };
+                if !self.allowed_chats.contains(&chat.id) {
+                    log::warn!("Ignoring {:?} from chat {}", command, chat.id);
+                    continue;
+                }
+                let reply = handle(app, chat.id, &command).await.unwrap_or_else(|e| {
+                    log::error!("Failed to handle {:?}: {}", command, e);
+                    format!("Failed: {e}")
+                });
+                if let Err(e) = self.reply(chat.id, &reply).await {
+                    log::error!("Failed to reply to chat {}: {}", chat.id, e);
+                }
+            }
+        }
+    }
+
+    /// Summarises the topics of due subscriptions and sends them to their
+    /// chats as digests, so they show up in the delivery log.
+    async fn deliver_subscriptions(&self, app: &Encrawl) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(SCHEDULE_TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let due = match due_subscriptions(app.postgres()?).await {
+                Ok(due) => due,
+                Err(e) => {
+                    log::error!("Failed to load due subscriptions: {}", e);
+                    continue;
+                }
+            };
+            // Topics of a chat due together share their articles, so a story
+            // is only summarised under the topic it matches best.
+            let mut by_chat = BTreeMap::<i64, Vec<Subscription>>::new();
+            for subscription in due {
+                by_chat
+                    .entry(subscription.chat_id)
+                    .or_default()
+                    .push(subscription);
+            }
+            for (chat_id, subscriptions) in by_chat {
+                let topics = subscriptions
+                    .iter()
+                    .map(|subscription| subscription.topic.clone())
+                    .collect::<Vec<_>>();
+                match digest::sections(app, &topics, RESULTS).await {
+                    Ok(sections) => {
+                        for (subscription, section) in subscriptions.iter().zip(&sections) {
+                            if let Err(e) = self.deliver_section(app, chat_id, section).await {
+                                log::error!(
+                                    "Failed to send {:?} to chat {}: {}",
+                                    subscription.topic,
+                                    chat_id,
+                                    e
+                                );
+                            }
+                        }
+                    }
+                    Err(e) => log::error!(
+                        "Failed to retrieve {:?} for chat {}: {}",
+                        topics,
+                        chat_id,
+                        e
+                    ),
+                }
+                // Failed deliveries wait for the next interval too, so a broken
+                // topic doesn't keep the generator busy; they can be redelivered.
+                for subscription in &subscriptions {
+                    sqlx::query(
+                        "UPDATE telegram_subscriptions SET last_sent_at = now() WHERE id = $1",
+                    )
+                    .bind(subscription.id)
+                    .execute(app.postgres()?)
+                    .await?;
+                }
+            }
+        }
+    }
+
+    async fn deliver_section(
+        &self,
+        app: &Encrawl,
+        chat_id: i64,
+        section: &Section,
+    ) -> anyhow::Result<()> {
+        let (summary, provenance) = section.summarize(app).await?;
+        let digest = sink::save(
+            app.postgres()?,
+            &section.topic,
+            &summary,
+            provenance.as_ref(),
+        )
+        .await?;
+        let sink = ConfiguredSink::new(Arc::new(TelegramSink::new(
+            self.http.clone(),
+            self.bot_token.clone(),
+            chat_id.to_string(),
+        )));
+        sink::deliver(app, &digest, &[sink]).await?;
+        Ok(())
+    }
+
+    fn method_url(&self, method: &str) -> String {
+        format!("https://api.telegram.org/bot{}/{}", self.bot_token, method)
+    }
+
+    async fn updates(&self, offset: i64) -> anyhow::Result<Vec<Update>> {
+        let req = self.http.get(self.method_url("getUpdates")).query(&[
+            ("offset", offset.to_string()),
+            ("timeout", POLL_TIMEOUT_SECS.to_string()),
+            ("allowed_updates", r#"["message"]"#.to_string()),
+        ]);
+        let res = self.http.send(req).await?;
+        let res: ApiResponse<Vec<Update>> = serde_json::from_slice(&res.bytes().await?)?;
+        if !res.ok {
+            anyhow::bail!(res.description.unwrap_or_default());
+        }
+        Ok(res.result.unwrap_or_default())
+    }
+
+    async fn reply(&self, chat_id: i64, text: &str) -> anyhow::Result<()> {
+        let body = serde_json::json!({
+            "chat_id": chat_id,
+            "text": truncate(text, TELEGRAM_MAX_CHARS),
+            "disable_web_page_preview": true,
+        });
+        let req = self
+            .http
+            .post(self.method_url("sendMessage"))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?);
+        self.http.send(req).await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Runs `command` sent from `chat_id` and returns the reply.
+pub async fn handle(app: &Encrawl, chat_id: i64, command: &BotCommand) -> anyhow::Result<String> {
+    match command {
+        BotCommand::Search(query) => {
+            let articles = search(app, query.clone(), RESULTS).await?;
+            if articles.is_empty() {
+                return Ok("No articles found".to_string());
+            }
+            Ok(articles
+                .iter()
+                .map(|article| format!("{}\n{}", article.title, article.url))
+                .collect::<Vec<_>>()
+                .join("\n\n"))
+        }
+        BotCommand::Summarize(query) => summarize(app, query).await,
+        BotCommand::AddSource(source) => add_source(app, source).await,
+        BotCommand::Subscribe { topic, interval } => {
+            if topic.is_empty() {
+                anyhow::bail!("missing the topic to subscribe to");
+            }
+            if *interval < MIN_SUBSCRIPTION_INTERVAL {
+                anyhow::bail!(
+                    "subscriptions can't be sent more often than every {}",
+                    humantime::format_duration(MIN_SUBSCRIPTION_INTERVAL)
+                );
+            }
+            let subscription = subscribe(app.postgres()?, chat_id, topic, *interval).await?;
+            Ok(format!(
+                "Subscribed to {}, sent every {}",
+                subscription.topic,
+                humantime::format_duration(subscription.interval())
+            ))
+        }
+        BotCommand::Unsubscribe(topic) => {
+            Ok(if unsubscribe(app.postgres()?, chat_id, topic).await? {
+                format!("Unsubscribed from {topic}")
+            } else {
+                format!("You aren't subscribed to {topic}")
+            })
+        }
+        BotCommand::Subscriptions => {
+            let subscriptions = subscriptions(app.postgres()?, chat_id).await?;
+            if subscriptions.is_empty() {
+                return Ok("No subscriptions".to_string());
+            }
+            Ok(subscriptions
+                .iter()
+                .map(|subscription| {
+                    format!(
+                        "{} every {}",
+                        subscription.topic,
+                        humantime::format_duration(subscription.interval())
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        BotCommand::Help => Ok(HELP.to_string()),
+    }
+}
+
+/// Summarises the articles closest to `query`, or reuses a recent summary
+/// of the same articles.
+async fn summarize(app: &Encrawl, query: &str) -> anyhow::Result<String> {
+    let articles = search(app, query.to_string(), RESULTS).await?;
+    let max_age = app.config().summary_max_age;
+    Ok(summaries::summarize(app, query, &articles, max_age)
+        .await?
+        .body)
+}
+
+/// Appends a subreddit to the subs list or a feed to the feeds list and
+/// reloads the config, so the next crawl picks it up.
+async fn add_source(app: &Encrawl, source: &str) -> anyhow::Result<String> {
+    let config = app.config();
+    let (path, line, known) = if let Some(name) = source
+        .strip_prefix("/r/")
+        .or_else(|| source.strip_prefix("r/"))
+    {
+        let name = name.trim_end_matches('/');
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("{} is not a subreddit name", name);
+        }
+        let known = config
+            .subs
+            .iter()
+            .any(|sub| sub.name.eq_ignore_ascii_case(name));
+        (config.subs_path.clone(), name.to_string(), known)
+    } else if url::Url::parse(source).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+        let known = config.feeds.iter().any(|feed| feed == source);
+        (config.feeds_path.clone(), source.to_string(), known)
+    } else {
+        anyhow::bail!("expected r/<subreddit> or a feed URL, got {}", source);
+    };
+    if known {
+        return Ok(format!("{source} is already crawled"));
+    }
+    tokio::task::spawn_blocking(move || append_line(&path, &line)).await??;
+    app.reload().await?;
+    Ok(format!("Added {source}, it is crawled from the next run"))
+}
+
+fn append_line(path: &Path, line: &str) -> std::io::Result<()> {
+    let existing = std::fs::read_to_string(path).unwrap_or_default();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        writeln!(file)?;
+    }
+    writeln!(file, "{line}")
+}
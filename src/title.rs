@@ -0,0 +1,202 @@
+//! Cleaning of the boilerplate sites wrap their headlines in, like the
+//! `Live updates: ... | Site Name — Section` of `<title>` tags, which makes
+//! titles embed and read poorly. Extracted titles are cleaned with the
+//! [`TitleRules`] of their scraper, or for sites read with the generic
+//! extractor those of their domain in `config.toml`:
+//!
+//! ```toml
+//! [title_rules."example.com"]
+//! separators = [" | ", " - "]
+//! prefixes = ["Opinion:"]
+//! ```
+//!
+//! Domains without rules get the defaults.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+/// How titles of a domain are cleaned, set with `title_rules` in
+/// `scrapers.ron` or `config.toml`. Rules left out keep their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TitleRules {
+    /// Separators site names and sections are set off with. Of the parts of
+    /// a title split at them, the longest is kept.
+    pub separators: Vec<String>,
+    /// Labels removed from the start of titles, ignoring case.
+    pub prefixes: Vec<String>,
+    /// Remove emoji.
+    pub strip_emoji: bool,
+}
+
+impl Default for TitleRules {
+    fn default() -> Self {
+        Self {
+            separators: [" | ", " – ", " — ", " :: "].map(str::to_string).to_vec(),
+            prefixes: [
+                "LIVE:",
+                "Live updates:",
+                "BREAKING:",
+                "Breaking news:",
+                "WATCH:",
+                "UPDATE:",
+                "EXCLUSIVE:",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            strip_emoji: true,
+        }
+    }
+}
+
+impl TitleRules {
+    /// `title` without emoji, site suffixes and labels, with its whitespace
+    /// collapsed. Titles that would end up empty are only trimmed.
+    pub fn clean(&self, title: &str) -> String {
+        let mut clean = if self.strip_emoji {
+            title.chars().filter(|&c| !is_emoji(c)).collect()
+        } else {
+            title.to_string()
+        };
+        for separator in self.separators.iter().filter(|s| !s.is_empty()) {
+            clean = clean.replace(separator.as_str(), "\0");
+        }
+        // The first of equally long parts, the headline usually comes first.
+        let mut clean = clean
+            .split('\0')
+            .rev()
+            .max_by_key(|part| part.trim().chars().count())
+            .unwrap_or_default()
+            .trim();
+        while let Some(rest) = self
+            .prefixes
+            .iter()
+            .find_map(|prefix| strip_prefix(clean, prefix))
+        {
+            clean = rest.trim_start();
+        }
+        let clean = clean.split_whitespace().collect::<Vec<_>>().join(" ");
+        if clean.is_empty() {
+            title.trim().to_string()
+        } else {
+            clean
+        }
+    }
+}
+
+/// The rules of the most specific domain of `title_rules` the host of `url`
+/// is on, subdomains included, or the defaults.
+pub fn rules_for<'a>(title_rules: &'a BTreeMap<String, TitleRules>, url: &str) -> &'a TitleRules {
+    static DEFAULT: LazyLock<TitleRules> = LazyLock::new(TitleRules::default);
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default();
+    title_rules
+        .iter()
+        .filter(|(domain, _)| {
+            let domain = domain.to_lowercase();
+            host == domain
+                || host
+                    .strip_suffix(&domain)
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+        .max_by_key(|(domain, _)| domain.len())
+        .map_or(&*DEFAULT, |(_, rules)| rules)
+}
+
+fn strip_prefix<'a>(title: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = title.get(..prefix.len())?;
+    (!prefix.is_empty() && head.eq_ignore_ascii_case(prefix)).then(|| &title[prefix.len()..])
+}
+
+/// Pictographs, dingbats, flags and the joiners and selectors emoji are
+/// built with.
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F | 0x200D | 0x20E3
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_site_names_and_sections() {
+        let rules = TitleRules::default();
+        assert_eq!(
+            rules.clean("Fed holds rates steady | Example News"),
+            "Fed holds rates steady"
+        );
+        assert_eq!(
+            rules.clean("Markets — Oil prices jump after the OPEC meeting — Example"),
+            "Oil prices jump after the OPEC meeting"
+        );
+    }
+
+    #[test]
+    fn keeps_the_longest_part() {
+        let rules = TitleRules::default();
+        assert_eq!(
+            rules.clean("Example | Stocks slide as bond yields climb"),
+            "Stocks slide as bond yields climb"
+        );
+        // The first of equally long parts.
+        assert_eq!(rules.clean("Left side | Rightside"), "Left side");
+    }
+
+    #[test]
+    fn removes_labels_ignoring_case() {
+        let rules = TitleRules::default();
+        assert_eq!(
+            rules.clean("BREAKING: live updates: Bank collapses"),
+            "Bank collapses"
+        );
+        assert_eq!(rules.clean("Breaking: Bank collapses"), "Bank collapses");
+    }
+
+    #[test]
+    fn removes_emoji_and_collapses_whitespace() {
+        let rules = TitleRules::default();
+        assert_eq!(
+            rules.clean("🚨 Bitcoin  tops 🚀 $100k 🇺🇸"),
+            "Bitcoin tops $100k"
+        );
+        let keep = TitleRules {
+            strip_emoji: false,
+            ..TitleRules::default()
+        };
+        assert_eq!(keep.clean("Bitcoin 🚀"), "Bitcoin 🚀");
+    }
+
+    #[test]
+    fn keeps_titles_that_would_end_up_empty() {
+        let rules = TitleRules::default();
+        assert_eq!(rules.clean("  🚀  "), "🚀");
+        assert_eq!(rules.clean("LIVE:"), "LIVE:");
+    }
+
+    #[test]
+    fn picks_the_rules_of_the_most_specific_domain() {
+        let opinion = TitleRules {
+            prefixes: vec!["Opinion:".to_string()],
+            ..TitleRules::default()
+        };
+        let dashes = TitleRules {
+            separators: vec![" - ".to_string()],
+            ..TitleRules::default()
+        };
+        let title_rules = BTreeMap::from([
+            ("example.com".to_string(), opinion),
+            ("markets.example.com".to_string(), dashes),
+        ]);
+        let title = "Opinion: Rates - Example";
+        let clean = |url| rules_for(&title_rules, url).clean(title);
+        assert_eq!(clean("https://www.example.com/a"), "Rates - Example");
+        assert_eq!(clean("https://markets.example.com/a"), "Opinion: Rates");
+        assert_eq!(clean("https://notexample.com/a"), title);
+    }
+}
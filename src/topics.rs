@@ -0,0 +1,175 @@
+//! What recent articles are about, found by clustering their embeddings.
+
+use chrono::{DateTime, Utc};
+
+use crate::app::Encrawl;
+use crate::store::{check_embedding_dim, cosine_similarity, Article};
+use crate::summaries;
+
+/// Iterations of k-means after which clustering stops even if articles
+/// still move between clusters.
+const MAX_ITERATIONS: usize = 50;
+
+/// Titles a topic is labelled with.
+const LABEL_TITLES: usize = 3;
+
+/// Articles closest to the centroid of a topic that are summarised.
+const SUMMARY_ARTICLES: usize = 5;
+
+/// A group of recent articles with similar titles.
+#[derive(Debug)]
+pub struct Topic {
+    /// Articles of the topic, closest to its centroid first.
+    pub articles: Vec<Article>,
+    /// Mean similarity of the articles to the centroid, higher for tighter topics.
+    pub cohesion: f32,
+}
+
+impl Topic {
+    /// Titles of the articles most representative of the topic.
+    pub fn label(&self) -> Vec<&str> {
+        self.articles
+            .iter()
+            .take(LABEL_TITLES)
+            .map(|article| article.title.as_str())
+            .collect()
+    }
+}
+
+/// Clusters the articles fetched since `since` into at most `k` topics with
+/// spherical k-means over their title embeddings. Topics with fewer than
+/// `min_size` articles are dropped, the rest are returned largest first.
+pub async fn topics(
+    app: &Encrawl,
+    since: DateTime<Utc>,
+    k: usize,
+    min_size: usize,
+) -> anyhow::Result<Vec<Topic>> {
+    check_embedding_dim(app).await?;
+    let rows: Vec<(i64, pgvector::Vector)> = sqlx::query_as(
+        "SELECT id, embedding FROM articles
+        WHERE fetched_at >= $1 AND embedding IS NOT NULL ORDER BY id",
+    )
+    .bind(since)
+    .fetch_all(app.read_db().await?)
+    .await?;
+    let embeddings = rows
+        .iter()
+        .map(|(_, embedding)| normalized(embedding.as_slice()))
+        .collect::<Vec<_>>();
+    let (assignments, centroids) = kmeans(&embeddings, k);
+
+    let mut clusters = vec![vec![]; centroids.len()];
+    for (i, cluster) in assignments.into_iter().enumerate() {
+        let similarity = cosine_similarity(&embeddings[i], &centroids[cluster]);
+        clusters[cluster].push((rows[i].0, similarity));
+    }
+    clusters.retain(|members| !members.is_empty() && members.len() >= min_size);
+    clusters.sort_by_key(|members| std::cmp::Reverse(members.len()));
+
+    let mut topics = vec![];
+    for mut members in clusters {
+        members.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let ids = members.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let mut articles: Vec<Article> = sqlx::query_as(
+            "SELECT id, title, content, url, author, annotation, extractor, source, domain, lang,
+                published_at, fetched_at
+            FROM articles WHERE id = ANY($1)",
+        )
+        .bind(&ids)
+        .fetch_all(app.read_db().await?)
+        .await?;
+        articles.sort_by_key(|article| ids.iter().position(|id| Some(*id) == article.id));
+        topics.push(Topic {
+            articles,
+            cohesion: members.iter().map(|(_, s)| s).sum::<f32>() / members.len() as f32,
+        });
+    }
+    Ok(topics)
+}
+
+fn normalized(v: &[f32]) -> Vec<f32> {
+    let norm = v
+        .iter()
+        .map(|x| x * x)
+        .sum::<f32>()
+        .sqrt()
+        .max(f32::EPSILON);
+    v.iter().map(|x| x / norm).collect()
+}
+
+/// Spherical k-means over unit `vectors`, returning the cluster of every
+/// vector and the centroids. Seeded with the farthest-first traversal, so the
+/// same articles always give the same topics.
+fn kmeans(vectors: &[Vec<f32>], k: usize) -> (Vec<usize>, Vec<Vec<f32>>) {
+    let k = k.min(vectors.len());
+    if k == 0 {
+        return (vec![], vec![]);
+    }
+    let mut centroids = vec![vectors[0].clone()];
+    let mut closest = vectors
+        .iter()
+        .map(|v| cosine_similarity(v, &centroids[0]))
+        .collect::<Vec<_>>();
+    while centroids.len() < k {
+        let (farthest, _) = closest
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        centroids.push(vectors[farthest].clone());
+        for (similarity, v) in closest.iter_mut().zip(vectors) {
+            *similarity = similarity.max(cosine_similarity(v, &vectors[farthest]));
+        }
+    }
+
+    let mut assignments = vec![usize::MAX; vectors.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut moved = false;
+        for (assignment, v) in assignments.iter_mut().zip(vectors) {
+            let nearest = centroids
+                .iter()
+                .enumerate()
+                .max_by(|a, b| cosine_similarity(v, a.1).total_cmp(&cosine_similarity(v, b.1)))
+                .map(|(i, _)| i)
+                .unwrap();
+            moved |= *assignment != nearest;
+            *assignment = nearest;
+        }
+        if !moved {
+            break;
+        }
+        let dim = vectors[0].len();
+        let mut sums = vec![vec![0f32; dim]; k];
+        for (cluster, v) in assignments.iter().zip(vectors) {
+            for (sum, x) in sums[*cluster].iter_mut().zip(v) {
+                *sum += x;
+            }
+        }
+        for (centroid, sum) in centroids.iter_mut().zip(sums) {
+            // An emptied cluster keeps its centroid.
+            if sum.iter().any(|x| *x != 0.0) {
+                *centroid = normalized(&sum);
+            }
+        }
+    }
+    (assignments, centroids)
+}
+
+/// A Markdown digest of `topics` with one section per topic, summarising the
+/// articles closest to the centroid of each.
+pub async fn digest(app: &Encrawl, topics: &[Topic]) -> anyhow::Result<String> {
+    let mut digest = String::new();
+    let config = app.config();
+    for topic in topics {
+        let articles = &topic.articles[..topic.articles.len().min(SUMMARY_ARTICLES)];
+        let title = topic.label().first().copied().unwrap_or_default();
+        let summary = summaries::summarize(app, title, articles, config.summary_max_age).await?;
+        digest.push_str(&format!("## {title}\n\n{}\n\n", summary.body.trim()));
+        for article in topic.articles.iter().take(LABEL_TITLES) {
+            digest.push_str(&format!("- [{}]({})\n", article.title, article.url));
+        }
+        digest.push('\n');
+    }
+    Ok(digest)
+}
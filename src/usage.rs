@@ -0,0 +1,59 @@
+//! Accounting of the tokens spent on text generation.
+
+use sqlx::{FromRow, Pool, Postgres};
+use std::time::Duration;
+
+use crate::mamba::TokenUsage;
+
+/// Name recorded for generations made with the local Mamba model.
+pub const MAMBA_BACKEND: &str = "mamba";
+
+/// Name recorded for generations made by an OpenAI-compatible server.
+pub const OPENAI_BACKEND: &str = "openai";
+
+/// Token totals of one topic and backend.
+#[derive(Debug, FromRow)]
+pub struct UsageTotal {
+    pub topic: String,
+    pub backend: String,
+    pub generations: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+/// Records one generation for `topic` made by `backend`. Nothing is
+/// recorded without a database, when the articles are kept in SQLite.
+pub async fn record(
+    db: Option<&Pool<Postgres>>,
+    topic: &str,
+    backend: &str,
+    usage: TokenUsage,
+) -> anyhow::Result<()> {
+    let Some(db) = db else {
+        return Ok(());
+    };
+    sqlx::query(
+        "INSERT INTO usage (topic, backend, prompt_tokens, completion_tokens) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(topic)
+    .bind(backend)
+    .bind(usage.prompt_tokens as i64)
+    .bind(usage.completion_tokens as i64)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Sums the usage of the last `since` per topic and backend, biggest spenders first.
+pub async fn report(db: &Pool<Postgres>, since: Duration) -> anyhow::Result<Vec<UsageTotal>> {
+    Ok(sqlx::query_as::<_, UsageTotal>(
+        "SELECT topic, backend, COUNT(*) AS generations,
+            SUM(prompt_tokens)::BIGINT AS prompt_tokens, SUM(completion_tokens)::BIGINT AS completion_tokens
+        FROM usage WHERE created_at >= now() - make_interval(secs => $1)
+        GROUP BY topic, backend
+        ORDER BY SUM(prompt_tokens + completion_tokens) DESC",
+    )
+    .bind(since.as_secs_f64())
+    .fetch_all(db)
+    .await?)
+}
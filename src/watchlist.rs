@@ -0,0 +1,130 @@
+//! Saved queries that are checked against the corpus over time.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres};
+
+use crate::app::Encrawl;
+use crate::store::{check_embedding_dim, cosine_similarity};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Watchlist {
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+    /// How far (1 - cosine similarity) the centroid of the matches may move
+    /// between two checks before it is reported as drift.
+    pub drift_threshold: f32,
+}
+
+/// Result of comparing the current matches of a watchlist with the last check.
+#[derive(Debug)]
+pub struct Drift {
+    pub watchlist: Watchlist,
+    /// Distance the centroid moved since the previous check, if there was one.
+    pub drift: Option<f32>,
+    /// Similarity between the current centroid and the query itself.
+    pub query_similarity: f32,
+    pub articles: usize,
+}
+
+impl Drift {
+    pub fn exceeded(&self) -> bool {
+        self.drift
+            .is_some_and(|drift| drift > self.watchlist.drift_threshold)
+    }
+}
+
+/// Saves `query` under `name`, replacing any watchlist with the same name.
+pub async fn add(
+    db: &Pool<Postgres>,
+    name: &str,
+    query: &str,
+    drift_threshold: f32,
+) -> anyhow::Result<Watchlist> {
+    Ok(sqlx::query_as(
+        "INSERT INTO watchlists (name, query, drift_threshold) VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO UPDATE SET query = EXCLUDED.query, drift_threshold = EXCLUDED.drift_threshold
+        RETURNING id, name, query, drift_threshold",
+    )
+    .bind(name)
+    .bind(query)
+    .bind(drift_threshold)
+    .fetch_one(db)
+    .await?)
+}
+
+pub async fn list(db: &Pool<Postgres>) -> anyhow::Result<Vec<Watchlist>> {
+    Ok(
+        sqlx::query_as("SELECT id, name, query, drift_threshold FROM watchlists ORDER BY name")
+            .fetch_all(db)
+            .await?,
+    )
+}
+
+/// Deletes the watchlist called `name`, returning whether it existed.
+pub async fn remove(db: &Pool<Postgres>, name: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM watchlists WHERE name = $1")
+        .bind(name)
+        .execute(db)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Recomputes the centroid of the `sample` best matches of every watchlist,
+/// compares it with the previous one and stores it for the next check.
+pub async fn check_drift(app: &Encrawl, sample: i64) -> anyhow::Result<Vec<Drift>> {
+    check_embedding_dim(app).await?;
+    let mut drifts = vec![];
+    for watchlist in list(app.postgres()?).await? {
+        let query = app
+            .embed(std::slice::from_ref(&watchlist.query))
+            .await?
+            .remove(0);
+        let matches: Vec<(pgvector::Vector,)> =
+            sqlx::query_as("SELECT embedding FROM articles ORDER BY embedding <=> $1 LIMIT $2")
+                .bind(pgvector::Vector::from(query.clone()))
+                .bind(sample)
+                .fetch_all(app.postgres()?)
+                .await?;
+        if matches.is_empty() {
+            continue;
+        }
+        let mut centroid = vec![0f32; query.len()];
+        for (embedding,) in &matches {
+            for (c, x) in centroid.iter_mut().zip(embedding.as_slice()) {
+                *c += x / matches.len() as f32;
+            }
+        }
+        let previous: Option<(pgvector::Vector,)> = sqlx::query_as(
+            "SELECT centroid FROM watchlist_centroids WHERE watchlist_id = $1
+            ORDER BY computed_at DESC LIMIT 1",
+        )
+        .bind(watchlist.id)
+        .fetch_optional(app.postgres()?)
+        .await?;
+        sqlx::query(
+            "INSERT INTO watchlist_centroids (watchlist_id, article_count, centroid) VALUES ($1, $2, $3)",
+        )
+        .bind(watchlist.id)
+        .bind(matches.len() as i32)
+        .bind(pgvector::Vector::from(centroid.clone()))
+        .execute(app.postgres()?)
+        .await?;
+        let drift = Drift {
+            drift: previous
+                .map(|(previous,)| 1.0 - cosine_similarity(previous.as_slice(), &centroid)),
+            query_similarity: cosine_similarity(&query, &centroid),
+            articles: matches.len(),
+            watchlist,
+        };
+        if drift.exceeded() {
+            log::warn!(
+                "Watchlist {} drifted by {:.3}, it may be matching a different story now",
+                drift.watchlist.name,
+                drift.drift.unwrap_or_default()
+            );
+        }
+        drifts.push(drift);
+    }
+    Ok(drifts)
+}
@@ -0,0 +1,68 @@
+//! Crawls of a local site with failures injected, see `encrawl_rust::chaos`.
+
+mod common;
+
+use encrawl_rust::chaos::ChaosConfig;
+use encrawl_rust::crawl::crawl;
+use encrawl_rust::source::Source;
+
+const PAGES: usize = 20;
+
+async fn crawl_with(
+    name: &str,
+    chaos: ChaosConfig,
+    max_retries: u32,
+) -> encrawl_rust::report::CrawlReport {
+    let dir = common::temp_dir(name);
+    let pages = (0..PAGES)
+        .map(|i| common::article_html(i, name))
+        .collect::<Vec<_>>();
+    let urls = common::serve(pages).await;
+    let mut config = common::config(&dir);
+    config.chaos = chaos;
+    config.max_retries = max_retries;
+    config.embed_batch_size = 2;
+    let app = common::app(&dir, config).await;
+    let sources: Vec<Box<dyn Source>> = vec![Box::new(common::Links(urls))];
+    crawl(&app, &sources).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn crawl_with_injected_failures_completes_and_reports_them() {
+    let chaos = ChaosConfig {
+        fetch_timeout: 0.3,
+        malformed_html: 0.3,
+        db_error: 0.3,
+        seed: 7,
+    };
+    let report = crawl_with("failures", chaos, 0).await;
+    let injected = &report.injected;
+    assert!(injected.fetch_timeouts > 0, "{injected:?}");
+    assert!(injected.malformed_html > 0, "{injected:?}");
+    assert!(injected.db_errors > 0, "{injected:?}");
+    // Without retries every injected failure fails its article, and every
+    // article fails or is stored.
+    let totals = report.totals();
+    assert_eq!(totals.posts, PAGES);
+    assert_eq!(
+        totals.failed,
+        injected.fetch_timeouts + injected.malformed_html + injected.db_error_articles
+    );
+    assert_eq!(totals.new + totals.failed, PAGES);
+    assert_eq!(report.store_errors.len(), injected.db_errors);
+    assert_eq!(report.stages.stored, totals.new);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn injected_timeouts_are_retried() {
+    let chaos = ChaosConfig {
+        fetch_timeout: 0.3,
+        seed: 11,
+        ..ChaosConfig::default()
+    };
+    let report = crawl_with("retries", chaos, 8).await;
+    assert!(report.injected.fetch_timeouts > 0);
+    let totals = report.totals();
+    assert_eq!(totals.failed, 0);
+    assert_eq!(totals.new, PAGES);
+}
@@ -0,0 +1,157 @@
+//! What the integration tests share: an [`Encrawl`] on SQLite with a tiny
+//! random BERT model, so they run offline, and a local site to crawl.
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use axum::response::Html;
+use axum::routing::get;
+use candle_core::{DType, Device};
+use candle_nn::{VarBuilder, VarMap};
+use candle_transformers::models::bert::{self, BertModel};
+use encrawl_rust::embedding::{EmbeddingBackend, EmbeddingModel};
+use encrawl_rust::source::{PostCandidate, Source};
+use encrawl_rust::{Config, Encrawl};
+
+const MODEL_CONFIG: &str = r#"{
+    "vocab_size": 64,
+    "hidden_size": 8,
+    "num_hidden_layers": 1,
+    "num_attention_heads": 2,
+    "intermediate_size": 16,
+    "hidden_act": "gelu",
+    "hidden_dropout_prob": 0.0,
+    "max_position_embeddings": 512,
+    "type_vocab_size": 2,
+    "initializer_range": 0.02,
+    "layer_norm_eps": 1e-12,
+    "pad_token_id": 0
+}"#;
+
+/// Splits on whitespace and punctuation and maps every word to the unknown
+/// token but a few, which is all a model with random weights needs.
+const TOKENIZER: &str = r#"{
+    "version": "1.0",
+    "truncation": null,
+    "padding": null,
+    "added_tokens": [],
+    "normalizer": {"type": "Lowercase"},
+    "pre_tokenizer": {"type": "Whitespace"},
+    "post_processor": null,
+    "decoder": null,
+    "model": {
+        "type": "WordLevel",
+        "vocab": {"[UNK]": 0, "the": 1, "market": 2, "bank": 3, "rates": 4, "oil": 5},
+        "unk_token": "[UNK]"
+    }
+}"#;
+
+/// An empty directory of its own for the test `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("encrawl-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a BERT model with random weights to `dir`, in the layout the
+/// candle backend loads local models from.
+pub fn tiny_model(dir: &Path) -> PathBuf {
+    let dir = dir.join("model");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.json"), MODEL_CONFIG).unwrap();
+    std::fs::write(dir.join("tokenizer.json"), TOKENIZER).unwrap();
+    let config: bert::Config = serde_json::from_str(MODEL_CONFIG).unwrap();
+    let weights = VarMap::new();
+    let vb = VarBuilder::from_varmap(&weights, DType::F32, &Device::Cpu);
+    BertModel::load(vb, &config).unwrap();
+    weights.save(dir.join("model.safetensors")).unwrap();
+    dir
+}
+
+/// The config of a crawl of the local site: no files, the tiny model, no
+/// rate limit and no robots.txt.
+pub fn config(dir: &Path) -> Config {
+    let missing = dir.join("missing");
+    let mut config = Config::load(
+        missing.clone(),
+        missing.clone(),
+        missing.clone(),
+        missing.clone(),
+        missing.clone(),
+        missing,
+    )
+    .unwrap();
+    config.embedding_model = EmbeddingModel::Local(tiny_model(dir));
+    config.embedding_backend = EmbeddingBackend::Candle;
+    config.rate_limit = 0.0;
+    config.ignore_robots = true;
+    config
+}
+
+/// An [`Encrawl`] keeping its articles in a SQLite file in `dir`.
+pub async fn app(dir: &Path, config: Config) -> Encrawl {
+    let db_url = format!("sqlite:{}", dir.join("articles.db").display());
+    Encrawl::new(&db_url, None, config).await.unwrap()
+}
+
+/// A news article about `topic`, with enough paragraphs for the generic
+/// extractor.
+pub fn article_html(i: usize, topic: &str) -> String {
+    let paragraph = format!(
+        "The {topic} story number {i} goes on about what the bank said, how the market \
+        took it and what rates and oil did in the hours after the news came out."
+    );
+    format!(
+        "<html><head><title>Story {i} about {topic}</title></head><body><article>\
+        <h1>Story {i} about {topic}</h1><p>{paragraph}</p><p>{paragraph} Again.</p>\
+        <p>{paragraph} Once more.</p></article></body></html>"
+    )
+}
+
+/// Serves `pages` as HTML at `/news/<index>` on a free local port and
+/// returns their URLs.
+pub async fn serve(pages: Vec<String>) -> Vec<String> {
+    let mut router = axum::Router::new();
+    for (i, page) in pages.iter().enumerate() {
+        let page = page.clone();
+        router = router.route(
+            &format!("/news/{i}"),
+            get(move || async move { Html(page) }),
+        );
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    (0..pages.len())
+        .map(|i| format!("http://{addr}/news/{i}"))
+        .collect()
+}
+
+/// A source linking to fixed URLs.
+pub struct Links(pub Vec<String>);
+
+#[async_trait]
+impl Source for Links {
+    fn name(&self) -> String {
+        "links".to_string()
+    }
+
+    async fn fetch_posts(&self) -> anyhow::Result<Vec<PostCandidate>> {
+        Ok(self
+            .0
+            .iter()
+            .map(|url| PostCandidate {
+                url: url.clone(),
+                title: String::new(),
+                source: self.name(),
+                id: None,
+                posted_at: None,
+                referrer: None,
+                score: None,
+            })
+            .collect())
+    }
+}
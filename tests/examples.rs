@@ -0,0 +1,76 @@
+//! The logic of the examples run on their fixtures, offline.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+use encrawl_rust::pipeline::FetchedPage;
+use encrawl_rust::telegram::{self, BotCommand};
+use encrawl_rust::{corpus, scrape, search, ScraperConfig};
+
+const ARTICLES: &str = "examples/fixtures/articles.jsonl";
+const PAGES: &str = "examples/fixtures/pages.jsonl";
+const BOT_COMMANDS: &str = "examples/fixtures/bot_commands.txt";
+
+fn articles() -> std::io::BufReader<std::fs::File> {
+    std::io::BufReader::new(std::fs::File::open(ARTICLES).unwrap())
+}
+
+#[test]
+fn crawl_to_jsonl_extracts_the_fixture_pages() {
+    let scrapers = ScraperConfig::from_file("scrapers.ron".into()).unwrap();
+    let pages = std::io::BufReader::new(std::fs::File::open(PAGES).unwrap());
+    let mut extracted = 0;
+    for line in pages.lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let FetchedPage { candidate, page } = serde_json::from_str(&line).unwrap();
+        let url = candidate.as_str().to_string();
+        let article = scrape::extract_page(&scrapers, &BTreeMap::new(), url, &page.html)
+            .unwrap_or_else(|e| panic!("{candidate}: {e}"));
+        assert!(!article.title.is_empty(), "{candidate} has no title");
+        assert!(!article.content.is_empty(), "{candidate} has no content");
+        extracted += 1;
+    }
+    assert_eq!(extracted, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn search_server_imports_and_searches_the_fixture_articles() {
+    let dir = common::temp_dir("search-server");
+    let app = common::app(&dir, common::config(&dir)).await;
+    let counts = corpus::import(&app, articles(), 32).await.unwrap();
+    assert_eq!(counts.new, 4);
+    let again = corpus::import(&app, articles(), 32).await.unwrap();
+    assert_eq!(again.new, 0);
+    let found = search(&app, "interest rates".to_string(), 2).await.unwrap();
+    assert_eq!(found.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn digest_bot_answers_the_fixture_commands_without_postgres() {
+    let dir = common::temp_dir("digest-bot");
+    let app = common::app(&dir, common::config(&dir)).await;
+    corpus::import(&app, articles(), 32).await.unwrap();
+    let commands = std::fs::read_to_string(BOT_COMMANDS).unwrap();
+    let mut answered = 0;
+    for line in commands.lines() {
+        let Some(command) = BotCommand::parse(line) else {
+            continue;
+        };
+        let reply = telegram::handle(&app, 0, &command).await;
+        match command {
+            BotCommand::Search(_) => {
+                assert!(reply.unwrap().contains("https://"), "{line}");
+            }
+            // Subscriptions are kept in Postgres.
+            BotCommand::Subscriptions => assert!(reply.is_err(), "{line}"),
+            _ => assert!(!reply.unwrap().is_empty(), "{line}"),
+        }
+        answered += 1;
+    }
+    assert_eq!(answered, 4);
+}
@@ -0,0 +1,112 @@
+//! Storing articles in SQLite, see `encrawl_rust::sqlite`.
+
+mod common;
+
+use encrawl_rust::embedding::EmbeddingModel;
+use encrawl_rust::store::store_batch;
+use encrawl_rust::{search_filtered, Article, Encrawl, SearchFilter, Stored};
+
+fn article(url: &str, title: &str, content: &str) -> Article {
+    by("Staff", url, title, content)
+}
+
+fn by(author: &str, url: &str, title: &str, content: &str) -> Article {
+    Article {
+        title: title.to_string(),
+        url: url.to_string(),
+        content: content.to_string(),
+        author: author.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn articles_are_stored_again_only_when_their_embeddings_are_stale() {
+    let dir = common::temp_dir("sqlite-store");
+    let app = common::app(&dir, common::config(&dir)).await;
+    let content = "The bank raised rates by half a point and the market fell.";
+    let first = article("https://example.com/rates", "Rates up", content);
+    let [stored] = store_batch(&app, std::slice::from_ref(&first))
+        .await
+        .unwrap()[..]
+    else {
+        panic!("one article stored");
+    };
+    let Stored::New(id) = stored else {
+        panic!("{stored:?}");
+    };
+
+    // Embedded as it is now, so it's a duplicate, as is its content elsewhere.
+    let again = store_batch(
+        &app,
+        &[
+            first.clone(),
+            article("https://example.org/rates", "Rates up", content),
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(again, [Stored::Duplicate, Stored::Duplicate]);
+
+    // A new title at the same URL is stored again in place.
+    let retitled = article("https://example.com/rates", "Rates up again", content);
+    let stored = store_batch(&app, std::slice::from_ref(&retitled))
+        .await
+        .unwrap();
+    assert_eq!(stored, [Stored::Updated(id)]);
+
+    // As is the same article once another model is configured.
+    let mut config = common::config(&dir);
+    config.embedding_model = EmbeddingModel::Local(common::tiny_model(&dir.join("other")));
+    let db_url = format!("sqlite:{}", dir.join("articles.db").display());
+    let app = Encrawl::new(&db_url, None, config).await.unwrap();
+    let stored = store_batch(&app, &[retitled]).await.unwrap();
+    assert_eq!(stored, [Stored::Updated(id)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn author_filter_matches_wildcards_literally() {
+    let dir = common::temp_dir("sqlite-author");
+    let app = common::app(&dir, common::config(&dir)).await;
+    let articles = [
+        by(
+            "Jane Doe",
+            "https://example.com/a",
+            "Oil up",
+            "Oil rose on supply cuts.",
+        ),
+        by(
+            "j_doe",
+            "https://example.com/b",
+            "Oil down",
+            "Oil fell on weak demand.",
+        ),
+        by(
+            "Ann 50% Co",
+            "https://example.com/c",
+            "Oil flat",
+            "Oil held steady all day.",
+        ),
+    ];
+    store_batch(&app, &articles).await.unwrap();
+    let authors = |author: &str| {
+        let filter = SearchFilter {
+            author: Some(author.to_string()),
+            ..Default::default()
+        };
+        let app = &app;
+        async move {
+            let mut found = search_filtered(app, "oil".to_string(), 10, &filter)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|article| article.author)
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        }
+    };
+    assert_eq!(authors("_").await, ["j_doe"]);
+    assert_eq!(authors("%").await, ["Ann 50% Co"]);
+    assert_eq!(authors("DOE").await, ["Jane Doe", "j_doe"]);
+}